@@ -0,0 +1,65 @@
+-- Friend Knowledgebase Relationship Timeline
+-- Migration: 002_relationship_timeline.sql
+--
+-- Relationships change over time: coworkers leave, couples split up,
+-- someone passes away. This adds optional start/end dates and a status to
+-- both relationship tables, plus an append-only status history for each.
+
+-- =============================================================================
+-- COLUMNS
+-- =============================================================================
+
+-- Friend-to-friend relationships
+ALTER TABLE friend_relationships
+    ADD COLUMN started_on DATE,
+    ADD COLUMN ended_on DATE,
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'active',
+    ADD CONSTRAINT friend_relationships_dates_ordered
+        CHECK (started_on IS NULL OR ended_on IS NULL OR ended_on >= started_on);
+
+-- User-to-friend relationships
+ALTER TABLE user_friend_relationships
+    ADD COLUMN started_on DATE,
+    ADD COLUMN ended_on DATE,
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'active',
+    ADD CONSTRAINT user_friend_relationships_dates_ordered
+        CHECK (started_on IS NULL OR ended_on IS NULL OR ended_on >= started_on);
+
+-- =============================================================================
+-- TABLES
+-- =============================================================================
+
+-- Status history for friend-to-friend relationships (append-only)
+CREATE TABLE friend_relationship_status_history (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    relationship_id UUID NOT NULL REFERENCES friend_relationships(id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    effective_on DATE NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Status history for user-to-friend relationships (append-only)
+CREATE TABLE user_friend_relationship_status_history (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    relationship_id UUID NOT NULL REFERENCES user_friend_relationships(id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    effective_on DATE NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Backfill: every existing relationship starts with one 'active' entry
+INSERT INTO friend_relationship_status_history (relationship_id, status, effective_on)
+SELECT id, status, created_at::date FROM friend_relationships;
+
+INSERT INTO user_friend_relationship_status_history (relationship_id, status, effective_on)
+SELECT id, status, created_at::date FROM user_friend_relationships;
+
+-- =============================================================================
+-- INDEXES
+-- =============================================================================
+
+CREATE INDEX idx_friend_relationship_status_history_relationship_id
+    ON friend_relationship_status_history(relationship_id);
+
+CREATE INDEX idx_user_friend_relationship_status_history_relationship_id
+    ON user_friend_relationship_status_history(relationship_id);
@@ -0,0 +1,33 @@
+-- Friend Knowledgebase Calendar Feeds
+-- Migration: 003_calendar_feeds.sql
+--
+-- Each user can publish their friends' birthdays and other important dates
+-- as an iCalendar feed. Calendar apps can't log in, so the feed URL itself
+-- is the credential: it carries a long random token that the user can
+-- rotate to revoke old subscriptions.
+
+-- =============================================================================
+-- TABLES
+-- =============================================================================
+
+-- One feed per user
+CREATE TABLE calendar_feeds (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    -- 64 hex characters from two random UUIDs (244 random bits)
+    token TEXT NOT NULL UNIQUE
+        DEFAULT replace(gen_random_uuid()::text || gen_random_uuid()::text, '-', ''),
+    -- Reminder lead time; NULL means events carry no VALARM
+    alarm_minutes_before INTEGER
+        CHECK (alarm_minutes_before IS NULL OR alarm_minutes_before >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ
+);
+
+-- =============================================================================
+-- TRIGGERS
+-- =============================================================================
+
+CREATE TRIGGER update_calendar_feeds_updated_at
+    BEFORE UPDATE ON calendar_feeds
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,137 @@
+-- Friend Knowledgebase CardDAV
+-- Migration: 004_carddav.sql
+--
+-- Phones sync contacts incrementally: "what changed since last time?".
+-- Triggers log every change to a friend's card (the friend row, its
+-- attributes, its group memberships, or a rename of one of its groups)
+-- so the answer doesn't depend on which code path made the change.
+-- Deletions are logged too; the sync code tells them apart by checking
+-- whether the friend still exists.
+
+-- =============================================================================
+-- TABLES
+-- =============================================================================
+
+-- Append-only change log; `id` is the sync position
+CREATE TABLE friend_changes (
+    id BIGSERIAL PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- No foreign key: deleted friends are logged as well
+    friend_id UUID NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Resource names chosen by CardDAV clients ("3F2A....vcf"). Friends
+-- without a row here are served as "<friend id>.vcf".
+CREATE TABLE carddav_resources (
+    -- No foreign key: the name must outlive the friend so sync reports
+    -- can tell clients which resource was deleted
+    friend_id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CONSTRAINT carddav_resources_user_name_unique UNIQUE (user_id, name)
+);
+
+-- =============================================================================
+-- INDEXES
+-- =============================================================================
+
+CREATE INDEX idx_friend_changes_user_id ON friend_changes(user_id, id);
+
+-- =============================================================================
+-- TRIGGER FUNCTIONS
+-- =============================================================================
+
+-- Log a change for a friend, unless their user is being deleted.
+--
+-- The advisory lock serializes change logging per user until commit, so
+-- a user's change IDs become visible in increasing order and a reader
+-- that has seen ID n can never later find an uncommitted ID below n.
+CREATE OR REPLACE FUNCTION log_friend_change_for(changed_friend UUID)
+RETURNS VOID AS $$
+DECLARE
+    owner UUID;
+BEGIN
+    SELECT f.user_id INTO owner
+    FROM friends f
+    INNER JOIN users u ON u.id = f.user_id
+    WHERE f.id = changed_friend;
+
+    IF owner IS NOT NULL THEN
+        PERFORM pg_advisory_xact_lock(hashtextextended(owner::text, 0));
+        INSERT INTO friend_changes (user_id, friend_id) VALUES (owner, changed_friend);
+    END IF;
+END;
+$$ LANGUAGE plpgsql;
+
+-- friends: inserts and updates
+CREATE OR REPLACE FUNCTION log_friend_change()
+RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM log_friend_change_for(NEW.id);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- friends: deletes (the row is already gone, so use OLD.user_id)
+CREATE OR REPLACE FUNCTION log_friend_delete()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM users WHERE id = OLD.user_id) THEN
+        PERFORM pg_advisory_xact_lock(hashtextextended(OLD.user_id::text, 0));
+        INSERT INTO friend_changes (user_id, friend_id) VALUES (OLD.user_id, OLD.id);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- friend_attributes and friend_groups: any change to a child row
+CREATE OR REPLACE FUNCTION log_friend_child_change()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM log_friend_change_for(OLD.friend_id);
+    ELSE
+        PERFORM log_friend_change_for(NEW.friend_id);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- groups: a rename changes every member's categories
+CREATE OR REPLACE FUNCTION log_group_rename()
+RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM log_friend_change_for(fg.friend_id)
+    FROM friend_groups fg
+    WHERE fg.group_id = NEW.id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- =============================================================================
+-- TRIGGERS
+-- =============================================================================
+
+CREATE TRIGGER log_friends_change
+    AFTER INSERT OR UPDATE ON friends
+    FOR EACH ROW EXECUTE FUNCTION log_friend_change();
+
+CREATE TRIGGER log_friends_delete
+    AFTER DELETE ON friends
+    FOR EACH ROW EXECUTE FUNCTION log_friend_delete();
+
+CREATE TRIGGER log_friend_attributes_change
+    AFTER INSERT OR UPDATE OR DELETE ON friend_attributes
+    FOR EACH ROW EXECUTE FUNCTION log_friend_child_change();
+
+CREATE TRIGGER log_friend_groups_change
+    AFTER INSERT OR UPDATE OR DELETE ON friend_groups
+    FOR EACH ROW EXECUTE FUNCTION log_friend_child_change();
+
+CREATE TRIGGER log_groups_rename
+    AFTER UPDATE OF name ON groups
+    FOR EACH ROW
+    WHEN (OLD.name IS DISTINCT FROM NEW.name)
+    EXECUTE FUNCTION log_group_rename();
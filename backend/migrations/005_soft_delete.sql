@@ -0,0 +1,24 @@
+-- Friend Knowledgebase Soft Delete
+-- Migration: 005_soft_delete.sql
+--
+-- Deleting a friend moves them to the trash instead of removing the row.
+-- Their attributes, group memberships and relationships stay where they
+-- are, hidden by the queries that read them, so restoring the friend
+-- brings everything back. A purge job removes friends that have been in
+-- the trash too long; only then do the ON DELETE CASCADE rules run.
+
+-- =============================================================================
+-- COLUMNS
+-- =============================================================================
+
+-- NULL for live friends; set when moved to the trash
+ALTER TABLE friends ADD COLUMN deleted_at TIMESTAMPTZ;
+
+-- =============================================================================
+-- INDEXES
+-- =============================================================================
+
+-- The purge job scans the whole trash by age; per-user trash listings
+-- go through idx_friends_user_id like every other friend query
+CREATE INDEX idx_friends_deleted_at ON friends(deleted_at)
+    WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,181 @@
+-- Friend Knowledgebase Audit History
+-- Migration: 006_friend_history.sql
+--
+-- An append-only record of every change to a friend: their own row, their
+-- attributes, their group memberships and their relationships, each with
+-- the row as JSON before and after. Triggers write it, so every code path
+-- (repositories, imports, CardDAV, manual SQL) is covered. Replaying the
+-- history up to a timestamp reconstructs the friend as of that moment.
+
+-- =============================================================================
+-- TABLES
+-- =============================================================================
+
+CREATE TABLE friend_history (
+    id BIGSERIAL PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- No foreign key: the history outlives purged friends
+    friend_id UUID NOT NULL,
+    -- friends, friend_attributes, friend_groups, friend_relationships or
+    -- user_friend_relationships
+    table_name TEXT NOT NULL,
+    -- The row's key within its table; "<friend id>:<group id>" for
+    -- memberships, which have no ID of their own
+    row_key TEXT NOT NULL,
+    -- BASELINE entries record rows that existed before this migration
+    operation TEXT NOT NULL
+        CHECK (operation IN ('INSERT', 'UPDATE', 'DELETE', 'BASELINE')),
+    -- NULL for inserts
+    before JSONB,
+    -- NULL for deletes
+    after JSONB,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- =============================================================================
+-- INDEXES
+-- =============================================================================
+
+CREATE INDEX idx_friend_history_friend_id ON friend_history(friend_id, changed_at, id);
+
+-- =============================================================================
+-- TRIGGER FUNCTIONS
+-- =============================================================================
+
+-- Record a change to a friend or one of their child rows.
+--
+-- Relationship rows concern two friends and are recorded once for each.
+-- Rows deleted by a cascade from a deleted account or purged friend are
+-- skipped: the owner lookup goes through the (already deleted) parent.
+CREATE OR REPLACE FUNCTION log_friend_history()
+RETURNS TRIGGER AS $$
+DECLARE
+    before_row JSONB := CASE WHEN TG_OP <> 'INSERT' THEN to_jsonb(OLD) END;
+    after_row JSONB := CASE WHEN TG_OP <> 'DELETE' THEN to_jsonb(NEW) END;
+    current_row JSONB := COALESCE(after_row, before_row);
+    key TEXT := current_row->>'id';
+    friend_ids UUID[];
+    changed_friend UUID;
+    owner UUID;
+BEGIN
+    -- Saves that only bump updated_at change nothing worth recording
+    IF TG_OP = 'UPDATE' AND (before_row - 'updated_at') = (after_row - 'updated_at') THEN
+        RETURN NULL;
+    END IF;
+
+    CASE TG_TABLE_NAME
+        WHEN 'friends' THEN
+            friend_ids := ARRAY[(current_row->>'id')::uuid];
+        WHEN 'friend_relationships' THEN
+            friend_ids := ARRAY[
+                (current_row->>'friend_a_id')::uuid,
+                (current_row->>'friend_b_id')::uuid
+            ];
+        WHEN 'friend_groups' THEN
+            key := (current_row->>'friend_id') || ':' || (current_row->>'group_id');
+            friend_ids := ARRAY[(current_row->>'friend_id')::uuid];
+            -- Keep the group's name as it was, since renames aren't history
+            -- of the friend
+            SELECT before_row || jsonb_build_object('group_name', g.name),
+                   after_row || jsonb_build_object('group_name', g.name)
+            INTO before_row, after_row
+            FROM (SELECT 1) AS one
+            LEFT JOIN groups g ON g.id = (current_row->>'group_id')::uuid;
+        ELSE
+            friend_ids := ARRAY[(current_row->>'friend_id')::uuid];
+    END CASE;
+
+    FOREACH changed_friend IN ARRAY friend_ids LOOP
+        IF TG_TABLE_NAME = 'friends' THEN
+            SELECT u.id INTO owner FROM users u WHERE u.id = (current_row->>'user_id')::uuid;
+        ELSE
+            SELECT u.id INTO owner
+            FROM friends f
+            INNER JOIN users u ON u.id = f.user_id
+            WHERE f.id = changed_friend;
+        END IF;
+
+        IF owner IS NOT NULL THEN
+            INSERT INTO friend_history
+                (user_id, friend_id, table_name, row_key, operation, before, after)
+            VALUES
+                (owner, changed_friend, TG_TABLE_NAME, key, TG_OP, before_row, after_row);
+        END IF;
+    END LOOP;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- History is append-only. The one exception is deleting an account, whose
+-- history goes with it through the ON DELETE CASCADE above.
+CREATE OR REPLACE FUNCTION reject_friend_history_rewrite()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' AND NOT EXISTS (SELECT 1 FROM users WHERE id = OLD.user_id) THEN
+        RETURN OLD;
+    END IF;
+    RAISE EXCEPTION 'friend_history is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+-- =============================================================================
+-- TRIGGERS
+-- =============================================================================
+
+CREATE TRIGGER log_friends_history
+    AFTER INSERT OR UPDATE OR DELETE ON friends
+    FOR EACH ROW EXECUTE FUNCTION log_friend_history();
+
+CREATE TRIGGER log_friend_attributes_history
+    AFTER INSERT OR UPDATE OR DELETE ON friend_attributes
+    FOR EACH ROW EXECUTE FUNCTION log_friend_history();
+
+CREATE TRIGGER log_friend_groups_history
+    AFTER INSERT OR UPDATE OR DELETE ON friend_groups
+    FOR EACH ROW EXECUTE FUNCTION log_friend_history();
+
+CREATE TRIGGER log_friend_relationships_history
+    AFTER INSERT OR UPDATE OR DELETE ON friend_relationships
+    FOR EACH ROW EXECUTE FUNCTION log_friend_history();
+
+CREATE TRIGGER log_user_friend_relationships_history
+    AFTER INSERT OR UPDATE OR DELETE ON user_friend_relationships
+    FOR EACH ROW EXECUTE FUNCTION log_friend_history();
+
+CREATE TRIGGER protect_friend_history
+    BEFORE UPDATE OR DELETE ON friend_history
+    FOR EACH ROW EXECUTE FUNCTION reject_friend_history_rewrite();
+
+-- =============================================================================
+-- BASELINE
+-- =============================================================================
+
+-- Record what exists now, so "as of" views work from this point on even
+-- for friends that haven't changed since
+
+INSERT INTO friend_history (user_id, friend_id, table_name, row_key, operation, after)
+SELECT f.user_id, f.id, 'friends', f.id::text, 'BASELINE', to_jsonb(f)
+FROM friends f;
+
+INSERT INTO friend_history (user_id, friend_id, table_name, row_key, operation, after)
+SELECT f.user_id, a.friend_id, 'friend_attributes', a.id::text, 'BASELINE', to_jsonb(a)
+FROM friend_attributes a
+INNER JOIN friends f ON f.id = a.friend_id;
+
+INSERT INTO friend_history (user_id, friend_id, table_name, row_key, operation, after)
+SELECT f.user_id, fg.friend_id, 'friend_groups', fg.friend_id || ':' || fg.group_id, 'BASELINE',
+       to_jsonb(fg) || jsonb_build_object('group_name', g.name)
+FROM friend_groups fg
+INNER JOIN friends f ON f.id = fg.friend_id
+INNER JOIN groups g ON g.id = fg.group_id;
+
+INSERT INTO friend_history (user_id, friend_id, table_name, row_key, operation, after)
+SELECT r.user_id, side.friend_id, 'friend_relationships', r.id::text, 'BASELINE', to_jsonb(r)
+FROM friend_relationships r
+CROSS JOIN LATERAL (VALUES (r.friend_a_id), (r.friend_b_id)) AS side(friend_id);
+
+INSERT INTO friend_history (user_id, friend_id, table_name, row_key, operation, after)
+SELECT f.user_id, r.friend_id, 'user_friend_relationships', r.id::text, 'BASELINE', to_jsonb(r)
+FROM user_friend_relationships r
+INNER JOIN friends f ON f.id = r.friend_id;
@@ -0,0 +1,135 @@
+-- Friend Knowledgebase Undo
+-- Migration: 007_undo.sql
+--
+-- Per-user undo and redo, built on friend_history (006). Every
+-- transaction that changes a user's friends becomes one undo step; the
+-- history rows it wrote hold the before and after image of each row it
+-- touched, which is everything needed to revert it or apply it again.
+
+-- =============================================================================
+-- COLUMNS
+-- =============================================================================
+
+-- Groups history rows into the transactions (user actions) that wrote them
+ALTER TABLE friend_history
+    ADD COLUMN transaction_id BIGINT NOT NULL DEFAULT txid_current();
+
+-- =============================================================================
+-- TABLES
+-- =============================================================================
+
+-- A user's undo stack (undone_at NULL) and redo stack (undone_at set)
+CREATE TABLE undo_steps (
+    id BIGSERIAL PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    transaction_id BIGINT NOT NULL,
+    undone_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (user_id, transaction_id)
+);
+
+-- =============================================================================
+-- INDEXES
+-- =============================================================================
+
+CREATE INDEX idx_friend_history_transaction ON friend_history(user_id, transaction_id, id);
+
+-- =============================================================================
+-- FUNCTIONS
+-- =============================================================================
+
+-- Make every history row a transaction writes part of an undo step.
+--
+-- Undo and redo run with fkb.undoing set, so their own changes don't
+-- become steps. Any other change starts a new branch: the redo stack is
+-- dropped, and only the newest 50 steps are kept.
+CREATE OR REPLACE FUNCTION record_undo_step()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.operation = 'BASELINE' OR current_setting('fkb.undoing', true) = 'on' THEN
+        RETURN NULL;
+    END IF;
+
+    INSERT INTO undo_steps (user_id, transaction_id)
+    VALUES (NEW.user_id, NEW.transaction_id)
+    ON CONFLICT (user_id, transaction_id) DO NOTHING;
+
+    IF FOUND THEN
+        DELETE FROM undo_steps
+        WHERE user_id = NEW.user_id AND undone_at IS NOT NULL;
+
+        DELETE FROM undo_steps
+        WHERE user_id = NEW.user_id
+          AND id <= (
+              SELECT id FROM undo_steps
+              WHERE user_id = NEW.user_id
+              ORDER BY id DESC
+              OFFSET 50 LIMIT 1
+          );
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Put one row back into the state a history entry recorded.
+--
+-- `expected` is the state the row must be in now (NULL: must not exist)
+-- and `target` the state to leave it in (NULL: delete it). Both are
+-- compared without updated_at, which the tables' own trigger maintains,
+-- and group_name, which the history adds to memberships.
+--
+-- Returns false, changing nothing, if the row isn't in the expected
+-- state, i.e. something else changed it since.
+CREATE OR REPLACE FUNCTION apply_history_row(tbl TEXT, key TEXT, expected JSONB, target JSONB)
+RETURNS BOOLEAN AS $$
+DECLARE
+    key_filter TEXT;
+    current_row JSONB;
+    columns TEXT;
+BEGIN
+    IF tbl NOT IN ('friends', 'friend_attributes', 'friend_groups',
+                   'friend_relationships', 'user_friend_relationships') THEN
+        RAISE EXCEPTION 'no history for table %', tbl;
+    END IF;
+
+    IF tbl = 'friend_groups' THEN
+        key_filter := format('friend_id = %L AND group_id = %L',
+                             split_part(key, ':', 1), split_part(key, ':', 2));
+    ELSE
+        key_filter := format('id = %L', key);
+    END IF;
+
+    EXECUTE format('SELECT to_jsonb(t) FROM %I t WHERE %s FOR UPDATE', tbl, key_filter)
+    INTO current_row;
+
+    IF (current_row - 'updated_at') IS DISTINCT FROM (expected - 'updated_at' - 'group_name') THEN
+        RETURN false;
+    END IF;
+
+    IF target IS NULL THEN
+        EXECUTE format('DELETE FROM %I WHERE %s', tbl, key_filter);
+    ELSIF current_row IS NULL THEN
+        EXECUTE format('INSERT INTO %I SELECT * FROM jsonb_populate_record(NULL::%I, $1)', tbl, tbl)
+        USING target;
+    ELSE
+        SELECT string_agg(quote_ident(attname), ', ' ORDER BY attnum) INTO columns
+        FROM pg_attribute
+        WHERE attrelid = tbl::regclass AND attnum > 0 AND NOT attisdropped;
+
+        EXECUTE format('UPDATE %I SET (%s) = (SELECT %s FROM jsonb_populate_record(NULL::%I, $1)) WHERE %s',
+                       tbl, columns, columns, tbl, key_filter)
+        USING target;
+    END IF;
+
+    RETURN true;
+END;
+$$ LANGUAGE plpgsql;
+
+-- =============================================================================
+-- TRIGGERS
+-- =============================================================================
+
+CREATE TRIGGER record_friend_history_undo_step
+    AFTER INSERT ON friend_history
+    FOR EACH ROW EXECUTE FUNCTION record_undo_step();
@@ -0,0 +1,119 @@
+-- Friend Knowledgebase Row Versions
+-- Migration: 008_versions.sql
+--
+-- A version number on every mutable row, for optimistic concurrency:
+-- a writer states the version it last read, and the update fails if the
+-- row has moved on since. The HTTP layer exposes it as the ETag.
+
+-- =============================================================================
+-- COLUMNS
+-- =============================================================================
+
+ALTER TABLE users ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE friends ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE groups ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE friend_attributes ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE friend_relationships ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE user_friend_relationships ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+
+-- =============================================================================
+-- FUNCTIONS
+-- =============================================================================
+
+-- Count every update that changes something.
+--
+-- Saves that change nothing (or only updated_at) keep the version, so
+-- they neither invalidate other writers' ETags nor show up in
+-- friend_history, whose no-op check compares everything but updated_at.
+-- Writers can't set the version themselves.
+CREATE OR REPLACE FUNCTION bump_version()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF (to_jsonb(NEW) - 'updated_at' - 'version') IS DISTINCT FROM
+       (to_jsonb(OLD) - 'updated_at' - 'version') THEN
+        NEW.version = OLD.version + 1;
+    ELSE
+        NEW.version = OLD.version;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- As in 007, but ignoring versions: undo and redo bump them like any
+-- other change, and history from before this migration has none. A row
+-- put back by an insert gets the version it had, or 1.
+CREATE OR REPLACE FUNCTION apply_history_row(tbl TEXT, key TEXT, expected JSONB, target JSONB)
+RETURNS BOOLEAN AS $$
+DECLARE
+    key_filter TEXT;
+    current_row JSONB;
+    columns TEXT;
+BEGIN
+    IF tbl NOT IN ('friends', 'friend_attributes', 'friend_groups',
+                   'friend_relationships', 'user_friend_relationships') THEN
+        RAISE EXCEPTION 'no history for table %', tbl;
+    END IF;
+
+    IF tbl = 'friend_groups' THEN
+        key_filter := format('friend_id = %L AND group_id = %L',
+                             split_part(key, ':', 1), split_part(key, ':', 2));
+    ELSE
+        key_filter := format('id = %L', key);
+    END IF;
+
+    EXECUTE format('SELECT to_jsonb(t) FROM %I t WHERE %s FOR UPDATE', tbl, key_filter)
+    INTO current_row;
+
+    IF (current_row - 'updated_at' - 'version') IS DISTINCT FROM
+       (expected - 'updated_at' - 'version' - 'group_name') THEN
+        RETURN false;
+    END IF;
+
+    IF target IS NULL THEN
+        EXECUTE format('DELETE FROM %I WHERE %s', tbl, key_filter);
+    ELSIF current_row IS NULL THEN
+        -- Memberships have no version; the extra key is ignored
+        target := jsonb_build_object('version', 1) || target;
+        EXECUTE format('INSERT INTO %I SELECT * FROM jsonb_populate_record(NULL::%I, $1)', tbl, tbl)
+        USING target;
+    ELSE
+        SELECT string_agg(quote_ident(attname), ', ' ORDER BY attnum) INTO columns
+        FROM pg_attribute
+        WHERE attrelid = tbl::regclass AND attnum > 0 AND NOT attisdropped;
+
+        EXECUTE format('UPDATE %I SET (%s) = (SELECT %s FROM jsonb_populate_record(NULL::%I, $1)) WHERE %s',
+                       tbl, columns, columns, tbl, key_filter)
+        USING target;
+    END IF;
+
+    RETURN true;
+END;
+$$ LANGUAGE plpgsql;
+
+-- =============================================================================
+-- TRIGGERS
+-- =============================================================================
+
+CREATE TRIGGER bump_users_version
+    BEFORE UPDATE ON users
+    FOR EACH ROW EXECUTE FUNCTION bump_version();
+
+CREATE TRIGGER bump_friends_version
+    BEFORE UPDATE ON friends
+    FOR EACH ROW EXECUTE FUNCTION bump_version();
+
+CREATE TRIGGER bump_groups_version
+    BEFORE UPDATE ON groups
+    FOR EACH ROW EXECUTE FUNCTION bump_version();
+
+CREATE TRIGGER bump_friend_attributes_version
+    BEFORE UPDATE ON friend_attributes
+    FOR EACH ROW EXECUTE FUNCTION bump_version();
+
+CREATE TRIGGER bump_friend_relationships_version
+    BEFORE UPDATE ON friend_relationships
+    FOR EACH ROW EXECUTE FUNCTION bump_version();
+
+CREATE TRIGGER bump_user_friend_relationships_version
+    BEFORE UPDATE ON user_friend_relationships
+    FOR EACH ROW EXECUTE FUNCTION bump_version();
@@ -0,0 +1,52 @@
+-- Friend Knowledgebase Purges Outside Undo
+-- Migration: 009_purge_outside_undo.sql
+--
+-- Purging the trash (the hourly job, or one friend at a time) is not a
+-- user action: it must not become an undo step, nor clear anyone's redo
+-- stack. Undoing it couldn't work anyway, since the cascaded deletes of
+-- the friend's attributes, memberships and relationships aren't in the
+-- history (006 skips them), so only a hollow friend would come back.
+--
+-- Purges run with fkb.purging set; the purging code also drops the undo
+-- steps that touched the purged friends, which can't be applied anymore.
+
+-- =============================================================================
+-- FUNCTIONS
+-- =============================================================================
+
+-- Make every history row a transaction writes part of an undo step.
+--
+-- Undo and redo run with fkb.undoing set, and purges with fkb.purging,
+-- so their own changes don't become steps. Any other change starts a new
+-- branch: the redo stack is dropped, and only the newest 50 steps are
+-- kept.
+CREATE OR REPLACE FUNCTION record_undo_step()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.operation = 'BASELINE'
+       OR current_setting('fkb.undoing', true) = 'on'
+       OR current_setting('fkb.purging', true) = 'on' THEN
+        RETURN NULL;
+    END IF;
+
+    INSERT INTO undo_steps (user_id, transaction_id)
+    VALUES (NEW.user_id, NEW.transaction_id)
+    ON CONFLICT (user_id, transaction_id) DO NOTHING;
+
+    IF FOUND THEN
+        DELETE FROM undo_steps
+        WHERE user_id = NEW.user_id AND undone_at IS NOT NULL;
+
+        DELETE FROM undo_steps
+        WHERE user_id = NEW.user_id
+          AND id <= (
+              SELECT id FROM undo_steps
+              WHERE user_id = NEW.user_id
+              ORDER BY id DESC
+              OFFSET 50 LIMIT 1
+          );
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
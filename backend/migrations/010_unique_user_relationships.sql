@@ -0,0 +1,43 @@
+-- Friend Knowledgebase Unique User Relationships
+-- Migration: 010_unique_user_relationships.sql
+--
+-- The user knows a friend in a given way at most once: a second
+-- "coworker" relationship with the same friend is a duplicate, not a
+-- second job. A relationship that ended keeps its row, so picking it up
+-- again means reopening it.
+
+-- =============================================================================
+-- CHECKS
+-- =============================================================================
+
+-- Nothing stopped duplicates before, and they can't be constrained.
+-- Which of them to keep is up to their user, so stop here instead of
+-- deleting any.
+DO $$
+DECLARE
+    repeated BIGINT;
+BEGIN
+    SELECT count(*) INTO repeated
+    FROM (
+        SELECT 1
+        FROM user_friend_relationships
+        GROUP BY friend_id, relationship_type
+        HAVING count(*) > 1
+    ) duplicated;
+
+    IF repeated > 0 THEN
+        RAISE EXCEPTION '% user relationship types are repeated with the same friend', repeated
+            USING HINT = 'Run "fkb doctor" to list them ("the same relationship type '
+                'as ..."), delete the extra relationships, then run this migration '
+                'again.';
+    END IF;
+END
+$$;
+
+-- =============================================================================
+-- CONSTRAINTS
+-- =============================================================================
+
+ALTER TABLE user_friend_relationships
+    ADD CONSTRAINT user_friend_relationships_friend_type_unique
+        UNIQUE (friend_id, relationship_type);
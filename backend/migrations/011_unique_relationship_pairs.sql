@@ -0,0 +1,45 @@
+-- Friend Knowledgebase Unique Relationship Pairs
+-- Migration: 011_unique_relationship_pairs.sql
+--
+-- Two friends are related at most once, in either direction. The
+-- repository already refuses a second relationship between the same
+-- pair, but only by looking first; two writers at once could both find
+-- nothing and both insert. This index makes the database refuse too.
+
+-- =============================================================================
+-- CHECKS
+-- =============================================================================
+
+-- Pairs related twice (before the check existed, or by such a race) can't
+-- be indexed. Which of the relationships to keep is up to their user, so
+-- stop here instead of deleting any.
+DO $$
+DECLARE
+    pairs BIGINT;
+BEGIN
+    SELECT count(*) INTO pairs
+    FROM (
+        SELECT 1
+        FROM friend_relationships
+        GROUP BY user_id, LEAST(friend_a_id, friend_b_id), GREATEST(friend_a_id, friend_b_id)
+        HAVING count(*) > 1
+    ) duplicated;
+
+    IF pairs > 0 THEN
+        RAISE EXCEPTION '% friend pairs are related more than once', pairs
+            USING HINT = 'Run "fkb doctor" to list them ("these friends are already '
+                'related by ..."), delete the extra relationships, then run this '
+                'migration again.';
+    END IF;
+END
+$$;
+
+-- =============================================================================
+-- INDEXES
+-- =============================================================================
+
+CREATE UNIQUE INDEX friend_relationships_pair_unique ON friend_relationships (
+    user_id,
+    LEAST(friend_a_id, friend_b_id),
+    GREATEST(friend_a_id, friend_b_id)
+);
@@ -0,0 +1,60 @@
+-- Friend Knowledgebase Relationship Status Values
+-- Migration: 012_relationship_status_values.sql
+--
+-- Relationship statuses are a fixed set: "active", "ended" and
+-- "deceased". Anything else (a typo like "actve") would never count as
+-- current and never show up as ended either, so the database refuses it.
+-- The status histories hold the same values.
+
+-- =============================================================================
+-- CHECKS
+-- =============================================================================
+
+-- A status outside the set can't be constrained. Whether it was meant as
+-- "ended" or something else is up to its user, so stop here instead of
+-- rewriting any.
+DO $$
+DECLARE
+    unknown BIGINT;
+BEGIN
+    SELECT count(*) INTO unknown
+    FROM (
+        SELECT status FROM friend_relationships
+        UNION ALL
+        SELECT status FROM user_friend_relationships
+        UNION ALL
+        SELECT status FROM friend_relationship_status_history
+        UNION ALL
+        SELECT status FROM user_friend_relationship_status_history
+    ) statuses
+    WHERE status NOT IN ('active', 'ended', 'deceased');
+
+    IF unknown > 0 THEN
+        RAISE EXCEPTION '% relationship statuses are not active, ended or deceased', unknown
+            USING HINT = 'Find them with "SELECT id, status FROM friend_relationships '
+                'WHERE status NOT IN (''active'', ''ended'', ''deceased'')" (and the same '
+                'for user_friend_relationships and both status history tables), fix '
+                'them, then run this migration again.';
+    END IF;
+END
+$$;
+
+-- =============================================================================
+-- CONSTRAINTS
+-- =============================================================================
+
+ALTER TABLE friend_relationships
+    ADD CONSTRAINT friend_relationships_status_known
+        CHECK (status IN ('active', 'ended', 'deceased'));
+
+ALTER TABLE user_friend_relationships
+    ADD CONSTRAINT user_friend_relationships_status_known
+        CHECK (status IN ('active', 'ended', 'deceased'));
+
+ALTER TABLE friend_relationship_status_history
+    ADD CONSTRAINT friend_relationship_status_history_status_known
+        CHECK (status IN ('active', 'ended', 'deceased'));
+
+ALTER TABLE user_friend_relationship_status_history
+    ADD CONSTRAINT user_friend_relationship_status_history_status_known
+        CHECK (status IN ('active', 'ended', 'deceased'));
@@ -0,0 +1,65 @@
+//! # HTTP Basic Authentication
+//!
+//! DAV clients (phone address books, calendar apps) only reliably speak
+//! Basic auth, so that is what the DAV endpoints use: the account's email
+//! as the user name and its password.
+//!
+//! Basic auth sends the password with every request; only expose these
+//! endpoints over HTTPS (e.g. behind a TLS-terminating proxy).
+
+use axum::extract::FromRequestParts;
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use crate::models::User;
+use crate::services::AuthService;
+
+use super::AppState;
+use super::error::ApiError;
+
+/// Extractor for the user a request authenticated as.
+///
+/// Rejects with `ApiError::Unauthorized` (401 plus a Basic challenge)
+/// when credentials are missing, malformed or wrong.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// async fn handler(AuthenticatedUser(user): AuthenticatedUser) -> String {
+///     format!("Hello, {}", user.first_name)
+/// }
+/// ```
+pub struct AuthenticatedUser(pub User);
+
+impl FromRequestParts<AppState> for AuthenticatedUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, ApiError> {
+        let (email, password) = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(basic_credentials)
+            .ok_or(ApiError::Unauthorized)?;
+
+        let user = AuthService::new(state.ctx.clone())
+            .authenticate(&email, &password)
+            .await?
+            .ok_or(ApiError::Unauthorized)?;
+
+        Ok(AuthenticatedUser(user))
+    }
+}
+
+/// Decode "Basic <base64 of user:password>".
+fn basic_credentials(header: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = header.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
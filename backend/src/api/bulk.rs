@@ -0,0 +1,156 @@
+//! # Bulk Endpoints
+//!
+//! ```text
+//! POST /bulk/groups/<id>/add       add friends to a group
+//! POST /bulk/groups/<id>/remove    remove friends from a group
+//! POST /bulk/attributes            give friends the same attribute
+//! POST /bulk/delete                move friends to the trash
+//! POST /bulk/create                create friends
+//! ```
+//!
+//! All need Basic auth. The first four take the friends as a list of IDs,
+//! plus the attribute for `/bulk/attributes`:
+//!
+//! ```json
+//! { "friends": ["0192f3c4-...", "0192f3c5-..."], "key": "city", "value": "Oslo" }
+//! ```
+//!
+//! `/bulk/create` takes the new friends, with the fields of
+//! `PATCH /friends/<id>`:
+//!
+//! ```json
+//! { "friends": [{ "first_name": "Ada", "last_name": "Lovelace" }] }
+//! ```
+//!
+//! Each batch is one transaction and one undo step. The answer is a
+//! report with a result per item (`changed`, `unchanged` or `failed` with
+//! the reason): friends that aren't the caller's fail on their own
+//! without stopping the rest. At most 1000 items per batch.
+
+use axum::Json;
+use axum::extract::{Path, State};
+use serde::Deserialize;
+use time::Date;
+use uuid::Uuid;
+
+use crate::repositories::{
+    BulkAttributeInput, BulkReport, CreateFriendInput, FriendBulkRepository,
+};
+
+use super::AppState;
+use super::auth::AuthenticatedUser;
+use super::error::ApiError;
+
+/// Body naming the friends a batch applies to.
+#[derive(Deserialize)]
+pub struct FriendIds {
+    pub friends: Vec<Uuid>,
+}
+
+/// Body of `/bulk/attributes`.
+#[derive(Deserialize)]
+pub struct AttributeRequest {
+    pub friends: Vec<Uuid>,
+    pub key: String,
+    pub value: String,
+    /// Defaults to "text"
+    pub value_type: Option<String>,
+}
+
+/// One new friend in `/bulk/create`.
+#[derive(Deserialize)]
+pub struct NewFriend {
+    pub first_name: String,
+    pub last_name: Option<String>,
+    /// e.g. "1990-02-28"
+    pub date_of_birth: Option<Date>,
+    pub likes: Option<String>,
+    pub dislikes: Option<String>,
+    pub notes: Option<String>,
+}
+
+/// Body of `/bulk/create`.
+#[derive(Deserialize)]
+pub struct CreateRequest {
+    pub friends: Vec<NewFriend>,
+}
+
+/// Add friends to the group in the path.
+pub async fn add_to_group(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(group_id): Path<Uuid>,
+    Json(request): Json<FriendIds>,
+) -> Result<Json<BulkReport>, ApiError> {
+    let report = FriendBulkRepository::new(state.ctx.clone())
+        .add_to_group(user.id, group_id, &request.friends)
+        .await?;
+    Ok(Json(report))
+}
+
+/// Remove friends from the group in the path.
+pub async fn remove_from_group(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(group_id): Path<Uuid>,
+    Json(request): Json<FriendIds>,
+) -> Result<Json<BulkReport>, ApiError> {
+    let report = FriendBulkRepository::new(state.ctx.clone())
+        .remove_from_group(user.id, group_id, &request.friends)
+        .await?;
+    Ok(Json(report))
+}
+
+/// Give friends the same attribute.
+pub async fn set_attribute(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<AttributeRequest>,
+) -> Result<Json<BulkReport>, ApiError> {
+    let attribute = BulkAttributeInput {
+        key: request.key,
+        value: request.value,
+        value_type: request.value_type,
+    };
+    let report = FriendBulkRepository::new(state.ctx.clone())
+        .set_attribute(user.id, &request.friends, attribute)
+        .await?;
+    Ok(Json(report))
+}
+
+/// Move friends to the trash.
+pub async fn delete(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<FriendIds>,
+) -> Result<Json<BulkReport>, ApiError> {
+    let report = FriendBulkRepository::new(state.ctx.clone())
+        .delete(user.id, &request.friends)
+        .await?;
+    Ok(Json(report))
+}
+
+/// Create friends for the caller.
+pub async fn create(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<CreateRequest>,
+) -> Result<Json<BulkReport>, ApiError> {
+    let inputs = request
+        .friends
+        .into_iter()
+        .map(|friend| CreateFriendInput {
+            user_id: user.id,
+            first_name: friend.first_name,
+            last_name: friend.last_name,
+            date_of_birth: friend.date_of_birth,
+            likes: friend.likes,
+            dislikes: friend.dislikes,
+            notes: friend.notes,
+        })
+        .collect();
+    let report = FriendBulkRepository::new(state.ctx.clone())
+        .create(inputs)
+        .await?;
+    Ok(Json(report))
+}
@@ -0,0 +1,48 @@
+//! # Calendar Feed Endpoint
+//!
+//! `GET /calendar/<token>.ics` serves a user's birthdays and important
+//! dates to calendar apps. Those apps can't log in, so the unguessable
+//! token in the URL is the only credential: an unknown or rotated token
+//! gets a plain 404, indistinguishable from a feed that never existed.
+
+use axum::extract::{Path, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+
+use crate::interchange::ics::IcsExporter;
+use crate::repositories::CalendarFeedRepository;
+
+use super::AppState;
+use super::error::ApiError;
+
+/// Name shown for the calendar in subscribing apps.
+const CALENDAR_NAME: &str = "Friends' birthdays";
+
+/// Serve the feed for a token.
+///
+/// The ".ics" suffix is optional; some apps strip it, others need it to
+/// recognise the URL as a calendar.
+pub async fn feed(
+    State(state): State<AppState>,
+    Path(file): Path<String>,
+) -> Result<Response, ApiError> {
+    let token = file.strip_suffix(".ics").unwrap_or(&file);
+
+    let feed = CalendarFeedRepository::new(state.ctx.clone())
+        .find_by_token(token)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    let calendar = IcsExporter::new(state.ctx.clone())
+        .export(feed.user_id, CALENDAR_NAME, feed.alarm_minutes_before)
+        .await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/calendar; charset=utf-8"),
+            // The URL is a secret; keep shared caches from storing the body
+            (header::CACHE_CONTROL, "private, max-age=3600"),
+        ],
+        calendar,
+    )
+        .into_response())
+}
@@ -0,0 +1,230 @@
+//! # CalDAV
+//!
+//! A read-only calendar (RFC 4791) of the same events as the iCalendar
+//! feed: a yearly all-day event for every birthday and date-typed
+//! attribute, computed from `friends` and `friend_attributes` on every
+//! request. Each event is its own resource, named after its UID
+//! (`birthday-<friend id>.ics`, `attribute-<attribute id>.ics`).
+//!
+//! ## Read-Only
+//!
+//! Dates are edited on the friend (or on the card, over CardDAV), so the
+//! privilege set is just `read` and writes are refused with
+//! `need-privileges`.
+//!
+//! ## Time Ranges
+//!
+//! `calendar-query` evaluates `comp-filter` and `time-range` and nothing
+//! else: an event matches if any of its yearly occurrences overlaps the
+//! range (see `CalendarEvent::occurs_between`). Queries for components
+//! other than `VEVENT` match nothing.
+//!
+//! ## Change Detection
+//!
+//! There is no `sync-collection`: the change log records friends, not
+//! events, so it can't name the event a deleted attribute had. Instead
+//! the calendar's `getctag` is the change log position, and clients
+//! compare event ETags after it moves.
+//!
+//! ## Reminders
+//!
+//! Events carry no `VALARM`; CalDAV clients apply their own default
+//! reminders to all-day events.
+
+use axum::http::StatusCode;
+use axum::http::header::{CONTENT_TYPE, ETAG};
+use axum::response::{IntoResponse, Response};
+use uuid::Uuid;
+
+use crate::interchange::ics::{self, CalendarEvent};
+use crate::models::User;
+use crate::repositories::{BackupRepository, FriendChangeRepository, RepositoryContext};
+
+use super::xml::{self, CALDAV, CALENDARSERVER, DAV, Multistatus, PropName, PropRequest, Report};
+use super::{Resource, Tree, etag};
+use crate::api::AppState;
+use crate::api::error::ApiError;
+
+/// `Content-Type` of events.
+const ICALENDAR_CONTENT_TYPE: &str = "text/calendar; charset=utf-8; component=VEVENT";
+
+/// One event resource.
+pub struct Event {
+    /// Last path segment of the event's URL
+    pub name: String,
+    /// The rendered calendar object
+    pub data: String,
+    /// Quoted strong ETag
+    pub etag: String,
+    pub event: CalendarEvent,
+}
+
+impl Event {
+    /// Event-specific properties.
+    pub(super) fn prop(&self, name: &PropName) -> Option<String> {
+        if name.is(DAV, "getetag") {
+            Some(name.element(&xml::escape(&self.etag)))
+        } else if name.is(DAV, "getcontenttype") {
+            Some(name.element(ICALENDAR_CONTENT_TYPE))
+        } else if name.is(CALDAV, "calendar-data") {
+            Some(name.element(&xml::escape(&self.data)))
+        } else {
+            None
+        }
+    }
+}
+
+/// A user's calendar, computed from one snapshot.
+pub struct Calendar {
+    /// Change log position the snapshot is at least as new as
+    position: i64,
+    events: Vec<Event>,
+}
+
+impl Calendar {
+    /// Load and render every event for a user.
+    pub async fn load(ctx: &RepositoryContext, user_id: Uuid) -> Result<Self, ApiError> {
+        // Position first, as for the address book: a change landing in
+        // between moves the ctag again later rather than being missed
+        let position = FriendChangeRepository::new(ctx.clone())
+            .current_position(user_id)
+            .await?;
+        let snapshot = BackupRepository::new(ctx.clone())
+            .snapshot_live(user_id)
+            .await?;
+
+        let events = ics::events(&snapshot)
+            .into_iter()
+            .map(|event| {
+                let data = ics::render_event(&event, None);
+                Event {
+                    name: resource_name(&event),
+                    etag: etag(&data),
+                    data,
+                    event,
+                }
+            })
+            .collect();
+        Ok(Self { position, events })
+    }
+
+    /// Every event, in friend creation order.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// The event with this resource name.
+    pub fn event(&self, name: &str) -> Option<&Event> {
+        self.events.iter().find(|event| event.name == name)
+    }
+
+    /// Calendar properties.
+    pub(super) fn prop(&self, name: &PropName) -> Option<String> {
+        if name.is(DAV, "displayname") {
+            Some(name.element("Friends' dates"))
+        } else if name.is(CALDAV, "calendar-description") {
+            Some(name.element("Birthdays and important dates from the Friend Knowledgebase"))
+        } else if name.is(CALDAV, "supported-calendar-component-set") {
+            Some(name.element(r#"<cal:comp name="VEVENT"/>"#))
+        } else if name.is(CALDAV, "supported-calendar-data") {
+            Some(name.element(r#"<cal:calendar-data content-type="text/calendar" version="2.0"/>"#))
+        } else if name.is(DAV, "supported-report-set") {
+            let reports: String = ["calendar-multiget", "calendar-query"]
+                .iter()
+                .map(|report| {
+                    format!("<d:supported-report><d:report><cal:{report}/></d:report></d:supported-report>")
+                })
+                .collect();
+            Some(name.element(&reports))
+        } else if name.is(CALENDARSERVER, "getctag") {
+            Some(name.element(&self.position.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+/// `REPORT` on the calendar.
+pub async fn report(state: &AppState, user: &User, body: &str) -> Result<Response, ApiError> {
+    let Some(report) = xml::parse_report(body)? else {
+        return Ok(xml::unsupported_report());
+    };
+
+    let calendar = Calendar::load(&state.ctx, user.id).await?;
+    let tree = Tree {
+        user,
+        book: None,
+        calendar: Some(&calendar),
+    };
+    let describe = |multistatus: &mut Multistatus, name: &str, props: &[PropName]| {
+        let request = if props.is_empty() {
+            PropRequest::AllProp
+        } else {
+            PropRequest::Prop(props.to_vec())
+        };
+        tree.describe(multistatus, &Resource::Event(name.to_string()), &request);
+    };
+
+    let mut multistatus = Multistatus::new();
+    match report {
+        Report::CalendarMultiget { props, hrefs } => {
+            for href in hrefs {
+                match Resource::from_href(&href) {
+                    Some(Resource::Event(name)) if calendar.event(&name).is_some() => {
+                        describe(&mut multistatus, &name, &props)
+                    }
+                    _ => multistatus.not_found(&href),
+                }
+            }
+        }
+        Report::CalendarQuery {
+            props,
+            components,
+            start,
+            end,
+        } => {
+            let events_wanted = components
+                .iter()
+                .zip(["VCALENDAR", "VEVENT"])
+                .all(|(component, expected)| component.eq_ignore_ascii_case(expected))
+                && components.len() <= 2;
+            if events_wanted {
+                for event in calendar.events() {
+                    if event.event.occurs_between(start, end) {
+                        describe(&mut multistatus, &event.name, &props);
+                    }
+                }
+            }
+        }
+        _ => return Ok(xml::unsupported_report()),
+    }
+    Ok(multistatus.into_response())
+}
+
+/// `GET` (and `HEAD`) of an event.
+pub async fn get(state: &AppState, user: &User, name: &str) -> Result<Response, ApiError> {
+    let calendar = Calendar::load(&state.ctx, user.id).await?;
+    let event = calendar.event(name).ok_or(ApiError::NotFound)?;
+    Ok((
+        [
+            (CONTENT_TYPE, ICALENDAR_CONTENT_TYPE),
+            (ETAG, event.etag.as_str()),
+        ],
+        event.data.clone(),
+    )
+        .into_response())
+}
+
+/// `PUT` or `DELETE` of an event: the calendar is read-only.
+pub fn read_only() -> Response {
+    xml::precondition_failed(
+        StatusCode::FORBIDDEN,
+        &PropName::new(DAV, "need-privileges"),
+    )
+}
+
+/// "birthday-<id>@fkb" → "birthday-<id>.ics".
+fn resource_name(event: &CalendarEvent) -> String {
+    let stem = event.uid.strip_suffix("@fkb").unwrap_or(&event.uid);
+    format!("{stem}.ics")
+}
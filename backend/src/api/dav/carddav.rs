@@ -0,0 +1,395 @@
+//! # CardDAV
+//!
+//! The address book (RFC 6352): every friend as a vCard 4.0 resource,
+//! rendered and parsed by `interchange::vcard` and written back through
+//! `ImportWriter`, i.e. `FriendRepository` and friends.
+//!
+//! ## Groups
+//!
+//! Groups are the card's `CATEGORIES` rather than separate address books.
+//! A friend can be in several groups, and one address book per group
+//! would show up on the phone as several copies of the same contact.
+//! Adding a category on the phone adds the friend to that group (created
+//! if needed); removing it removes them.
+//!
+//! ## ETags
+//!
+//! A card's ETag is a hash of its rendered text, so it changes exactly
+//! when the card does. `PUT` responses carry no ETag because the stored
+//! card is re-rendered rather than kept byte for byte; clients fetch it
+//! again instead.
+//!
+//! ## Sync Tokens
+//!
+//! `urn:fkb:sync:<n>`, where n is a position in the friend change log
+//! (see `FriendChangeRepository`).
+//!
+//! ## Limitations
+//!
+//! `addressbook-query` filters aren't evaluated; every card is returned.
+
+use std::collections::HashMap;
+
+use axum::http::header::{CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use uuid::Uuid;
+
+use crate::interchange::ImportWriter;
+use crate::interchange::vcard::{self, ATTRIBUTE_PREFIX, write_card};
+use crate::models::{Friend, FriendAttribute, Group, User};
+use crate::repositories::{
+    BackupRepository, CardDavRepository, FriendChangeRepository, FriendRepository, Repository,
+    RepositoryContext, RepositoryError, UserSnapshot,
+};
+
+use super::xml::{self, CALENDARSERVER, CARDDAV, DAV, Multistatus, PropName, PropRequest, Report};
+use super::{Resource, Tree, etag};
+use crate::api::AppState;
+use crate::api::error::ApiError;
+
+/// Prefix of every sync token.
+const SYNC_TOKEN_PREFIX: &str = "urn:fkb:sync:";
+
+/// `Content-Type` of cards.
+const VCARD_CONTENT_TYPE: &str = "text/vcard; charset=utf-8";
+
+/// One friend's card.
+pub struct Card {
+    pub friend_id: Uuid,
+    /// Last path segment of the card's URL
+    pub name: String,
+    /// The rendered vCard
+    pub data: String,
+    /// Quoted strong ETag
+    pub etag: String,
+}
+
+impl Card {
+    /// Card-specific properties.
+    pub(super) fn prop(&self, name: &PropName) -> Option<String> {
+        if name.is(DAV, "getetag") {
+            Some(name.element(&xml::escape(&self.etag)))
+        } else if name.is(DAV, "getcontenttype") {
+            Some(name.element(VCARD_CONTENT_TYPE))
+        } else if name.is(CARDDAV, "address-data") {
+            Some(name.element(&xml::escape(&self.data)))
+        } else {
+            None
+        }
+    }
+}
+
+/// A user's address book, read in one consistent snapshot.
+pub struct AddressBook {
+    /// Change log position the snapshot is at least as new as
+    position: i64,
+    snapshot: UserSnapshot,
+    /// Client-chosen resource names by friend ID
+    names: HashMap<Uuid, String>,
+    cards: Vec<Card>,
+}
+
+impl AddressBook {
+    /// Load and render every card for a user.
+    pub async fn load(ctx: &RepositoryContext, user_id: Uuid) -> Result<Self, ApiError> {
+        // Read the position first: a change landing in between is then
+        // both in the snapshot and reported again next sync, never lost
+        let position = FriendChangeRepository::new(ctx.clone())
+            .current_position(user_id)
+            .await?;
+        let snapshot = BackupRepository::new(ctx.clone())
+            .snapshot_live(user_id)
+            .await?;
+        let names = CardDavRepository::new(ctx.clone())
+            .names_by_user(user_id)
+            .await?;
+
+        let mut book = Self {
+            position,
+            snapshot,
+            names,
+            cards: Vec::new(),
+        };
+        book.cards = book
+            .snapshot
+            .friends
+            .iter()
+            .map(|friend| {
+                let data = write_card(
+                    friend,
+                    &book.groups_of(friend.id),
+                    &book.attributes_of(friend.id),
+                );
+                Card {
+                    friend_id: friend.id,
+                    name: book.name_of(friend.id),
+                    etag: etag(&data),
+                    data,
+                }
+            })
+            .collect();
+        Ok(book)
+    }
+
+    /// Every card, in friend creation order.
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
+
+    /// The card with this resource name.
+    pub fn card(&self, name: &str) -> Option<&Card> {
+        self.cards.iter().find(|card| card.name == name)
+    }
+
+    /// The token for the state this book was loaded in.
+    pub fn sync_token(&self) -> String {
+        format!("{SYNC_TOKEN_PREFIX}{}", self.position)
+    }
+
+    /// Address book properties.
+    pub(super) fn prop(&self, name: &PropName) -> Option<String> {
+        if name.is(DAV, "displayname") {
+            Some(name.element("Friends"))
+        } else if name.is(CARDDAV, "addressbook-description") {
+            Some(name.element("Friends from the Friend Knowledgebase"))
+        } else if name.is(CARDDAV, "supported-address-data") {
+            Some(
+                name.element(
+                    r#"<card:address-data-type content-type="text/vcard" version="4.0"/>"#,
+                ),
+            )
+        } else if name.is(DAV, "supported-report-set") {
+            let reports: String = [
+                ("card", "addressbook-multiget"),
+                ("card", "addressbook-query"),
+                ("d", "sync-collection"),
+            ]
+            .iter()
+            .map(|(prefix, report)| {
+                format!("<d:supported-report><d:report><{prefix}:{report}/></d:report></d:supported-report>")
+            })
+            .collect();
+            Some(name.element(&reports))
+        } else if name.is(DAV, "sync-token") || name.is(CALENDARSERVER, "getctag") {
+            Some(name.element(&self.sync_token()))
+        } else {
+            None
+        }
+    }
+
+    fn friend(&self, friend_id: Uuid) -> Option<&Friend> {
+        self.snapshot.friends.iter().find(|f| f.id == friend_id)
+    }
+
+    fn name_of(&self, friend_id: Uuid) -> String {
+        self.names
+            .get(&friend_id)
+            .cloned()
+            .unwrap_or_else(|| format!("{friend_id}.vcf"))
+    }
+
+    fn attributes_of(&self, friend_id: Uuid) -> Vec<FriendAttribute> {
+        self.snapshot
+            .friend_attributes
+            .iter()
+            .filter(|a| a.friend_id == friend_id)
+            .cloned()
+            .collect()
+    }
+
+    fn groups_of(&self, friend_id: Uuid) -> Vec<Group> {
+        self.snapshot
+            .groups
+            .iter()
+            .filter(|g| {
+                self.snapshot
+                    .friend_groups
+                    .iter()
+                    .any(|m| m.friend_id == friend_id && m.group_id == g.id)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// `REPORT` on the address book.
+pub async fn report(state: &AppState, user: &User, body: &str) -> Result<Response, ApiError> {
+    let Some(report) = xml::parse_report(body)? else {
+        return Ok(xml::unsupported_report());
+    };
+
+    let book = AddressBook::load(&state.ctx, user.id).await?;
+    let tree = Tree {
+        user,
+        book: Some(&book),
+        calendar: None,
+    };
+    let describe = |multistatus: &mut Multistatus, name: &str, props: &[PropName]| {
+        let request = if props.is_empty() {
+            PropRequest::AllProp
+        } else {
+            PropRequest::Prop(props.to_vec())
+        };
+        tree.describe(multistatus, &Resource::Card(name.to_string()), &request);
+    };
+
+    let mut multistatus = Multistatus::new();
+    match report {
+        Report::AddressbookMultiget { props, hrefs } => {
+            for href in hrefs {
+                match Resource::from_href(&href) {
+                    Some(Resource::Card(name)) if book.card(&name).is_some() => {
+                        describe(&mut multistatus, &name, &props)
+                    }
+                    _ => multistatus.not_found(&href),
+                }
+            }
+        }
+        Report::AddressbookQuery { props } => {
+            for card in book.cards() {
+                describe(&mut multistatus, &card.name, &props);
+            }
+        }
+        Report::SyncCollection { token, props } => {
+            if token.is_empty() {
+                for card in book.cards() {
+                    describe(&mut multistatus, &card.name, &props);
+                }
+            } else {
+                let Some(since) = token
+                    .strip_prefix(SYNC_TOKEN_PREFIX)
+                    .and_then(|n| n.parse::<i64>().ok())
+                    .filter(|n| (0..=book.position).contains(n))
+                else {
+                    return Ok(xml::precondition_failed(
+                        StatusCode::FORBIDDEN,
+                        &PropName::new(DAV, "valid-sync-token"),
+                    ));
+                };
+                let changed = FriendChangeRepository::new(state.ctx.clone())
+                    .changed_between(user.id, since, book.position)
+                    .await?;
+                for friend_id in changed {
+                    let name = book.name_of(friend_id);
+                    if book.friend(friend_id).is_some() {
+                        describe(&mut multistatus, &name, &props);
+                    } else {
+                        multistatus.not_found(&Resource::Card(name).href());
+                    }
+                }
+            }
+            multistatus.sync_token(&book.sync_token());
+        }
+        _ => return Ok(xml::unsupported_report()),
+    }
+    Ok(multistatus.into_response())
+}
+
+/// `GET` (and `HEAD`) of a card.
+pub async fn get(state: &AppState, user: &User, name: &str) -> Result<Response, ApiError> {
+    let book = AddressBook::load(&state.ctx, user.id).await?;
+    let card = book.card(name).ok_or(ApiError::NotFound)?;
+    Ok((
+        [
+            (CONTENT_TYPE, VCARD_CONTENT_TYPE),
+            (ETAG, card.etag.as_str()),
+        ],
+        card.data.clone(),
+    )
+        .into_response())
+}
+
+/// `PUT` of a card: create a friend, or overwrite one.
+pub async fn put(
+    state: &AppState,
+    user: &User,
+    name: &str,
+    headers: &HeaderMap,
+    body: &str,
+) -> Result<Response, ApiError> {
+    let mut contacts = vcard::parse(body)?;
+    if contacts.len() != 1 {
+        return Ok(xml::precondition_failed(
+            StatusCode::FORBIDDEN,
+            &PropName::new(CARDDAV, "valid-address-data"),
+        ));
+    }
+    let mut imported = contacts.remove(0);
+
+    let book = AddressBook::load(&state.ctx, user.id).await?;
+    let current = book.card(name);
+    check_preconditions(headers, current)?;
+
+    let mut writer = ImportWriter::load(state.ctx.clone(), user.id).await?;
+    let mut tx = state.ctx.transaction().await?;
+    let status = match current.and_then(|card| book.friend(card.friend_id)) {
+        Some(friend) => {
+            // Our own default UID coming back isn't worth an attribute
+            let default_uid = format!("urn:uuid:{}", friend.id);
+            imported
+                .attributes
+                .retain(|a| !(a.key == format!("{ATTRIBUTE_PREFIX}UID") && a.value == default_uid));
+            writer
+                .replace_friend(
+                    &mut tx,
+                    friend,
+                    &book.attributes_of(friend.id),
+                    &book.groups_of(friend.id),
+                    imported,
+                )
+                .await?;
+            StatusCode::NO_CONTENT
+        }
+        None => {
+            let friend = writer.create_friend(&mut tx, imported).await?;
+            CardDavRepository::new(state.ctx.clone())
+                .set_name_in(&mut tx, user.id, friend.id, name)
+                .await?;
+            StatusCode::CREATED
+        }
+    };
+    tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+
+    Ok(status.into_response())
+}
+
+/// `DELETE` of a card, moving the friend to the trash.
+pub async fn delete(
+    state: &AppState,
+    user: &User,
+    name: &str,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    let book = AddressBook::load(&state.ctx, user.id).await?;
+    let card = book.card(name).ok_or(ApiError::NotFound)?;
+    check_preconditions(headers, Some(card))?;
+
+    FriendRepository::new(state.ctx.clone())
+        .delete(card.friend_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Evaluate `If-Match` and `If-None-Match` against the current card.
+fn check_preconditions(headers: &HeaderMap, current: Option<&Card>) -> Result<(), ApiError> {
+    let matches = |value: &str| match current {
+        Some(card) => value
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag == card.etag),
+        None => false,
+    };
+
+    if let Some(value) = headers.get(IF_MATCH).and_then(|v| v.to_str().ok())
+        && !matches(value)
+    {
+        return Err(ApiError::PreconditionFailed);
+    }
+    if let Some(value) = headers.get(IF_NONE_MATCH).and_then(|v| v.to_str().ok())
+        && matches(value)
+    {
+        return Err(ApiError::PreconditionFailed);
+    }
+    Ok(())
+}
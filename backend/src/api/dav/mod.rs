@@ -0,0 +1,434 @@
+//! # WebDAV
+//!
+//! The resource tree DAV clients discover their collections in:
+//!
+//! ```text
+//! /.well-known/carddav          redirect to /dav/
+//! /.well-known/caldav           redirect to /dav/
+//! /dav/                         root
+//! /dav/principal/               the logged-in user
+//! /dav/addressbooks/            their address book home
+//! /dav/addressbooks/friends/    their friends, one vCard each
+//! /dav/calendars/               their calendar home
+//! /dav/calendars/dates/         birthdays and dates, one event each
+//! ```
+//!
+//! Everything under `/dav` requires Basic auth (see `api::auth`). Paths
+//! don't name the user: each account only ever sees its own tree.
+//!
+//! ## Methods
+//!
+//! `OPTIONS` and `PROPFIND` work everywhere; `REPORT` on the address
+//! book and the calendar; `GET`, `HEAD`, `PUT` and `DELETE` on cards;
+//! `GET` and `HEAD` on events. `PROPFIND` with `Depth: infinity` is
+//! answered as `Depth: 1`.
+
+pub mod caldav;
+
+pub mod carddav;
+pub mod xml;
+
+use axum::extract::State;
+use axum::http::header::{ALLOW, LOCATION};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use sha2::{Digest, Sha256};
+
+use crate::models::User;
+
+use super::AppState;
+use super::auth::AuthenticatedUser;
+use super::error::ApiError;
+use caldav::Calendar;
+use carddav::AddressBook;
+use xml::{CALDAV, CALENDARSERVER, CARDDAV, DAV, Multistatus, PropName, PropRequest};
+
+/// Root of the DAV tree.
+pub const ROOT: &str = "/dav/";
+
+/// The current user's principal.
+pub const PRINCIPAL: &str = "/dav/principal/";
+
+/// Collection holding the user's address books.
+pub const ADDRESSBOOK_HOME: &str = "/dav/addressbooks/";
+
+/// The user's one address book.
+pub const ADDRESSBOOK: &str = "/dav/addressbooks/friends/";
+
+/// Collection holding the user's calendars.
+pub const CALENDAR_HOME: &str = "/dav/calendars/";
+
+/// The user's one (read-only) calendar.
+pub const CALENDAR: &str = "/dav/calendars/dates/";
+
+/// Methods `OPTIONS` advertises.
+const ALLOWED: &str = "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, REPORT";
+
+/// Characters left unescaped in hrefs, besides ASCII letters and digits.
+const HREF_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// A resource in the DAV tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resource {
+    Root,
+    Principal,
+    AddressBookHome,
+    AddressBook,
+    /// A card, by its (decoded) resource name
+    Card(String),
+    CalendarHome,
+    Calendar,
+    /// An event, by its (decoded) resource name
+    Event(String),
+}
+
+impl Resource {
+    /// Resolve a request path such as "/dav/addressbooks/friends/a%20b.vcf".
+    pub fn from_path(path: &str) -> Option<Self> {
+        let rest = path.strip_prefix("/dav")?;
+        let segments = rest
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| {
+                percent_decode_str(segment)
+                    .decode_utf8()
+                    .ok()
+                    .map(|s| s.into_owned())
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        match segments.as_slice() {
+            [] => Some(Resource::Root),
+            ["principal"] => Some(Resource::Principal),
+            ["addressbooks"] => Some(Resource::AddressBookHome),
+            ["addressbooks", "friends"] => Some(Resource::AddressBook),
+            ["addressbooks", "friends", name] => Some(Resource::Card(name.to_string())),
+            ["calendars"] => Some(Resource::CalendarHome),
+            ["calendars", "dates"] => Some(Resource::Calendar),
+            ["calendars", "dates", name] => Some(Resource::Event(name.to_string())),
+            _ => None,
+        }
+    }
+
+    /// Resolve an href from a request body, which may be a full URL.
+    pub fn from_href(href: &str) -> Option<Self> {
+        let path = match href.split_once("://") {
+            Some((_, rest)) => &rest[rest.find('/')?..],
+            None => href,
+        };
+        Self::from_path(path)
+    }
+
+    /// The resource's path, percent-encoded.
+    pub fn href(&self) -> String {
+        match self {
+            Resource::Root => ROOT.to_string(),
+            Resource::Principal => PRINCIPAL.to_string(),
+            Resource::AddressBookHome => ADDRESSBOOK_HOME.to_string(),
+            Resource::AddressBook => ADDRESSBOOK.to_string(),
+            Resource::Card(name) => {
+                format!("{ADDRESSBOOK}{}", utf8_percent_encode(name, HREF_SEGMENT))
+            }
+            Resource::CalendarHome => CALENDAR_HOME.to_string(),
+            Resource::Calendar => CALENDAR.to_string(),
+            Resource::Event(name) => {
+                format!("{CALENDAR}{}", utf8_percent_encode(name, HREF_SEGMENT))
+            }
+        }
+    }
+}
+
+/// Handle any request under `/dav`.
+pub async fn handle(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response, ApiError> {
+    let resource = Resource::from_path(uri.path()).ok_or(ApiError::NotFound)?;
+
+    match (method.as_str(), &resource) {
+        ("OPTIONS", _) => Ok((
+            StatusCode::OK,
+            [
+                (
+                    "DAV",
+                    HeaderValue::from_static("1, 3, addressbook, calendar-access"),
+                ),
+                (ALLOW.as_str(), HeaderValue::from_static(ALLOWED)),
+            ],
+        )
+            .into_response()),
+        ("PROPFIND", _) => propfind(&state, &user, &resource, &headers, &body).await,
+        ("REPORT", Resource::AddressBook) => carddav::report(&state, &user, &body).await,
+        ("GET" | "HEAD", Resource::Card(name)) => carddav::get(&state, &user, name).await,
+        ("PUT", Resource::Card(name)) => carddav::put(&state, &user, name, &headers, &body).await,
+        ("DELETE", Resource::Card(name)) => carddav::delete(&state, &user, name, &headers).await,
+        ("REPORT", Resource::Calendar) => caldav::report(&state, &user, &body).await,
+        ("GET" | "HEAD", Resource::Event(name)) => caldav::get(&state, &user, name).await,
+        ("PUT" | "DELETE", Resource::Event(_)) => Ok(caldav::read_only()),
+        _ => Ok((
+            StatusCode::METHOD_NOT_ALLOWED,
+            [(ALLOW, HeaderValue::from_static(ALLOWED))],
+        )
+            .into_response()),
+    }
+}
+
+/// `/.well-known/carddav` and `/.well-known/caldav` (RFC 6764): point
+/// clients at the DAV root.
+pub async fn well_known() -> Response {
+    (
+        StatusCode::MOVED_PERMANENTLY,
+        [(LOCATION, HeaderValue::from_static(ROOT))],
+    )
+        .into_response()
+}
+
+async fn propfind(
+    state: &AppState,
+    user: &User,
+    resource: &Resource,
+    headers: &HeaderMap,
+    body: &str,
+) -> Result<Response, ApiError> {
+    let request = xml::parse_propfind(body)?;
+    let depth = headers
+        .get("Depth")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("infinity");
+
+    let (book, calendar) = match resource {
+        Resource::Root | Resource::Principal => (None, None),
+        Resource::AddressBookHome | Resource::AddressBook | Resource::Card(_) => {
+            (Some(AddressBook::load(&state.ctx, user.id).await?), None)
+        }
+        Resource::CalendarHome | Resource::Calendar | Resource::Event(_) => {
+            (None, Some(Calendar::load(&state.ctx, user.id).await?))
+        }
+    };
+    let exists = match resource {
+        Resource::Card(name) => book.as_ref().is_some_and(|b| b.card(name).is_some()),
+        Resource::Event(name) => calendar.as_ref().is_some_and(|c| c.event(name).is_some()),
+        _ => true,
+    };
+    if !exists {
+        return Err(ApiError::NotFound);
+    }
+
+    let tree = Tree {
+        user,
+        book: book.as_ref(),
+        calendar: calendar.as_ref(),
+    };
+    let mut targets = vec![resource.clone()];
+    if depth != "0" {
+        targets.extend(tree.children(resource));
+    }
+
+    let mut multistatus = Multistatus::new();
+    for target in &targets {
+        tree.describe(&mut multistatus, target, &request);
+    }
+    Ok(multistatus.into_response())
+}
+
+/// Property lookup over the tree, for one request's user.
+pub struct Tree<'a> {
+    pub user: &'a User,
+    /// Needed for anything at or below the address book home
+    pub book: Option<&'a AddressBook>,
+    /// Needed for anything at or below the calendar home
+    pub calendar: Option<&'a Calendar>,
+}
+
+impl Tree<'_> {
+    /// Add a resource's requested properties to a multistatus.
+    pub fn describe(
+        &self,
+        multistatus: &mut Multistatus,
+        resource: &Resource,
+        request: &PropRequest,
+    ) {
+        let all = self.prop_names(resource);
+        let (found, missing) = match request {
+            PropRequest::PropName => (
+                all.iter().map(|name| name.element("")).collect(),
+                Vec::new(),
+            ),
+            PropRequest::AllProp => (
+                all.iter()
+                    .filter_map(|name| self.prop(resource, name))
+                    .collect(),
+                Vec::new(),
+            ),
+            PropRequest::Prop(names) => {
+                let mut found = Vec::new();
+                let mut missing = Vec::new();
+                for name in names {
+                    match self.prop(resource, name) {
+                        Some(element) => found.push(element),
+                        None => missing.push(name.clone()),
+                    }
+                }
+                (found, missing)
+            }
+        };
+        multistatus.response(&resource.href(), &found, &missing);
+    }
+
+    fn children(&self, resource: &Resource) -> Vec<Resource> {
+        match resource {
+            Resource::Root => vec![
+                Resource::Principal,
+                Resource::AddressBookHome,
+                Resource::CalendarHome,
+            ],
+            Resource::AddressBookHome => vec![Resource::AddressBook],
+            Resource::AddressBook => self
+                .book
+                .map(|book| {
+                    book.cards()
+                        .iter()
+                        .map(|card| Resource::Card(card.name.clone()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Resource::CalendarHome => vec![Resource::Calendar],
+            Resource::Calendar => self
+                .calendar
+                .map(|calendar| {
+                    calendar
+                        .events()
+                        .iter()
+                        .map(|event| Resource::Event(event.name.clone()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Resource::Principal | Resource::Card(_) | Resource::Event(_) => Vec::new(),
+        }
+    }
+
+    /// Properties `allprop` and `propname` report for a resource.
+    fn prop_names(&self, resource: &Resource) -> Vec<PropName> {
+        let mut names = vec![
+            PropName::new(DAV, "resourcetype"),
+            PropName::new(DAV, "current-user-principal"),
+        ];
+        let extra: &[(&str, &str)] = match resource {
+            Resource::Root | Resource::AddressBookHome | Resource::CalendarHome => {
+                &[(DAV, "displayname"), (DAV, "current-user-privilege-set")]
+            }
+            Resource::Principal => &[
+                (DAV, "displayname"),
+                (DAV, "principal-URL"),
+                (CARDDAV, "addressbook-home-set"),
+                (CALDAV, "calendar-home-set"),
+            ],
+            Resource::AddressBook => &[
+                (DAV, "displayname"),
+                (DAV, "current-user-privilege-set"),
+                (DAV, "supported-report-set"),
+                (DAV, "sync-token"),
+                (CALENDARSERVER, "getctag"),
+                (CARDDAV, "addressbook-description"),
+                (CARDDAV, "supported-address-data"),
+            ],
+            Resource::Calendar => &[
+                (DAV, "displayname"),
+                (DAV, "current-user-privilege-set"),
+                (DAV, "supported-report-set"),
+                (CALENDARSERVER, "getctag"),
+                (CALDAV, "calendar-description"),
+                (CALDAV, "supported-calendar-component-set"),
+                (CALDAV, "supported-calendar-data"),
+            ],
+            Resource::Card(_) | Resource::Event(_) => &[(DAV, "getetag"), (DAV, "getcontenttype")],
+        };
+        names.extend(extra.iter().map(|(ns, name)| PropName::new(ns, name)));
+        names
+    }
+
+    /// One property as a complete element, or `None` if the resource
+    /// doesn't have it.
+    fn prop(&self, resource: &Resource, name: &PropName) -> Option<String> {
+        let href = |path: &str| format!("<d:href>{path}</d:href>");
+
+        if name.is(DAV, "current-user-principal") {
+            return Some(name.element(&href(PRINCIPAL)));
+        }
+        if name.is(DAV, "resourcetype") {
+            let types = match resource {
+                Resource::Root | Resource::AddressBookHome | Resource::CalendarHome => {
+                    "<d:collection/>"
+                }
+                Resource::Principal => "<d:collection/><d:principal/>",
+                Resource::AddressBook => "<d:collection/><card:addressbook/>",
+                Resource::Calendar => "<d:collection/><cal:calendar/>",
+                Resource::Card(_) | Resource::Event(_) => "",
+            };
+            return Some(name.element(types));
+        }
+
+        match resource {
+            Resource::Root
+            | Resource::AddressBookHome
+            | Resource::AddressBook
+            | Resource::CalendarHome
+            | Resource::Calendar
+                if name.is(DAV, "current-user-privilege-set") =>
+            {
+                let privileges = if *resource == Resource::AddressBook {
+                    ["read", "write", "write-content", "bind", "unbind"].as_slice()
+                } else {
+                    ["read"].as_slice()
+                };
+                let privileges: String = privileges
+                    .iter()
+                    .map(|p| format!("<d:privilege><d:{p}/></d:privilege>"))
+                    .collect();
+                Some(name.element(&privileges))
+            }
+            Resource::Root if name.is(DAV, "displayname") => {
+                Some(name.element("Friend Knowledgebase"))
+            }
+            Resource::AddressBookHome if name.is(DAV, "displayname") => {
+                Some(name.element("Address books"))
+            }
+            Resource::CalendarHome if name.is(DAV, "displayname") => {
+                Some(name.element("Calendars"))
+            }
+            Resource::Root | Resource::Principal if name.is(CARDDAV, "addressbook-home-set") => {
+                Some(name.element(&href(ADDRESSBOOK_HOME)))
+            }
+            Resource::Root | Resource::Principal if name.is(CALDAV, "calendar-home-set") => {
+                Some(name.element(&href(CALENDAR_HOME)))
+            }
+            Resource::Principal if name.is(DAV, "principal-URL") => {
+                Some(name.element(&href(PRINCIPAL)))
+            }
+            Resource::Principal if name.is(DAV, "displayname") => Some(name.element(&xml::escape(
+                &format!("{} {}", self.user.first_name, self.user.last_name),
+            ))),
+            Resource::AddressBook => self.book?.prop(name),
+            Resource::Card(card) => self.book?.card(card)?.prop(name),
+            Resource::Calendar => self.calendar?.prop(name),
+            Resource::Event(event) => self.calendar?.event(event)?.prop(name),
+            _ => None,
+        }
+    }
+}
+
+/// Quoted hash of a resource's text.
+fn etag(data: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(data.as_bytes()));
+    format!("\"{}\"", &digest[..32])
+}
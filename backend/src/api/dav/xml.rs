@@ -0,0 +1,596 @@
+//! # DAV XML
+//!
+//! Parsing of PROPFIND and REPORT request bodies, and writing of
+//! `207 Multi-Status` responses.
+//!
+//! Requests are parsed with `roxmltree`, which resolves namespaces and
+//! refuses DTDs (so no entity expansion tricks). Responses are written by
+//! hand with fixed prefixes: `d` for `DAV:`, `card` for CardDAV, `cal` for
+//! CalDAV and `cs` for the CalendarServer extensions.
+
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use roxmltree::{Document, Node};
+use time::{Date, Month, OffsetDateTime, Time};
+
+use crate::api::error::ApiError;
+
+/// WebDAV namespace.
+pub const DAV: &str = "DAV:";
+
+/// CardDAV namespace (RFC 6352).
+pub const CARDDAV: &str = "urn:ietf:params:xml:ns:carddav";
+
+/// CalDAV namespace (RFC 4791).
+pub const CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+
+/// CalendarServer extensions (`getctag`).
+pub const CALENDARSERVER: &str = "http://calendarserver.org/ns/";
+
+/// Known namespaces and the prefixes responses use for them.
+const PREFIXES: [(&str, &str); 4] = [
+    (DAV, "d"),
+    (CARDDAV, "card"),
+    (CALDAV, "cal"),
+    (CALENDARSERVER, "cs"),
+];
+
+/// A namespaced property (or element) name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropName {
+    pub namespace: String,
+    pub name: String,
+}
+
+impl PropName {
+    pub fn new(namespace: &str, name: &str) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    /// Whether this is `name` in `namespace`.
+    pub fn is(&self, namespace: &str, name: &str) -> bool {
+        self.namespace == namespace && self.name == name
+    }
+
+    /// `<prefix:name>inner</prefix:name>`, declaring unknown namespaces.
+    pub fn element(&self, inner: &str) -> String {
+        let (open, close) = match PREFIXES.iter().find(|(ns, _)| *ns == self.namespace) {
+            Some((_, prefix)) => (
+                format!("{prefix}:{}", self.name),
+                format!("{prefix}:{}", self.name),
+            ),
+            None => (
+                format!("x:{} xmlns:x=\"{}\"", self.name, escape(&self.namespace)),
+                format!("x:{}", self.name),
+            ),
+        };
+        if inner.is_empty() {
+            format!("<{open}/>")
+        } else {
+            format!("<{open}>{inner}</{close}>")
+        }
+    }
+}
+
+/// What a PROPFIND asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropRequest {
+    /// Every property the server offers (also an empty body)
+    AllProp,
+    /// Just the names of every property
+    PropName,
+    /// These properties
+    Prop(Vec<PropName>),
+}
+
+/// A REPORT request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Report {
+    /// `card:addressbook-multiget`: these cards
+    AddressbookMultiget {
+        props: Vec<PropName>,
+        hrefs: Vec<String>,
+    },
+    /// `card:addressbook-query`: cards matching a filter
+    AddressbookQuery { props: Vec<PropName> },
+    /// `d:sync-collection`: changes since a token (empty for everything)
+    SyncCollection { token: String, props: Vec<PropName> },
+    /// `cal:calendar-multiget`: these events
+    CalendarMultiget {
+        props: Vec<PropName>,
+        hrefs: Vec<String>,
+    },
+    /// `cal:calendar-query`: events matching a filter
+    CalendarQuery {
+        props: Vec<PropName>,
+        /// Names of the nested `comp-filter`s, outermost first, e.g.
+        /// `["VCALENDAR", "VEVENT"]`
+        components: Vec<String>,
+        /// `time-range` start (inclusive), if given
+        start: Option<OffsetDateTime>,
+        /// `time-range` end (exclusive), if given
+        end: Option<OffsetDateTime>,
+    },
+}
+
+/// Parse a PROPFIND body.
+///
+/// # Errors
+///
+/// Returns `ApiError::BadRequest` for malformed XML or a root element
+/// other than `d:propfind`.
+pub fn parse_propfind(body: &str) -> Result<PropRequest, ApiError> {
+    if body.trim().is_empty() {
+        return Ok(PropRequest::AllProp);
+    }
+    let document = parse(body)?;
+    let root = document.root_element();
+    if !is(root, DAV, "propfind") {
+        return Err(ApiError::BadRequest("expected DAV:propfind".to_string()));
+    }
+
+    for child in elements(root) {
+        if is(child, DAV, "prop") {
+            return Ok(PropRequest::Prop(prop_names(child)));
+        }
+        if is(child, DAV, "propname") {
+            return Ok(PropRequest::PropName);
+        }
+        if is(child, DAV, "allprop") {
+            return Ok(PropRequest::AllProp);
+        }
+    }
+    Err(ApiError::BadRequest(
+        "DAV:propfind needs prop, propname or allprop".to_string(),
+    ))
+}
+
+/// Parse a REPORT body.
+///
+/// # Returns
+///
+/// `Ok(None)` for well-formed reports this server doesn't support.
+///
+/// # Errors
+///
+/// Returns `ApiError::BadRequest` for malformed XML or an unreadable
+/// `time-range`.
+pub fn parse_report(body: &str) -> Result<Option<Report>, ApiError> {
+    let document = parse(body)?;
+    let root = document.root_element();
+    let props = elements(root)
+        .find(|child| is(*child, DAV, "prop"))
+        .map(prop_names)
+        .unwrap_or_default();
+
+    let hrefs = || {
+        elements(root)
+            .filter(|child| is(*child, DAV, "href"))
+            .map(|href| href.text().unwrap_or("").trim().to_string())
+            .collect()
+    };
+
+    let report = if is(root, CARDDAV, "addressbook-multiget") {
+        Report::AddressbookMultiget {
+            props,
+            hrefs: hrefs(),
+        }
+    } else if is(root, CARDDAV, "addressbook-query") {
+        Report::AddressbookQuery { props }
+    } else if is(root, DAV, "sync-collection") {
+        let token = elements(root)
+            .find(|child| is(*child, DAV, "sync-token"))
+            .and_then(|token| token.text())
+            .unwrap_or("")
+            .trim()
+            .to_string();
+        Report::SyncCollection { token, props }
+    } else if is(root, CALDAV, "calendar-multiget") {
+        Report::CalendarMultiget {
+            props,
+            hrefs: hrefs(),
+        }
+    } else if is(root, CALDAV, "calendar-query") {
+        let mut components = Vec::new();
+        let mut start = None;
+        let mut end = None;
+        let mut filter = elements(root).find(|child| is(*child, CALDAV, "filter"));
+        while let Some(parent) = filter {
+            filter = elements(parent).find(|child| is(*child, CALDAV, "comp-filter"));
+            let Some(comp) = filter else {
+                break;
+            };
+            components.push(comp.attribute("name").unwrap_or("").to_string());
+            if let Some(range) = elements(comp).find(|child| is(*child, CALDAV, "time-range")) {
+                start = range.attribute("start").map(parse_utc).transpose()?;
+                end = range.attribute("end").map(parse_utc).transpose()?;
+            }
+        }
+        Report::CalendarQuery {
+            props,
+            components,
+            start,
+            end,
+        }
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(report))
+}
+
+/// Parse a `time-range` bound such as "20240101T000000Z".
+fn parse_utc(value: &str) -> Result<OffsetDateTime, ApiError> {
+    let invalid = || ApiError::BadRequest(format!("invalid time-range bound: {value}"));
+    let (date, time) = value
+        .strip_suffix('Z')
+        .and_then(|v| v.split_once('T'))
+        .filter(|(date, time)| date.len() == 8 && time.len() == 6 && value.is_ascii())
+        .ok_or_else(invalid)?;
+    let number = |digits: &str| digits.parse::<u32>().map_err(|_| invalid());
+
+    let month = Month::try_from(number(&date[4..6])? as u8).map_err(|_| invalid())?;
+    let date =
+        Date::from_calendar_date(number(&date[..4])? as i32, month, number(&date[6..])? as u8)
+            .map_err(|_| invalid())?;
+    let time = Time::from_hms(
+        number(&time[..2])? as u8,
+        number(&time[2..4])? as u8,
+        number(&time[4..])? as u8,
+    )
+    .map_err(|_| invalid())?;
+    Ok(date.with_time(time).assume_utc())
+}
+
+fn parse(body: &str) -> Result<Document<'_>, ApiError> {
+    Document::parse(body).map_err(|e| ApiError::BadRequest(format!("invalid XML: {e}")))
+}
+
+fn elements<'a, 'input>(node: Node<'a, 'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(|child| child.is_element())
+}
+
+fn is(node: Node, namespace: &str, name: &str) -> bool {
+    node.tag_name().namespace() == Some(namespace) && node.tag_name().name() == name
+}
+
+fn prop_names(prop: Node) -> Vec<PropName> {
+    elements(prop)
+        .map(|child| {
+            PropName::new(
+                child.tag_name().namespace().unwrap_or(""),
+                child.tag_name().name(),
+            )
+        })
+        .collect()
+}
+
+/// Builds a `207 Multi-Status` response.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let mut multistatus = Multistatus::new();
+/// multistatus.response(href, &found, &missing);
+/// multistatus.sync_token(&token);
+/// return Ok(multistatus.into_response());
+/// ```
+pub struct Multistatus {
+    body: String,
+}
+
+impl Default for Multistatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Multistatus {
+    pub fn new() -> Self {
+        let namespaces: String = PREFIXES
+            .iter()
+            .map(|(ns, prefix)| format!(" xmlns:{prefix}=\"{ns}\""))
+            .collect();
+        Self {
+            body: format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<d:multistatus{namespaces}>\n"
+            ),
+        }
+    }
+
+    /// Add a resource's properties.
+    ///
+    /// # Arguments
+    ///
+    /// * `href` - The resource's (already encoded) path
+    /// * `found` - Complete property elements, from `PropName::element`
+    /// * `missing` - Requested properties the resource doesn't have
+    pub fn response(&mut self, href: &str, found: &[String], missing: &[PropName]) {
+        self.body
+            .push_str(&format!("<d:response><d:href>{}</d:href>", escape(href)));
+        if !found.is_empty() || missing.is_empty() {
+            self.propstat(&found.concat(), "200 OK");
+        }
+        if !missing.is_empty() {
+            let empty: String = missing.iter().map(|name| name.element("")).collect();
+            self.propstat(&empty, "404 Not Found");
+        }
+        self.body.push_str("</d:response>\n");
+    }
+
+    /// Add a resource that doesn't exist (deleted, or an unknown href).
+    pub fn not_found(&mut self, href: &str) {
+        self.body.push_str(&format!(
+            "<d:response><d:href>{}</d:href><d:status>HTTP/1.1 404 Not Found</d:status></d:response>\n",
+            escape(href)
+        ));
+    }
+
+    /// Add the sync token a `sync-collection` report ends with.
+    pub fn sync_token(&mut self, token: &str) {
+        self.body
+            .push_str(&format!("<d:sync-token>{}</d:sync-token>\n", escape(token)));
+    }
+
+    fn propstat(&mut self, props: &str, status: &str) {
+        self.body.push_str(&format!(
+            "<d:propstat><d:prop>{props}</d:prop><d:status>HTTP/1.1 {status}</d:status></d:propstat>"
+        ));
+    }
+}
+
+impl IntoResponse for Multistatus {
+    fn into_response(mut self) -> Response {
+        self.body.push_str("</d:multistatus>\n");
+        xml_response(StatusCode::MULTI_STATUS, self.body)
+    }
+}
+
+/// A `d:error` response naming the precondition that failed.
+///
+/// `condition` is the element inside `d:error`, e.g.
+/// `PropName::new(DAV, "valid-sync-token")`.
+pub fn precondition_failed(status: StatusCode, condition: &PropName) -> Response {
+    let namespaces: String = PREFIXES
+        .iter()
+        .map(|(ns, prefix)| format!(" xmlns:{prefix}=\"{ns}\""))
+        .collect();
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<d:error{namespaces}>{}</d:error>\n",
+        condition.element("")
+    );
+    xml_response(status, body)
+}
+
+/// `403` with `d:supported-report`, for reports a collection doesn't do.
+pub fn unsupported_report() -> Response {
+    precondition_failed(
+        StatusCode::FORBIDDEN,
+        &PropName::new(DAV, "supported-report"),
+    )
+}
+
+fn xml_response(status: StatusCode, body: String) -> Response {
+    (
+        status,
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/xml; charset=utf-8"),
+        )],
+        body,
+    )
+        .into_response()
+}
+
+/// Escape text for XML content and attribute values.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn propfind_reads_the_requested_properties() {
+        let body = r#"<?xml version="1.0"?>
+            <propfind xmlns="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav"
+                      xmlns:CS="http://calendarserver.org/ns/">
+              <prop><getetag/><C:address-data/><CS:getctag/></prop>
+            </propfind>"#;
+
+        let request = parse_propfind(body).unwrap();
+        assert_eq!(
+            request,
+            PropRequest::Prop(vec![
+                PropName::new(DAV, "getetag"),
+                PropName::new(CARDDAV, "address-data"),
+                PropName::new(CALENDARSERVER, "getctag"),
+            ])
+        );
+    }
+
+    #[test]
+    fn propfind_without_prop() {
+        assert_eq!(parse_propfind("").unwrap(), PropRequest::AllProp);
+        assert_eq!(
+            parse_propfind(r#"<d:propfind xmlns:d="DAV:"><d:allprop/></d:propfind>"#).unwrap(),
+            PropRequest::AllProp
+        );
+        assert_eq!(
+            parse_propfind(r#"<d:propfind xmlns:d="DAV:"><d:propname/></d:propfind>"#).unwrap(),
+            PropRequest::PropName
+        );
+    }
+
+    #[test]
+    fn propfind_rejects_other_documents() {
+        // Right name, wrong namespace
+        assert!(matches!(
+            parse_propfind("<propfind><prop/></propfind>"),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            parse_propfind(r#"<d:propfind xmlns:d="DAV:"/>"#),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            parse_propfind("<d:propfind"),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn doctypes_are_refused() {
+        let body = r#"<!DOCTYPE d [<!ENTITY e "boom">]>
+            <d:propfind xmlns:d="DAV:"><d:prop>&e;</d:prop></d:propfind>"#;
+        assert!(matches!(parse_propfind(body), Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn addressbook_multiget_reads_props_and_hrefs() {
+        let body = r#"<C:addressbook-multiget xmlns:d="DAV:"
+                          xmlns:C="urn:ietf:params:xml:ns:carddav">
+              <d:prop><d:getetag/></d:prop>
+              <d:href> /dav/addressbook/a.vcf </d:href>
+              <d:href>/dav/addressbook/b.vcf</d:href>
+            </C:addressbook-multiget>"#;
+
+        let report = parse_report(body).unwrap();
+        assert_eq!(
+            report,
+            Some(Report::AddressbookMultiget {
+                props: vec![PropName::new(DAV, "getetag")],
+                hrefs: vec![
+                    "/dav/addressbook/a.vcf".to_string(),
+                    "/dav/addressbook/b.vcf".to_string(),
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn sync_collection_reads_the_token() {
+        let body = r#"<d:sync-collection xmlns:d="DAV:">
+              <d:sync-token>http://example.com/sync/42</d:sync-token>
+              <d:sync-level>1</d:sync-level>
+              <d:prop><d:getetag/></d:prop>
+            </d:sync-collection>"#;
+        assert_eq!(
+            parse_report(body).unwrap(),
+            Some(Report::SyncCollection {
+                token: "http://example.com/sync/42".to_string(),
+                props: vec![PropName::new(DAV, "getetag")],
+            })
+        );
+
+        // An initial sync has an empty token
+        let body = r#"<d:sync-collection xmlns:d="DAV:"><d:sync-token/></d:sync-collection>"#;
+        assert_eq!(
+            parse_report(body).unwrap(),
+            Some(Report::SyncCollection {
+                token: String::new(),
+                props: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_reports_are_none() {
+        let body = r#"<d:expand-property xmlns:d="DAV:"/>"#;
+        assert_eq!(parse_report(body).unwrap(), None);
+    }
+
+    #[test]
+    fn elements_use_the_known_prefixes() {
+        assert_eq!(
+            PropName::new(DAV, "getetag").element("\"3\""),
+            "<d:getetag>\"3\"</d:getetag>"
+        );
+        assert_eq!(
+            PropName::new(CARDDAV, "addressbook").element(""),
+            "<card:addressbook/>"
+        );
+        assert_eq!(
+            PropName::new("urn:x", "color").element(""),
+            "<x:color xmlns:x=\"urn:x\"/>"
+        );
+    }
+
+    #[test]
+    fn calendar_query_reads_components_and_time_range() {
+        let body = r#"<C:calendar-query xmlns:d="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+              <d:prop><d:getetag/><C:calendar-data/></d:prop>
+              <C:filter>
+                <C:comp-filter name="VCALENDAR">
+                  <C:comp-filter name="VEVENT">
+                    <C:time-range start="20240101T000000Z" end="20240201T120000Z"/>
+                  </C:comp-filter>
+                </C:comp-filter>
+              </C:filter>
+            </C:calendar-query>"#;
+
+        let report = parse_report(body).unwrap();
+        let start = Date::from_calendar_date(2024, Month::January, 1)
+            .unwrap()
+            .midnight()
+            .assume_utc();
+        let end = Date::from_calendar_date(2024, Month::February, 1)
+            .unwrap()
+            .with_hms(12, 0, 0)
+            .unwrap()
+            .assume_utc();
+        assert_eq!(
+            report,
+            Some(Report::CalendarQuery {
+                props: vec![
+                    PropName::new(DAV, "getetag"),
+                    PropName::new(CALDAV, "calendar-data"),
+                ],
+                components: vec!["VCALENDAR".to_string(), "VEVENT".to_string()],
+                start: Some(start),
+                end: Some(end),
+            })
+        );
+    }
+
+    #[test]
+    fn time_range_bounds_are_optional() {
+        let body = r#"<C:calendar-query xmlns:C="urn:ietf:params:xml:ns:caldav">
+              <C:filter><C:comp-filter name="VCALENDAR"><C:comp-filter name="VEVENT">
+                <C:time-range start="20240101T000000Z"/>
+              </C:comp-filter></C:comp-filter></C:filter>
+            </C:calendar-query>"#;
+        let Some(Report::CalendarQuery { start, end, .. }) = parse_report(body).unwrap() else {
+            panic!("not a calendar-query");
+        };
+        assert!(start.is_some());
+        assert_eq!(end, None);
+    }
+
+    #[test]
+    fn time_range_bounds_must_be_utc_date_times() {
+        for bound in [
+            "20240101",
+            "20240101T000000",
+            "2024-01-01T00:00:00Z",
+            "20241301T000000Z",
+            "20240230T000000Z",
+            "20240101T250000Z",
+            "2024010１T000000Z",
+        ] {
+            assert!(
+                matches!(parse_utc(bound), Err(ApiError::BadRequest(_))),
+                "accepted {bound}"
+            );
+        }
+        let leap_day = parse_utc("20240229T235959Z").unwrap();
+        assert_eq!(leap_day.day(), 29);
+        assert_eq!(leap_day.second(), 59);
+    }
+}
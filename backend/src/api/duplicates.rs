@@ -0,0 +1,65 @@
+//! # Duplicate Endpoints
+//!
+//! ```text
+//! GET  /duplicates              likely duplicate pairs, best match first
+//! POST /friends/<id>/merge      merge another friend into this one
+//! ```
+//!
+//! Both need Basic auth. The merge body names the friend to merge in and,
+//! optionally, how to resolve attributes both friends have:
+//!
+//! ```json
+//! { "other": "0192f3c4-...", "attributes": { "phone": "other", "email": "both" } }
+//! ```
+//!
+//! Choices are "keep", "other" or "both" (the default). The answer is the
+//! merge report, including the merged friend; `POST /undo` reverts it.
+
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::duplicates::{DuplicateCandidate, DuplicateFinder};
+use crate::repositories::{AttributeChoice, MergeReport};
+use crate::services::FriendService;
+
+use super::AppState;
+use super::auth::AuthenticatedUser;
+use super::error::ApiError;
+
+/// Body of a merge.
+#[derive(Deserialize)]
+pub struct MergeRequest {
+    /// The friend merged in and deleted
+    pub other: Uuid,
+    /// Conflict choices by attribute key
+    #[serde(default)]
+    pub attributes: HashMap<String, AttributeChoice>,
+}
+
+/// Likely duplicates among the caller's friends.
+pub async fn list(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<DuplicateCandidate>>, ApiError> {
+    let candidates = DuplicateFinder::new(state.ctx.clone())
+        .find(user.id)
+        .await?;
+    Ok(Json(candidates))
+}
+
+/// Merge `other` into the friend in the path.
+pub async fn merge(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(friend_id): Path<Uuid>,
+    Json(request): Json<MergeRequest>,
+) -> Result<Json<MergeReport>, ApiError> {
+    let report = FriendService::new(state.ctx.clone())
+        .merge(user.id, friend_id, request.other, request.attributes)
+        .await?;
+    Ok(Json(report))
+}
@@ -0,0 +1,103 @@
+//! # API Error Type
+//!
+//! Maps repository and interchange failures onto HTTP responses.
+
+use axum::http::header::WWW_AUTHENTICATE;
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use thiserror::Error;
+
+use crate::interchange::InterchangeError;
+use crate::repositories::RepositoryError;
+
+/// Error type for HTTP handlers.
+///
+/// # Variants
+///
+/// - `NotFound` - Nothing at this URL (or the caller may not know it exists)
+/// - `BadRequest` - The request body or headers couldn't be understood
+/// - `Unauthorized` - Missing or wrong credentials
+/// - `PreconditionFailed` - An `If-Match` / `If-None-Match` check failed
+///   (a repository `Conflict` is answered the same way: the record has
+///   changed since the client read it)
+/// - `Repository` - A database operation failed
+/// - `Interchange` - Rendering or parsing a document failed
+///
+/// # Response Bodies
+///
+/// Only client-caused errors describe the problem. Everything else
+/// answers "Internal server error" so database details never reach the
+/// client; the request log has the full error.
+#[derive(Error, Debug)]
+pub enum ApiError {
+    /// Nothing at this URL
+    #[error("Not found")]
+    NotFound,
+
+    /// The request couldn't be understood
+    /// The string says what was wrong with it
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+
+    /// Missing or wrong credentials
+    #[error("Unauthorized")]
+    Unauthorized,
+
+    /// A conditional request's precondition didn't hold
+    #[error("Precondition failed")]
+    PreconditionFailed,
+
+    /// A database operation failed
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+
+    /// Rendering or parsing a document failed
+    #[error(transparent)]
+    Interchange(#[from] InterchangeError),
+}
+
+impl ApiError {
+    /// The HTTP status this error is answered with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound | ApiError::Repository(RepositoryError::NotFound) => {
+                StatusCode::NOT_FOUND
+            }
+            ApiError::BadRequest(_)
+            | ApiError::Interchange(InterchangeError::Parse { .. })
+            | ApiError::Interchange(InterchangeError::Unsupported(_)) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::PreconditionFailed
+            | ApiError::Repository(RepositoryError::Conflict { .. })
+            | ApiError::Interchange(InterchangeError::Repository(RepositoryError::Conflict {
+                ..
+            })) => StatusCode::PRECONDITION_FAILED,
+            ApiError::Repository(RepositoryError::Validation(_))
+            | ApiError::Interchange(InterchangeError::Repository(RepositoryError::Validation(_))) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ApiError::Repository(RepositoryError::Duplicate(_)) => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let body = if status.is_server_error() {
+            log::error!("{self}");
+            "Internal server error".to_string()
+        } else {
+            self.to_string()
+        };
+        let mut response = (status, body).into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response.headers_mut().insert(
+                WWW_AUTHENTICATE,
+                HeaderValue::from_static(r#"Basic realm="Friend Knowledgebase", charset="UTF-8""#),
+            );
+        }
+        response
+    }
+}
@@ -0,0 +1,217 @@
+//! # Friend Endpoints
+//!
+//! ```text
+//! GET   /friends/<id>               the friend, with their version as ETag
+//! PATCH /friends/<id>               change some of the friend's fields
+//! GET   /friends/<id>/attributes    the friend's attributes
+//! PUT   /friends/<id>/attributes    replace them with a complete set
+//! GET   /friends/<id>/profile       the friend with everything attached
+//! GET   /profiles                   every friend's profile, by first name
+//! ```
+//!
+//! All need Basic auth and only ever show the caller's own friends.
+//!
+//! ## Concurrency
+//!
+//! The `ETag` is the friend's version, e.g. `"3"`. Sent back as
+//! `If-Match` on `PATCH`, it makes the change conditional: if anyone has
+//! changed the friend since (another device, a CardDAV client, an
+//! import), the answer is 412 Precondition Failed and nothing is changed.
+//! The client should then fetch the friend again and redo its edit.
+//! Without `If-Match` the change is made regardless.
+//!
+//! `null` in a `PATCH` clears a field (`"notes": null`); `first_name`
+//! can't be cleared.
+//!
+//! ## Profiles
+//!
+//! A profile is the friend plus their groups, attributes, relationships
+//! (with the other friend's name) and the caller's relationships with
+//! them, in one response. `?current=true` leaves out relationships that
+//! aren't in effect today.
+//!
+//! ## Attributes
+//!
+//! `PUT` takes every attribute the friend should have; the ones left out
+//! are deleted. The answer lists the keys that were added, updated and
+//! removed:
+//!
+//! ```json
+//! [{ "key": "email", "value": "ada@example.com", "value_type": "email" },
+//!  { "key": "city", "value": "London" }]
+//! ```
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
+use axum::response::Response;
+use serde::Deserialize;
+use time::Date;
+use uuid::Uuid;
+
+use crate::models::{Friend, FriendAttribute};
+use crate::repositories::{
+    AttributeChanges, AttributeInput, FriendProfile, Timeline, UpdateFriendInput,
+};
+use crate::services::FriendService;
+
+use super::auth::AuthenticatedUser;
+use super::error::ApiError;
+use super::{AppState, if_match, present, versioned};
+
+/// Body of `PATCH`. Fields left out are kept as they are; `null` clears
+/// all but `first_name`.
+#[derive(Deserialize)]
+pub struct FriendPatch {
+    pub first_name: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub last_name: Option<Option<String>>,
+    /// e.g. "1990-02-28"
+    #[serde(default, deserialize_with = "present")]
+    pub date_of_birth: Option<Option<Date>>,
+    #[serde(default, deserialize_with = "present")]
+    pub likes: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub dislikes: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub notes: Option<Option<String>>,
+}
+
+impl From<FriendPatch> for UpdateFriendInput {
+    fn from(patch: FriendPatch) -> Self {
+        UpdateFriendInput {
+            first_name: patch.first_name,
+            last_name: patch.last_name,
+            date_of_birth: patch.date_of_birth,
+            likes: patch.likes,
+            dislikes: patch.dislikes,
+            notes: patch.notes,
+        }
+    }
+}
+
+/// Query string of the profile endpoints.
+#[derive(Deserialize)]
+pub struct ProfileQuery {
+    /// Only relationships in effect today
+    #[serde(default)]
+    pub current: bool,
+}
+
+impl ProfileQuery {
+    pub(super) fn timeline(&self) -> Timeline {
+        if self.current {
+            Timeline::Current
+        } else {
+            Timeline::Full
+        }
+    }
+}
+
+/// One attribute in the body of `PUT /friends/<id>/attributes`.
+#[derive(Deserialize)]
+pub struct AttributeEntry {
+    pub key: String,
+    pub value: String,
+    /// Defaults to "text"
+    pub value_type: Option<String>,
+}
+
+impl From<AttributeEntry> for AttributeInput {
+    fn from(entry: AttributeEntry) -> Self {
+        AttributeInput {
+            key: entry.key,
+            value: entry.value,
+            value_type: entry.value_type,
+        }
+    }
+}
+
+/// The friend.
+pub async fn get(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(friend_id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let friend = FriendService::new(state.ctx.clone())
+        .get(user.id, friend_id)
+        .await?;
+    Ok(with_etag(friend))
+}
+
+/// Change some of the friend's fields, if `If-Match` still holds.
+pub async fn update(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(friend_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(patch): Json<FriendPatch>,
+) -> Result<Response, ApiError> {
+    let friends = FriendService::new(state.ctx.clone());
+    let friend = friends.get(user.id, friend_id).await?;
+    let expected_version = if_match(&headers, friend.version)?;
+
+    let friend = friends
+        .update(user.id, friend_id, expected_version, patch.into())
+        .await?;
+    Ok(with_etag(friend))
+}
+
+/// The friend's attributes, by key.
+pub async fn attributes(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(friend_id): Path<Uuid>,
+) -> Result<Json<Vec<FriendAttribute>>, ApiError> {
+    let attributes = FriendService::new(state.ctx.clone())
+        .attributes(user.id, friend_id)
+        .await?;
+    Ok(Json(attributes))
+}
+
+/// Replace the friend's attributes with the given set.
+pub async fn replace_attributes(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(friend_id): Path<Uuid>,
+    Json(entries): Json<Vec<AttributeEntry>>,
+) -> Result<Json<AttributeChanges>, ApiError> {
+    let changes = FriendService::new(state.ctx.clone())
+        .replace_attributes(
+            user.id,
+            friend_id,
+            entries.into_iter().map(Into::into).collect(),
+        )
+        .await?;
+    Ok(Json(changes))
+}
+
+/// The friend with everything attached.
+pub async fn profile(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(friend_id): Path<Uuid>,
+    Query(query): Query<ProfileQuery>,
+) -> Result<Json<FriendProfile>, ApiError> {
+    let profile = FriendService::new(state.ctx.clone())
+        .profile(user.id, friend_id, query.timeline())
+        .await?;
+    Ok(Json(profile))
+}
+
+/// Every one of the caller's friends with everything attached.
+pub async fn profiles(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Query(query): Query<ProfileQuery>,
+) -> Result<Json<Vec<FriendProfile>>, ApiError> {
+    let profiles = FriendService::new(state.ctx.clone())
+        .profiles(user.id, query.timeline())
+        .await?;
+    Ok(Json(profiles))
+}
+
+/// Answer with the friend as JSON and their version as ETag.
+fn with_etag(friend: Friend) -> Response {
+    versioned(friend.version, friend)
+}
@@ -0,0 +1,165 @@
+//! # Group Endpoints
+//!
+//! ```text
+//! GET    /groups                          the caller's groups, by name
+//! POST   /groups                          create a group
+//! GET    /groups/<id>                     the group, with its version as ETag
+//! PATCH  /groups/<id>                     rename or re-describe it (If-Match)
+//! DELETE /groups/<id>                     delete it; its friends stay
+//! GET    /groups/<id>/members             the friends in it, by first name
+//! PUT    /groups/<id>/members/<friend>    put a friend in it
+//! DELETE /groups/<id>/members/<friend>    take a friend out of it
+//! ```
+//!
+//! All need Basic auth and only ever show the caller's own groups and
+//! friends. `If-Match` works as for friends (see `friends`).
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::models::{Friend, Group};
+use crate::repositories::UpdateGroupInput;
+use crate::services::{GroupService, NewGroup};
+
+use super::auth::AuthenticatedUser;
+use super::error::ApiError;
+use super::{AppState, if_match, versioned};
+
+/// Body of `POST /groups`.
+#[derive(Deserialize)]
+pub struct GroupRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// Body of `PATCH`. Fields left out are kept as they are.
+#[derive(Deserialize)]
+pub struct GroupPatch {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+/// The caller's groups.
+pub async fn list(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<Group>>, ApiError> {
+    let groups = GroupService::new(state.ctx.clone()).list(user.id).await?;
+    Ok(Json(groups))
+}
+
+/// Create a group for the caller.
+pub async fn create(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<GroupRequest>,
+) -> Result<Response, ApiError> {
+    let group = GroupService::new(state.ctx.clone())
+        .create(
+            user.id,
+            NewGroup {
+                name: request.name,
+                description: request.description,
+            },
+        )
+        .await?;
+    Ok((StatusCode::CREATED, with_etag(group)).into_response())
+}
+
+/// The group.
+pub async fn get(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(group_id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let group = GroupService::new(state.ctx.clone())
+        .get(user.id, group_id)
+        .await?;
+    Ok(with_etag(group))
+}
+
+/// Change the group, if `If-Match` still holds.
+pub async fn update(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(group_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(patch): Json<GroupPatch>,
+) -> Result<Response, ApiError> {
+    let groups = GroupService::new(state.ctx.clone());
+    let group = groups.get(user.id, group_id).await?;
+    let expected_version = if_match(&headers, group.version)?;
+
+    let group = groups
+        .update(
+            user.id,
+            group_id,
+            expected_version,
+            UpdateGroupInput {
+                name: patch.name,
+                description: patch.description,
+            },
+        )
+        .await?;
+    Ok(with_etag(group))
+}
+
+/// Delete the group.
+pub async fn delete(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(group_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    GroupService::new(state.ctx.clone())
+        .delete(user.id, group_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The friends in the group.
+pub async fn members(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(group_id): Path<Uuid>,
+) -> Result<Json<Vec<Friend>>, ApiError> {
+    let members = GroupService::new(state.ctx.clone())
+        .members(user.id, group_id)
+        .await?;
+    Ok(Json(members))
+}
+
+/// Put the friend in the group; nothing changes if they already are.
+pub async fn add_member(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path((group_id, friend_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, ApiError> {
+    GroupService::new(state.ctx.clone())
+        .add_member(user.id, group_id, friend_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Take the friend out of the group; 404 if they weren't in it.
+pub async fn remove_member(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path((group_id, friend_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, ApiError> {
+    let removed = GroupService::new(state.ctx.clone())
+        .remove_member(user.id, group_id, friend_id)
+        .await?;
+    if !removed {
+        return Err(ApiError::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Answer with the group as JSON and its version as ETag.
+fn with_etag(group: Group) -> Response {
+    versioned(group.version, group)
+}
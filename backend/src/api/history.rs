@@ -0,0 +1,64 @@
+//! # Friend History Endpoints
+//!
+//! ```text
+//! GET /friends/<id>/history                 every recorded change, oldest first
+//! GET /friends/<id>/as-of?at=<RFC 3339>     the friend as they were then
+//! ```
+//!
+//! Both need Basic auth and only ever show the caller's own friends; a
+//! friend of someone else's looks exactly like one that never existed.
+//! Trashed and purged friends keep their history, so both endpoints
+//! work for them too.
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use serde::Deserialize;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use uuid::Uuid;
+
+use crate::models::FriendHistoryEntry;
+use crate::repositories::{FriendAsOf, FriendHistoryRepository};
+
+use super::AppState;
+use super::auth::AuthenticatedUser;
+use super::error::ApiError;
+
+/// Query string of `as-of`.
+#[derive(Deserialize)]
+pub struct AsOfQuery {
+    /// e.g. "2025-01-01T00:00:00Z"
+    pub at: String,
+}
+
+/// Every recorded change to a friend.
+pub async fn list(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(friend_id): Path<Uuid>,
+) -> Result<Json<Vec<FriendHistoryEntry>>, ApiError> {
+    let entries = FriendHistoryRepository::new(state.ctx.clone())
+        .list_by_friend(user.id, friend_id)
+        .await?;
+    if entries.is_empty() {
+        return Err(ApiError::NotFound);
+    }
+    Ok(Json(entries))
+}
+
+/// A friend as they were at a moment.
+pub async fn as_of(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(friend_id): Path<Uuid>,
+    Query(query): Query<AsOfQuery>,
+) -> Result<Json<FriendAsOf>, ApiError> {
+    let at = OffsetDateTime::parse(&query.at, &Rfc3339)
+        .map_err(|e| ApiError::BadRequest(format!("at must be an RFC 3339 timestamp: {e}")))?;
+
+    let friend = FriendHistoryRepository::new(state.ctx.clone())
+        .as_of(user.id, friend_id, at)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Ok(Json(friend))
+}
@@ -0,0 +1,221 @@
+//! # API Module
+//!
+//! The HTTP server. Handlers are thin: they pull what they need out of the
+//! request, call the layer below and turn the result into a response.
+//!
+//! ## Layers
+//!
+//! | Handlers | Call |
+//! |----------|------|
+//! | `auth`, `friends`, `groups`, `relationships`, `duplicates` | services, which check ownership (see `services`) |
+//! | `bulk`, `history`, `undo` | repositories that take the caller and only touch their rows |
+//! | `dav`, `calendar` | interchange code to render and parse documents, and repositories scoped to the caller (for feeds, the token's owner) |
+//!
+//! Only the first row goes through services: the others work on the
+//! caller's rows as a whole, with nothing for a service to check.
+//!
+//! ## Routes
+//!
+//! ```text
+//! GET /calendar/<token>.ics   a user's birthdays and important dates
+//! GET /friends/<id>           a friend, with their version as ETag
+//! PATCH /friends/<id>         change a friend (If-Match, see `friends`)
+//! GET /friends/<id>/attributes  a friend's attributes
+//! PUT /friends/<id>/attributes  replace them with a complete set
+//! GET /friends/<id>/profile     a friend with everything attached
+//! GET /profiles                 every friend's profile, for list views
+//! GET /friends/<id>/relationships  a friend's relationships with others
+//! GET /friends/<id>/kinship   those plus the family relationships they imply
+//! /relationships/...          relate two friends (see `relationships`)
+//! /friends/<id>/user-relationships  how the caller knows a friend
+//! /user-relationships/...     change or delete one of those
+//! /groups/...                 groups and their members (see `groups`)
+//! POST /friends/<id>/merge    merge a duplicate into a friend
+//! GET /duplicates             likely duplicates (see `duplicates`)
+//! GET /friends/<id>/history   a friend's audit history (see `history`)
+//! GET /friends/<id>/as-of     a friend as they were at a moment
+//! POST /bulk/...               batch changes to many friends (see `bulk`)
+//! POST /undo                  revert the caller's last change (see `undo`)
+//! POST /redo                  re-apply the change undone last
+//! /.well-known/carddav        redirect to the DAV root
+//! /.well-known/caldav         redirect to the DAV root
+//! /dav/...                    CardDAV address book and CalDAV calendar
+//!                             (see `dav`)
+//! ```
+//!
+//! ## Logging
+//!
+//! Every request produces exactly one log line (see `log_request`) with
+//! the route template rather than the raw path, so secrets embedded in
+//! URLs (calendar tokens) never reach the logs.
+
+pub mod auth;
+pub mod bulk;
+pub mod calendar;
+pub mod dav;
+pub mod duplicates;
+pub mod error;
+pub mod friends;
+pub mod groups;
+pub mod history;
+pub mod relationships;
+pub mod undo;
+
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, Request};
+use axum::http::HeaderMap;
+use axum::http::header::{ETAG, IF_MATCH};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{any, get, patch, post, put};
+use axum::{Json, Router};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+
+use crate::repositories::RepositoryContext;
+
+pub use error::ApiError;
+
+/// State shared by every handler.
+#[derive(Clone)]
+pub struct AppState {
+    pub ctx: RepositoryContext,
+}
+
+/// Build the application router.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+/// axum::serve(listener, api::router(ctx)).await?;
+/// ```
+pub fn router(ctx: RepositoryContext) -> Router {
+    Router::new()
+        .route("/calendar/{file}", get(calendar::feed))
+        .route("/friends/{id}", get(friends::get).patch(friends::update))
+        .route(
+            "/friends/{id}/attributes",
+            get(friends::attributes).put(friends::replace_attributes),
+        )
+        .route("/friends/{id}/profile", get(friends::profile))
+        .route("/profiles", get(friends::profiles))
+        .route("/friends/{id}/relationships", get(relationships::list))
+        .route("/friends/{id}/kinship", get(relationships::kinship))
+        .route(
+            "/friends/{id}/user-relationships",
+            get(relationships::list_user_relationships)
+                .post(relationships::create_user_relationship),
+        )
+        .route("/relationships", post(relationships::create))
+        .route(
+            "/relationships/{id}",
+            get(relationships::get)
+                .patch(relationships::update)
+                .delete(relationships::delete),
+        )
+        .route(
+            "/user-relationships/{id}",
+            patch(relationships::update_user_relationship)
+                .delete(relationships::delete_user_relationship),
+        )
+        .route("/groups", get(groups::list).post(groups::create))
+        .route(
+            "/groups/{id}",
+            get(groups::get)
+                .patch(groups::update)
+                .delete(groups::delete),
+        )
+        .route("/groups/{id}/members", get(groups::members))
+        .route(
+            "/groups/{id}/members/{friend_id}",
+            put(groups::add_member).delete(groups::remove_member),
+        )
+        .route("/friends/{id}/merge", post(duplicates::merge))
+        .route("/duplicates", get(duplicates::list))
+        .route("/friends/{id}/history", get(history::list))
+        .route("/friends/{id}/as-of", get(history::as_of))
+        .route("/bulk/groups/{id}/add", post(bulk::add_to_group))
+        .route("/bulk/groups/{id}/remove", post(bulk::remove_from_group))
+        .route("/bulk/attributes", post(bulk::set_attribute))
+        .route("/bulk/delete", post(bulk::delete))
+        .route("/bulk/create", post(bulk::create))
+        .route("/undo", post(undo::undo))
+        .route("/redo", post(undo::redo))
+        .route("/.well-known/carddav", any(dav::well_known))
+        .route("/.well-known/caldav", any(dav::well_known))
+        .route("/dav", any(dav::handle))
+        .route("/dav/", any(dav::handle))
+        .route("/dav/{*path}", any(dav::handle))
+        .layer(middleware::from_fn(log_request))
+        .with_state(AppState { ctx })
+}
+
+/// The ETag of a record at a version, e.g. `"3"`.
+fn etag(version: i64) -> String {
+    format!("\"{version}\"")
+}
+
+/// Answer with a record as JSON and its version as ETag.
+fn versioned(version: i64, record: impl Serialize) -> Response {
+    ([(ETAG, etag(version))], Json(record)).into_response()
+}
+
+/// The version a change must be made against, given the record's
+/// current one.
+///
+/// `None` without `If-Match`. A matching `If-Match` pins the change to the
+/// version just read, so a change landing in between still fails (as a
+/// version conflict).
+///
+/// # Errors
+///
+/// - `PreconditionFailed` if no tag in `If-Match` matches
+fn if_match(headers: &HeaderMap, current: i64) -> Result<Option<i64>, ApiError> {
+    let Some(value) = headers.get(IF_MATCH) else {
+        return Ok(None);
+    };
+    let current_tag = etag(current);
+    let matches = value.to_str().is_ok_and(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag == current_tag)
+    });
+    if !matches {
+        return Err(ApiError::PreconditionFailed);
+    }
+    Ok(Some(current))
+}
+
+/// Deserialize a field that is present, `null` or not, as `Some`; with
+/// `#[serde(default)]`, a missing one stays `None`.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// Emit one structured log line per request.
+async fn log_request(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string());
+
+    let response = next.run(request).await;
+
+    let event = json!({
+        "method": method,
+        "route": route,
+        "status": response.status().as_u16(),
+        "duration_ms": started.elapsed().as_millis() as u64,
+    });
+    log::info!("{event}");
+    response
+}
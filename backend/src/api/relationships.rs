@@ -0,0 +1,313 @@
+//! # Relationship Endpoints
+//!
+//! ```text
+//! GET    /friends/<id>/relationships        the friend's relationships with other friends
+//! GET    /friends/<id>/kinship              those plus the family relationships they imply
+//! POST   /relationships                     relate two friends
+//! GET    /relationships/<id>                a relationship, with its version as ETag
+//! PATCH  /relationships/<id>                change it (If-Match)
+//! DELETE /relationships/<id>                delete it
+//! GET    /friends/<id>/user-relationships   how the caller knows the friend
+//! POST   /friends/<id>/user-relationships   add a way the caller knows them
+//! PATCH  /user-relationships/<id>           change it (If-Match)
+//! DELETE /user-relationships/<id>           delete it
+//! ```
+//!
+//! All need Basic auth and only ever show the caller's own friends and
+//! relationships. `If-Match` works as for friends (see `friends`), and
+//! `?current=true` on the lists leaves out relationships that aren't in
+//! effect today.
+//!
+//! ## Creating
+//!
+//! ```json
+//! { "friend_a_id": "…", "friend_b_id": "…",
+//!   "a_to_b": "parent of", "b_to_a": "child of",
+//!   "started_on": "1990-02-28" }
+//! ```
+//!
+//! Leaving out `b_to_a` makes the relationship symmetric. Contradicting
+//! labels, a friend related to themselves, a second relationship
+//! between the same two friends and a `status` other than "active",
+//! "ended" or "deceased" are answered with 422; a second user
+//! relationship of the same type with a friend with 409.
+//!
+//! ## Kinship
+//!
+//! `/kinship` answers with the friend's stored relationships and the
+//! family relationships derived from the caller's whole family graph
+//! ("Ann is parent of Bob, Bob is parent of Cat" makes Ann Cat's
+//! grandparent), each with the rule and stored relationships behind it.
+//! Derived relationships are never stored.
+//!
+//! ## Clearing Fields
+//!
+//! `"ended_on": null` in a `PATCH` clears the end date of a relationship
+//! that ended, reopening it, and `"b_to_a": null` clears the reverse
+//! label, making the relationship symmetric. Leaving either out keeps it
+//! as it is.
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use time::Date;
+use uuid::Uuid;
+
+use crate::kinship::KinshipReport;
+use crate::models::{FriendRelationship, UserFriendRelationship};
+use crate::repositories::{
+    CreateUserFriendRelationshipInput, UpdateFriendRelationshipInput,
+    UpdateUserFriendRelationshipInput,
+};
+use crate::services::{NewRelationship, RelationshipService};
+
+use super::auth::AuthenticatedUser;
+use super::error::ApiError;
+use super::friends::ProfileQuery;
+use super::{AppState, if_match, present, versioned};
+
+/// Body of `POST /relationships`.
+#[derive(Deserialize)]
+pub struct RelationshipRequest {
+    pub friend_a_id: Uuid,
+    pub friend_b_id: Uuid,
+    pub a_to_b: String,
+    pub b_to_a: Option<String>,
+    pub started_on: Option<Date>,
+    pub ended_on: Option<Date>,
+    /// Defaults to "active"
+    pub status: Option<String>,
+}
+
+/// Body of `PATCH /relationships/<id>`. Fields left out are kept as they
+/// are.
+#[derive(Deserialize)]
+pub struct RelationshipPatch {
+    pub a_to_b: Option<String>,
+    /// `null` clears the reverse label, making the relationship symmetric
+    #[serde(default, deserialize_with = "present")]
+    pub b_to_a: Option<Option<String>>,
+    pub started_on: Option<Date>,
+    /// `null` clears the end date, reopening the relationship
+    #[serde(default, deserialize_with = "present")]
+    pub ended_on: Option<Option<Date>>,
+    pub status: Option<String>,
+    /// When a new `status` took effect; defaults to today
+    pub status_effective_on: Option<Date>,
+}
+
+/// Body of `POST /friends/<id>/user-relationships`.
+#[derive(Deserialize)]
+pub struct UserRelationshipRequest {
+    /// e.g. "coworker", "neighbor"
+    pub relationship_type: String,
+    pub started_on: Option<Date>,
+    pub ended_on: Option<Date>,
+    /// Defaults to "active"
+    pub status: Option<String>,
+}
+
+/// Body of `PATCH /user-relationships/<id>`. Fields left out are kept as
+/// they are.
+#[derive(Deserialize)]
+pub struct UserRelationshipPatch {
+    pub relationship_type: Option<String>,
+    pub started_on: Option<Date>,
+    /// `null` clears the end date, reopening the relationship
+    #[serde(default, deserialize_with = "present")]
+    pub ended_on: Option<Option<Date>>,
+    pub status: Option<String>,
+    /// When a new `status` took effect; defaults to today
+    pub status_effective_on: Option<Date>,
+}
+
+/// The friend's relationships with the caller's other friends.
+pub async fn list(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(friend_id): Path<Uuid>,
+    Query(query): Query<ProfileQuery>,
+) -> Result<Json<Vec<FriendRelationship>>, ApiError> {
+    let relationships = RelationshipService::new(state.ctx.clone())
+        .list(user.id, friend_id, query.timeline())
+        .await?;
+    Ok(Json(relationships))
+}
+
+/// The friend's stored relationships and the family relationships they
+/// imply.
+pub async fn kinship(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(friend_id): Path<Uuid>,
+) -> Result<Json<KinshipReport>, ApiError> {
+    let report = RelationshipService::new(state.ctx.clone())
+        .kinship(user.id, friend_id)
+        .await?;
+    Ok(Json(report))
+}
+
+/// Relate two of the caller's friends.
+pub async fn create(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<RelationshipRequest>,
+) -> Result<Response, ApiError> {
+    let relationship = RelationshipService::new(state.ctx.clone())
+        .create(
+            user.id,
+            NewRelationship {
+                friend_a_id: request.friend_a_id,
+                friend_b_id: request.friend_b_id,
+                a_to_b: request.a_to_b,
+                b_to_a: request.b_to_a,
+                started_on: request.started_on,
+                ended_on: request.ended_on,
+                status: request.status,
+            },
+        )
+        .await?;
+    Ok((
+        StatusCode::CREATED,
+        versioned(relationship.version, relationship),
+    )
+        .into_response())
+}
+
+/// The relationship.
+pub async fn get(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(relationship_id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let relationship = RelationshipService::new(state.ctx.clone())
+        .get(user.id, relationship_id)
+        .await?;
+    Ok(versioned(relationship.version, relationship))
+}
+
+/// Change the relationship, if `If-Match` still holds.
+pub async fn update(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(relationship_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(patch): Json<RelationshipPatch>,
+) -> Result<Response, ApiError> {
+    let relationships = RelationshipService::new(state.ctx.clone());
+    let relationship = relationships.get(user.id, relationship_id).await?;
+    let expected_version = if_match(&headers, relationship.version)?;
+
+    let relationship = relationships
+        .update(
+            user.id,
+            relationship_id,
+            expected_version,
+            UpdateFriendRelationshipInput {
+                a_to_b: patch.a_to_b,
+                b_to_a: patch.b_to_a,
+                started_on: patch.started_on,
+                ended_on: patch.ended_on,
+                status: patch.status,
+                status_effective_on: patch.status_effective_on,
+            },
+        )
+        .await?;
+    Ok(versioned(relationship.version, relationship))
+}
+
+/// Delete the relationship.
+pub async fn delete(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(relationship_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    RelationshipService::new(state.ctx.clone())
+        .delete(user.id, relationship_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// How the caller knows the friend.
+pub async fn list_user_relationships(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(friend_id): Path<Uuid>,
+    Query(query): Query<ProfileQuery>,
+) -> Result<Json<Vec<UserFriendRelationship>>, ApiError> {
+    let relationships = RelationshipService::new(state.ctx.clone())
+        .list_user_relationships(user.id, friend_id, query.timeline())
+        .await?;
+    Ok(Json(relationships))
+}
+
+/// Add a way the caller knows the friend.
+pub async fn create_user_relationship(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(friend_id): Path<Uuid>,
+    Json(request): Json<UserRelationshipRequest>,
+) -> Result<Response, ApiError> {
+    let relationship = RelationshipService::new(state.ctx.clone())
+        .create_user_relationship(
+            user.id,
+            CreateUserFriendRelationshipInput {
+                friend_id,
+                relationship_type: request.relationship_type,
+                started_on: request.started_on,
+                ended_on: request.ended_on,
+                status: request.status,
+            },
+        )
+        .await?;
+    Ok((
+        StatusCode::CREATED,
+        versioned(relationship.version, relationship),
+    )
+        .into_response())
+}
+
+/// Change a way the caller knows a friend, if `If-Match` still holds.
+pub async fn update_user_relationship(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(relationship_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(patch): Json<UserRelationshipPatch>,
+) -> Result<Response, ApiError> {
+    let relationships = RelationshipService::new(state.ctx.clone());
+    let relationship = relationships
+        .get_user_relationship(user.id, relationship_id)
+        .await?;
+    let expected_version = if_match(&headers, relationship.version)?;
+
+    let relationship = relationships
+        .update_user_relationship(
+            user.id,
+            relationship_id,
+            expected_version,
+            UpdateUserFriendRelationshipInput {
+                relationship_type: patch.relationship_type,
+                started_on: patch.started_on,
+                ended_on: patch.ended_on,
+                status: patch.status,
+                status_effective_on: patch.status_effective_on,
+            },
+        )
+        .await?;
+    Ok(versioned(relationship.version, relationship))
+}
+
+/// Delete a way the caller knows a friend.
+pub async fn delete_user_relationship(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(relationship_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    RelationshipService::new(state.ctx.clone())
+        .delete_user_relationship(user.id, relationship_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,42 @@
+//! # Undo Endpoints
+//!
+//! ```text
+//! POST /undo    revert the caller's most recent change
+//! POST /redo    re-apply the change undone last
+//! ```
+//!
+//! Both need Basic auth and answer with the step they applied. A change
+//! is everything one request (or import, or CardDAV upload) did, and it is
+//! reverted or re-applied as a whole or not at all.
+//!
+//! ## Status Codes
+//!
+//! - 404 if there is nothing to undo (or redo)
+//! - 422 if something has changed the same rows since; nothing is changed
+
+use axum::Json;
+use axum::extract::State;
+
+use crate::repositories::{UndoRepository, UndoStep};
+
+use super::AppState;
+use super::auth::AuthenticatedUser;
+use super::error::ApiError;
+
+/// Revert the most recent change.
+pub async fn undo(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<UndoStep>, ApiError> {
+    let step = UndoRepository::new(state.ctx.clone()).undo(user.id).await?;
+    Ok(Json(step))
+}
+
+/// Re-apply the change undone last.
+pub async fn redo(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<UndoStep>, ApiError> {
+    let step = UndoRepository::new(state.ctx.clone()).redo(user.id).await?;
+    Ok(Json(step))
+}
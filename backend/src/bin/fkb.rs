@@ -0,0 +1,573 @@
+//! # fkb
+//!
+//! Command-line tools for the Friend Knowledgebase.
+//!
+//! ```text
+//! fkb export --user ada@example.com > ada.json
+//! fkb import ada.json
+//! fkb import --user someone-else@example.com ada.json
+//! fkb export-vault --user ada@example.com --dir ~/Notes/People
+//! fkb export-site --user ada@example.com --dir ~/friends-site
+//! fkb export-graph --user ada@example.com --format graphml --group Family
+//! fkb sync-vault --user ada@example.com --dir ~/Notes/People --watch
+//! fkb calendar-feed --user ada@example.com --alarm-minutes 540
+//! fkb trash --user ada@example.com
+//! fkb restore --user ada@example.com 0192f3c4-...
+//! fkb purge-trash --days 30
+//! fkb duplicates --user ada@example.com
+//! fkb merge --user ada@example.com 0192f3c4-... 0192f3c5-... --attribute phone=other
+//! fkb doctor --user ada@example.com
+//! ```
+//!
+//! The database is taken from `--database-url` or `DATABASE_URL` (a `.env`
+//! file is read if present).
+
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand, ValueEnum};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use friend_knowledgebase_backend::doctor::Doctor;
+use friend_knowledgebase_backend::duplicates::DuplicateFinder;
+use friend_knowledgebase_backend::interchange::backup::{
+    BackupDocument, BackupExporter, BackupImporter,
+};
+use friend_knowledgebase_backend::interchange::graph::{GraphExporter, GraphFilter, GraphFormat};
+use friend_knowledgebase_backend::interchange::html::{HtmlExporter, write_site};
+use friend_knowledgebase_backend::interchange::markdown::sync::{
+    ConflictResolution, SyncReport, VaultSync,
+};
+use friend_knowledgebase_backend::interchange::markdown::{MarkdownExporter, write_vault};
+use friend_knowledgebase_backend::repositories::{
+    AttributeChoice, CalendarFeedRepository, FriendMergeRepository, FriendRepository,
+    MergeFriendsInput, RepositoryContext, UserRepository,
+};
+
+#[derive(Parser)]
+#[command(name = "fkb", about = "Friend Knowledgebase command-line tools")]
+struct Cli {
+    /// PostgreSQL connection string
+    #[arg(long, env = "DATABASE_URL", global = true, hide_env_values = true)]
+    database_url: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write a user's whole knowledgebase as a JSON backup
+    Export {
+        /// Email of the account to back up
+        #[arg(long)]
+        user: String,
+
+        /// Output file; standard output if omitted
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Write a user's friends and groups as a Markdown vault
+    ExportVault {
+        /// Email of the account to export
+        #[arg(long)]
+        user: String,
+
+        /// Vault folder; created if missing
+        #[arg(long)]
+        dir: PathBuf,
+    },
+
+    /// Write a user's knowledgebase as a static HTML site
+    ExportSite {
+        /// Email of the account to export
+        #[arg(long)]
+        user: String,
+
+        /// Output folder; created if missing
+        #[arg(long)]
+        dir: PathBuf,
+
+        /// Site title; defaults to "<first name>'s friends"
+        #[arg(long)]
+        title: Option<String>,
+    },
+
+    /// Write a user's relationship graph for Graphviz, Gephi, D3, ...
+    ExportGraph {
+        /// Email of the account to export
+        #[arg(long)]
+        user: String,
+
+        #[arg(long, value_enum, default_value = "dot")]
+        format: Format,
+
+        /// Only friends in this group (repeatable)
+        #[arg(long = "group")]
+        groups: Vec<String>,
+
+        /// Only relationships with this label (repeatable)
+        #[arg(long = "type")]
+        relationship_types: Vec<String>,
+
+        /// Output file; standard output if omitted
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Sync a user's knowledgebase with a Markdown vault, both ways
+    SyncVault {
+        /// Email of the account to sync
+        #[arg(long)]
+        user: String,
+
+        /// Vault folder; created if missing
+        #[arg(long)]
+        dir: PathBuf,
+
+        /// Keep running, syncing again every `--interval` seconds
+        #[arg(long)]
+        watch: bool,
+
+        /// Seconds between syncs in watch mode
+        #[arg(long, default_value_t = 5)]
+        interval: u64,
+
+        /// Resolve notes changed on both sides instead of reporting them
+        #[arg(long, value_enum)]
+        prefer: Option<Prefer>,
+    },
+
+    /// Print a user's secret calendar feed URL, creating the feed if needed
+    CalendarFeed {
+        /// Email of the account
+        #[arg(long)]
+        user: String,
+
+        /// Issue a new URL; existing subscriptions stop updating
+        #[arg(long)]
+        rotate: bool,
+
+        /// Remind this many minutes before the start of each day
+        #[arg(long, conflicts_with = "no_alarm")]
+        alarm_minutes: Option<u32>,
+
+        /// Remove reminders from the feed
+        #[arg(long)]
+        no_alarm: bool,
+
+        /// Public address of the server, used to build the URL
+        #[arg(long, env = "FKB_PUBLIC_URL", default_value = "http://localhost:3000")]
+        base_url: String,
+    },
+
+    /// List a user's deleted friends, most recently deleted first
+    Trash {
+        /// Email of the account
+        #[arg(long)]
+        user: String,
+    },
+
+    /// Bring a deleted friend back, with their attributes, groups and
+    /// relationships
+    Restore {
+        /// Email of the account the friend belongs to
+        #[arg(long)]
+        user: String,
+
+        /// ID of the friend, as listed by `fkb trash`
+        friend: Uuid,
+    },
+
+    /// Permanently delete friends that have been in the trash too long,
+    /// across all accounts
+    PurgeTrash {
+        /// Purge friends deleted more than this many days ago
+        #[arg(long, default_value_t = 30)]
+        days: u32,
+    },
+
+    /// List friends that are probably the same person, best match first
+    Duplicates {
+        /// Email of the account
+        #[arg(long)]
+        user: String,
+    },
+
+    /// Merge one friend into another and delete it
+    Merge {
+        /// Email of the account the friends belong to
+        #[arg(long)]
+        user: String,
+
+        /// ID of the friend to keep
+        keep: Uuid,
+
+        /// ID of the friend to merge in
+        other: Uuid,
+
+        /// How to resolve an attribute both friends have, as
+        /// KEY=keep|other|both (default both); repeatable
+        #[arg(long = "attribute", value_parser = parse_attribute_choice)]
+        attributes: Vec<(String, AttributeChoice)>,
+    },
+
+    /// Check stored data for problems, e.g. relationships stored twice;
+    /// fails if any are found
+    Doctor {
+        /// Email of the account to check; every account if omitted
+        #[arg(long)]
+        user: Option<String>,
+    },
+
+    /// Restore a JSON backup into an empty (or new) account
+    Import {
+        /// Backup file; "-" reads standard input
+        file: PathBuf,
+
+        /// Restore into this account instead of the one in the backup
+        #[arg(long)]
+        user: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Dot,
+    Graphml,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Prefer {
+    Vault,
+    Database,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+
+    let database_url = cli
+        .database_url
+        .context("set --database-url or DATABASE_URL")?;
+    let pool = PgPool::connect(&database_url)
+        .await
+        .context("connecting to the database")?;
+    let ctx = RepositoryContext::new(pool);
+
+    match cli.command {
+        Command::Export { user, output } => {
+            let json = BackupExporter::new(ctx).export(&user).await?.to_json()?;
+            match output {
+                Some(path) => std::fs::write(&path, json)
+                    .with_context(|| format!("writing {}", path.display()))?,
+                None => std::io::stdout().write_all(json.as_bytes())?,
+            }
+        }
+        Command::ExportVault { user, dir } => {
+            let account = UserRepository::new(ctx.clone())
+                .find_by_email(&user)
+                .await?
+                .with_context(|| format!("no account with email {user}"))?;
+            let notes = MarkdownExporter::new(ctx).export(account.id).await?;
+            write_vault(&dir, &notes).with_context(|| format!("writing {}", dir.display()))?;
+            eprintln!("Wrote {} notes to {}", notes.len(), dir.display());
+        }
+        Command::ExportSite { user, dir, title } => {
+            let account = UserRepository::new(ctx.clone())
+                .find_by_email(&user)
+                .await?
+                .with_context(|| format!("no account with email {user}"))?;
+            let title = title.unwrap_or_else(|| format!("{}'s friends", account.first_name));
+            let files = HtmlExporter::new(ctx).export(account.id, &title).await?;
+            write_site(&dir, &files).with_context(|| format!("writing {}", dir.display()))?;
+            eprintln!(
+                "Wrote {} files; open {}",
+                files.len(),
+                dir.join("index.html").display()
+            );
+        }
+        Command::ExportGraph {
+            user,
+            format,
+            groups,
+            relationship_types,
+            output,
+        } => {
+            let account = UserRepository::new(ctx.clone())
+                .find_by_email(&user)
+                .await?
+                .with_context(|| format!("no account with email {user}"))?;
+            let format = match format {
+                Format::Dot => GraphFormat::Dot,
+                Format::Graphml => GraphFormat::GraphMl,
+                Format::Json => GraphFormat::Json,
+            };
+            let filter = GraphFilter {
+                groups,
+                relationship_types,
+            };
+            let graph = GraphExporter::new(ctx)
+                .export(account.id, format, &filter)
+                .await?;
+            match output {
+                Some(path) => std::fs::write(&path, graph)
+                    .with_context(|| format!("writing {}", path.display()))?,
+                None => std::io::stdout().write_all(graph.as_bytes())?,
+            }
+        }
+        Command::SyncVault {
+            user,
+            dir,
+            watch,
+            interval,
+            prefer,
+        } => {
+            let account = UserRepository::new(ctx.clone())
+                .find_by_email(&user)
+                .await?
+                .with_context(|| format!("no account with email {user}"))?;
+            let resolution = match prefer {
+                None => ConflictResolution::Report,
+                Some(Prefer::Vault) => ConflictResolution::PreferVault,
+                Some(Prefer::Database) => ConflictResolution::PreferDatabase,
+            };
+            std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+
+            let sync = VaultSync::new(ctx);
+            loop {
+                let report = sync.sync(account.id, &dir, resolution).await?;
+                print_sync_report(&report);
+                if !watch {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_secs(interval)).await;
+            }
+        }
+        Command::CalendarFeed {
+            user,
+            rotate,
+            alarm_minutes,
+            no_alarm,
+            base_url,
+        } => {
+            let account = UserRepository::new(ctx.clone())
+                .find_by_email(&user)
+                .await?
+                .with_context(|| format!("no account with email {user}"))?;
+            let feeds = CalendarFeedRepository::new(ctx);
+            let mut feed = feeds.get_or_create(account.id).await?;
+            if rotate {
+                feed = feeds.rotate_token(account.id).await?;
+            }
+            if let Some(minutes) = alarm_minutes {
+                let minutes = i32::try_from(minutes).context("--alarm-minutes is too large")?;
+                feed = feeds.set_alarm(account.id, Some(minutes)).await?;
+            } else if no_alarm {
+                feed = feeds.set_alarm(account.id, None).await?;
+            }
+
+            println!(
+                "{}/calendar/{}.ics",
+                base_url.trim_end_matches('/'),
+                feed.token
+            );
+            match feed.alarm_minutes_before {
+                Some(minutes) => eprintln!("Reminders {minutes} minutes before each day"),
+                None => eprintln!("No reminders"),
+            }
+        }
+        Command::Trash { user } => {
+            let account = UserRepository::new(ctx.clone())
+                .find_by_email(&user)
+                .await?
+                .with_context(|| format!("no account with email {user}"))?;
+            let trash = FriendRepository::new(ctx).list_trash(account.id).await?;
+            for trashed in &trash {
+                let friend = &trashed.friend;
+                println!(
+                    "{}  {}  {} {}",
+                    trashed.deleted_at.date(),
+                    friend.id,
+                    friend.first_name,
+                    friend.last_name.as_deref().unwrap_or("")
+                );
+            }
+            eprintln!("{} friends in the trash", trash.len());
+        }
+        Command::Restore { user, friend } => {
+            let account = UserRepository::new(ctx.clone())
+                .find_by_email(&user)
+                .await?
+                .with_context(|| format!("no account with email {user}"))?;
+            let friends = FriendRepository::new(ctx);
+            let in_trash = friends
+                .list_trash(account.id)
+                .await?
+                .iter()
+                .any(|trashed| trashed.friend.id == friend);
+            anyhow::ensure!(in_trash, "{user} has no friend {friend} in the trash");
+
+            let restored = friends.restore(friend).await?;
+            eprintln!("Restored {} ({})", restored.first_name, restored.id);
+        }
+        Command::PurgeTrash { days } => {
+            let cutoff = OffsetDateTime::now_utc() - time::Duration::days(days.into());
+            let purged = FriendRepository::new(ctx)
+                .purge_trashed_before(cutoff)
+                .await?;
+            eprintln!("Purged {purged} friends deleted more than {days} days ago");
+        }
+        Command::Duplicates { user } => {
+            let account = UserRepository::new(ctx.clone())
+                .find_by_email(&user)
+                .await?
+                .with_context(|| format!("no account with email {user}"))?;
+            let candidates = DuplicateFinder::new(ctx).find(account.id).await?;
+            for pair in &candidates {
+                println!(
+                    "{:.2}  {}  {}  {} / {}",
+                    pair.score, pair.friend_a, pair.friend_b, pair.name_a, pair.name_b
+                );
+            }
+            eprintln!("{} likely duplicates", candidates.len());
+        }
+        Command::Merge {
+            user,
+            keep,
+            other,
+            attributes,
+        } => {
+            let account = UserRepository::new(ctx.clone())
+                .find_by_email(&user)
+                .await?
+                .with_context(|| format!("no account with email {user}"))?;
+            let owned = FriendRepository::new(ctx.clone())
+                .find_owners(&[keep])
+                .await?
+                .get(&keep)
+                == Some(&account.id);
+            anyhow::ensure!(owned, "{user} has no friend {keep}");
+
+            let report = FriendMergeRepository::new(ctx)
+                .merge(MergeFriendsInput {
+                    keep_id: keep,
+                    other_id: other,
+                    attribute_choices: attributes.into_iter().collect(),
+                })
+                .await?;
+            eprintln!(
+                "Merged into {} ({}): {} attributes moved, {} replaced, {} dropped; \
+                 {} groups added; {} relationships moved, {} dropped",
+                report.friend.first_name,
+                report.friend.id,
+                report.attributes_moved,
+                report.attributes_replaced,
+                report.attributes_dropped,
+                report.groups_added,
+                report.relationships_moved + report.user_relationships_moved,
+                report.relationships_dropped + report.user_relationships_dropped
+            );
+        }
+        Command::Doctor { user } => {
+            let doctor = Doctor::new(ctx.clone());
+            let report = match user {
+                Some(user) => {
+                    let account = UserRepository::new(ctx)
+                        .find_by_email(&user)
+                        .await?
+                        .with_context(|| format!("no account with email {user}"))?;
+                    doctor.check_user(account.id).await?
+                }
+                None => doctor.check_all().await?,
+            };
+            let problems = report
+                .relationship_problems
+                .iter()
+                .chain(&report.user_relationship_problems);
+            for problem in problems.clone() {
+                println!("{}  {}", problem.relationship_id, problem.issue);
+            }
+            eprintln!(
+                "Checked {} relationships and {} user relationships",
+                report.relationships_checked, report.user_relationships_checked
+            );
+            anyhow::ensure!(report.is_healthy(), "{} problems found", problems.count());
+        }
+        Command::Import { file, user } => {
+            let input = read_input(&file)?;
+            let document = BackupDocument::from_json(&input)?;
+            let report = BackupImporter::new(ctx)
+                .restore(&document, user.as_deref())
+                .await?;
+
+            eprintln!(
+                "Restored {} friends, {} groups and {} relationships into {}{}",
+                report.friends,
+                report.groups,
+                report.relationships,
+                report.user_id,
+                if report.created_account {
+                    " (new account; reset its password before logging in)"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Print what a sync did, one line per note.
+fn print_sync_report(report: &SyncReport) {
+    let sections = [
+        ("imported", &report.imported),
+        ("created", &report.created),
+        ("written", &report.written),
+        ("removed", &report.removed),
+    ];
+    for (action, paths) in sections {
+        for path in paths {
+            eprintln!("{action:>9}  {path}");
+        }
+    }
+    for conflict in &report.conflicts {
+        eprintln!(" conflict  {}: {}", conflict.path, conflict.reason);
+    }
+}
+
+/// Parse a `--attribute KEY=CHOICE` argument.
+fn parse_attribute_choice(arg: &str) -> Result<(String, AttributeChoice), String> {
+    let (key, choice) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=CHOICE, got {arg:?}"))?;
+    let choice = match choice {
+        "keep" => AttributeChoice::Keep,
+        "other" => AttributeChoice::Other,
+        "both" => AttributeChoice::Both,
+        _ => {
+            return Err(format!(
+                "choice must be keep, other or both, not {choice:?}"
+            ));
+        }
+    };
+    Ok((key.to_string(), choice))
+}
+
+/// Read a file, or standard input for "-".
+fn read_input(path: &PathBuf) -> anyhow::Result<String> {
+    if path.as_os_str() == "-" {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        return Ok(input);
+    }
+    std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))
+}
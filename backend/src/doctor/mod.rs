@@ -0,0 +1,116 @@
+//! # Doctor Module
+//!
+//! Consistency checks for data already in the database.
+//!
+//! The repositories validate new writes (see `validation`), but rows
+//! created before those checks existed (or inserted by hand) can still be
+//! inconsistent. The
+//! `Doctor` scans stored data and reports every problem it finds without
+//! changing anything.
+//!
+//! ## Relationship Checks
+//!
+//! - Self-relationships (`friend_a_id == friend_b_id`)
+//! - Contradictory labels (A "parent of" B and B "parent of" A)
+//! - Duplicate pairs stored in both directions
+//! - Friends owned by a different user than the relationship
+//! - The user knowing a friend the same way twice (two "coworker" user
+//!   relationships with one friend)
+
+pub mod relationships;
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::models::{FriendRelationship, UserFriendRelationship};
+use crate::repositories::{
+    FriendRelationshipRepository, FriendRepository, RepositoryContext, RepositoryError,
+    UserFriendRelationshipRepository,
+};
+
+pub use crate::validation::RelationshipIssue;
+pub use relationships::{RelationshipProblem, diagnose_user_relationships};
+
+/// Result of a doctor scan.
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    /// Number of relationships that were checked
+    pub relationships_checked: usize,
+    /// Every problem found, grouped by relationship in scan order
+    pub relationship_problems: Vec<RelationshipProblem>,
+    /// Number of user relationships that were checked
+    pub user_relationships_checked: usize,
+    /// Every problem found with user relationships
+    pub user_relationship_problems: Vec<RelationshipProblem>,
+}
+
+impl DoctorReport {
+    /// `true` if the scan found nothing wrong.
+    pub fn is_healthy(&self) -> bool {
+        self.relationship_problems.is_empty() && self.user_relationship_problems.is_empty()
+    }
+}
+
+/// Full-scan consistency checker.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let doctor = Doctor::new(ctx.clone());
+/// let report = doctor.check_user(user_id).await?;
+///
+/// for problem in &report.relationship_problems {
+///     println!("{}: {}", problem.relationship_id, problem.issue);
+/// }
+/// ```
+pub struct Doctor {
+    friends: FriendRepository,
+    relationships: FriendRelationshipRepository,
+    user_relationships: UserFriendRelationshipRepository,
+}
+
+impl Doctor {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            friends: FriendRepository::new(ctx.clone()),
+            relationships: FriendRelationshipRepository::new(ctx.clone()),
+            user_relationships: UserFriendRelationshipRepository::new(ctx),
+        }
+    }
+
+    /// Check all data belonging to one user.
+    pub async fn check_user(&self, user_id: Uuid) -> Result<DoctorReport, RepositoryError> {
+        let relationships = self.relationships.list_by_user(user_id).await?;
+        let user_relationships = self.user_relationships.list_by_user(user_id).await?;
+        self.check(relationships, user_relationships).await
+    }
+
+    /// Check all data in the database, across every user.
+    pub async fn check_all(&self) -> Result<DoctorReport, RepositoryError> {
+        let relationships = self.relationships.list_all().await?;
+        let user_relationships = self.user_relationships.list_all().await?;
+        self.check(relationships, user_relationships).await
+    }
+
+    async fn check(
+        &self,
+        relationships: Vec<FriendRelationship>,
+        user_relationships: Vec<UserFriendRelationship>,
+    ) -> Result<DoctorReport, RepositoryError> {
+        let mut friend_ids: Vec<Uuid> = relationships
+            .iter()
+            .flat_map(|r| [r.friend_a_id, r.friend_b_id])
+            .collect();
+        friend_ids.sort();
+        friend_ids.dedup();
+
+        let owners = self.friends.find_owners(&friend_ids).await?;
+
+        Ok(DoctorReport {
+            relationships_checked: relationships.len(),
+            relationship_problems: relationships::diagnose(&relationships, &owners),
+            user_relationships_checked: user_relationships.len(),
+            user_relationship_problems: diagnose_user_relationships(&user_relationships),
+        })
+    }
+}
@@ -0,0 +1,341 @@
+//! # Relationship Consistency Checks
+//!
+//! Full-scan checks for stored `friend_relationships` rows. The per-row
+//! rules come from `validation::relationships`, which the repository also
+//! applies to new writes; what only shows across rows (duplicate and
+//! conflicting pairs) is worked out here, along with repeated
+//! `user_friend_relationships` types.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::kinship::Kinship;
+use crate::models::{FriendRelationship, UserFriendRelationship};
+use crate::validation::relationships::{
+    RelationshipIssue, check_ownership, check_row, family_kinds,
+};
+
+/// A problem found on a stored relationship.
+#[derive(Debug, Clone, Serialize)]
+pub struct RelationshipProblem {
+    pub relationship_id: Uuid,
+    #[serde(flatten)]
+    pub issue: RelationshipIssue,
+}
+
+/// Run every check over a set of stored relationships.
+///
+/// # Duplicates
+///
+/// Rows are grouped by their unordered friend pair. Within a group the
+/// oldest row is treated as the original; every later row is reported as
+/// a duplicate of it, and additionally as a conflict if it asserts a
+/// different family relationship.
+///
+/// # Arguments
+///
+/// * `relationships` - The rows to check
+/// * `owners` - Map of friend ID → owning user ID for every friend referenced
+pub fn diagnose(
+    relationships: &[FriendRelationship],
+    owners: &HashMap<Uuid, Uuid>,
+) -> Vec<RelationshipProblem> {
+    let mut problems = Vec::new();
+    let mut push = |relationship_id: Uuid, issue: RelationshipIssue| {
+        problems.push(RelationshipProblem {
+            relationship_id,
+            issue,
+        });
+    };
+
+    let mut by_pair: HashMap<(Uuid, Uuid), Vec<&FriendRelationship>> = HashMap::new();
+
+    for relationship in relationships {
+        let (a, b) = (relationship.friend_a_id, relationship.friend_b_id);
+
+        for issue in check_row(a, b, &relationship.a_to_b, relationship.b_to_a.as_deref()) {
+            push(relationship.id, issue);
+        }
+        for issue in check_ownership(relationship.user_id, [a, b], owners) {
+            push(relationship.id, issue);
+        }
+
+        by_pair
+            .entry((a.min(b), a.max(b)))
+            .or_default()
+            .push(relationship);
+    }
+
+    let mut pairs: Vec<_> = by_pair
+        .into_iter()
+        .filter(|(_, rows)| rows.len() > 1)
+        .collect();
+    pairs.sort_by_key(|(pair, _)| *pair);
+
+    for ((low, _), mut rows) in pairs {
+        rows.sort_by_key(|r| (r.created_at, r.id));
+        let original = rows[0];
+        let original_kinds = oriented_kinds(original, low);
+
+        for duplicate in &rows[1..] {
+            push(
+                duplicate.id,
+                RelationshipIssue::DuplicatePair {
+                    duplicate_of: original.id,
+                },
+            );
+
+            let kinds = oriented_kinds(duplicate, low);
+            let conflicts = |a: Option<Kinship>, b: Option<Kinship>| matches!((a, b), (Some(a), Some(b)) if a != b);
+            if conflicts(kinds.0, original_kinds.0) || conflicts(kinds.1, original_kinds.1) {
+                push(
+                    duplicate.id,
+                    RelationshipIssue::ConflictingPair {
+                        conflicts_with: original.id,
+                    },
+                );
+            }
+        }
+    }
+
+    problems
+}
+
+/// Find user relationships repeating another's type with the same friend.
+///
+/// The oldest of each `(friend, type)` is treated as the original; every
+/// later one is reported as a duplicate of it.
+pub fn diagnose_user_relationships(
+    relationships: &[UserFriendRelationship],
+) -> Vec<RelationshipProblem> {
+    let mut by_type: HashMap<(Uuid, &str), Vec<&UserFriendRelationship>> = HashMap::new();
+    for relationship in relationships {
+        by_type
+            .entry((relationship.friend_id, &relationship.relationship_type))
+            .or_default()
+            .push(relationship);
+    }
+
+    let mut repeated: Vec<_> = by_type
+        .into_values()
+        .filter(|rows| rows.len() > 1)
+        .collect();
+    for rows in &mut repeated {
+        rows.sort_by_key(|r| (r.created_at, r.id));
+    }
+    repeated.sort_by_key(|rows| (rows[0].created_at, rows[0].id));
+
+    repeated
+        .into_iter()
+        .flat_map(|rows| {
+            let original = rows[0].id;
+            rows.into_iter()
+                .skip(1)
+                .map(move |duplicate| RelationshipProblem {
+                    relationship_id: duplicate.id,
+                    issue: RelationshipIssue::DuplicateType {
+                        duplicate_of: original,
+                    },
+                })
+        })
+        .collect()
+}
+
+/// Family kinds of a row, oriented so the first element is what `from`
+/// is to the other friend.
+fn oriented_kinds(
+    relationship: &FriendRelationship,
+    from: Uuid,
+) -> (Option<Kinship>, Option<Kinship>) {
+    let (forward, reverse) = family_kinds(&relationship.a_to_b, relationship.b_to_a.as_deref());
+    if relationship.friend_a_id == from {
+        (forward, reverse)
+    } else {
+        (reverse, forward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::*;
+
+    const USER: Uuid = Uuid::from_u128(10);
+    const ADA: Uuid = Uuid::from_u128(1);
+    const BOB: Uuid = Uuid::from_u128(2);
+
+    /// A relationship created `age` seconds after the epoch.
+    fn relationship(
+        id: u128,
+        (a, b): (Uuid, Uuid),
+        a_to_b: &str,
+        b_to_a: Option<&str>,
+        age: i64,
+    ) -> FriendRelationship {
+        FriendRelationship {
+            id: Uuid::from_u128(id),
+            user_id: USER,
+            friend_a_id: a,
+            friend_b_id: b,
+            a_to_b: a_to_b.to_string(),
+            b_to_a: b_to_a.map(str::to_string),
+            started_on: None,
+            ended_on: None,
+            status: "active".to_string(),
+            created_at: OffsetDateTime::from_unix_timestamp(age).unwrap(),
+            updated_at: None,
+            version: 1,
+        }
+    }
+
+    fn owners() -> HashMap<Uuid, Uuid> {
+        HashMap::from([(ADA, USER), (BOB, USER)])
+    }
+
+    fn issues(problems: &[RelationshipProblem]) -> Vec<(Uuid, RelationshipIssue)> {
+        problems
+            .iter()
+            .map(|p| (p.relationship_id, p.issue.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn consistent_rows_are_healthy() {
+        let rows = [relationship(
+            100,
+            (ADA, BOB),
+            "parent of",
+            Some("child of"),
+            0,
+        )];
+        assert!(diagnose(&rows, &owners()).is_empty());
+    }
+
+    #[test]
+    fn row_checks_are_reported_per_row() {
+        let stranger = Uuid::from_u128(3);
+        let rows = [
+            relationship(100, (ADA, ADA), "friend of", None, 0),
+            relationship(101, (ADA, stranger), "parent of", None, 0),
+        ];
+        assert_eq!(
+            issues(&diagnose(&rows, &owners())),
+            vec![
+                (Uuid::from_u128(100), RelationshipIssue::SelfRelationship),
+                (
+                    Uuid::from_u128(101),
+                    RelationshipIssue::ContradictoryLabels {
+                        a_to_b: "parent of".to_string(),
+                        b_to_a: None,
+                    }
+                ),
+                (
+                    Uuid::from_u128(101),
+                    RelationshipIssue::ForeignFriend {
+                        friend_id: stranger,
+                        owner_id: None,
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn later_rows_of_a_pair_are_duplicates_in_either_direction() {
+        let original = Uuid::from_u128(100);
+        let rows = [
+            relationship(101, (BOB, ADA), "friend of", None, 5),
+            relationship(100, (ADA, BOB), "friend of", None, 1),
+        ];
+        assert_eq!(
+            issues(&diagnose(&rows, &owners())),
+            vec![(
+                Uuid::from_u128(101),
+                RelationshipIssue::DuplicatePair {
+                    duplicate_of: original
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn duplicates_asserting_other_family_kinds_conflict() {
+        let original = Uuid::from_u128(100);
+        let rows = [
+            relationship(100, (ADA, BOB), "parent of", Some("child of"), 1),
+            // Bob as Ada's parent: the other way around
+            relationship(101, (BOB, ADA), "parent of", Some("child of"), 2),
+            // The same statement, seen from Bob
+            relationship(102, (BOB, ADA), "child of", Some("parent of"), 3),
+        ];
+        assert_eq!(
+            issues(&diagnose(&rows, &owners())),
+            vec![
+                (
+                    Uuid::from_u128(101),
+                    RelationshipIssue::DuplicatePair {
+                        duplicate_of: original
+                    }
+                ),
+                (
+                    Uuid::from_u128(101),
+                    RelationshipIssue::ConflictingPair {
+                        conflicts_with: original
+                    }
+                ),
+                (
+                    Uuid::from_u128(102),
+                    RelationshipIssue::DuplicatePair {
+                        duplicate_of: original
+                    }
+                ),
+            ]
+        );
+    }
+
+    fn user_relationship(id: u128, friend: Uuid, kind: &str, age: i64) -> UserFriendRelationship {
+        UserFriendRelationship {
+            id: Uuid::from_u128(id),
+            friend_id: friend,
+            relationship_type: kind.to_string(),
+            started_on: None,
+            ended_on: None,
+            status: "active".to_string(),
+            created_at: OffsetDateTime::from_unix_timestamp(age).unwrap(),
+            updated_at: None,
+            version: 1,
+        }
+    }
+
+    #[test]
+    fn repeated_user_relationship_types_are_duplicates_of_the_oldest() {
+        let rows = [
+            user_relationship(101, ADA, "coworker", 5),
+            user_relationship(100, ADA, "coworker", 1),
+            user_relationship(102, ADA, "neighbor", 2),
+            user_relationship(103, BOB, "coworker", 3),
+            user_relationship(104, ADA, "coworker", 9),
+        ];
+        let original = Uuid::from_u128(100);
+        assert_eq!(
+            issues(&diagnose_user_relationships(&rows)),
+            vec![
+                (
+                    Uuid::from_u128(101),
+                    RelationshipIssue::DuplicateType {
+                        duplicate_of: original
+                    }
+                ),
+                (
+                    Uuid::from_u128(104),
+                    RelationshipIssue::DuplicateType {
+                        duplicate_of: original
+                    }
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! # Duplicates Module
+//!
+//! Finds friends that are probably the same person, e.g. "Bob Smith" and
+//! "Robert Smith" after importing the same contact from two sources.
+//! Merging a pair is `FriendMergeRepository`'s job; this module only
+//! suggests candidates and never changes anything.
+//!
+//! ## Scoring
+//!
+//! Every pair of the user's friends is scored on the evidence below,
+//! capped at 1. Pairs scoring at least `MIN_SCORE` are reported.
+//!
+//! | Evidence | Score |
+//! |----------|-------|
+//! | Same first name | 0.4 |
+//! | First name is a nickname of the other ("Bob", "Robert") | 0.35 |
+//! | Similar first name (Jaro-Winkler ≥ 0.88, "Micheal", "Michael") | 0.3 |
+//! | Same last name | 0.3 |
+//! | Similar last name (Jaro-Winkler ≥ 0.88, "Smith", "Smyth") | 0.2 |
+//! | Same birthday | 0.3 |
+//! | Different birthdays | −0.5 |
+//! | A shared email address | 0.25 |
+//! | A shared phone number | 0.2 |
+//!
+//! So a nickname and the same last name are enough on their own, while
+//! shared contact details only ever strengthen a name match: couples and
+//! families share addresses and landlines.
+//!
+//! ## Contact Attributes
+//!
+//! Emails are attributes with `value_type` "email" or a key starting with
+//! "email", compared case-insensitively. Phones are attributes with
+//! `value_type` "phone" or a key starting with "phone" or "tel", compared
+//! by their last nine digits, so "+1 555 010 0199" matches "555-010-0199".
+
+pub mod names;
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::models::{Friend, FriendAttribute};
+use crate::repositories::{
+    FriendAttributeRepository, FriendRepository, RepositoryContext, RepositoryError,
+};
+
+pub use names::NameMatch;
+
+/// Lowest score reported as a likely duplicate.
+pub const MIN_SCORE: f64 = 0.6;
+
+/// Lowest Jaro-Winkler similarity for two names to count as similar.
+const SIMILAR_NAME: f64 = 0.88;
+
+/// One piece of evidence that two friends are the same person.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MatchReason {
+    SameFirstName,
+    Nickname,
+    SimilarFirstName {
+        similarity: f64,
+    },
+    SameLastName,
+    SimilarLastName {
+        similarity: f64,
+    },
+    SameBirthday,
+    /// Counts against the pair
+    DifferentBirthday,
+    SharedEmail {
+        email: String,
+    },
+    SharedPhone {
+        phone: String,
+    },
+}
+
+/// Two friends that are probably the same person.
+///
+/// `friend_a` is the one created first, usually the one to keep.
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateCandidate {
+    pub friend_a: Uuid,
+    pub friend_b: Uuid,
+    /// Display names, to show the pair without another lookup
+    pub name_a: String,
+    pub name_b: String,
+    /// From `MIN_SCORE` to 1
+    pub score: f64,
+    pub reasons: Vec<MatchReason>,
+}
+
+/// A friend with the contact details matching needs.
+struct Profile<'a> {
+    friend: &'a Friend,
+    emails: HashSet<String>,
+    phones: HashSet<String>,
+}
+
+impl<'a> Profile<'a> {
+    fn new(friend: &'a Friend, attributes: &[&FriendAttribute]) -> Self {
+        let mut emails = HashSet::new();
+        let mut phones = HashSet::new();
+        for attribute in attributes {
+            let key = attribute.key.to_lowercase();
+            if attribute.value_type == "email" || key.starts_with("email") {
+                let email = attribute.value.trim().to_lowercase();
+                if !email.is_empty() {
+                    emails.insert(email);
+                }
+            } else if attribute.value_type == "phone"
+                || key.starts_with("phone")
+                || key.starts_with("tel")
+            {
+                let digits: String = attribute
+                    .value
+                    .chars()
+                    .filter(char::is_ascii_digit)
+                    .collect();
+                if digits.len() >= 7 {
+                    phones.insert(digits[digits.len().saturating_sub(9)..].to_string());
+                }
+            }
+        }
+        Self {
+            friend,
+            emails,
+            phones,
+        }
+    }
+
+    fn display_name(&self) -> String {
+        match &self.friend.last_name {
+            Some(last) => format!("{} {}", self.friend.first_name, last),
+            None => self.friend.first_name.clone(),
+        }
+    }
+}
+
+/// Score a pair of friends (see "Scoring" above).
+fn score(a: &Profile, b: &Profile) -> (f64, Vec<MatchReason>) {
+    let mut reasons = Vec::new();
+
+    match names::compare(&a.friend.first_name, &b.friend.first_name) {
+        NameMatch::Same => reasons.push(MatchReason::SameFirstName),
+        NameMatch::Nickname => reasons.push(MatchReason::Nickname),
+        NameMatch::Different(similarity) if similarity >= SIMILAR_NAME => {
+            reasons.push(MatchReason::SimilarFirstName { similarity })
+        }
+        NameMatch::Different(_) => {}
+    }
+
+    if let (Some(x), Some(y)) = (&a.friend.last_name, &b.friend.last_name) {
+        // Nicknames are for first names only
+        match names::compare(x, y) {
+            NameMatch::Same => reasons.push(MatchReason::SameLastName),
+            NameMatch::Different(similarity) if similarity >= SIMILAR_NAME => {
+                reasons.push(MatchReason::SimilarLastName { similarity })
+            }
+            NameMatch::Nickname | NameMatch::Different(_) => {}
+        }
+    }
+
+    if let (Some(x), Some(y)) = (a.friend.date_of_birth, b.friend.date_of_birth) {
+        reasons.push(if x == y {
+            MatchReason::SameBirthday
+        } else {
+            MatchReason::DifferentBirthday
+        });
+    }
+
+    let mut emails: Vec<_> = a.emails.intersection(&b.emails).collect();
+    emails.sort();
+    if let Some(email) = emails.first() {
+        reasons.push(MatchReason::SharedEmail {
+            email: email.to_string(),
+        });
+    }
+    let mut phones: Vec<_> = a.phones.intersection(&b.phones).collect();
+    phones.sort();
+    if let Some(phone) = phones.first() {
+        reasons.push(MatchReason::SharedPhone {
+            phone: phone.to_string(),
+        });
+    }
+
+    let total: f64 = reasons
+        .iter()
+        .map(|reason| match reason {
+            MatchReason::SameFirstName => 0.4,
+            MatchReason::Nickname => 0.35,
+            MatchReason::SimilarFirstName { .. } => 0.3,
+            MatchReason::SameLastName => 0.3,
+            MatchReason::SimilarLastName { .. } => 0.2,
+            MatchReason::SameBirthday => 0.3,
+            MatchReason::DifferentBirthday => -0.5,
+            MatchReason::SharedEmail { .. } => 0.25,
+            MatchReason::SharedPhone { .. } => 0.2,
+        })
+        .sum();
+
+    (total.clamp(0.0, 1.0), reasons)
+}
+
+/// Finds likely duplicates among a user's friends.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// for pair in DuplicateFinder::new(ctx.clone()).find(user.id).await? {
+///     println!("{} / {} ({:.2})", pair.name_a, pair.name_b, pair.score);
+/// }
+/// ```
+pub struct DuplicateFinder {
+    friends: FriendRepository,
+    attributes: FriendAttributeRepository,
+}
+
+impl DuplicateFinder {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            friends: FriendRepository::new(ctx.clone()),
+            attributes: FriendAttributeRepository::new(ctx),
+        }
+    }
+
+    /// Every likely duplicate pair among the user's friends (trashed
+    /// friends aside), highest score first.
+    pub async fn find(&self, user_id: Uuid) -> Result<Vec<DuplicateCandidate>, RepositoryError> {
+        let mut friends = self.friends.list_by_user(user_id).await?;
+        friends.sort_by_key(|friend| (friend.created_at, friend.id));
+        let attributes = self.attributes.list_by_user(user_id).await?;
+
+        let mut by_friend: HashMap<Uuid, Vec<&FriendAttribute>> = HashMap::new();
+        for attribute in &attributes {
+            by_friend
+                .entry(attribute.friend_id)
+                .or_default()
+                .push(attribute);
+        }
+        let profiles: Vec<Profile> = friends
+            .iter()
+            .map(|friend| {
+                let attributes = by_friend.get(&friend.id).map(Vec::as_slice).unwrap_or(&[]);
+                Profile::new(friend, attributes)
+            })
+            .collect();
+
+        let mut candidates = Vec::new();
+        for (i, a) in profiles.iter().enumerate() {
+            for b in &profiles[i + 1..] {
+                let (score, reasons) = score(a, b);
+                if score >= MIN_SCORE {
+                    candidates.push(DuplicateCandidate {
+                        friend_a: a.friend.id,
+                        friend_b: b.friend.id,
+                        name_a: a.display_name(),
+                        name_b: b.display_name(),
+                        score,
+                        reasons,
+                    });
+                }
+            }
+        }
+
+        candidates.sort_by(|x, y| y.score.total_cmp(&x.score));
+        Ok(candidates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Date, Month, OffsetDateTime};
+
+    use super::*;
+
+    fn friend(first_name: &str, last_name: Option<&str>, date_of_birth: Option<Date>) -> Friend {
+        Friend {
+            id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            first_name: first_name.to_string(),
+            last_name: last_name.map(str::to_string),
+            date_of_birth,
+            likes: None,
+            dislikes: None,
+            notes: None,
+            created_at: OffsetDateTime::now_utc(),
+            updated_at: None,
+            version: 1,
+        }
+    }
+
+    fn attribute(key: &str, value: &str, value_type: &str) -> FriendAttribute {
+        FriendAttribute {
+            id: Uuid::nil(),
+            friend_id: Uuid::nil(),
+            key: key.to_string(),
+            value: value.to_string(),
+            value_type: value_type.to_string(),
+            created_at: OffsetDateTime::now_utc(),
+            updated_at: None,
+            version: 1,
+        }
+    }
+
+    fn birthday(day: u8) -> Option<Date> {
+        Some(Date::from_calendar_date(1990, Month::February, day).unwrap())
+    }
+
+    /// Score two friends without contact attributes.
+    fn score_names(a: &Friend, b: &Friend) -> (f64, Vec<MatchReason>) {
+        score(&Profile::new(a, &[]), &Profile::new(b, &[]))
+    }
+
+    fn assert_score(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn each_name_match_scores_as_in_the_table() {
+        let cases = [
+            ("Ada", "Ada", 0.4),
+            ("Bob", "Robert", 0.35),
+            ("Micheal", "Michael", 0.3),
+            ("Ada", "Grace", 0.0),
+        ];
+        for (a, b, expected) in cases {
+            let (total, _) = score_names(&friend(a, None, None), &friend(b, None, None));
+            assert_score(total, expected);
+        }
+
+        let (total, reasons) = score_names(
+            &friend("Ada", Some("Smith"), None),
+            &friend("Grace", Some("Smyth"), None),
+        );
+        assert_score(total, 0.2);
+        assert!(matches!(reasons[..], [MatchReason::SimilarLastName { .. }]));
+
+        let (total, _) = score_names(
+            &friend("Ada", Some("Smith"), None),
+            &friend("Grace", Some("smith"), None),
+        );
+        assert_score(total, 0.3);
+    }
+
+    #[test]
+    fn last_names_have_no_nicknames() {
+        let (_, reasons) = score_names(
+            &friend("Ada", Some("Bob"), None),
+            &friend("Grace", Some("Robert"), None),
+        );
+        assert!(reasons.is_empty());
+    }
+
+    #[test]
+    fn a_nickname_and_the_same_last_name_are_enough() {
+        let (total, reasons) = score_names(
+            &friend("Bob", Some("Smith"), None),
+            &friend("Robert", Some("Smith"), None),
+        );
+        assert_score(total, 0.65);
+        assert!(total >= MIN_SCORE);
+        assert_eq!(
+            reasons,
+            vec![MatchReason::Nickname, MatchReason::SameLastName]
+        );
+    }
+
+    #[test]
+    fn birthdays_count_for_or_against() {
+        let (total, _) = score_names(
+            &friend("Ada", None, birthday(28)),
+            &friend("Ada", None, birthday(28)),
+        );
+        assert_score(total, 0.7);
+
+        let (total, reasons) = score_names(
+            &friend("Ada", Some("Lovelace"), birthday(28)),
+            &friend("Ada", Some("Lovelace"), birthday(27)),
+        );
+        assert_score(total, 0.2);
+        assert!(reasons.contains(&MatchReason::DifferentBirthday));
+        assert!(total < MIN_SCORE);
+    }
+
+    #[test]
+    fn the_total_is_clamped() {
+        let (total, _) = score_names(
+            &friend("Ada", Some("Lovelace"), birthday(28)),
+            &friend("Ada", Some("Lovelace"), birthday(28)),
+        );
+        assert_score(total, 1.0);
+
+        let (total, _) = score_names(
+            &friend("Ada", None, birthday(28)),
+            &friend("Grace", None, birthday(27)),
+        );
+        assert_score(total, 0.0);
+    }
+
+    #[test]
+    fn shared_contact_details_only_strengthen_a_name_match() {
+        let ada = friend("Ada", Some("Lovelace"), None);
+        let grace = friend("Grace", Some("Hopper"), None);
+        let a_attributes = [
+            attribute("Email", "Home@Example.com", "text"),
+            attribute("mobile", "+1 555 010 0199", "phone"),
+        ];
+        let b_attributes = [
+            attribute("email work", "home@example.com ", "text"),
+            attribute("tel", "555-010-0199", "text"),
+        ];
+        let a_refs: Vec<_> = a_attributes.iter().collect();
+        let b_refs: Vec<_> = b_attributes.iter().collect();
+
+        let (total, reasons) = score(&Profile::new(&ada, &a_refs), &Profile::new(&grace, &b_refs));
+        assert_score(total, 0.45);
+        assert!(total < MIN_SCORE);
+        assert_eq!(
+            reasons,
+            vec![
+                MatchReason::SharedEmail {
+                    email: "home@example.com".to_string()
+                },
+                MatchReason::SharedPhone {
+                    phone: "550100199".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn short_numbers_are_not_phones() {
+        let ada = friend("Ada", None, None);
+        let attributes = [attribute("phone", "12-34", "phone")];
+        let refs: Vec<_> = attributes.iter().collect();
+        assert!(Profile::new(&ada, &refs).phones.is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+//! # Name Matching
+//!
+//! Compares names the way people write them: "Bob" is a nickname of
+//! "Robert", and "Micheal" / "Michael" or "Smith" / "Smyth" are near-misses,
+//! scored by Jaro-Winkler similarity.
+
+/// Names that are the same person's, one group per formal name.
+///
+/// A name may appear in several groups ("sam" is short for Samuel and
+/// Samantha); two names match if any group has both.
+const NICKNAMES: &[&[&str]] = &[
+    &["robert", "bob", "bobby", "rob", "robbie", "bert"],
+    &["william", "bill", "billy", "will", "willy", "liam"],
+    &["richard", "rick", "ricky", "dick", "rich", "richie"],
+    &["james", "jim", "jimmy", "jamie"],
+    &["john", "jack", "johnny", "jon"],
+    &["jonathan", "jon", "jonny", "nathan"],
+    &["joseph", "joe", "joey"],
+    &["michael", "mike", "mikey", "mick"],
+    &["thomas", "tom", "tommy"],
+    &["charles", "charlie", "chuck"],
+    &["christopher", "chris", "kit"],
+    &["daniel", "dan", "danny"],
+    &["david", "dave", "davey"],
+    &["edward", "ed", "eddie", "ted", "ned"],
+    &["anthony", "tony"],
+    &["andrew", "andy", "drew"],
+    &["matthew", "matt"],
+    &["nicholas", "nick", "nicky"],
+    &["stephen", "steven", "steve"],
+    &["benjamin", "ben", "benny"],
+    &["alexander", "alex", "sandy", "xander"],
+    &["samuel", "sam", "sammy"],
+    &["timothy", "tim", "timmy"],
+    &["peter", "pete"],
+    &["patrick", "pat", "paddy"],
+    &[
+        "elizabeth",
+        "liz",
+        "lizzie",
+        "beth",
+        "betty",
+        "eliza",
+        "libby",
+    ],
+    &["margaret", "maggie", "meg", "peggy", "marge"],
+    &[
+        "katherine",
+        "catherine",
+        "kate",
+        "katie",
+        "kathy",
+        "cathy",
+        "kat",
+    ],
+    &["jennifer", "jen", "jenny"],
+    &["rebecca", "becky", "becca"],
+    &["susan", "sue", "susie"],
+    &["deborah", "debbie", "deb"],
+    &["patricia", "pat", "patty", "trish"],
+    &["alexandra", "alex", "sandra", "sandy", "lexi"],
+    &["victoria", "vicky", "tori"],
+    &["jessica", "jess", "jessie"],
+    &["christina", "christine", "chris", "tina"],
+    &["samantha", "sam", "sammy"],
+    &["abigail", "abby"],
+    &["dorothy", "dot", "dottie"],
+];
+
+/// How two names relate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NameMatch {
+    /// Equal, ignoring case and surrounding punctuation
+    Same,
+    /// One is a nickname of the other, or both of the same name
+    Nickname,
+    /// Neither, with this Jaro-Winkler similarity (0 to 1)
+    Different(f64),
+}
+
+/// Lower-case a name and drop what people add or leave out
+/// inconsistently: surrounding whitespace, periods, quotes.
+pub fn normalize(name: &str) -> String {
+    name.trim()
+        .chars()
+        .filter(|c| !matches!(c, '.' | '"' | '\''))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Compare two names, nicknames included.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// assert_eq!(compare("Bob", "Robert"), NameMatch::Nickname);
+/// ```
+pub fn compare(a: &str, b: &str) -> NameMatch {
+    let (a, b) = (normalize(a), normalize(b));
+    if a == b {
+        return NameMatch::Same;
+    }
+    let nicknames = NICKNAMES
+        .iter()
+        .any(|group| group.contains(&a.as_str()) && group.contains(&b.as_str()));
+    if nicknames {
+        NameMatch::Nickname
+    } else {
+        NameMatch::Different(jaro_winkler(&a, &b))
+    }
+}
+
+/// Jaro-Winkler similarity of two strings: 1 for equal strings, 0 for
+/// nothing in common, with a bonus for a shared prefix of up to four
+/// characters (names tend to differ at the end, not the start).
+pub fn jaro_winkler(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() || b.is_empty() {
+        return if a == b { 1.0 } else { 0.0 };
+    }
+
+    // Characters match if equal and no further apart than this
+    let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut a_matched = vec![false; a.len()];
+    let mut b_matched = vec![false; b.len()];
+    let mut matches = 0;
+    for (i, &c) in a.iter().enumerate() {
+        let start = i.saturating_sub(window);
+        let end = (i + window + 1).min(b.len());
+        for j in start..end {
+            if !b_matched[j] && b[j] == c {
+                a_matched[i] = true;
+                b_matched[j] = true;
+                matches += 1;
+                break;
+            }
+        }
+    }
+    if matches == 0 {
+        return 0.0;
+    }
+
+    // Matched characters that appear in a different order
+    let a_order = a
+        .iter()
+        .zip(&a_matched)
+        .filter(|(_, m)| **m)
+        .map(|(c, _)| c);
+    let b_order = b
+        .iter()
+        .zip(&b_matched)
+        .filter(|(_, m)| **m)
+        .map(|(c, _)| c);
+    let transpositions = a_order.zip(b_order).filter(|(x, y)| x != y).count() / 2;
+
+    let m = matches as f64;
+    let jaro = (m / a.len() as f64 + m / b.len() as f64 + (m - transpositions as f64) / m) / 3.0;
+
+    let prefix = a.iter().zip(&b).take(4).take_while(|(x, y)| x == y).count();
+    jaro + prefix as f64 * 0.1 * (1.0 - jaro)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn similarity(a: &str, b: &str) -> f64 {
+        match compare(a, b) {
+            NameMatch::Different(similarity) => similarity,
+            other => panic!("{a} / {b} compared as {other:?}"),
+        }
+    }
+
+    #[test]
+    fn jaro_winkler_matches_the_reference_values() {
+        // From Winkler's paper
+        assert!((jaro_winkler("martha", "marhta") - 0.961).abs() < 0.001);
+        assert!((jaro_winkler("dwayne", "duane") - 0.84).abs() < 0.001);
+        assert!((jaro_winkler("dixon", "dicksonx") - 0.813).abs() < 0.001);
+    }
+
+    #[test]
+    fn jaro_winkler_bounds() {
+        assert_eq!(jaro_winkler("ada", "ada"), 1.0);
+        assert_eq!(jaro_winkler("", ""), 1.0);
+        assert_eq!(jaro_winkler("ada", ""), 0.0);
+        assert_eq!(jaro_winkler("abc", "xyz"), 0.0);
+    }
+
+    #[test]
+    fn same_ignores_case_and_punctuation() {
+        assert_eq!(compare("Ada", " ada "), NameMatch::Same);
+        assert_eq!(compare("J.R.", "jr"), NameMatch::Same);
+        assert_eq!(compare("O'Brien", "OBrien"), NameMatch::Same);
+    }
+
+    #[test]
+    fn nicknames_match_within_a_group() {
+        assert_eq!(compare("Bob", "Robert"), NameMatch::Nickname);
+        assert_eq!(compare("bobby", "Rob"), NameMatch::Nickname);
+        // "sam" is in two groups
+        assert_eq!(compare("Sam", "Samuel"), NameMatch::Nickname);
+        assert_eq!(compare("Sam", "Samantha"), NameMatch::Nickname);
+        // ... which doesn't make those two the same name
+        assert!(matches!(
+            compare("Samuel", "Samantha"),
+            NameMatch::Different(_)
+        ));
+    }
+
+    #[test]
+    fn near_misses_clear_the_similarity_threshold() {
+        use super::super::SIMILAR_NAME;
+
+        assert!(similarity("Micheal", "Michael") >= SIMILAR_NAME);
+        assert!(similarity("Smith", "Smyth") >= SIMILAR_NAME);
+        assert!(similarity("Ada", "Grace") < SIMILAR_NAME);
+        assert!(similarity("Smith", "Jones") < SIMILAR_NAME);
+    }
+}
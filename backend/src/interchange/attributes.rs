@@ -0,0 +1,74 @@
+//! # Imported Attributes
+//!
+//! Imported fields without a dedicated column become `friend_attributes`.
+//!
+//! ## Repeated Fields
+//!
+//! Since `(friend_id, key)` is unique, a field that appears more than once
+//! (two phone numbers, two occupations) needs distinct keys. Importers
+//! number repeats with a `#n` suffix: `email`, `email#2`, `email#3`.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+/// An attribute read from an import file, before it has a friend to
+/// belong to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportedAttribute {
+    /// Unique key within the friend (see `AttributeKeys`)
+    pub key: String,
+    /// The value as text
+    pub value: String,
+    /// Type hint, e.g. "text", "email", "phone", "date"
+    pub value_type: String,
+}
+
+impl ImportedAttribute {
+    pub fn new(key: String, value: impl Into<String>, value_type: &str) -> Self {
+        Self {
+            key,
+            value: value.into(),
+            value_type: value_type.to_string(),
+        }
+    }
+}
+
+/// Hands out attribute keys that are unique within one friend.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let mut keys = AttributeKeys::default();
+/// assert_eq!(keys.next("email"), "email");
+/// assert_eq!(keys.next("email"), "email#2");
+/// ```
+#[derive(Debug, Default)]
+pub struct AttributeKeys {
+    counts: HashMap<String, usize>,
+}
+
+impl AttributeKeys {
+    /// The next unused key for `base`.
+    pub fn next(&mut self, base: &str) -> String {
+        let count = self.counts.entry(base.to_string()).or_insert(0);
+        *count += 1;
+
+        if *count == 1 {
+            base.to_string()
+        } else {
+            format!("{base}#{count}")
+        }
+    }
+
+    /// Mark `base` as taken without using it, so the next call to `next`
+    /// returns `base#2`.
+    pub fn reserve(&mut self, base: &str) {
+        self.counts.entry(base.to_string()).or_insert(1);
+    }
+}
+
+/// Remove a `#n` repeat suffix from a key or key segment.
+pub fn strip_repeat_suffix(key: &str) -> &str {
+    key.split('#').next().unwrap_or(key)
+}
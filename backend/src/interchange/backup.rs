@@ -0,0 +1,462 @@
+//! # JSON Backup and Restore
+//!
+//! A lossless dump of one user's knowledgebase: every friend (the trash
+//! included), group, membership, attribute, relationship and status
+//! history entry, with UUIDs and timestamps as stored.
+//!
+//! ## Document Shape
+//!
+//! ```json
+//! {
+//!   "format": "fkb-backup",
+//!   "version": 5,
+//!   "exported_at": "...",
+//!   "user": { "id": "...", "email": "...", ... },
+//!   "friends": [...],
+//!   "trashed_friends": [...],
+//!   "groups": [...],
+//!   "friend_groups": [...],
+//!   ...
+//! }
+//! ```
+//!
+//! ## Versioning
+//!
+//! `version` is bumped whenever the shape changes. Older documents are
+//! upgraded on read by running the JSON through `MIGRATIONS` in order
+//! (entry `n` turns version `n + 1` into `n + 2`), so a backup taken today
+//! can still be restored after the schema moves on. Documents from a newer
+//! version than this build are refused rather than guessed at.
+//!
+//! ## Restoring
+//!
+//! A backup restores into an account with no friends and no groups. If no
+//! account has the target email, one is created with the backup's user ID
+//! and a locked password (see `LOCKED_PASSWORD_HASH`), so restoring into
+//! an empty database reproduces the data exactly.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::models::User;
+use crate::repositories::{
+    BackupRepository, RepositoryContext, RepositoryError, RestoredAccount, UserRepository,
+    UserSnapshot,
+};
+
+use super::error::InterchangeError;
+
+/// Value of the `format` field; guards against restoring unrelated JSON.
+pub const FORMAT: &str = "fkb-backup";
+
+/// Version written by this build.
+pub const CURRENT_VERSION: u64 = 5;
+
+/// Password hash for accounts created by a restore. It isn't a valid
+/// bcrypt hash, so nobody can log in until the password is reset.
+pub const LOCKED_PASSWORD_HASH: &str = "!restored";
+
+/// Upgrades a document by one version, in place.
+type Migration = fn(&mut Value) -> Result<(), InterchangeError>;
+
+/// `MIGRATIONS[n]` upgrades version `n + 1` to `n + 2`.
+///
+/// Must have exactly `CURRENT_VERSION - 1` entries.
+const MIGRATIONS: &[Migration] = &[
+    add_row_versions,
+    add_trash,
+    drop_duplicate_user_relationships,
+    drop_duplicate_relationship_pairs,
+];
+
+/// Version 2 added each row's `version` (its optimistic concurrency
+/// counter). Rows from version 1 start over at 1.
+fn add_row_versions(document: &mut Value) -> Result<(), InterchangeError> {
+    for table in [
+        "friends",
+        "groups",
+        "friend_attributes",
+        "friend_relationships",
+        "user_friend_relationships",
+    ] {
+        let Some(rows) = document.get_mut(table).and_then(Value::as_array_mut) else {
+            continue;
+        };
+        for row in rows.iter_mut().filter_map(Value::as_object_mut) {
+            row.entry("version").or_insert(1.into());
+        }
+    }
+    Ok(())
+}
+
+/// Version 3 added `trashed_friends`, the friends in the trash with
+/// their `deleted_at`. Version 2 backups left the trash out entirely, so
+/// theirs is empty.
+fn add_trash(document: &mut Value) -> Result<(), InterchangeError> {
+    if let Some(document) = document.as_object_mut() {
+        document
+            .entry("trashed_friends")
+            .or_insert_with(|| Value::Array(Vec::new()));
+    }
+    Ok(())
+}
+
+/// Version 4 has each user relationship type at most once per friend.
+/// Older backups may repeat one; as in the database migration, the oldest
+/// is kept and the others are dropped with their status history.
+fn drop_duplicate_user_relationships(document: &mut Value) -> Result<(), InterchangeError> {
+    drop_repeats(
+        document,
+        "user_friend_relationships",
+        "user_friend_relationship_status_history",
+        |row| format!("{}|{}", row["friend_id"], row["relationship_type"]),
+    );
+    Ok(())
+}
+
+/// Version 5 relates two friends at most once, in either direction. As
+/// with version 4, the oldest relationship of a pair is kept.
+fn drop_duplicate_relationship_pairs(document: &mut Value) -> Result<(), InterchangeError> {
+    drop_repeats(
+        document,
+        "friend_relationships",
+        "friend_relationship_status_history",
+        |row| {
+            let (a, b) = (
+                row["friend_a_id"].to_string(),
+                row["friend_b_id"].to_string(),
+            );
+            if a < b {
+                format!("{a}|{b}")
+            } else {
+                format!("{b}|{a}")
+            }
+        },
+    );
+    Ok(())
+}
+
+/// Drop the rows of `table` whose `key` an older row already has, and
+/// the entries of `history` that belong to them.
+fn drop_repeats(document: &mut Value, table: &str, history: &str, key: impl Fn(&Value) -> String) {
+    let Some(rows) = document.get_mut(table).and_then(Value::as_array_mut) else {
+        return;
+    };
+
+    let created_at =
+        |row: &Value| serde_json::from_value::<OffsetDateTime>(row["created_at"].clone()).ok();
+    let mut oldest_first: Vec<&Value> = rows.iter().collect();
+    oldest_first.sort_by_key(|row| (created_at(row), row["id"].as_str().unwrap_or_default()));
+
+    let mut seen = HashSet::new();
+    let dropped: HashSet<String> = oldest_first
+        .into_iter()
+        .filter(|row| !seen.insert(key(row)))
+        .filter_map(|row| row["id"].as_str().map(str::to_string))
+        .collect();
+    if dropped.is_empty() {
+        return;
+    }
+
+    let kept = |id: &Value| id.as_str().is_none_or(|id| !dropped.contains(id));
+    rows.retain(|row| kept(&row["id"]));
+    if let Some(entries) = document.get_mut(history).and_then(Value::as_array_mut) {
+        entries.retain(|entry| kept(&entry["relationship_id"]));
+    }
+}
+
+/// The account a backup belongs to. The password hash is deliberately
+/// left out of backups.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupUser {
+    pub id: Uuid,
+    pub first_name: String,
+    pub last_name: String,
+    pub email: String,
+    pub created_at: OffsetDateTime,
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+impl From<&User> for BackupUser {
+    fn from(user: &User) -> Self {
+        Self {
+            id: user.id,
+            first_name: user.first_name.clone(),
+            last_name: user.last_name.clone(),
+            email: user.email.clone(),
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+/// A complete backup document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupDocument {
+    pub format: String,
+    pub version: u64,
+    pub exported_at: OffsetDateTime,
+    pub user: BackupUser,
+    #[serde(flatten)]
+    pub data: UserSnapshot,
+}
+
+impl BackupDocument {
+    /// Serialize as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String, InterchangeError> {
+        serde_json::to_string_pretty(self).map_err(|err| RepositoryError::from(err).into())
+    }
+
+    /// Parse a backup, upgrading older versions first.
+    ///
+    /// # Errors
+    ///
+    /// - `Parse` if the input isn't JSON or doesn't match the format
+    /// - `Unsupported` if it isn't a backup or comes from a newer version
+    pub fn from_json(input: &str) -> Result<Self, InterchangeError> {
+        let mut value: Value = serde_json::from_str(input).map_err(json_error)?;
+
+        if value.get("format").and_then(Value::as_str) != Some(FORMAT) {
+            return Err(InterchangeError::Unsupported(format!(
+                "not an {FORMAT} document"
+            )));
+        }
+        let version = value
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| InterchangeError::parse(1, "missing \"version\""))?;
+        if version == 0 || version > CURRENT_VERSION {
+            return Err(InterchangeError::Unsupported(format!(
+                "backup version {version}; this build reads versions 1 to {CURRENT_VERSION}"
+            )));
+        }
+
+        for migration in &MIGRATIONS[version as usize - 1..] {
+            migration(&mut value)?;
+        }
+        value["version"] = CURRENT_VERSION.into();
+
+        serde_json::from_value(value).map_err(json_error)
+    }
+}
+
+/// Convert a serde_json error, keeping its line number when it has one.
+fn json_error(err: serde_json::Error) -> InterchangeError {
+    InterchangeError::parse(err.line().max(1), err.to_string())
+}
+
+/// Outcome of a restore.
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreReport {
+    /// The account the data was restored into
+    pub user_id: Uuid,
+    /// `true` if the account didn't exist and was created
+    pub created_account: bool,
+    pub friends: usize,
+    pub groups: usize,
+    pub relationships: usize,
+}
+
+/// Writes backups.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let document = BackupExporter::new(ctx.clone()).export("ada@example.com").await?;
+/// std::fs::write("ada.json", document.to_json()?)?;
+/// ```
+pub struct BackupExporter {
+    users: UserRepository,
+    backups: BackupRepository,
+}
+
+impl BackupExporter {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            users: UserRepository::new(ctx.clone()),
+            backups: BackupRepository::new(ctx),
+        }
+    }
+
+    /// Back up the account with the given email.
+    ///
+    /// # Errors
+    ///
+    /// `Repository(NotFound)` if no account has that email.
+    pub async fn export(&self, email: &str) -> Result<BackupDocument, InterchangeError> {
+        let user = self
+            .users
+            .find_by_email(email)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+
+        Ok(BackupDocument {
+            format: FORMAT.to_string(),
+            version: CURRENT_VERSION,
+            exported_at: OffsetDateTime::now_utc(),
+            user: BackupUser::from(&user),
+            data: self.backups.snapshot(user.id).await?,
+        })
+    }
+}
+
+/// Restores backups.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let document = BackupDocument::from_json(&std::fs::read_to_string("ada.json")?)?;
+/// let report = BackupImporter::new(ctx.clone()).restore(&document, None).await?;
+/// ```
+pub struct BackupImporter {
+    ctx: RepositoryContext,
+    users: UserRepository,
+    backups: BackupRepository,
+}
+
+impl BackupImporter {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            users: UserRepository::new(ctx.clone()),
+            backups: BackupRepository::new(ctx.clone()),
+            ctx,
+        }
+    }
+
+    /// Restore a backup in one transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - The backup to restore
+    /// * `email` - Target account; defaults to the backup's own email
+    ///
+    /// # Errors
+    ///
+    /// `Unsupported` if the target account already has friends or groups.
+    pub async fn restore(
+        &self,
+        document: &BackupDocument,
+        email: Option<&str>,
+    ) -> Result<RestoreReport, InterchangeError> {
+        let email = email.unwrap_or(&document.user.email);
+        let existing = self.users.find_by_email(email).await?;
+
+        if let Some(user) = &existing
+            && !self.backups.is_empty(user.id).await?
+        {
+            return Err(InterchangeError::Unsupported(format!(
+                "account {email} already has data; restore into an empty account"
+            )));
+        }
+
+        let mut tx = self.ctx.transaction().await?;
+
+        let user_id = match &existing {
+            Some(user) => user.id,
+            None => {
+                let user = &document.user;
+                self.backups
+                    .restore_account_in(
+                        &mut tx,
+                        RestoredAccount {
+                            id: user.id,
+                            first_name: &user.first_name,
+                            last_name: &user.last_name,
+                            email,
+                            password_hash: LOCKED_PASSWORD_HASH,
+                            created_at: user.created_at,
+                            updated_at: user.updated_at,
+                        },
+                    )
+                    .await?;
+                user.id
+            }
+        };
+
+        self.backups
+            .restore_in(&mut tx, user_id, &document.data)
+            .await?;
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+
+        Ok(RestoreReport {
+            user_id,
+            created_account: existing.is_none(),
+            friends: document.data.friends.len(),
+            groups: document.data.groups.len(),
+            relationships: document.data.friend_relationships.len()
+                + document.data.user_friend_relationships.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn version_4_keeps_the_oldest_user_relationship_of_a_type() {
+        let mut document = json!({
+            "user_friend_relationships": [
+                { "id": "a", "friend_id": "f", "relationship_type": "coworker",
+                  "created_at": "2024-05-01 00:00:00.0 +00:00:00" },
+                { "id": "z", "friend_id": "f", "relationship_type": "coworker",
+                  "created_at": "2023-05-01 00:00:00.0 +00:00:00" },
+                { "id": "c", "friend_id": "f", "relationship_type": "neighbor",
+                  "created_at": "2024-05-01 00:00:00.0 +00:00:00" },
+                { "id": "d", "friend_id": "g", "relationship_type": "coworker",
+                  "created_at": "2025-05-01 00:00:00.0 +00:00:00" }
+            ],
+            "user_friend_relationship_status_history": [
+                { "id": "h1", "relationship_id": "z" },
+                { "id": "h2", "relationship_id": "a" }
+            ]
+        });
+
+        drop_duplicate_user_relationships(&mut document).unwrap();
+
+        assert_eq!(ids(&document, "user_friend_relationships"), ["z", "c", "d"]);
+        assert_eq!(
+            ids(&document, "user_friend_relationship_status_history"),
+            ["h1"]
+        );
+    }
+
+    #[test]
+    fn version_5_keeps_the_oldest_relationship_of_a_pair() {
+        let mut document = json!({
+            "friend_relationships": [
+                { "id": "r1", "friend_a_id": "x", "friend_b_id": "y",
+                  "created_at": "2024-05-01 00:00:00.0 +00:00:00" },
+                { "id": "r2", "friend_a_id": "y", "friend_b_id": "x",
+                  "created_at": "2023-05-01 00:00:00.0 +00:00:00" },
+                { "id": "r3", "friend_a_id": "x", "friend_b_id": "z",
+                  "created_at": "2024-05-01 00:00:00.0 +00:00:00" }
+            ],
+            "friend_relationship_status_history": [
+                { "id": "h1", "relationship_id": "r1" },
+                { "id": "h2", "relationship_id": "r2" }
+            ]
+        });
+
+        drop_duplicate_relationship_pairs(&mut document).unwrap();
+
+        assert_eq!(ids(&document, "friend_relationships"), ["r2", "r3"]);
+        assert_eq!(ids(&document, "friend_relationship_status_history"), ["h2"]);
+    }
+
+    /// The `id`s of a table's rows, in order.
+    fn ids(document: &Value, table: &str) -> Vec<String> {
+        document[table]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| row["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+}
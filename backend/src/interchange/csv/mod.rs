@@ -0,0 +1,445 @@
+//! # CSV Import
+//!
+//! Bulk-loads friends from spreadsheet exports using a column mapping
+//! profile (see `profile`).
+//!
+//! ## Header Row
+//!
+//! Some exports put a preamble above the header (LinkedIn adds a few lines
+//! of notes). The header is the first non-blank row where at least one
+//! cell matches a column in the profile; everything before it is skipped.
+//!
+//! ## All or Nothing
+//!
+//! The import runs in one transaction. Each row is written inside its own
+//! savepoint, so a row the database rejects (e.g., a constraint violation)
+//! is rolled back and reported without aborting the rows around it. The
+//! transaction is only committed once every row has been tried.
+
+pub mod profile;
+pub mod reader;
+
+use serde::Serialize;
+use sqlx::Connection;
+use time::{Date, Month};
+use uuid::Uuid;
+
+use crate::models::{Friend, Group};
+use crate::repositories::{RepositoryContext, RepositoryError};
+
+use super::attributes::{AttributeKeys, ImportedAttribute};
+use super::error::InterchangeError;
+pub use super::imported::RejectedRow;
+use super::imported::{ImportWriter, ImportedFriend, split_full_name};
+use super::merge::{ImportPlan, ImportPlanner, MergeStrategy};
+pub use profile::{ColumnMapping, ColumnTarget, CsvProfile};
+use reader::CsvRecord;
+
+/// Result of a CSV import.
+#[derive(Debug, Clone, Serialize)]
+pub struct CsvImportReport {
+    /// Friends created, in file order
+    pub friends: Vec<Friend>,
+    /// Groups created for names the user didn't have yet
+    pub groups_created: Vec<Group>,
+    /// Every row that was skipped, with the reason
+    pub rejected: Vec<RejectedRow>,
+}
+
+/// One data row after mapping: the friend and its line number, or the
+/// reason it can't be imported.
+pub type MappedRow = Result<(usize, ImportedFriend), RejectedRow>;
+
+/// Parse a CSV file with `profile`.
+///
+/// # Errors
+///
+/// Returns `InterchangeError::Parse` if the file isn't valid CSV, and
+/// `InterchangeError::Unsupported` if no header row matches the profile.
+pub fn parse(input: &str, profile: &CsvProfile) -> Result<Vec<MappedRow>, InterchangeError> {
+    let records = reader::parse(input, profile.delimiter)?;
+
+    let header_index = records
+        .iter()
+        .position(|r| !r.is_blank() && r.fields.iter().any(|f| profile.mapping_for(f).is_some()))
+        .ok_or_else(|| {
+            InterchangeError::Unsupported(format!(
+                "no header row matches the {:?} profile",
+                profile.name
+            ))
+        })?;
+    let header = &records[header_index];
+    let mappings: Vec<Option<&ColumnMapping>> = header
+        .fields
+        .iter()
+        .map(|h| profile.mapping_for(h))
+        .collect();
+
+    Ok(records[header_index + 1..]
+        .iter()
+        .filter(|r| !r.is_blank())
+        .map(|record| {
+            map_row(&header.fields, &mappings, record)
+                .map(|friend| (record.line, friend))
+                .map_err(|reason| RejectedRow {
+                    line: record.line,
+                    reason,
+                })
+        })
+        .collect())
+}
+
+/// Map one data row onto an `ImportedFriend`.
+fn map_row(
+    headers: &[String],
+    mappings: &[Option<&ColumnMapping>],
+    record: &CsvRecord,
+) -> Result<ImportedFriend, String> {
+    if record.fields.len() > headers.len() {
+        return Err(format!(
+            "row has {} fields but the header has {}",
+            record.fields.len(),
+            headers.len()
+        ));
+    }
+
+    let mut friend = ImportedFriend::named("");
+    let mut full_name = None;
+    let mut keys = AttributeKeys::default();
+
+    for ((header, mapping), value) in headers.iter().zip(mappings).zip(&record.fields) {
+        let (Some(mapping), value) = (mapping, value.trim()) else {
+            continue;
+        };
+        if value.is_empty() {
+            continue;
+        }
+
+        // Scalar fields keep the first non-empty value
+        let first = |slot: &mut Option<String>| {
+            slot.get_or_insert_with(|| value.to_string());
+        };
+
+        match &mapping.target {
+            ColumnTarget::FirstName => {
+                if friend.first_name.is_empty() {
+                    friend.first_name = value.to_string();
+                }
+            }
+            ColumnTarget::LastName => first(&mut friend.last_name),
+            ColumnTarget::FullName => first(&mut full_name),
+            ColumnTarget::Likes => first(&mut friend.likes),
+            ColumnTarget::Dislikes => first(&mut friend.dislikes),
+            ColumnTarget::Notes => first(&mut friend.notes),
+            ColumnTarget::DateOfBirth => {
+                match parse_date(value) {
+                    Ok(Some(date)) => {
+                        friend.date_of_birth.get_or_insert(date);
+                    }
+                    Ok(None) => {}
+                    // A birthday without a year can't go in `date_of_birth`
+                    Err(_) if is_month_day(value) => friend
+                        .attributes
+                        .push(ImportedAttribute::new(keys.next("birthday"), value, "text")),
+                    Err(reason) => return Err(format!("column {header:?}: {reason}")),
+                }
+            }
+            ColumnTarget::Groups {
+                separator,
+                skip_prefix,
+            } => {
+                for name in value.split(separator.as_str()).map(str::trim) {
+                    let skipped = skip_prefix.as_deref().is_some_and(|p| name.starts_with(p));
+                    let seen = friend.groups.iter().any(|g| g.eq_ignore_ascii_case(name));
+                    if !name.is_empty() && !skipped && !seen {
+                        friend.groups.push(name.to_string());
+                    }
+                }
+            }
+            ColumnTarget::Attribute { key, value_type } => {
+                let value = if value_type == "date" {
+                    match parse_date(value) {
+                        Ok(Some(date)) => date.to_string(),
+                        Ok(None) => continue,
+                        Err(reason) => return Err(format!("column {header:?}: {reason}")),
+                    }
+                } else {
+                    value.to_string()
+                };
+                friend
+                    .attributes
+                    .push(ImportedAttribute::new(keys.next(key), value, value_type));
+            }
+        }
+    }
+
+    if friend.first_name.is_empty() {
+        match full_name.as_deref().and_then(split_full_name) {
+            Some((first, last)) => {
+                friend.first_name = first;
+                if friend.last_name.is_none() {
+                    friend.last_name = last;
+                }
+            }
+            None => return Err("missing first name".to_string()),
+        }
+    }
+
+    Ok(friend)
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Parse the date formats address book exports use.
+///
+/// Accepts "1950-03-12", "1950/03/12", "3/12/1950" (US month/day/year)
+/// and "12 Mar 1950".
+///
+/// # Returns
+///
+/// - `Ok(None)` for Outlook's "0/0/00" placeholder
+/// - `Err` with a message for anything else that isn't a full date
+fn parse_date(value: &str) -> Result<Option<Date>, String> {
+    let value = value.trim();
+    if value == "0/0/00" || value == "0/0/0000" {
+        return Ok(None);
+    }
+
+    let invalid = || format!("invalid date {value:?}");
+    let number = |s: &str| s.trim().parse::<u32>().map_err(|_| invalid());
+
+    let (year, month, day) =
+        if let Some((y, rest)) = value.split_once(['-', '/']).filter(|(y, _)| y.len() == 4) {
+            let (m, d) = rest.split_once(['-', '/']).ok_or_else(invalid)?;
+            (number(y)?, number(m)?, number(d)?)
+        } else if value.contains('/') {
+            let mut parts = value.split('/');
+            let (m, d, y) = (
+                parts.next().ok_or_else(invalid)?,
+                parts.next().ok_or_else(invalid)?,
+                parts.next().ok_or_else(invalid)?,
+            );
+            (number(y)?, number(m)?, number(d)?)
+        } else {
+            let mut parts = value.split_whitespace();
+            let (d, m, y) = (
+                parts.next().ok_or_else(invalid)?,
+                parts.next().ok_or_else(invalid)?,
+                parts.next().ok_or_else(invalid)?,
+            );
+            let m = m.to_lowercase();
+            let month = MONTH_NAMES
+                .iter()
+                .position(|name| m.starts_with(name))
+                .ok_or_else(invalid)?;
+            (number(y)?, month as u32 + 1, number(d)?)
+        };
+
+    let year = i32::try_from(year).map_err(|_| invalid())?;
+    let month = u8::try_from(month)
+        .ok()
+        .and_then(|m| Month::try_from(m).ok())
+        .ok_or_else(invalid)?;
+    let day = u8::try_from(day).map_err(|_| invalid())?;
+    Date::from_calendar_date(year, month, day)
+        .map(Some)
+        .map_err(|_| invalid())
+}
+
+/// "--03-12" (Google) or "03-12": a birthday with no year.
+fn is_month_day(value: &str) -> bool {
+    let digits = value.trim_start_matches('-');
+    digits.len() == 5 && digits.as_bytes()[2] == b'-'
+}
+
+/// Imports friends from a CSV file in one transaction.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let importer = CsvImporter::new(ctx.clone());
+/// let profile = CsvProfile::builtin("google").unwrap();
+/// let report = importer.import(user_id, &profile, &csv_text).await?;
+/// for row in &report.rejected {
+///     println!("line {}: {}", row.line, row.reason);
+/// }
+/// ```
+pub struct CsvImporter {
+    ctx: RepositoryContext,
+}
+
+impl CsvImporter {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self { ctx }
+    }
+
+    /// Dry run: match every row against the user's friends and report
+    /// what `ImportPlanner::apply` would do with `strategy`.
+    pub async fn preview(
+        &self,
+        user_id: Uuid,
+        profile: &CsvProfile,
+        input: &str,
+        strategy: MergeStrategy,
+    ) -> Result<ImportPlan, InterchangeError> {
+        let (records, rejected): (Vec<_>, Vec<_>) =
+            parse(input, profile)?.into_iter().partition(Result::is_ok);
+
+        let records = records.into_iter().flatten().map(|(_, f)| f).collect();
+        let mut plan = ImportPlanner::new(self.ctx.clone())
+            .plan(user_id, records, strategy)
+            .await?;
+        plan.rejected = rejected.into_iter().filter_map(Result::err).collect();
+        Ok(plan)
+    }
+
+    /// Import every row of `input` as a new friend.
+    ///
+    /// Rows that fail mapping or are rejected by the database are listed
+    /// in `rejected`; the rest are committed together.
+    pub async fn import(
+        &self,
+        user_id: Uuid,
+        profile: &CsvProfile,
+        input: &str,
+    ) -> Result<CsvImportReport, InterchangeError> {
+        let rows = parse(input, profile)?;
+        let mut writer = ImportWriter::load(self.ctx.clone(), user_id).await?;
+        let mut friends = Vec::new();
+        let mut rejected = Vec::new();
+
+        let mut tx = self.ctx.transaction().await?;
+
+        for row in rows {
+            let (line, imported) = match row {
+                Ok(row) => row,
+                Err(row) => {
+                    rejected.push(row);
+                    continue;
+                }
+            };
+
+            let checkpoint = writer.checkpoint();
+            let mut savepoint = tx.begin().await.map_err(RepositoryError::from_sqlx)?;
+
+            match writer.create_friend(&mut savepoint, imported).await {
+                Ok(friend) => {
+                    savepoint
+                        .commit()
+                        .await
+                        .map_err(RepositoryError::from_sqlx)?;
+                    friends.push(friend);
+                }
+                Err(
+                    err @ (RepositoryError::Validation(_)
+                    | RepositoryError::Duplicate(_)
+                    | RepositoryError::ForeignKeyViolation(_)),
+                ) => {
+                    savepoint
+                        .rollback()
+                        .await
+                        .map_err(RepositoryError::from_sqlx)?;
+                    writer.rollback_to(checkpoint);
+                    rejected.push(RejectedRow {
+                        line,
+                        reason: err.to_string(),
+                    });
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(CsvImportReport {
+            friends,
+            groups_created: writer.into_created_groups(),
+            rejected,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: Month, day: u8) -> Option<Date> {
+        Some(Date::from_calendar_date(year, month, day).unwrap())
+    }
+
+    #[test]
+    fn parses_the_supported_formats() {
+        for value in [
+            "1950-03-12",
+            "1950/03/12",
+            "3/12/1950",
+            "12 Mar 1950",
+            " 12 march 1950 ",
+        ] {
+            assert_eq!(
+                parse_date(value),
+                Ok(date(1950, Month::March, 12)),
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn outlook_placeholders_are_no_date() {
+        assert_eq!(parse_date("0/0/00"), Ok(None));
+        assert_eq!(parse_date("0/0/0000"), Ok(None));
+    }
+
+    #[test]
+    fn rejects_parts_that_would_wrap_around() {
+        // 257 and 268 are 1 and 12 modulo 256
+        for value in [
+            "2024-257-268",
+            "2024-01-268",
+            "257/12/2024",
+            "12 Mar 4294967295",
+        ] {
+            assert!(parse_date(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn rejects_impossible_dates() {
+        for value in [
+            "2024-13-01",
+            "2024-00-10",
+            "2023-02-29",
+            "4/31/2024",
+            "32 Jan 2024",
+        ] {
+            assert!(parse_date(value).is_err(), "{value}");
+        }
+        assert_eq!(
+            parse_date("2024-02-29"),
+            Ok(date(2024, Month::February, 29))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        for value in [
+            "",
+            "yesterday",
+            "2024-03",
+            "3/12",
+            "12 Smarch 1950",
+            "2024-0x1-01",
+            "-1/12/1950",
+        ] {
+            assert!(parse_date(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn tells_month_days_apart() {
+        assert!(is_month_day("--03-12"));
+        assert!(is_month_day("03-12"));
+        assert!(!is_month_day("1950-03-12"));
+    }
+}
@@ -0,0 +1,236 @@
+//! # CSV Column Mapping Profiles
+//!
+//! A profile says which spreadsheet column feeds which friend field.
+//! Built-in profiles cover the exports of common address books; custom
+//! profiles can be built in code or deserialized from JSON:
+//!
+//! ```json
+//! {
+//!   "name": "club roster",
+//!   "delimiter": ";",
+//!   "columns": [
+//!     { "column": "Vorname", "target": { "field": "first_name" } },
+//!     { "column": "Nachname", "target": { "field": "last_name" } },
+//!     { "column": "Telefon *", "target": { "field": "attribute", "key": "phone", "value_type": "phone" } }
+//!   ]
+//! }
+//! ```
+//!
+//! Column names are matched case-insensitively, and `*` matches any text
+//! so numbered columns ("E-mail 1 - Value", "E-mail 2 - Value") share one
+//! mapping. Columns without a mapping are ignored.
+
+use serde::{Deserialize, Serialize};
+
+/// Where a column's value goes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "field", rename_all = "snake_case")]
+pub enum ColumnTarget {
+    FirstName,
+    LastName,
+    /// "First Last" in one column; used only when no first name column
+    /// has a value
+    FullName,
+    DateOfBirth,
+    Likes,
+    Dislikes,
+    Notes,
+    /// Group names separated by `separator`. Names starting with
+    /// `skip_prefix` are dropped (e.g., Google's "* myContacts").
+    Groups {
+        separator: String,
+        #[serde(default)]
+        skip_prefix: Option<String>,
+    },
+    /// A friend attribute. Values with `value_type` "date" are normalized
+    /// to ISO 8601.
+    Attribute {
+        key: String,
+        #[serde(default = "default_value_type")]
+        value_type: String,
+    },
+}
+
+fn default_value_type() -> String {
+    "text".to_string()
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+/// One column → field mapping.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnMapping {
+    /// Header text to match; `*` is a wildcard
+    pub column: String,
+    pub target: ColumnTarget,
+}
+
+impl ColumnMapping {
+    pub fn new(column: &str, target: ColumnTarget) -> Self {
+        Self {
+            column: column.to_string(),
+            target,
+        }
+    }
+
+    /// Shorthand for an attribute mapping.
+    pub fn attribute(column: &str, key: &str, value_type: &str) -> Self {
+        Self::new(
+            column,
+            ColumnTarget::Attribute {
+                key: key.to_string(),
+                value_type: value_type.to_string(),
+            },
+        )
+    }
+
+    /// `true` if this mapping applies to the given header.
+    pub fn matches(&self, header: &str) -> bool {
+        wildcard_match(&self.column.to_lowercase(), &header.trim().to_lowercase())
+    }
+}
+
+/// A named set of column mappings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CsvProfile {
+    pub name: String,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    pub columns: Vec<ColumnMapping>,
+}
+
+impl CsvProfile {
+    /// Names accepted by `CsvProfile::builtin`.
+    pub const BUILTIN: [&'static str; 3] = ["google", "outlook", "linkedin"];
+
+    /// Look up a built-in profile by name (case-insensitive).
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "google" => Some(Self::google()),
+            "outlook" => Some(Self::outlook()),
+            "linkedin" => Some(Self::linkedin()),
+            _ => None,
+        }
+    }
+
+    /// The first mapping that applies to `header`, if any.
+    pub fn mapping_for(&self, header: &str) -> Option<&ColumnMapping> {
+        self.columns.iter().find(|m| m.matches(header))
+    }
+
+    /// Google Contacts "Google CSV" export.
+    ///
+    /// Covers both the older layout ("Given Name", "Group Membership") and
+    /// the current one ("First Name", "Labels").
+    pub fn google() -> Self {
+        use ColumnTarget::*;
+        let groups = || Groups {
+            separator: ":::".to_string(),
+            skip_prefix: Some("*".to_string()),
+        };
+
+        Self {
+            name: "google".to_string(),
+            delimiter: ',',
+            columns: vec![
+                ColumnMapping::new("Given Name", FirstName),
+                ColumnMapping::new("First Name", FirstName),
+                ColumnMapping::new("Family Name", LastName),
+                ColumnMapping::new("Last Name", LastName),
+                ColumnMapping::new("Name", FullName),
+                ColumnMapping::new("Birthday", DateOfBirth),
+                ColumnMapping::new("Notes", Notes),
+                ColumnMapping::new("Group Membership", groups()),
+                ColumnMapping::new("Labels", groups()),
+                ColumnMapping::attribute("Nickname", "nickname", "text"),
+                ColumnMapping::attribute("E-mail * - Value", "email", "email"),
+                ColumnMapping::attribute("Phone * - Value", "phone", "phone"),
+                ColumnMapping::attribute("Address * - Formatted", "address", "text"),
+                ColumnMapping::attribute("Organization * - Name", "company", "text"),
+                ColumnMapping::attribute("Organization Name", "company", "text"),
+                ColumnMapping::attribute("Organization * - Title", "job_title", "text"),
+                ColumnMapping::attribute("Organization Title", "job_title", "text"),
+                ColumnMapping::attribute("Website * - Value", "url", "url"),
+            ],
+        }
+    }
+
+    /// Microsoft Outlook "Comma Separated Values" export.
+    pub fn outlook() -> Self {
+        use ColumnTarget::*;
+
+        Self {
+            name: "outlook".to_string(),
+            delimiter: ',',
+            columns: vec![
+                ColumnMapping::new("First Name", FirstName),
+                ColumnMapping::new("Last Name", LastName),
+                ColumnMapping::new("Birthday", DateOfBirth),
+                ColumnMapping::new("Notes", Notes),
+                ColumnMapping::new(
+                    "Categories",
+                    Groups {
+                        separator: ";".to_string(),
+                        skip_prefix: None,
+                    },
+                ),
+                ColumnMapping::attribute("E-mail*Address", "email", "email"),
+                ColumnMapping::attribute("Mobile Phone", "phone.cell", "phone"),
+                ColumnMapping::attribute("Home Phone", "phone.home", "phone"),
+                ColumnMapping::attribute("Business Phone", "phone.work", "phone"),
+                ColumnMapping::attribute("Home Street", "address.home.street", "text"),
+                ColumnMapping::attribute("Home City", "address.home.city", "text"),
+                ColumnMapping::attribute("Home Postal Code", "address.home.postal_code", "text"),
+                ColumnMapping::attribute("Home Country*", "address.home.country", "text"),
+                ColumnMapping::attribute("Company", "company", "text"),
+                ColumnMapping::attribute("Job Title", "job_title", "text"),
+                ColumnMapping::attribute("Web Page", "url", "url"),
+                ColumnMapping::attribute("Anniversary", "anniversary", "date"),
+            ],
+        }
+    }
+
+    /// LinkedIn "Connections.csv" from the data export.
+    pub fn linkedin() -> Self {
+        use ColumnTarget::*;
+
+        Self {
+            name: "linkedin".to_string(),
+            delimiter: ',',
+            columns: vec![
+                ColumnMapping::new("First Name", FirstName),
+                ColumnMapping::new("Last Name", LastName),
+                ColumnMapping::attribute("Email Address", "email", "email"),
+                ColumnMapping::attribute("URL", "linkedin", "url"),
+                ColumnMapping::attribute("Company", "company", "text"),
+                ColumnMapping::attribute("Position", "job_title", "text"),
+                ColumnMapping::attribute("Connected On", "linkedin_connected_on", "date"),
+            ],
+        }
+    }
+}
+
+/// Match `text` against `pattern`, where `*` matches any run of characters.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard: the whole text must match
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
@@ -0,0 +1,108 @@
+//! # CSV Reader
+//!
+//! A small RFC 4180 reader:
+//! - Fields are separated by a delimiter (usually `,`)
+//! - Fields may be wrapped in double quotes; quoted fields can contain
+//!   delimiters, newlines and `""` for a literal quote
+//! - Records end with LF or CRLF
+
+use crate::interchange::error::InterchangeError;
+
+/// One record and the 1-based line it starts on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvRecord {
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+impl CsvRecord {
+    /// `true` for rows with no content (e.g., ",,,").
+    pub fn is_blank(&self) -> bool {
+        self.fields.iter().all(|f| f.trim().is_empty())
+    }
+}
+
+/// Parse a whole file into records.
+///
+/// # Errors
+///
+/// Returns `InterchangeError::Parse` for an unterminated quoted field or
+/// text after a closing quote (e.g., `"abc"def`).
+pub fn parse(input: &str, delimiter: char) -> Result<Vec<CsvRecord>, InterchangeError> {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut in_quotes = false;
+    // Set after a closing quote; only a delimiter or line end may follow
+    let mut after_quote = false;
+
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => {
+                    in_quotes = false;
+                    after_quote = true;
+                }
+                '\n' => {
+                    line += 1;
+                    field.push('\n');
+                }
+                _ => field.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' if field.is_empty() && !after_quote => in_quotes = true,
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                records.push(CsvRecord {
+                    line: record_line,
+                    fields: std::mem::take(&mut fields),
+                });
+                after_quote = false;
+                line += 1;
+                record_line = line;
+            }
+            c if c == delimiter => {
+                fields.push(std::mem::take(&mut field));
+                after_quote = false;
+            }
+            _ if after_quote => {
+                return Err(InterchangeError::parse(
+                    line,
+                    "unexpected text after a closing quote",
+                ));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err(InterchangeError::parse(
+            record_line,
+            "quoted field is never closed",
+        ));
+    }
+
+    // Last record without a trailing newline
+    if !field.is_empty() || !fields.is_empty() || after_quote {
+        fields.push(field);
+        records.push(CsvRecord {
+            line: record_line,
+            fields,
+        });
+    }
+
+    Ok(records)
+}
@@ -0,0 +1,45 @@
+//! # Interchange Error Types
+//!
+//! Errors for importing and exporting data in external formats.
+
+use thiserror::Error;
+
+use crate::repositories::RepositoryError;
+
+/// Error type for import/export operations.
+///
+/// # Variants
+///
+/// - `Parse` - The input file is malformed (includes the line number)
+/// - `Unsupported` - The input is well-formed but uses a feature we don't handle
+/// - `Repository` - A database operation failed while importing or exporting
+/// - `Io` - Reading or writing files failed (vault sync)
+#[derive(Error, Debug)]
+pub enum InterchangeError {
+    /// The input couldn't be parsed
+    /// `line` is 1-based so it matches what a text editor shows
+    #[error("Parse error on line {line}: {message}")]
+    Parse { line: usize, message: String },
+
+    /// The input uses a feature or version this importer doesn't support
+    #[error("Unsupported input: {0}")]
+    Unsupported(String),
+
+    /// A repository call failed
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+
+    /// A file couldn't be read or written
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl InterchangeError {
+    /// Shorthand for building a `Parse` error.
+    pub fn parse(line: usize, message: impl Into<String>) -> Self {
+        InterchangeError::Parse {
+            line,
+            message: message.into(),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! # GEDCOM Dates
+//!
+//! GEDCOM dates look like "12 MAR 1950". They can also be partial
+//! ("MAR 1950", "1950") or qualified ("ABT 1950", "BEF 12 MAR 1950").
+//! Only exact, complete dates can become a `time::Date`; everything else
+//! is kept as text by the importer.
+
+use time::{Date, Month};
+
+const MONTHS: [(&str, Month); 12] = [
+    ("JAN", Month::January),
+    ("FEB", Month::February),
+    ("MAR", Month::March),
+    ("APR", Month::April),
+    ("MAY", Month::May),
+    ("JUN", Month::June),
+    ("JUL", Month::July),
+    ("AUG", Month::August),
+    ("SEP", Month::September),
+    ("OCT", Month::October),
+    ("NOV", Month::November),
+    ("DEC", Month::December),
+];
+
+/// Parse an exact GEDCOM date ("12 MAR 1950").
+///
+/// # Returns
+///
+/// `None` for partial, approximate, ranged or invalid dates.
+pub fn parse_date(value: &str) -> Option<Date> {
+    let mut parts = value.split_whitespace();
+    let (day, month, year) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let day: u8 = day.parse().ok()?;
+    let month = MONTHS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(month))
+        .map(|(_, month)| *month)?;
+    let year: i32 = year.parse().ok()?;
+
+    Date::from_calendar_date(year, month, day).ok()
+}
+
+/// Format a date the way GEDCOM expects ("12 MAR 1950").
+pub fn format_date(date: Date) -> String {
+    let month = MONTHS
+        .iter()
+        .find(|(_, month)| *month == date.month())
+        .map(|(name, _)| *name)
+        .expect("every month has a name");
+
+    format!("{} {} {}", date.day(), month, date.year())
+}
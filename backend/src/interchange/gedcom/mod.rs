@@ -0,0 +1,1178 @@
+//! # GEDCOM Import/Export
+//!
+//! Moves family trees between FKB and genealogy software using GEDCOM 5.5.1.
+//!
+//! ## Import Mapping
+//!
+//! | GEDCOM | FKB |
+//! |--------|-----|
+//! | `INDI` | `friends` row |
+//! | `NAME` / `GIVN` / `SURN` | `first_name`, `last_name` |
+//! | `BIRT.DATE` (exact dates only) | `date_of_birth` |
+//! | `NOTE` | `notes` |
+//! | `FAM` `HUSB`/`WIFE` | spouse relationship |
+//! | `FAM` `HUSB`/`WIFE` → `CHIL` | parent/child relationship |
+//! | `FAM` `CHIL` ↔ `CHIL` | sibling relationship |
+//! | anything else | `friend_attributes` keyed `gedcom.<PATH>` |
+//!
+//! Unknown fields keep their nesting in the attribute key, so
+//! `1 BIRT / 2 PLAC Boston` becomes `gedcom.BIRT.PLAC = Boston`. A repeated
+//! tag gets a `#n` suffix (`gedcom.OCCU#2`). On export these attributes are
+//! turned back into GEDCOM lines, so a round trip keeps them.
+//!
+//! Individuals are matched against existing friends like any other import
+//! (see `interchange::merge`), so re-importing a tree doesn't duplicate
+//! anyone; `GedcomImporter::preview` shows the plan first.
+//!
+//! ## Export
+//!
+//! Exports every friend with at least one family-typed relationship
+//! (parent, child, sibling, spouse - see `Kinship::from_label`). Families
+//! are rebuilt from parent sets and spouse pairs; siblings without known
+//! parents share a parentless `FAM` record.
+//!
+//! ## Limitations
+//!
+//! - Input must be UTF-8 (ANSEL-encoded files should be converted first)
+//! - Only exact birth dates map to `date_of_birth`; "ABT 1950" and friends
+//!   are kept as the `gedcom.BIRT.DATE` attribute
+
+pub mod date;
+pub mod record;
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::Serialize;
+use sqlx::Connection;
+use time::Date;
+use uuid::Uuid;
+
+use crate::kinship::Kinship;
+use crate::models::{Friend, FriendAttribute, FriendRelationship};
+use crate::repositories::{
+    CreateFriendRelationshipInput, FriendAttributeRepository, FriendRelationshipRepository,
+    FriendRepository, Repository, RepositoryContext, RepositoryError,
+};
+
+use super::attributes::{AttributeKeys, ImportedAttribute, strip_repeat_suffix};
+use super::error::InterchangeError;
+use super::imported::{ImportWriter, ImportedFriend};
+use super::merge::{ApplyReport, ImportPlan, ImportPlanner, MergeStrategy};
+use record::GedcomNode;
+
+/// Prefix for attributes holding GEDCOM fields FKB has no column for.
+pub const ATTRIBUTE_PREFIX: &str = "gedcom.";
+
+/// Tags under `INDI` that are handled explicitly rather than kept as attributes.
+/// `FAMS`/`FAMC` are rebuilt from relationships; `CHAN` is file metadata.
+const HANDLED_INDI_TAGS: &[&str] = &["NAME", "BIRT", "NOTE", "FAMS", "FAMC", "CHAN"];
+
+/// Relationship labels as [male, female, unknown] for imported families.
+const SPOUSE_LABELS: [&str; 3] = ["husband of", "wife of", "spouse of"];
+const PARENT_LABELS: [&str; 3] = ["father of", "mother of", "parent of"];
+const CHILD_LABELS: [&str; 3] = ["son of", "daughter of", "child of"];
+const SIBLING_LABELS: [&str; 3] = ["brother of", "sister of", "sibling of"];
+
+/// An individual read from a GEDCOM file.
+#[derive(Debug, Clone)]
+pub struct GedcomIndividual {
+    /// The record's xref (e.g., "I1")
+    pub xref: String,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    /// "M", "F", "U" or None
+    pub sex: Option<String>,
+    pub date_of_birth: Option<Date>,
+    pub notes: Option<String>,
+    /// Unmapped fields as (`gedcom.<PATH>`, value) pairs
+    pub attributes: Vec<(String, String)>,
+}
+
+/// A family read from a GEDCOM file.
+#[derive(Debug, Clone)]
+pub struct GedcomFamily {
+    pub xref: String,
+    pub husband: Option<String>,
+    pub wife: Option<String>,
+    pub children: Vec<String>,
+}
+
+/// Individuals and families parsed from a GEDCOM file.
+#[derive(Debug, Clone)]
+pub struct GedcomDocument {
+    pub individuals: Vec<GedcomIndividual>,
+    pub families: Vec<GedcomFamily>,
+}
+
+/// A relationship to create, expressed with GEDCOM xrefs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GedcomLink {
+    pub from_xref: String,
+    pub to_xref: String,
+    pub a_to_b: String,
+    pub b_to_a: Option<String>,
+}
+
+impl GedcomDocument {
+    /// Parse a GEDCOM file.
+    pub fn parse(input: &str) -> Result<Self, InterchangeError> {
+        let records = record::parse(input)?;
+
+        if !records.iter().any(|r| r.tag == "HEAD") {
+            return Err(InterchangeError::Unsupported(
+                "missing HEAD record - is this a GEDCOM file?".to_string(),
+            ));
+        }
+
+        // Shared NOTE records, referenced as `1 NOTE @N1@`
+        let notes: HashMap<&str, &str> = records
+            .iter()
+            .filter(|r| r.tag == "NOTE")
+            .filter_map(|r| Some((r.xref.as_deref()?, r.value.as_deref().unwrap_or(""))))
+            .collect();
+
+        let individuals = records
+            .iter()
+            .filter(|r| r.tag == "INDI")
+            .filter_map(|r| Some(read_individual(r.xref.as_deref()?, r, &notes)))
+            .collect();
+
+        let families = records
+            .iter()
+            .filter(|r| r.tag == "FAM")
+            .filter_map(|r| {
+                let pointers = |tag: &str| -> Vec<String> {
+                    r.children
+                        .iter()
+                        .filter(|c| c.tag == tag)
+                        .filter_map(|c| c.pointer().map(str::to_string))
+                        .collect()
+                };
+                Some(GedcomFamily {
+                    xref: r.xref.clone()?,
+                    husband: pointers("HUSB").into_iter().next(),
+                    wife: pointers("WIFE").into_iter().next(),
+                    children: pointers("CHIL"),
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            individuals,
+            families,
+        })
+    }
+
+    /// The parent, spouse and sibling relationships implied by the families.
+    ///
+    /// Labels are gendered when `SEX` is known ("mother of", "son of") and
+    /// symmetric labels use a NULL `b_to_a`. Each pair of individuals
+    /// appears at most once, even if several families link them.
+    pub fn links(&self) -> Vec<GedcomLink> {
+        let sex: HashMap<&str, &str> = self
+            .individuals
+            .iter()
+            .filter_map(|i| Some((i.xref.as_str(), i.sex.as_deref()?)))
+            .collect();
+        let label =
+            |xref: &str, [male, female, neutral]: [&'static str; 3]| match sex.get(xref).copied() {
+                Some("M") => male,
+                Some("F") => female,
+                _ => neutral,
+            };
+
+        let mut links = Vec::new();
+        let mut seen = BTreeSet::new();
+        let mut push = |from: &str, to: &str, a_to_b: &str, b_to_a: &str| {
+            let pair = if from < to { (from, to) } else { (to, from) };
+            if from == to || !seen.insert((pair.0.to_string(), pair.1.to_string())) {
+                return;
+            }
+            links.push(GedcomLink {
+                from_xref: from.to_string(),
+                to_xref: to.to_string(),
+                a_to_b: a_to_b.to_string(),
+                b_to_a: (a_to_b != b_to_a).then(|| b_to_a.to_string()),
+            });
+        };
+
+        for family in &self.families {
+            let parents: Vec<&str> = family
+                .husband
+                .iter()
+                .chain(&family.wife)
+                .map(String::as_str)
+                .collect();
+
+            if let (Some(husband), Some(wife)) = (&family.husband, &family.wife) {
+                push(
+                    husband,
+                    wife,
+                    label(husband, SPOUSE_LABELS),
+                    label(wife, SPOUSE_LABELS),
+                );
+            }
+
+            for child in &family.children {
+                for parent in &parents {
+                    push(
+                        parent,
+                        child,
+                        label(parent, PARENT_LABELS),
+                        label(child, CHILD_LABELS),
+                    );
+                }
+            }
+
+            for (i, first) in family.children.iter().enumerate() {
+                for second in &family.children[i + 1..] {
+                    push(
+                        first,
+                        second,
+                        label(first, SIBLING_LABELS),
+                        label(second, SIBLING_LABELS),
+                    );
+                }
+            }
+        }
+
+        links
+    }
+}
+
+/// Read one `INDI` record.
+fn read_individual(xref: &str, node: &GedcomNode, notes: &HashMap<&str, &str>) -> GedcomIndividual {
+    let mut attributes = Vec::new();
+    let mut keys = AttributeKeys::default();
+
+    // Name: "John Paul /Smith/ Jr" with optional GIVN/SURN overrides
+    let name = node.child("NAME");
+    let (mut given, mut surname) = name
+        .and_then(|n| n.value.as_deref())
+        .map(split_name)
+        .unwrap_or_default();
+    if let Some(name) = name {
+        if let Some(givn) = name.child_value("GIVN") {
+            given = givn.to_string();
+        }
+        if let Some(surn) = name.child_value("SURN") {
+            surname = surn.to_string();
+        }
+        for child in name
+            .children
+            .iter()
+            .filter(|c| c.tag != "GIVN" && c.tag != "SURN")
+        {
+            flatten(child, "gedcom.NAME", &mut attributes, &mut keys);
+        }
+    }
+    // Additional names (aliases, married names) and births are kept as
+    // attributes, numbered from #2 since the first one maps to columns
+    for tag in ["NAME", "BIRT"] {
+        keys.reserve(&format!("gedcom.{tag}"));
+        for extra in node.children.iter().filter(|c| c.tag == tag).skip(1) {
+            flatten(extra, "gedcom", &mut attributes, &mut keys);
+        }
+    }
+
+    // Birth: exact dates map to the column, everything else is kept
+    let mut date_of_birth = None;
+    if let Some(birth) = node.child("BIRT") {
+        date_of_birth = birth.child_value("DATE").and_then(date::parse_date);
+
+        let rest: Vec<&GedcomNode> = birth
+            .children
+            .iter()
+            .filter(|c| !(c.tag == "DATE" && date_of_birth.is_some()))
+            .collect();
+        if rest.is_empty() && date_of_birth.is_none() {
+            // "1 BIRT Y" - born, details unknown
+            out_leaf(birth, &mut attributes);
+        }
+        for child in rest {
+            flatten(child, "gedcom.BIRT", &mut attributes, &mut keys);
+        }
+    }
+
+    // Notes: inline text or pointers to shared NOTE records
+    let note_texts: Vec<&str> = node
+        .children
+        .iter()
+        .filter(|c| c.tag == "NOTE")
+        .filter_map(|c| match c.pointer() {
+            Some(pointer) => notes.get(pointer).copied(),
+            None => c.value.as_deref(),
+        })
+        .filter(|text| !text.trim().is_empty())
+        .collect();
+
+    for child in &node.children {
+        if !HANDLED_INDI_TAGS.contains(&child.tag.as_str()) {
+            flatten(child, "gedcom", &mut attributes, &mut keys);
+        }
+    }
+
+    let first_name = given.trim();
+    let surname = surname.trim();
+
+    GedcomIndividual {
+        xref: xref.to_string(),
+        first_name: if first_name.is_empty() {
+            "Unknown"
+        } else {
+            first_name
+        }
+        .to_string(),
+        last_name: (!surname.is_empty()).then(|| surname.to_string()),
+        sex: node.child_value("SEX").map(|s| s.trim().to_uppercase()),
+        date_of_birth,
+        notes: (!note_texts.is_empty()).then(|| note_texts.join("\n\n")),
+        attributes,
+    }
+}
+
+/// Keep a childless node as a single `gedcom.<TAG>` attribute.
+fn out_leaf(node: &GedcomNode, out: &mut Vec<(String, String)>) {
+    out.push((
+        format!("{ATTRIBUTE_PREFIX}{}", node.tag),
+        node.value.clone().unwrap_or_default(),
+    ));
+}
+
+/// Split "John Paul /Smith/ Jr" into ("John Paul", "Smith").
+///
+/// Suffixes after the surname are dropped here; `NSFX` carries them.
+fn split_name(name: &str) -> (String, String) {
+    match name.split_once('/') {
+        Some((given, rest)) => {
+            let surname = rest.split('/').next().unwrap_or("");
+            (given.trim().to_string(), surname.trim().to_string())
+        }
+        None => (name.trim().to_string(), String::new()),
+    }
+}
+
+/// Flatten a node and its children into `prefix.TAG` attributes.
+///
+/// Repeated keys get a `#n` suffix so they stay unique per friend.
+fn flatten(
+    node: &GedcomNode,
+    prefix: &str,
+    out: &mut Vec<(String, String)>,
+    keys: &mut AttributeKeys,
+) {
+    let key = keys.next(&format!("{prefix}.{}", node.tag));
+
+    // Keep valueless leaves (e.g., "1 BIRT") so they survive a round trip
+    if node.value.is_some() || node.children.is_empty() {
+        out.push((key.clone(), node.value.clone().unwrap_or_default()));
+    }
+    for child in &node.children {
+        flatten(child, &key, out, keys);
+    }
+}
+
+impl GedcomIndividual {
+    /// The individual in the format-neutral shape the import planner
+    /// matches and writes.
+    pub fn to_imported(&self) -> ImportedFriend {
+        ImportedFriend {
+            first_name: self.first_name.clone(),
+            last_name: self.last_name.clone(),
+            date_of_birth: self.date_of_birth,
+            notes: self.notes.clone(),
+            attributes: self
+                .attributes
+                .iter()
+                .map(|(key, value)| ImportedAttribute::new(key.clone(), value, "text"))
+                .collect(),
+            ..ImportedFriend::named("")
+        }
+    }
+}
+
+/// Result of a GEDCOM import.
+#[derive(Debug, Clone, Serialize)]
+pub struct GedcomImportReport {
+    /// Friends created or updated, one plan record per `INDI` record
+    #[serde(flatten)]
+    pub friends: ApplyReport,
+    /// Number of relationships created from `FAM` records
+    pub relationships_created: usize,
+    /// Relationships that were rejected by validation, with the reason
+    pub skipped: Vec<String>,
+}
+
+/// Imports GEDCOM individuals and families for a user.
+///
+/// Individuals go through `ImportPlanner` like any other import, so an
+/// individual who is already a friend is matched rather than duplicated,
+/// and `preview` shows what `import` will do.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let importer = GedcomImporter::new(ctx.clone());
+/// let input = std::fs::read_to_string("tree.ged")?;
+/// let report = importer.import(user_id, &input, MergeStrategy::KeepExisting).await?;
+/// println!("Imported {} people", report.friends.created.len());
+/// ```
+pub struct GedcomImporter {
+    ctx: RepositoryContext,
+    planner: ImportPlanner,
+    relationships: FriendRelationshipRepository,
+}
+
+impl GedcomImporter {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            planner: ImportPlanner::new(ctx.clone()),
+            relationships: FriendRelationshipRepository::new(ctx.clone()),
+            ctx,
+        }
+    }
+
+    /// Dry run: match every individual against the user's friends and
+    /// report what `import` would do with `strategy`.
+    pub async fn preview(
+        &self,
+        user_id: Uuid,
+        input: &str,
+        strategy: MergeStrategy,
+    ) -> Result<ImportPlan, InterchangeError> {
+        let document = GedcomDocument::parse(input)?;
+        self.plan(user_id, &document, strategy).await
+    }
+
+    /// Import every individual and family in `input`, in one transaction.
+    ///
+    /// Individuals are planned and applied with `strategy`; families then
+    /// become relationships between the friends they were created as or
+    /// matched to. Relationships rejected by `FriendRelationshipRepository`
+    /// validation (including pairs that are already related) are reported
+    /// in `skipped` rather than failing the whole import; any other
+    /// failure rolls back everything, so an import never stops halfway.
+    pub async fn import(
+        &self,
+        user_id: Uuid,
+        input: &str,
+        strategy: MergeStrategy,
+    ) -> Result<GedcomImportReport, InterchangeError> {
+        let document = GedcomDocument::parse(input)?;
+        let plan = self.plan(user_id, &document, strategy).await?;
+        let mut writer = ImportWriter::load(self.ctx.clone(), user_id).await?;
+
+        let mut tx = self.ctx.transaction().await?;
+
+        let friends = self.planner.apply_in(&mut tx, &mut writer, plan).await?;
+
+        // Plan records are in document order, one per individual
+        let ids: HashMap<&str, Option<Uuid>> = document
+            .individuals
+            .iter()
+            .map(|i| i.xref.as_str())
+            .zip(friends.friend_ids.iter().copied())
+            .collect();
+
+        let mut relationships_created = 0;
+        let mut skipped = Vec::new();
+
+        for link in document.links() {
+            let (a, b) = match (
+                ids.get(link.from_xref.as_str()),
+                ids.get(link.to_xref.as_str()),
+            ) {
+                (Some(Some(a)), Some(Some(b))) => (*a, *b),
+                (Some(_), Some(_)) => {
+                    skipped.push(format!(
+                        "{} → {}: an individual conflicts with existing friends and was not imported",
+                        link.from_xref, link.to_xref
+                    ));
+                    continue;
+                }
+                _ => {
+                    skipped.push(format!(
+                        "{} → {}: family references a missing individual",
+                        link.from_xref, link.to_xref
+                    ));
+                    continue;
+                }
+            };
+
+            // A savepoint, so a rejected relationship leaves the rest intact
+            let mut savepoint = tx.begin().await.map_err(RepositoryError::from_sqlx)?;
+            let result = self
+                .relationships
+                .create_in(
+                    &mut savepoint,
+                    CreateFriendRelationshipInput {
+                        user_id,
+                        friend_a_id: a,
+                        friend_b_id: b,
+                        a_to_b: link.a_to_b.clone(),
+                        b_to_a: link.b_to_a.clone(),
+                        started_on: None,
+                        ended_on: None,
+                        status: None,
+                    },
+                )
+                .await;
+
+            match result {
+                Ok(_) => {
+                    savepoint
+                        .commit()
+                        .await
+                        .map_err(RepositoryError::from_sqlx)?;
+                    relationships_created += 1;
+                }
+                Err(RepositoryError::Validation(reason)) => {
+                    savepoint
+                        .rollback()
+                        .await
+                        .map_err(RepositoryError::from_sqlx)?;
+                    skipped.push(format!("{} → {}: {reason}", link.from_xref, link.to_xref));
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(GedcomImportReport {
+            friends,
+            relationships_created,
+            skipped,
+        })
+    }
+
+    /// Plan the document's individuals, one record each, in order.
+    async fn plan(
+        &self,
+        user_id: Uuid,
+        document: &GedcomDocument,
+        strategy: MergeStrategy,
+    ) -> Result<ImportPlan, InterchangeError> {
+        let records = document
+            .individuals
+            .iter()
+            .map(GedcomIndividual::to_imported)
+            .collect();
+        self.planner.plan(user_id, records, strategy).await
+    }
+}
+
+/// A family-typed relationship normalized for export.
+///
+/// Child relationships are flipped to parent ones so that only three
+/// kinds remain: parent (from → to), spouse and sibling.
+fn family_link(relationship: &FriendRelationship) -> Option<(Uuid, Kinship, Uuid)> {
+    let (a, b) = (relationship.friend_a_id, relationship.friend_b_id);
+    let (kind, from, to) = match Kinship::from_label(&relationship.a_to_b) {
+        Some(kind) => (kind, a, b),
+        None => (Kinship::from_label(relationship.b_to_a.as_deref()?)?, b, a),
+    };
+
+    match kind {
+        Kinship::Child => Some((to, Kinship::Parent, from)),
+        Kinship::Parent | Kinship::Spouse | Kinship::Sibling => Some((from, kind, to)),
+        _ => None,
+    }
+}
+
+/// Guess "M"/"F" from a gendered relationship label.
+fn sex_from_label(label: &str) -> Option<&'static str> {
+    let word = label.trim().to_lowercase();
+    let word = word.split_whitespace().next()?;
+    match word {
+        "father" | "dad" | "husband" | "son" | "brother" => Some("M"),
+        "mother" | "mom" | "wife" | "daughter" | "sister" => Some("F"),
+        _ => None,
+    }
+}
+
+/// A family being assembled for export.
+#[derive(Default)]
+struct ExportFamily {
+    parents: Vec<Uuid>,
+    children: Vec<Uuid>,
+}
+
+/// Exports a user's family-typed relationships as GEDCOM 5.5.1.
+pub struct GedcomExporter {
+    friends: FriendRepository,
+    attributes: FriendAttributeRepository,
+    relationships: FriendRelationshipRepository,
+}
+
+impl GedcomExporter {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            friends: FriendRepository::new(ctx.clone()),
+            attributes: FriendAttributeRepository::new(ctx.clone()),
+            relationships: FriendRelationshipRepository::new(ctx),
+        }
+    }
+
+    /// Render the user's family tree as a GEDCOM file.
+    pub async fn export(&self, user_id: Uuid) -> Result<String, InterchangeError> {
+        let friends = self.friends.list_by_user(user_id).await?;
+        let relationships = self.relationships.list_by_user(user_id).await?;
+
+        let mut attributes: HashMap<Uuid, Vec<FriendAttribute>> = HashMap::new();
+        for attribute in self.attributes.list_by_user(user_id).await? {
+            attributes
+                .entry(attribute.friend_id)
+                .or_default()
+                .push(attribute);
+        }
+
+        Ok(write_document(&friends, &attributes, &relationships))
+    }
+}
+
+/// Render friends and their family-typed relationships as a GEDCOM file.
+///
+/// Only friends with at least one family-typed relationship are written.
+///
+/// # Arguments
+///
+/// * `attributes` - Map of friend ID → the friend's attributes
+fn write_document(
+    friends: &[Friend],
+    attributes: &HashMap<Uuid, Vec<FriendAttribute>>,
+    relationships: &[FriendRelationship],
+) -> String {
+    let links: Vec<(Uuid, Kinship, Uuid)> = relationships.iter().filter_map(family_link).collect();
+
+    // Sex from GEDCOM attributes first, then from gendered labels
+    let mut sex: HashMap<Uuid, String> = HashMap::new();
+    for relationship in relationships {
+        let labels = [
+            (relationship.friend_a_id, Some(relationship.a_to_b.as_str())),
+            (relationship.friend_b_id, relationship.b_to_a.as_deref()),
+        ];
+        for (friend_id, label) in labels {
+            if let Some(guess) = label.and_then(sex_from_label) {
+                sex.entry(friend_id).or_insert_with(|| guess.to_string());
+            }
+        }
+    }
+
+    let in_tree: BTreeSet<Uuid> = links.iter().flat_map(|(a, _, b)| [*a, *b]).collect();
+    let members: Vec<&Friend> = friends.iter().filter(|f| in_tree.contains(&f.id)).collect();
+    let attributes_of =
+        |id: &Uuid| -> &[FriendAttribute] { attributes.get(id).map(Vec::as_slice).unwrap_or(&[]) };
+
+    for friend in &members {
+        if let Some(value) = attributes_of(&friend.id)
+            .iter()
+            .find(|a| a.key == "gedcom.SEX")
+        {
+            sex.insert(friend.id, value.value.trim().to_uppercase());
+        }
+    }
+
+    let families = build_families(&links);
+
+    let individual_xrefs: HashMap<Uuid, String> = members
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (f.id, format!("I{}", i + 1)))
+        .collect();
+
+    let mut records = vec![header()];
+
+    for friend in &members {
+        let mut node = individual_node(friend, attributes_of(&friend.id));
+        node.xref = Some(individual_xrefs[&friend.id].clone());
+
+        for (i, family) in families.iter().enumerate() {
+            let pointer = format!("@F{}@", i + 1);
+            if family.children.contains(&friend.id) {
+                node.children
+                    .push(GedcomNode::new("FAMC", Some(pointer.clone())));
+            }
+            if family.parents.contains(&friend.id) {
+                node.children.push(GedcomNode::new("FAMS", Some(pointer)));
+            }
+        }
+        records.push(node);
+    }
+
+    for (i, family) in families.iter().enumerate() {
+        let mut node = GedcomNode::new("FAM", None);
+        node.xref = Some(format!("F{}", i + 1));
+
+        let pointer = |id: &Uuid| format!("@{}@", individual_xrefs[id]);
+        let (husband, wife) = assign_roles(&family.parents, &sex);
+        if let Some(husband) = husband {
+            node.children
+                .push(GedcomNode::new("HUSB", Some(pointer(&husband))));
+        }
+        if let Some(wife) = wife {
+            node.children
+                .push(GedcomNode::new("WIFE", Some(pointer(&wife))));
+        }
+        for child in &family.children {
+            node.children
+                .push(GedcomNode::new("CHIL", Some(pointer(child))));
+        }
+        records.push(node);
+    }
+
+    records.push(GedcomNode::new("TRLR", None));
+
+    record::write(&records)
+}
+
+/// The `HEAD` record for exported files.
+fn header() -> GedcomNode {
+    let gedc = GedcomNode::new("GEDC", None)
+        .with_child("VERS", "5.5.1")
+        .with_child("FORM", "LINEAGE-LINKED");
+
+    let mut head = GedcomNode::new("HEAD", None).with_child("SOUR", "FKB");
+    head.children.push(gedc);
+    head.with_child("CHAR", "UTF-8")
+}
+
+/// Build the `INDI` record for a friend (without FAMS/FAMC pointers).
+fn individual_node(friend: &Friend, attributes: &[FriendAttribute]) -> GedcomNode {
+    let surname = friend.last_name.clone().unwrap_or_default();
+    let mut name = GedcomNode::new("NAME", Some(format!("{} /{}/", friend.first_name, surname)))
+        .with_child("GIVN", friend.first_name.clone());
+    if !surname.is_empty() {
+        name = name.with_child("SURN", surname);
+    }
+
+    let mut extra = attribute_nodes(attributes);
+
+    // Merge gedcom.NAME.* (nicknames, prefixes) into the primary NAME
+    if let Some(index) = extra
+        .iter()
+        .position(|n| n.tag == "NAME" && n.value.is_none())
+    {
+        name.children.extend(extra.remove(index).children);
+    }
+
+    let mut node = GedcomNode::new("INDI", None);
+    node.children.push(name);
+
+    // The date_of_birth column wins over an approximate date kept as text
+    if let Some(dob) = friend.date_of_birth {
+        let index = extra.iter().position(|n| n.tag == "BIRT");
+        let mut birth = index
+            .map(|i| extra.remove(i))
+            .unwrap_or_else(|| GedcomNode::new("BIRT", None));
+        birth.value = None;
+        birth.children.retain(|c| c.tag != "DATE");
+        birth
+            .children
+            .insert(0, GedcomNode::new("DATE", Some(date::format_date(dob))));
+        node.children.push(birth);
+    }
+
+    node.children.extend(extra);
+
+    if let Some(notes) = friend.notes.as_deref().filter(|n| !n.trim().is_empty()) {
+        node.children
+            .push(GedcomNode::new("NOTE", Some(notes.to_string())));
+    }
+
+    node
+}
+
+/// Rebuild GEDCOM nodes from `gedcom.*` attributes.
+fn attribute_nodes(attributes: &[FriendAttribute]) -> Vec<GedcomNode> {
+    // (segment with #n suffix, node) pairs so repeated tags stay separate
+    struct Branch {
+        segment: String,
+        value: Option<String>,
+        children: Vec<Branch>,
+    }
+
+    fn insert(branches: &mut Vec<Branch>, path: &[&str], value: &str) {
+        let Some((first, rest)) = path.split_first() else {
+            return;
+        };
+        let index = match branches.iter().position(|b| b.segment == *first) {
+            Some(index) => index,
+            None => {
+                branches.push(Branch {
+                    segment: first.to_string(),
+                    value: None,
+                    children: Vec::new(),
+                });
+                branches.len() - 1
+            }
+        };
+        if rest.is_empty() {
+            branches[index].value = Some(value.to_string());
+        } else {
+            insert(&mut branches[index].children, rest, value);
+        }
+    }
+
+    fn into_nodes(branches: Vec<Branch>) -> Vec<GedcomNode> {
+        branches
+            .into_iter()
+            .map(|b| {
+                let mut node = GedcomNode::new(
+                    strip_repeat_suffix(&b.segment),
+                    b.value.filter(|v| !v.is_empty()),
+                );
+                node.children = into_nodes(b.children);
+                node
+            })
+            .collect()
+    }
+
+    let mut roots = Vec::new();
+    for attribute in attributes {
+        if let Some(path) = attribute.key.strip_prefix(ATTRIBUTE_PREFIX) {
+            let segments: Vec<&str> = path.split('.').collect();
+            insert(&mut roots, &segments, &attribute.value);
+        }
+    }
+    into_nodes(roots)
+}
+
+/// Group parent/spouse/sibling links into GEDCOM families.
+fn build_families(links: &[(Uuid, Kinship, Uuid)]) -> Vec<ExportFamily> {
+    let mut parents_of: HashMap<Uuid, BTreeSet<Uuid>> = HashMap::new();
+    let mut spouses = BTreeSet::new();
+    let mut siblings = Vec::new();
+
+    for (from, kind, to) in links {
+        match kind {
+            Kinship::Parent => {
+                parents_of.entry(*to).or_default().insert(*from);
+            }
+            Kinship::Spouse => {
+                spouses.insert((*from.min(to), *from.max(to)));
+            }
+            Kinship::Sibling => siblings.push((*from, *to)),
+            _ => {}
+        }
+    }
+
+    let mut families: Vec<ExportFamily> = Vec::new();
+    let mut family_of_parents: HashMap<Vec<Uuid>, usize> = HashMap::new();
+    let mut family_of_child: HashMap<Uuid, usize> = HashMap::new();
+
+    let mut find_or_create = |parents: Vec<Uuid>, families: &mut Vec<ExportFamily>| -> usize {
+        *family_of_parents.entry(parents.clone()).or_insert_with(|| {
+            families.push(ExportFamily {
+                parents,
+                children: Vec::new(),
+            });
+            families.len() - 1
+        })
+    };
+
+    // Spouse pairs first, so their children land in the couple's family
+    for (a, b) in &spouses {
+        find_or_create(vec![*a, *b], &mut families);
+    }
+
+    let mut children: Vec<_> = parents_of.into_iter().collect();
+    children.sort();
+    for (child, parents) in children {
+        // GEDCOM families have at most two parents; extras get their own family
+        let parents: Vec<Uuid> = parents.into_iter().collect();
+        for chunk in parents.chunks(2) {
+            let index = find_or_create(chunk.to_vec(), &mut families);
+            families[index].children.push(child);
+            family_of_child.entry(child).or_insert(index);
+        }
+    }
+
+    // Siblings with no shared parents join an existing family or a new
+    // parentless one
+    for (a, b) in siblings {
+        match (
+            family_of_child.get(&a).copied(),
+            family_of_child.get(&b).copied(),
+        ) {
+            (Some(fa), Some(fb)) if fa == fb => {}
+            (Some(_), Some(_)) => {} // half-siblings: already linked via parents
+            (Some(index), None) | (None, Some(index)) => {
+                let missing = if family_of_child.contains_key(&a) {
+                    b
+                } else {
+                    a
+                };
+                families[index].children.push(missing);
+                family_of_child.insert(missing, index);
+            }
+            (None, None) => {
+                families.push(ExportFamily {
+                    parents: Vec::new(),
+                    children: vec![a, b],
+                });
+                family_of_child.insert(a, families.len() - 1);
+                family_of_child.insert(b, families.len() - 1);
+            }
+        }
+    }
+
+    families
+}
+
+/// Decide which parent is `HUSB` and which is `WIFE`.
+///
+/// Uses `SEX` when known; otherwise the first parent is `HUSB`.
+fn assign_roles(parents: &[Uuid], sex: &HashMap<Uuid, String>) -> (Option<Uuid>, Option<Uuid>) {
+    let is_female = |id: &Uuid| sex.get(id).map(String::as_str) == Some("F");
+    match parents {
+        [] => (None, None),
+        [only] if is_female(only) => (None, Some(*only)),
+        [only] => (Some(*only), None),
+        [first, second, ..] if is_female(first) && !is_female(second) => {
+            (Some(*second), Some(*first))
+        }
+        [first, second, ..] => (Some(*first), Some(*second)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::*;
+
+    /// John and Mary with a son and a daughter (listed twice, and the
+    /// children again as siblings), plus a couple of unknown sex.
+    const TREE: &str = "\
+0 HEAD
+1 CHAR UTF-8
+0 @I1@ INDI
+1 NAME John /Smith/
+1 SEX M
+0 @I2@ INDI
+1 NAME Mary /Smith/
+1 SEX F
+0 @I3@ INDI
+1 NAME Tom /Smith/
+1 SEX M
+0 @I4@ INDI
+1 NAME Ann /Smith/
+1 SEX F
+0 @I5@ INDI
+1 NAME Pat /Jones/
+0 @I6@ INDI
+1 NAME Sam /Jones/
+0 @F1@ FAM
+1 HUSB @I1@
+1 WIFE @I2@
+1 CHIL @I3@
+1 CHIL @I4@
+0 @F2@ FAM
+1 HUSB @I1@
+1 WIFE @I2@
+0 @F3@ FAM
+1 HUSB @I5@
+1 WIFE @I6@
+0 @F4@ FAM
+1 CHIL @I4@
+1 CHIL @I3@
+0 TRLR
+";
+
+    fn link(from: &str, to: &str, a_to_b: &str, b_to_a: Option<&str>) -> GedcomLink {
+        GedcomLink {
+            from_xref: from.to_string(),
+            to_xref: to.to_string(),
+            a_to_b: a_to_b.to_string(),
+            b_to_a: b_to_a.map(str::to_string),
+        }
+    }
+
+    fn id(n: u128) -> Uuid {
+        Uuid::from_u128(n)
+    }
+
+    #[test]
+    fn families_become_gendered_links_once_per_pair() {
+        let document = GedcomDocument::parse(TREE).unwrap();
+
+        assert_eq!(
+            document.links(),
+            vec![
+                link("I1", "I2", "husband of", Some("wife of")),
+                link("I1", "I3", "father of", Some("son of")),
+                link("I2", "I3", "mother of", Some("son of")),
+                link("I1", "I4", "father of", Some("daughter of")),
+                link("I2", "I4", "mother of", Some("daughter of")),
+                link("I3", "I4", "brother of", Some("sister of")),
+                // Unknown sex gives the neutral, symmetric label
+                link("I5", "I6", "spouse of", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn exported_families_match_the_imported_ones() {
+        let document = GedcomDocument::parse(TREE).unwrap();
+        let now = OffsetDateTime::UNIX_EPOCH;
+
+        // Store the tree the way an import would
+        let ids: HashMap<&str, Uuid> = document
+            .individuals
+            .iter()
+            .enumerate()
+            .map(|(i, individual)| (individual.xref.as_str(), id(i as u128 + 1)))
+            .collect();
+        let friends: Vec<Friend> = document
+            .individuals
+            .iter()
+            .map(|individual| Friend {
+                id: ids[individual.xref.as_str()],
+                user_id: Uuid::nil(),
+                first_name: individual.first_name.clone(),
+                last_name: individual.last_name.clone(),
+                date_of_birth: individual.date_of_birth,
+                likes: None,
+                dislikes: None,
+                notes: individual.notes.clone(),
+                created_at: now,
+                updated_at: None,
+                version: 1,
+            })
+            .collect();
+        let attributes: HashMap<Uuid, Vec<FriendAttribute>> = document
+            .individuals
+            .iter()
+            .map(|individual| {
+                let friend_id = ids[individual.xref.as_str()];
+                let list = individual
+                    .attributes
+                    .iter()
+                    .map(|(key, value)| FriendAttribute {
+                        id: Uuid::nil(),
+                        friend_id,
+                        key: key.clone(),
+                        value: value.clone(),
+                        value_type: "text".to_string(),
+                        created_at: now,
+                        updated_at: None,
+                        version: 1,
+                    })
+                    .collect();
+                (friend_id, list)
+            })
+            .collect();
+        let relationships: Vec<FriendRelationship> = document
+            .links()
+            .into_iter()
+            .map(|link| FriendRelationship {
+                id: Uuid::nil(),
+                user_id: Uuid::nil(),
+                friend_a_id: ids[link.from_xref.as_str()],
+                friend_b_id: ids[link.to_xref.as_str()],
+                a_to_b: link.a_to_b,
+                b_to_a: link.b_to_a,
+                started_on: None,
+                ended_on: None,
+                status: "active".to_string(),
+                created_at: now,
+                updated_at: None,
+                version: 1,
+            })
+            .collect();
+
+        let exported =
+            GedcomDocument::parse(&write_document(&friends, &attributes, &relationships)).unwrap();
+
+        // Compare by name, since the export numbers the xrefs afresh
+        let name = |xref: &str| -> Option<String> {
+            let individual = exported.individuals.iter().find(|i| i.xref == xref)?;
+            Some(individual.first_name.clone())
+        };
+        let mut families: Vec<(Option<String>, Option<String>, Vec<String>)> = exported
+            .families
+            .iter()
+            .map(|family| {
+                let mut children: Vec<String> =
+                    family.children.iter().filter_map(|c| name(c)).collect();
+                children.sort();
+                (
+                    family.husband.as_deref().and_then(name),
+                    family.wife.as_deref().and_then(name),
+                    children,
+                )
+            })
+            .collect();
+        families.sort();
+
+        let some = |name: &str| Some(name.to_string());
+        assert_eq!(
+            families,
+            vec![
+                (
+                    some("John"),
+                    some("Mary"),
+                    vec!["Ann".to_string(), "Tom".to_string()]
+                ),
+                (some("Pat"), some("Sam"), vec![]),
+            ]
+        );
+        // SEX was kept as an attribute and written back
+        let sexes: Vec<Option<&str>> = exported
+            .individuals
+            .iter()
+            .map(|i| i.sex.as_deref())
+            .collect();
+        assert_eq!(
+            sexes,
+            [Some("M"), Some("F"), Some("M"), Some("F"), None, None]
+        );
+    }
+
+    #[test]
+    fn siblings_join_their_siblings_family_or_share_a_parentless_one() {
+        let families = build_families(&[
+            (id(1), Kinship::Parent, id(3)),
+            (id(3), Kinship::Sibling, id(4)),
+            (id(7), Kinship::Sibling, id(8)),
+        ]);
+
+        let shapes: Vec<(Vec<Uuid>, Vec<Uuid>)> = families
+            .into_iter()
+            .map(|f| (f.parents, f.children))
+            .collect();
+        assert_eq!(
+            shapes,
+            vec![
+                (vec![id(1)], vec![id(3), id(4)]),
+                (vec![], vec![id(7), id(8)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn wives_are_never_husbands() {
+        let sex = HashMap::from([
+            (id(1), "M".to_string()),
+            (id(2), "F".to_string()),
+            (id(3), "F".to_string()),
+        ]);
+
+        assert_eq!(
+            assign_roles(&[id(2), id(1)], &sex),
+            (Some(id(1)), Some(id(2)))
+        );
+        assert_eq!(assign_roles(&[id(2)], &sex), (None, Some(id(2))));
+        assert_eq!(assign_roles(&[id(1)], &sex), (Some(id(1)), None));
+        // Unknown sex: the first parent is HUSB
+        assert_eq!(
+            assign_roles(&[id(5), id(6)], &sex),
+            (Some(id(5)), Some(id(6)))
+        );
+        // Two wives: kept in order rather than guessing
+        assert_eq!(
+            assign_roles(&[id(2), id(3)], &sex),
+            (Some(id(2)), Some(id(3)))
+        );
+    }
+}
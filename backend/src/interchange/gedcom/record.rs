@@ -0,0 +1,314 @@
+//! # GEDCOM Records
+//!
+//! Reads and writes the GEDCOM line format as a tree of nodes.
+//!
+//! ## Line Format
+//!
+//! ```text
+//! level [@xref@] TAG [value]
+//! ```
+//!
+//! A line at level N+1 is a child of the closest preceding line at level N.
+//! `CONT` (newline) and `CONC` (no separator) lines continue the parent's
+//! value and are folded into it while parsing, so callers never see them.
+
+use crate::interchange::error::InterchangeError;
+
+/// GEDCOM 5.5.1 caps lines at 255 characters. Values longer than this are
+/// split into `CONC` continuation lines when writing.
+const MAX_VALUE_CHARS: usize = 200;
+
+/// One GEDCOM line plus everything nested under it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GedcomNode {
+    /// Cross-reference ID without the `@` signs (e.g., "I1")
+    pub xref: Option<String>,
+    /// Upper-case tag (e.g., "INDI", "NAME", "_UID")
+    pub tag: String,
+    /// Line value with continuations already applied
+    pub value: Option<String>,
+    /// Nested lines, in file order
+    pub children: Vec<GedcomNode>,
+}
+
+impl GedcomNode {
+    pub fn new(tag: &str, value: Option<String>) -> Self {
+        Self {
+            xref: None,
+            tag: tag.to_string(),
+            value,
+            children: Vec::new(),
+        }
+    }
+
+    /// Builder-style helper to append a child with a value.
+    pub fn with_child(mut self, tag: &str, value: impl Into<String>) -> Self {
+        self.children.push(GedcomNode::new(tag, Some(value.into())));
+        self
+    }
+
+    /// First child with the given tag.
+    pub fn child(&self, tag: &str) -> Option<&GedcomNode> {
+        self.children.iter().find(|c| c.tag == tag)
+    }
+
+    /// Value of the first child with the given tag.
+    pub fn child_value(&self, tag: &str) -> Option<&str> {
+        self.child(tag).and_then(|c| c.value.as_deref())
+    }
+
+    /// Value as a pointer (e.g., "@I1@" → "I1").
+    pub fn pointer(&self) -> Option<&str> {
+        self.value
+            .as_deref()
+            .and_then(|v| v.strip_prefix('@'))
+            .and_then(|v| v.strip_suffix('@'))
+    }
+}
+
+/// Parse a GEDCOM file into its top-level records.
+///
+/// # Errors
+///
+/// Returns `InterchangeError::Parse` for lines without a level or tag,
+/// and for lines that skip a level (e.g., a level 3 line under level 1).
+pub fn parse(input: &str) -> Result<Vec<GedcomNode>, InterchangeError> {
+    let mut roots: Vec<GedcomNode> = Vec::new();
+    // Stack of currently open nodes; stack[n] is the open node at level n
+    let mut stack: Vec<GedcomNode> = Vec::new();
+
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+
+    for (index, raw) in input.lines().enumerate() {
+        let line_no = index + 1;
+        let line = raw.trim_start().trim_end_matches('\r');
+        if line.is_empty() {
+            continue;
+        }
+
+        let (level, rest) = line
+            .split_once(' ')
+            .ok_or_else(|| InterchangeError::parse(line_no, "expected a level and a tag"))?;
+        let level: usize = level
+            .parse()
+            .map_err(|_| InterchangeError::parse(line_no, format!("invalid level {level:?}")))?;
+
+        let (xref, rest) = match rest.strip_prefix('@') {
+            Some(after) => {
+                let (id, rest) = after
+                    .split_once("@ ")
+                    .ok_or_else(|| InterchangeError::parse(line_no, "unterminated xref"))?;
+                (Some(id.to_string()), rest)
+            }
+            None => (None, rest),
+        };
+
+        let (tag, value) = match rest.split_once(' ') {
+            Some((tag, value)) => (tag, Some(value.to_string())),
+            None => (rest, None),
+        };
+        let tag = tag.to_uppercase();
+
+        if level > stack.len() {
+            return Err(InterchangeError::parse(
+                line_no,
+                format!(
+                    "level {level} follows level {}",
+                    stack.len().saturating_sub(1)
+                ),
+            ));
+        }
+
+        close_to(&mut stack, &mut roots, level);
+
+        // Continuations extend the parent's value instead of nesting
+        if tag == "CONT" || tag == "CONC" {
+            let parent = stack
+                .last_mut()
+                .filter(|_| level > 0)
+                .ok_or_else(|| InterchangeError::parse(line_no, "continuation without a parent"))?;
+            let current = parent.value.get_or_insert_with(String::new);
+            if tag == "CONT" {
+                current.push('\n');
+            }
+            current.push_str(value.as_deref().unwrap_or(""));
+            continue;
+        }
+
+        stack.push(GedcomNode {
+            xref,
+            tag,
+            value,
+            children: Vec::new(),
+        });
+    }
+
+    close_to(&mut stack, &mut roots, 0);
+    Ok(roots)
+}
+
+/// Pop open nodes until only `level` remain, attaching each to its parent.
+fn close_to(stack: &mut Vec<GedcomNode>, roots: &mut Vec<GedcomNode>, level: usize) {
+    while stack.len() > level {
+        let node = stack.pop().expect("stack is non-empty");
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    }
+}
+
+/// Serialize records back to GEDCOM text.
+///
+/// Multi-line values become `CONT` lines and long values are split into
+/// `CONC` lines so no line exceeds the 255 character limit.
+pub fn write(records: &[GedcomNode]) -> String {
+    let mut out = String::new();
+    for record in records {
+        write_node(&mut out, record, 0);
+    }
+    out
+}
+
+fn write_node(out: &mut String, node: &GedcomNode, level: usize) {
+    let xref = node
+        .xref
+        .as_ref()
+        .map(|x| format!("@{x}@ "))
+        .unwrap_or_default();
+
+    match &node.value {
+        None => out.push_str(&format!("{level} {xref}{}\n", node.tag)),
+        Some(value) => {
+            for (i, line) in value.split('\n').enumerate() {
+                let chunks = split_chars(line, MAX_VALUE_CHARS);
+                for (j, chunk) in chunks.iter().enumerate() {
+                    match (i, j) {
+                        (0, 0) => out.push_str(&format!("{level} {xref}{} {chunk}\n", node.tag)),
+                        (_, 0) => out.push_str(&format!("{} CONT {chunk}\n", level + 1)),
+                        _ => out.push_str(&format!("{} CONC {chunk}\n", level + 1)),
+                    }
+                }
+            }
+        }
+    }
+
+    for child in &node.children {
+        write_node(out, child, level + 1);
+    }
+}
+
+/// Split a string into chunks of at most `max` characters.
+///
+/// Always returns at least one (possibly empty) chunk, and avoids splitting
+/// right before a space since GEDCOM readers may trim `CONC` values.
+fn split_chars(s: &str, max: usize) -> Vec<String> {
+    let chars: Vec<char> = s.chars().collect();
+    if chars.len() <= max {
+        return vec![s.to_string()];
+    }
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + max).min(chars.len());
+        while end < chars.len() && end > start + 1 && chars[end] == ' ' {
+            end -= 1;
+        }
+        chunks.push(chars[start..end].iter().collect());
+        start = end;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error_line(input: &str) -> usize {
+        match parse(input) {
+            Err(InterchangeError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn nests_lines_by_level() {
+        let roots = parse("0 HEAD\n1 GEDC\n2 VERS 5.5.1\n1 CHAR UTF-8\n0 TRLR\n").unwrap();
+
+        assert_eq!(roots.len(), 2);
+        let head = &roots[0];
+        assert_eq!(head.tag, "HEAD");
+        assert_eq!(head.children.len(), 2);
+        assert_eq!(head.children[0].child_value("VERS"), Some("5.5.1"));
+        assert_eq!(head.child_value("CHAR"), Some("UTF-8"));
+        assert_eq!(roots[1].tag, "TRLR");
+    }
+
+    #[test]
+    fn reads_xrefs_pointers_and_upper_cases_tags() {
+        let roots = parse("0 @I1@ INDI\n1 name Ada /Lovelace/\n1 famc @F1@\n").unwrap();
+
+        let individual = &roots[0];
+        assert_eq!(individual.xref.as_deref(), Some("I1"));
+        assert_eq!(individual.tag, "INDI");
+        assert_eq!(individual.value, None);
+        assert_eq!(individual.child_value("NAME"), Some("Ada /Lovelace/"));
+        assert_eq!(individual.child("FAMC").unwrap().pointer(), Some("F1"));
+    }
+
+    #[test]
+    fn folds_continuations_into_the_parent_value() {
+        let roots = parse("0 @N1@ NOTE First\n1 CONT second\n1 CONC  line\n").unwrap();
+
+        assert_eq!(roots[0].value.as_deref(), Some("First\nsecond line"));
+        assert!(roots[0].children.is_empty());
+    }
+
+    #[test]
+    fn tolerates_bom_crlf_indentation_and_blank_lines() {
+        let roots = parse("\u{feff}0 HEAD\r\n\r\n  1 CHAR UTF-8\r\n").unwrap();
+
+        assert_eq!(roots[0].child_value("CHAR"), Some("UTF-8"));
+    }
+
+    #[test]
+    fn rejects_lines_without_a_tag() {
+        assert_eq!(parse_error_line("0 HEAD\n1\n"), 2);
+    }
+
+    #[test]
+    fn rejects_non_numeric_levels() {
+        assert_eq!(parse_error_line("0 HEAD\nX NAME Ada\n"), 2);
+    }
+
+    #[test]
+    fn rejects_skipped_levels() {
+        assert_eq!(parse_error_line("0 HEAD\n1 GEDC\n3 VERS 5.5.1\n"), 3);
+        assert_eq!(parse_error_line("1 NAME Ada\n"), 1);
+    }
+
+    #[test]
+    fn rejects_unterminated_xrefs() {
+        assert_eq!(parse_error_line("0 @I1 INDI\n"), 1);
+    }
+
+    #[test]
+    fn rejects_continuations_without_a_parent() {
+        assert_eq!(parse_error_line("0 CONT orphan\n"), 1);
+    }
+
+    #[test]
+    fn write_splits_long_and_multi_line_values_and_parses_back() {
+        let long = "word ".repeat(100);
+        let note = format!("{long}\nsecond line");
+        let records = vec![GedcomNode::new("NOTE", Some(note.clone())).with_child("SOUR", "x")];
+
+        let text = write(&records);
+
+        assert!(text.lines().all(|line| line.chars().count() <= 255));
+        assert!(text.contains("\n1 CONC "));
+        assert!(text.contains("\n1 CONT second line\n"));
+        assert_eq!(parse(&text).unwrap(), records);
+    }
+}
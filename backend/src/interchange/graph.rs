@@ -0,0 +1,403 @@
+//! # Relationship Graph Export
+//!
+//! Exports a user's social graph for graph tools: friends are nodes and
+//! friend relationships are edges.
+//!
+//! ## Formats
+//!
+//! - `Dot` - Graphviz. A `digraph` where symmetric edges have `dir=none`
+//! - `GraphMl` - Gephi, yEd, Cytoscape. Undirected by default, with
+//!   `directed="true"` on asymmetric edges
+//! - `Json` - node-link JSON as read by D3 and NetworkX's
+//!   `node_link_graph`, with a `directed` flag per link
+//!
+//! ## Direction
+//!
+//! A relationship with a `b_to_a` label ("boss of" / "employee of") is
+//! directed from A to B; one without is symmetric ("sibling of") and
+//! undirected. Edges carry both labels either way.
+//!
+//! ## Filtering
+//!
+//! `GraphFilter::groups` limits nodes to members of any listed group, and
+//! `GraphFilter::relationship_types` limits edges to relationships with a
+//! matching label in either direction. Both match case-insensitively, and
+//! empty lists match everything. Edges are only kept when both ends are.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::Date;
+use uuid::Uuid;
+
+use crate::models::Friend;
+use crate::repositories::{BackupRepository, RepositoryContext, UserSnapshot};
+
+use super::error::InterchangeError;
+
+/// Output format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphFormat {
+    Dot,
+    GraphMl,
+    Json,
+}
+
+/// Which nodes and edges to export.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphFilter {
+    /// Group names; nodes must belong to at least one
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Relationship labels; edges must match `a_to_b` or `b_to_a`
+    #[serde(default)]
+    pub relationship_types: Vec<String>,
+}
+
+/// A friend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphNode {
+    pub id: Uuid,
+    pub label: String,
+    /// Group names, sorted
+    pub groups: Vec<String>,
+    pub date_of_birth: Option<Date>,
+}
+
+/// A relationship between two friends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphEdge {
+    pub id: Uuid,
+    pub source: Uuid,
+    pub target: Uuid,
+    pub a_to_b: String,
+    pub b_to_a: Option<String>,
+    pub status: String,
+}
+
+impl GraphEdge {
+    /// Asymmetric relationships are directed from A to B.
+    pub fn directed(&self) -> bool {
+        self.b_to_a.is_some()
+    }
+
+    /// "boss of / employee of", or just "sibling of".
+    pub fn label(&self) -> String {
+        match &self.b_to_a {
+            Some(b_to_a) => format!("{} / {}", self.a_to_b, b_to_a),
+            None => self.a_to_b.clone(),
+        }
+    }
+}
+
+/// Nodes and edges after filtering.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl Graph {
+    /// Build the graph from a snapshot of the user's data.
+    ///
+    /// # Errors
+    ///
+    /// `Unsupported` if a filter group doesn't exist, since an empty graph
+    /// from a typo is easy to mistake for "no relationships".
+    pub fn build(snapshot: &UserSnapshot, filter: &GraphFilter) -> Result<Self, InterchangeError> {
+        let group_names: HashMap<Uuid, &str> = snapshot
+            .groups
+            .iter()
+            .map(|g| (g.id, g.name.as_str()))
+            .collect();
+
+        let wanted_groups: HashSet<String> =
+            filter.groups.iter().map(|g| g.to_lowercase()).collect();
+        for name in &filter.groups {
+            if !group_names
+                .values()
+                .any(|g| g.to_lowercase() == name.to_lowercase())
+            {
+                return Err(InterchangeError::Unsupported(format!(
+                    "no group named {name:?}"
+                )));
+            }
+        }
+
+        let mut nodes = Vec::new();
+        for friend in &snapshot.friends {
+            let mut groups: Vec<String> = snapshot
+                .friend_groups
+                .iter()
+                .filter(|m| m.friend_id == friend.id)
+                .filter_map(|m| group_names.get(&m.group_id).map(|n| n.to_string()))
+                .collect();
+            groups.sort_by_key(|g| g.to_lowercase());
+
+            if !wanted_groups.is_empty()
+                && !groups
+                    .iter()
+                    .any(|g| wanted_groups.contains(&g.to_lowercase()))
+            {
+                continue;
+            }
+
+            nodes.push(GraphNode {
+                id: friend.id,
+                label: display_name(friend),
+                groups,
+                date_of_birth: friend.date_of_birth,
+            });
+        }
+
+        let included: HashSet<Uuid> = nodes.iter().map(|n| n.id).collect();
+        let wanted_types: HashSet<String> = filter
+            .relationship_types
+            .iter()
+            .map(|t| t.to_lowercase())
+            .collect();
+        let type_matches = |label: &str| wanted_types.contains(&label.to_lowercase());
+
+        let edges = snapshot
+            .friend_relationships
+            .iter()
+            .filter(|r| included.contains(&r.friend_a_id) && included.contains(&r.friend_b_id))
+            .filter(|r| {
+                wanted_types.is_empty()
+                    || type_matches(&r.a_to_b)
+                    || r.b_to_a.as_deref().is_some_and(type_matches)
+            })
+            .map(|r| GraphEdge {
+                id: r.id,
+                source: r.friend_a_id,
+                target: r.friend_b_id,
+                a_to_b: r.a_to_b.clone(),
+                b_to_a: r.b_to_a.clone(),
+                status: r.status.clone(),
+            })
+            .collect();
+
+        Ok(Self { nodes, edges })
+    }
+
+    /// Render in the given format.
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::GraphMl => self.to_graphml(),
+            GraphFormat::Json => self.to_json(),
+        }
+    }
+
+    /// Graphviz DOT.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph friends {\n  node [shape=box, style=rounded];\n");
+
+        for node in &self.nodes {
+            out.push_str(&format!(
+                "  \"{}\" [label={}, groups={}",
+                node.id,
+                dot_string(&node.label),
+                dot_string(&node.groups.join(";"))
+            ));
+            if let Some(born) = node.date_of_birth {
+                out.push_str(&format!(", date_of_birth=\"{born}\""));
+            }
+            out.push_str("];\n");
+        }
+
+        for edge in &self.edges {
+            out.push_str(&format!(
+                "  \"{}\" -> \"{}\" [label={}, a_to_b={}",
+                edge.source,
+                edge.target,
+                dot_string(&edge.label()),
+                dot_string(&edge.a_to_b)
+            ));
+            if let Some(b_to_a) = &edge.b_to_a {
+                out.push_str(&format!(", b_to_a={}", dot_string(b_to_a)));
+            } else {
+                out.push_str(", dir=none");
+            }
+            if edge.status != "active" {
+                out.push_str(&format!(
+                    ", status={}, style=dashed",
+                    dot_string(&edge.status)
+                ));
+            }
+            out.push_str("];\n");
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    /// GraphML with `label`, `groups` and `date_of_birth` on nodes and
+    /// `label`, `a_to_b`, `b_to_a` and `status` on edges.
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns"
+         xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+         xsi:schemaLocation="http://graphml.graphdrawing.org/xmlns http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd">
+  <key id="n_label" for="node" attr.name="label" attr.type="string"/>
+  <key id="n_groups" for="node" attr.name="groups" attr.type="string"/>
+  <key id="n_birth" for="node" attr.name="date_of_birth" attr.type="string"/>
+  <key id="e_label" for="edge" attr.name="label" attr.type="string"/>
+  <key id="e_a_to_b" for="edge" attr.name="a_to_b" attr.type="string"/>
+  <key id="e_b_to_a" for="edge" attr.name="b_to_a" attr.type="string"/>
+  <key id="e_status" for="edge" attr.name="status" attr.type="string"/>
+  <graph id="friends" edgedefault="undirected">
+"#,
+        );
+
+        for node in &self.nodes {
+            out.push_str(&format!("    <node id=\"{}\">\n", node.id));
+            out.push_str(&graphml_data("n_label", &node.label));
+            if !node.groups.is_empty() {
+                out.push_str(&graphml_data("n_groups", &node.groups.join(";")));
+            }
+            if let Some(born) = node.date_of_birth {
+                out.push_str(&graphml_data("n_birth", &born.to_string()));
+            }
+            out.push_str("    </node>\n");
+        }
+
+        for edge in &self.edges {
+            out.push_str(&format!(
+                "    <edge id=\"{}\" source=\"{}\" target=\"{}\" directed=\"{}\">\n",
+                edge.id,
+                edge.source,
+                edge.target,
+                edge.directed()
+            ));
+            out.push_str(&graphml_data("e_label", &edge.label()));
+            out.push_str(&graphml_data("e_a_to_b", &edge.a_to_b));
+            if let Some(b_to_a) = &edge.b_to_a {
+                out.push_str(&graphml_data("e_b_to_a", b_to_a));
+            }
+            out.push_str(&graphml_data("e_status", &edge.status));
+            out.push_str("    </edge>\n");
+        }
+
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+
+    /// Node-link JSON.
+    pub fn to_json(&self) -> String {
+        let nodes: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                json!({
+                    "id": node.id,
+                    "label": node.label,
+                    "groups": node.groups,
+                    "date_of_birth": node.date_of_birth.map(|d| d.to_string()),
+                })
+            })
+            .collect();
+        let links: Vec<_> = self
+            .edges
+            .iter()
+            .map(|edge| {
+                json!({
+                    "id": edge.id,
+                    "source": edge.source,
+                    "target": edge.target,
+                    "label": edge.label(),
+                    "a_to_b": edge.a_to_b,
+                    "b_to_a": edge.b_to_a,
+                    "directed": edge.directed(),
+                    "status": edge.status,
+                })
+            })
+            .collect();
+
+        let graph = json!({
+            "directed": self.edges.iter().any(GraphEdge::directed),
+            "multigraph": true,
+            "graph": {},
+            "nodes": nodes,
+            "links": links,
+        });
+        // A `Value` always serializes
+        serde_json::to_string_pretty(&graph).unwrap_or_default() + "\n"
+    }
+}
+
+/// "First Last", or just "First".
+fn display_name(friend: &Friend) -> String {
+    match &friend.last_name {
+        Some(last) if !last.trim().is_empty() => format!("{} {}", friend.first_name, last),
+        _ => friend.first_name.clone(),
+    }
+}
+
+/// Quote a DOT string.
+fn dot_string(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+fn graphml_data(key: &str, value: &str) -> String {
+    format!("      <data key=\"{key}\">{}</data>\n", xml_escape(value))
+}
+
+fn xml_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            // XML 1.0 can't carry most control characters at all
+            c if c.is_control() && !matches!(c, '\n' | '\t' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Exports a user's relationship graph.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let filter = GraphFilter {
+///     groups: vec!["Family".to_string()],
+///     ..Default::default()
+/// };
+/// let dot = GraphExporter::new(ctx.clone())
+///     .export(user_id, GraphFormat::Dot, &filter)
+///     .await?;
+/// ```
+pub struct GraphExporter {
+    backups: BackupRepository,
+}
+
+impl GraphExporter {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            backups: BackupRepository::new(ctx),
+        }
+    }
+
+    /// Build and render the user's graph.
+    pub async fn export(
+        &self,
+        user_id: Uuid,
+        format: GraphFormat,
+        filter: &GraphFilter,
+    ) -> Result<String, InterchangeError> {
+        let snapshot = self.backups.snapshot_live(user_id).await?;
+        Ok(Graph::build(&snapshot, filter)?.render(format))
+    }
+}
@@ -0,0 +1,74 @@
+//! # Static Assets
+//!
+//! The stylesheet and search script shared by every page. The search
+//! index is a script that sets a global rather than a JSON file, because
+//! browsers refuse `fetch` on `file://` URLs.
+
+/// `style.css`
+pub const STYLE: &str = r#"
+:root { --fg: #1f2328; --muted: #656d76; --accent: #0969da; --line: #d0d7de; --soft: #f6f8fa; }
+* { box-sizing: border-box; }
+body { margin: 0; font: 16px/1.5 system-ui, -apple-system, "Segoe UI", sans-serif; color: var(--fg); }
+header { border-bottom: 1px solid var(--line); background: var(--soft); }
+header nav { max-width: 60rem; margin: 0 auto; padding: .75rem 1rem; display: flex; gap: 1.25rem; align-items: center; }
+header nav strong { margin-right: auto; }
+main { max-width: 60rem; margin: 0 auto; padding: 1rem; }
+a { color: var(--accent); text-decoration: none; }
+a:hover { text-decoration: underline; }
+h1 { margin: .5rem 0 1rem; }
+h2 { border-bottom: 1px solid var(--line); padding-bottom: .25rem; margin-top: 2rem; }
+.muted { color: var(--muted); }
+.letters { columns: 16rem; }
+.letters section { break-inside: avoid; }
+.tags a { display: inline-block; background: var(--soft); border: 1px solid var(--line); border-radius: 1rem; padding: 0 .6rem; margin: 0 .25rem .25rem 0; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: .35rem .5rem; border-bottom: 1px solid var(--line); vertical-align: top; }
+th { width: 30%; color: var(--muted); font-weight: normal; }
+.soon { font-weight: bold; }
+#search { width: 100%; padding: .5rem .75rem; font-size: 1rem; border: 1px solid var(--line); border-radius: .375rem; }
+#results { list-style: none; padding: 0; }
+#results li { padding: .25rem 0; }
+"#;
+
+/// `search.js`; expects `FKB_SEARCH` (from `search-index.js`) and a
+/// `<meta name="fkb-root">` tag with the relative path to the site root.
+pub const SEARCH: &str = r#"
+(function () {
+  var input = document.getElementById("search");
+  var results = document.getElementById("results");
+  if (!input || !results || !window.FKB_SEARCH) return;
+  var root = document.querySelector('meta[name="fkb-root"]').content;
+
+  input.addEventListener("input", function () {
+    var words = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+    results.innerHTML = "";
+    if (!words.length) return;
+
+    var matches = window.FKB_SEARCH.filter(function (entry) {
+      var haystack = (entry.title + " " + entry.text).toLowerCase();
+      return words.every(function (word) { return haystack.indexOf(word) !== -1; });
+    }).slice(0, 25);
+
+    matches.forEach(function (entry) {
+      var li = document.createElement("li");
+      var a = document.createElement("a");
+      a.href = root + entry.url;
+      a.textContent = entry.title;
+      li.appendChild(a);
+      if (entry.kind === "group") {
+        var kind = document.createElement("span");
+        kind.className = "muted";
+        kind.textContent = " (group)";
+        li.appendChild(kind);
+      }
+      results.appendChild(li);
+    });
+    if (!matches.length) {
+      var none = document.createElement("li");
+      none.className = "muted";
+      none.textContent = "No matches";
+      results.appendChild(none);
+    }
+  });
+})();
+"#;
@@ -0,0 +1,675 @@
+//! # Static HTML Site Export
+//!
+//! Renders a user's knowledgebase as a self-contained HTML site that can
+//! be opened straight from disk, with no server.
+//!
+//! ## Layout
+//!
+//! ```text
+//! index.html              friend index (A–Z) and search box
+//! birthdays.html          upcoming birthdays, soonest first
+//! friends/<slug>.html     one page per friend
+//! groups/index.html       all groups
+//! groups/<slug>.html      one page per group
+//! search-index.js         client-side search data
+//! search.js, style.css
+//! ```
+//!
+//! Every link is relative, so the folder can be zipped, copied to a USB
+//! stick or opened with `file://`. Slugs come from names; clashes get
+//! "-2", "-3", ... in creation order.
+
+mod assets;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use serde::Serialize;
+use time::{Date, Month, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::models::{Friend, FriendRelationship, Group};
+use crate::repositories::{BackupRepository, RepositoryContext, UserSnapshot};
+
+use super::error::InterchangeError;
+
+/// Birthdays this many days away or fewer are highlighted.
+pub const SOON_DAYS: i64 = 30;
+
+/// One file of the site.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiteFile {
+    /// Path relative to the site root, with `/` separators
+    pub path: String,
+    pub contents: String,
+}
+
+/// An entry in `search-index.js`.
+#[derive(Serialize)]
+struct SearchEntry<'a> {
+    kind: &'a str,
+    title: String,
+    url: String,
+    /// Other searchable text: groups, attributes, likes, ...
+    text: String,
+}
+
+/// Lookups shared by every page.
+struct Site<'a> {
+    snapshot: &'a UserSnapshot,
+    friend_slugs: HashMap<Uuid, String>,
+    group_slugs: HashMap<Uuid, String>,
+    friends_by_id: HashMap<Uuid, &'a Friend>,
+    groups_by_id: HashMap<Uuid, &'a Group>,
+}
+
+/// Render the whole site.
+///
+/// # Arguments
+///
+/// * `snapshot` - The user's data
+/// * `title` - Site title, shown in every page header
+/// * `today` - Reference date for the birthdays page
+pub fn render_site(snapshot: &UserSnapshot, title: &str, today: Date) -> Vec<SiteFile> {
+    let site = Site {
+        snapshot,
+        friend_slugs: unique_slugs(snapshot.friends.iter().map(|f| (f.id, display_name(f)))),
+        group_slugs: unique_slugs(snapshot.groups.iter().map(|g| (g.id, g.name.clone()))),
+        friends_by_id: snapshot.friends.iter().map(|f| (f.id, f)).collect(),
+        groups_by_id: snapshot.groups.iter().map(|g| (g.id, g)).collect(),
+    };
+
+    let mut files = vec![
+        SiteFile {
+            path: "index.html".to_string(),
+            contents: page(title, "Friends", "", &site.index_body()),
+        },
+        SiteFile {
+            path: "birthdays.html".to_string(),
+            contents: page(title, "Upcoming birthdays", "", &site.birthdays_body(today)),
+        },
+        SiteFile {
+            path: "groups/index.html".to_string(),
+            contents: page(title, "Groups", "../", &site.groups_body()),
+        },
+        SiteFile {
+            path: "search-index.js".to_string(),
+            contents: site.search_index(),
+        },
+        SiteFile {
+            path: "search.js".to_string(),
+            contents: assets::SEARCH.trim_start().to_string(),
+        },
+        SiteFile {
+            path: "style.css".to_string(),
+            contents: assets::STYLE.trim_start().to_string(),
+        },
+    ];
+
+    for friend in &snapshot.friends {
+        files.push(SiteFile {
+            path: format!("friends/{}.html", site.friend_slugs[&friend.id]),
+            contents: page(
+                title,
+                &display_name(friend),
+                "../",
+                &site.friend_body(friend, today),
+            ),
+        });
+    }
+    for group in &snapshot.groups {
+        files.push(SiteFile {
+            path: format!("groups/{}.html", site.group_slugs[&group.id]),
+            contents: page(title, &group.name, "../", &site.group_body(group)),
+        });
+    }
+
+    files
+}
+
+/// Write site files under `dir`, creating folders as needed.
+pub fn write_site(dir: &Path, files: &[SiteFile]) -> std::io::Result<()> {
+    for file in files {
+        let path = dir.join(&file.path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, &file.contents)?;
+    }
+    Ok(())
+}
+
+impl Site<'_> {
+    /// Link to a friend page; `root` is the relative path to the site root.
+    fn friend_link(&self, friend: &Friend, root: &str) -> String {
+        format!(
+            r#"<a href="{root}friends/{}.html">{}</a>"#,
+            self.friend_slugs[&friend.id],
+            escape(&display_name(friend))
+        )
+    }
+
+    fn group_link(&self, group: &Group, root: &str) -> String {
+        format!(
+            r#"<a href="{root}groups/{}.html">{}</a>"#,
+            self.group_slugs[&group.id],
+            escape(&group.name)
+        )
+    }
+
+    fn groups_of(&self, friend_id: Uuid) -> Vec<&Group> {
+        let mut groups: Vec<&Group> = self
+            .snapshot
+            .friend_groups
+            .iter()
+            .filter(|m| m.friend_id == friend_id)
+            .filter_map(|m| self.groups_by_id.get(&m.group_id).copied())
+            .collect();
+        groups.sort_by_key(|g| g.name.to_lowercase());
+        groups
+    }
+
+    fn members_of(&self, group_id: Uuid) -> Vec<&Friend> {
+        let mut members: Vec<&Friend> = self
+            .snapshot
+            .friend_groups
+            .iter()
+            .filter(|m| m.group_id == group_id)
+            .filter_map(|m| self.friends_by_id.get(&m.friend_id).copied())
+            .collect();
+        members.sort_by_key(|f| sort_key(f));
+        members
+    }
+
+    fn index_body(&self) -> String {
+        let mut out = String::from(
+            r#"<input id="search" type="search" placeholder="Search friends, groups, notes…" autofocus>
+<ul id="results"></ul>
+"#,
+        );
+
+        let mut by_letter: BTreeMap<String, Vec<&Friend>> = BTreeMap::new();
+        for friend in &self.snapshot.friends {
+            let letter = display_name(friend)
+                .chars()
+                .next()
+                .filter(|c| c.is_alphabetic())
+                .map_or("#".to_string(), |c| c.to_uppercase().to_string());
+            by_letter.entry(letter).or_default().push(friend);
+        }
+
+        if by_letter.is_empty() {
+            out.push_str(r#"<p class="muted">No friends yet.</p>"#);
+            return out;
+        }
+
+        out.push_str(r#"<div class="letters">"#);
+        for (letter, mut friends) in by_letter {
+            friends.sort_by_key(|f| sort_key(f));
+            out.push_str(&format!("\n<section><h2>{}</h2><ul>", escape(&letter)));
+            for friend in friends {
+                out.push_str(&format!("<li>{}</li>", self.friend_link(friend, "")));
+            }
+            out.push_str("</ul></section>");
+        }
+        out.push_str("\n</div>\n");
+        out
+    }
+
+    fn friend_body(&self, friend: &Friend, today: Date) -> String {
+        let mut out = String::new();
+
+        if let Some(born) = friend.date_of_birth {
+            out.push_str(&format!(
+                r#"<p class="muted">Born {} · {} years old</p>"#,
+                format_date(born),
+                age_on(born, today)
+            ));
+            out.push('\n');
+        }
+
+        let groups = self.groups_of(friend.id);
+        if !groups.is_empty() {
+            out.push_str(r#"<p class="tags">"#);
+            for group in groups {
+                out.push_str(&self.group_link(group, "../"));
+            }
+            out.push_str("</p>\n");
+        }
+
+        for (heading, text) in [
+            ("Likes", &friend.likes),
+            ("Dislikes", &friend.dislikes),
+            ("Notes", &friend.notes),
+        ] {
+            if let Some(text) = text.as_deref().map(str::trim)
+                && !text.is_empty()
+            {
+                out.push_str(&format!("<h2>{heading}</h2>\n{}", paragraphs(text)));
+            }
+        }
+
+        let attributes: Vec<_> = self
+            .snapshot
+            .friend_attributes
+            .iter()
+            .filter(|a| a.friend_id == friend.id)
+            .collect();
+        if !attributes.is_empty() {
+            out.push_str("<h2>Details</h2>\n<table>\n");
+            for attribute in attributes {
+                out.push_str(&format!(
+                    "<tr><th>{}</th><td>{}</td></tr>\n",
+                    escape(&attribute.key),
+                    attribute_value(&attribute.value, &attribute.value_type)
+                ));
+            }
+            out.push_str("</table>\n");
+        }
+
+        let mine: Vec<_> = self
+            .snapshot
+            .user_friend_relationships
+            .iter()
+            .filter(|r| r.friend_id == friend.id)
+            .collect();
+        let theirs: Vec<String> = self
+            .snapshot
+            .friend_relationships
+            .iter()
+            .filter_map(|r| self.relationship_item(r, friend.id))
+            .collect();
+
+        if !mine.is_empty() || !theirs.is_empty() {
+            out.push_str("<h2>Relationships</h2>\n<ul>\n");
+            for relationship in mine {
+                out.push_str(&format!(
+                    "<li>Your {}{}</li>\n",
+                    escape(&relationship.relationship_type),
+                    details(
+                        &relationship.status,
+                        relationship.started_on,
+                        relationship.ended_on
+                    )
+                ));
+            }
+            for item in theirs {
+                out.push_str(&item);
+            }
+            out.push_str("</ul>\n");
+        }
+
+        out
+    }
+
+    /// The relationship as seen from `friend_id`, or `None` if it doesn't
+    /// involve them.
+    fn relationship_item(
+        &self,
+        relationship: &FriendRelationship,
+        friend_id: Uuid,
+    ) -> Option<String> {
+        let (label, other) = if relationship.friend_a_id == friend_id {
+            (relationship.a_to_b.as_str(), relationship.friend_b_id)
+        } else if relationship.friend_b_id == friend_id {
+            (
+                relationship
+                    .b_to_a
+                    .as_deref()
+                    .unwrap_or(&relationship.a_to_b),
+                relationship.friend_a_id,
+            )
+        } else {
+            return None;
+        };
+        let other = self.friends_by_id.get(&other)?;
+
+        Some(format!(
+            "<li>{} {}{}</li>\n",
+            escape(label),
+            self.friend_link(other, "../"),
+            details(
+                &relationship.status,
+                relationship.started_on,
+                relationship.ended_on
+            )
+        ))
+    }
+
+    fn groups_body(&self) -> String {
+        if self.snapshot.groups.is_empty() {
+            return r#"<p class="muted">No groups yet.</p>"#.to_string();
+        }
+
+        let mut groups: Vec<&Group> = self.snapshot.groups.iter().collect();
+        groups.sort_by_key(|g| g.name.to_lowercase());
+
+        let mut out = String::from("<ul>\n");
+        for group in groups {
+            out.push_str(&format!(
+                r#"<li>{} <span class="muted">({})</span></li>"#,
+                self.group_link(group, "../"),
+                self.members_of(group.id).len()
+            ));
+            out.push('\n');
+        }
+        out.push_str("</ul>\n");
+        out
+    }
+
+    fn group_body(&self, group: &Group) -> String {
+        let mut out = String::new();
+        if let Some(description) = group.description.as_deref().map(str::trim)
+            && !description.is_empty()
+        {
+            out.push_str(&paragraphs(description));
+        }
+
+        let members = self.members_of(group.id);
+        out.push_str(&format!("<h2>Members ({})</h2>\n", members.len()));
+        if members.is_empty() {
+            out.push_str(r#"<p class="muted">No members yet.</p>"#);
+            out.push('\n');
+            return out;
+        }
+        out.push_str("<ul>\n");
+        for friend in members {
+            out.push_str(&format!("<li>{}</li>\n", self.friend_link(friend, "../")));
+        }
+        out.push_str("</ul>\n");
+        out
+    }
+
+    fn birthdays_body(&self, today: Date) -> String {
+        let mut upcoming: Vec<(i64, Date, &Friend)> = self
+            .snapshot
+            .friends
+            .iter()
+            .filter_map(|f| {
+                let born = f.date_of_birth?;
+                let next = next_birthday(born, today);
+                Some(((next - today).whole_days(), next, f))
+            })
+            .collect();
+        upcoming.sort_by_key(|(days, _, friend)| (*days, sort_key(friend)));
+
+        if upcoming.is_empty() {
+            return r#"<p class="muted">No birthdays recorded yet.</p>"#.to_string();
+        }
+
+        let mut out = format!(
+            r#"<p class="muted">As of {}. Birthdays in the next {SOON_DAYS} days are in bold.</p>
+<table>
+<tr><th>Date</th><th>Friend</th><th>Turns</th><th>In</th></tr>
+"#,
+            format_date(today)
+        );
+        for (days, next, friend) in upcoming {
+            let class = if days <= SOON_DAYS {
+                r#" class="soon""#
+            } else {
+                ""
+            };
+            let when = match days {
+                0 => "today".to_string(),
+                1 => "tomorrow".to_string(),
+                n => format!("{n} days"),
+            };
+            out.push_str(&format!(
+                "<tr{class}><td>{}</td><td>{}</td><td>{}</td><td>{when}</td></tr>\n",
+                format_date(next),
+                self.friend_link(friend, ""),
+                next.year() - friend.date_of_birth.map_or(next.year(), |b| b.year())
+            ));
+        }
+        out.push_str("</table>\n");
+        out
+    }
+
+    fn search_index(&self) -> String {
+        let mut entries = Vec::new();
+        for friend in &self.snapshot.friends {
+            let mut text: Vec<&str> = self
+                .groups_of(friend.id)
+                .into_iter()
+                .map(|g| g.name.as_str())
+                .collect();
+            text.extend(
+                self.snapshot
+                    .friend_attributes
+                    .iter()
+                    .filter(|a| a.friend_id == friend.id)
+                    .map(|a| a.value.as_str()),
+            );
+            text.extend(
+                [&friend.likes, &friend.dislikes, &friend.notes]
+                    .into_iter()
+                    .filter_map(|t| t.as_deref()),
+            );
+
+            entries.push(SearchEntry {
+                kind: "friend",
+                title: display_name(friend),
+                url: format!("friends/{}.html", self.friend_slugs[&friend.id]),
+                text: text.join(" "),
+            });
+        }
+        for group in &self.snapshot.groups {
+            entries.push(SearchEntry {
+                kind: "group",
+                title: group.name.clone(),
+                url: format!("groups/{}.html", self.group_slugs[&group.id]),
+                text: group.description.clone().unwrap_or_default(),
+            });
+        }
+
+        // Serializing plain strings can't fail
+        let json = serde_json::to_string(&entries).unwrap_or_else(|_| "[]".to_string());
+        // Keep "</script>" in a note from ending an inline script early
+        format!("window.FKB_SEARCH = {};\n", json.replace("</", "<\\/"))
+    }
+}
+
+/// Wrap a body in the shared page layout.
+///
+/// `root` is the relative path from the page to the site root ("" or "../").
+fn page(site_title: &str, heading: &str, root: &str, body: &str) -> String {
+    let site_title = escape(site_title);
+    let heading = escape(heading);
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="fkb-root" content="{root}">
+<title>{heading} · {site_title}</title>
+<link rel="stylesheet" href="{root}style.css">
+</head>
+<body>
+<header><nav>
+<strong>{site_title}</strong>
+<a href="{root}index.html">Friends</a>
+<a href="{root}groups/index.html">Groups</a>
+<a href="{root}birthdays.html">Birthdays</a>
+</nav></header>
+<main>
+<h1>{heading}</h1>
+{body}</main>
+<script src="{root}search-index.js"></script>
+<script src="{root}search.js"></script>
+</body>
+</html>
+"#
+    )
+}
+
+/// Escape text for HTML element content and attribute values.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Free text as paragraphs: blank lines split paragraphs, single
+/// newlines become line breaks.
+fn paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| format!("<p>{}</p>\n", escape(p).replace('\n', "<br>\n")))
+        .collect()
+}
+
+/// Render an attribute value, linking emails, phone numbers and URLs.
+fn attribute_value(value: &str, value_type: &str) -> String {
+    let text = escape(value);
+    let href = match value_type {
+        "email" => Some(format!("mailto:{}", value.trim())),
+        "phone" => Some(format!("tel:{}", value.trim().replace(' ', ""))),
+        _ if value.starts_with("https://") || value.starts_with("http://") => {
+            Some(value.trim().to_string())
+        }
+        _ => None,
+    };
+    match href {
+        Some(href) => format!(r#"<a href="{}">{text}</a>"#, escape(&href)),
+        None => text.replace('\n', "<br>"),
+    }
+}
+
+/// " (ended, 2019-01-01 – 2020-06-30)"-style suffix; empty for an
+/// active relationship with no dates.
+fn details(status: &str, started_on: Option<Date>, ended_on: Option<Date>) -> String {
+    let mut parts = Vec::new();
+    if status != "active" {
+        parts.push(escape(status));
+    }
+    match (started_on, ended_on) {
+        (Some(start), Some(end)) => parts.push(format!("{start} – {end}")),
+        (Some(start), None) => parts.push(format!("since {start}")),
+        (None, Some(end)) => parts.push(format!("until {end}")),
+        (None, None) => {}
+    }
+
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(r#" <span class="muted">({})</span>"#, parts.join(", "))
+    }
+}
+
+/// "First Last", or just "First".
+fn display_name(friend: &Friend) -> String {
+    match &friend.last_name {
+        Some(last) if !last.trim().is_empty() => format!("{} {}", friend.first_name, last),
+        _ => friend.first_name.clone(),
+    }
+}
+
+/// Case-insensitive sort by display name.
+fn sort_key(friend: &Friend) -> String {
+    display_name(friend).to_lowercase()
+}
+
+/// URL-safe slug for each ID, suffixing clashes in iteration order.
+fn unique_slugs(names: impl Iterator<Item = (Uuid, String)>) -> HashMap<Uuid, String> {
+    let mut taken = HashSet::new();
+    let mut slugs = HashMap::new();
+
+    for (id, name) in names {
+        let mut base = String::new();
+        for c in name.to_lowercase().chars() {
+            if c.is_alphanumeric() {
+                base.push(c);
+            } else if !base.is_empty() && !base.ends_with('-') {
+                base.push('-');
+            }
+        }
+        let base = match base.trim_end_matches('-') {
+            "" => "page".to_string(),
+            // Would clash with groups/index.html
+            "index" => "index-page".to_string(),
+            slug => slug.to_string(),
+        };
+
+        let mut slug = base.clone();
+        let mut n = 2;
+        while !taken.insert(slug.clone()) {
+            slug = format!("{base}-{n}");
+            n += 1;
+        }
+        slugs.insert(id, slug);
+    }
+    slugs
+}
+
+/// "12 March 1950".
+fn format_date(date: Date) -> String {
+    format!("{} {} {}", date.day(), date.month(), date.year())
+}
+
+/// Whole years between `born` and `on`.
+fn age_on(born: Date, on: Date) -> i32 {
+    let mut age = on.year() - born.year();
+    if (on.month() as u8, on.day()) < (born.month() as u8, born.day()) {
+        age -= 1;
+    }
+    age
+}
+
+/// The next time `born`'s day comes round, today included. February 29
+/// falls on February 28 in other years.
+fn next_birthday(born: Date, today: Date) -> Date {
+    let in_year = |year: i32| {
+        Date::from_calendar_date(year, born.month(), born.day())
+            .or_else(|_| Date::from_calendar_date(year, Month::February, 28))
+            .unwrap_or(today)
+    };
+    let this_year = in_year(today.year());
+    if this_year >= today {
+        this_year
+    } else {
+        in_year(today.year() + 1)
+    }
+}
+
+/// Exports a user's knowledgebase as a static HTML site.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let files = HtmlExporter::new(ctx.clone()).export(user_id, "Ada's friends").await?;
+/// html::write_site(Path::new("site"), &files)?;
+/// ```
+pub struct HtmlExporter {
+    backups: BackupRepository,
+}
+
+impl HtmlExporter {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            backups: BackupRepository::new(ctx),
+        }
+    }
+
+    /// Render every page for a user, with birthdays counted from today.
+    pub async fn export(
+        &self,
+        user_id: Uuid,
+        title: &str,
+    ) -> Result<Vec<SiteFile>, InterchangeError> {
+        let snapshot = self.backups.snapshot_live(user_id).await?;
+        let today = OffsetDateTime::now_utc().date();
+        Ok(render_site(&snapshot, title, today))
+    }
+}
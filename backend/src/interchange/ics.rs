@@ -0,0 +1,554 @@
+//! # iCalendar Feed
+//!
+//! Renders a user's important dates as an iCalendar (RFC 5545) feed that
+//! calendar apps can subscribe to: a yearly all-day event for every
+//! friend's birthday and for every date-typed attribute ("anniversary",
+//! "name_day", ...).
+//!
+//! ## Stable UIDs
+//!
+//! Event UIDs come from database IDs (`birthday-<friend id>@fkb`,
+//! `attribute-<attribute id>@fkb`), which never change when a friend or
+//! attribute is edited. Subscribed calendars therefore update an event in
+//! place instead of adding a second copy. `DTSTAMP` is the row's last
+//! modification time, so the feed is byte-for-byte stable until the data
+//! actually changes.
+//!
+//! ## February 29
+//!
+//! A plain yearly rule on February 29 only fires in leap years, so those
+//! dates recur on the last day of February instead.
+//!
+//! ## Reminders
+//!
+//! With `alarm_minutes_before` set, every event carries a display
+//! `VALARM` that many minutes before the start of the day.
+
+use time::{Date, Duration, Month, OffsetDateTime, UtcOffset};
+use uuid::Uuid;
+
+use crate::models::{Friend, FriendAttribute};
+use crate::repositories::{BackupRepository, RepositoryContext, UserSnapshot};
+
+use super::error::InterchangeError;
+use super::vcard::property::{escape, write_line};
+
+/// `PRODID` of every generated calendar.
+const PRODID: &str = "-//Friend Knowledgebase//Calendar Feed//EN";
+
+/// How often subscribers are asked to refresh.
+const REFRESH_INTERVAL: &str = "PT12H";
+
+/// Render the calendar for a snapshot.
+///
+/// # Arguments
+///
+/// * `snapshot` - Everything the user owns
+/// * `name` - Calendar name shown by subscribing apps
+/// * `alarm_minutes_before` - Reminder lead time, or `None` for no reminders
+///
+/// # Returns
+///
+/// The calendar text, with CRLF line endings and folded lines.
+pub fn render_calendar(
+    snapshot: &UserSnapshot,
+    name: &str,
+    alarm_minutes_before: Option<i32>,
+) -> String {
+    let mut out = String::new();
+    begin_calendar(&mut out);
+    write_line(&mut out, "METHOD", &[], "PUBLISH");
+    write_line(&mut out, "X-WR-CALNAME", &[], &escape(name));
+    write_line(
+        &mut out,
+        "REFRESH-INTERVAL",
+        &[("VALUE", "DURATION")],
+        REFRESH_INTERVAL,
+    );
+    write_line(&mut out, "X-PUBLISHED-TTL", &[], REFRESH_INTERVAL);
+
+    for event in events(snapshot) {
+        event.write(&mut out, alarm_minutes_before);
+    }
+
+    write_line(&mut out, "END", &[], "VCALENDAR");
+    out
+}
+
+/// Render one event as a calendar of its own.
+///
+/// This is the shape CalDAV serves: one `VEVENT` per resource and no
+/// `METHOD` (RFC 4791, section 4.1).
+pub fn render_event(event: &CalendarEvent, alarm_minutes_before: Option<i32>) -> String {
+    let mut out = String::new();
+    begin_calendar(&mut out);
+    event.write(&mut out, alarm_minutes_before);
+    write_line(&mut out, "END", &[], "VCALENDAR");
+    out
+}
+
+/// Every event in a snapshot: each friend's birthday, then their dated
+/// attributes, in snapshot order.
+pub fn events(snapshot: &UserSnapshot) -> Vec<CalendarEvent> {
+    let mut events = Vec::new();
+
+    for friend in &snapshot.friends {
+        let name = display_name(friend);
+
+        if let Some(born) = friend.date_of_birth {
+            events.push(CalendarEvent {
+                uid: format!("birthday-{}@fkb", friend.id),
+                friend_id: friend.id,
+                stamp: modified_at(friend.created_at, friend.updated_at),
+                date: born,
+                summary: format!("{name}'s birthday"),
+                description: format!("Born {}", format_date(born)),
+            });
+        }
+
+        for attribute in dated_attributes(&snapshot.friend_attributes, friend.id) {
+            let Some(date) = parse_iso_date(attribute.value.trim()) else {
+                continue;
+            };
+            // The summary includes the friend's name, so renaming them
+            // changes this event too
+            let stamp = modified_at(attribute.created_at, attribute.updated_at)
+                .max(modified_at(friend.created_at, friend.updated_at));
+            let label = humanize(&attribute.key);
+            events.push(CalendarEvent {
+                uid: format!("attribute-{}@fkb", attribute.id),
+                friend_id: friend.id,
+                stamp,
+                date,
+                summary: format!("{name}: {label}"),
+                description: format!("{label}: {}", format_date(date)),
+            });
+        }
+    }
+
+    events
+}
+
+/// One yearly all-day event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalendarEvent {
+    /// Stable UID, e.g. "birthday-<friend id>@fkb"
+    pub uid: String,
+    /// The friend the event belongs to
+    pub friend_id: Uuid,
+    /// Last change to anything the event shows
+    pub stamp: OffsetDateTime,
+    /// The first occurrence
+    pub date: Date,
+    pub summary: String,
+    pub description: String,
+}
+
+impl CalendarEvent {
+    /// The day the event falls on in a year, if it has started by then.
+    pub fn occurrence_in(&self, year: i32) -> Option<Date> {
+        if year < self.date.year() {
+            return None;
+        }
+        if self.date.month() == Month::February && self.date.day() == 29 {
+            let march = Date::from_calendar_date(year, Month::March, 1).ok()?;
+            return march.previous_day();
+        }
+        Date::from_calendar_date(year, self.date.month(), self.date.day()).ok()
+    }
+
+    /// Whether any occurrence overlaps a time range.
+    ///
+    /// Occurrences are whole days, taken as UTC days since all-day events
+    /// have no time zone. As in a CalDAV `time-range`, `start` is
+    /// inclusive, `end` exclusive and a missing bound is unbounded.
+    pub fn occurs_between(
+        &self,
+        start: Option<OffsetDateTime>,
+        end: Option<OffsetDateTime>,
+    ) -> bool {
+        // A yearly event keeps occurring forever
+        let Some(end) = end else {
+            return true;
+        };
+        let first_year = start.map_or(self.date.year(), |start| {
+            start.to_offset(UtcOffset::UTC).year()
+        });
+        let last_year = end.to_offset(UtcOffset::UTC).year();
+
+        (first_year..=last_year)
+            .filter_map(|year| self.occurrence_in(year))
+            .any(|day| {
+                let from = day.midnight().assume_utc();
+                let to = from + Duration::days(1);
+                from < end && start.is_none_or(|start| to > start)
+            })
+    }
+
+    fn write(&self, out: &mut String, alarm_minutes_before: Option<i32>) {
+        let stamp = format_timestamp(self.stamp);
+
+        write_line(out, "BEGIN", &[], "VEVENT");
+        write_line(out, "UID", &[], &self.uid);
+        write_line(out, "DTSTAMP", &[], &stamp);
+        write_line(out, "LAST-MODIFIED", &[], &stamp);
+        write_line(
+            out,
+            "DTSTART",
+            &[("VALUE", "DATE")],
+            &format_ics_date(self.date),
+        );
+        write_line(out, "RRULE", &[], &yearly_rule(self.date));
+        write_line(out, "SUMMARY", &[], &escape(&self.summary));
+        write_line(out, "DESCRIPTION", &[], &escape(&self.description));
+        write_line(out, "TRANSP", &[], "TRANSPARENT");
+
+        if let Some(minutes) = alarm_minutes_before {
+            write_line(out, "BEGIN", &[], "VALARM");
+            write_line(out, "ACTION", &[], "DISPLAY");
+            write_line(out, "DESCRIPTION", &[], &escape(&self.summary));
+            write_line(out, "TRIGGER", &[], &trigger(minutes));
+            write_line(out, "END", &[], "VALARM");
+        }
+
+        write_line(out, "END", &[], "VEVENT");
+    }
+}
+
+/// `BEGIN:VCALENDAR` and the properties every calendar starts with.
+fn begin_calendar(out: &mut String) {
+    write_line(out, "BEGIN", &[], "VCALENDAR");
+    write_line(out, "VERSION", &[], "2.0");
+    write_line(out, "PRODID", &[], PRODID);
+    write_line(out, "CALSCALE", &[], "GREGORIAN");
+}
+
+/// A friend's date-typed attributes, in snapshot order.
+fn dated_attributes(
+    attributes: &[FriendAttribute],
+    friend_id: Uuid,
+) -> impl Iterator<Item = &FriendAttribute> {
+    attributes
+        .iter()
+        .filter(move |attribute| attribute.friend_id == friend_id && attribute.value_type == "date")
+}
+
+/// `FREQ=YEARLY`, moving February 29 to the last day of February.
+fn yearly_rule(date: Date) -> String {
+    if date.month() == Month::February && date.day() == 29 {
+        "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1".to_string()
+    } else {
+        "FREQ=YEARLY".to_string()
+    }
+}
+
+/// `-PT90M`; a zero lead time fires at the start of the day.
+fn trigger(minutes_before: i32) -> String {
+    if minutes_before == 0 {
+        "PT0M".to_string()
+    } else {
+        format!("-PT{minutes_before}M")
+    }
+}
+
+/// When a row was last changed.
+fn modified_at(created_at: OffsetDateTime, updated_at: Option<OffsetDateTime>) -> OffsetDateTime {
+    updated_at.unwrap_or(created_at)
+}
+
+/// "19500312".
+fn format_ics_date(date: Date) -> String {
+    format!(
+        "{:04}{:02}{:02}",
+        date.year(),
+        date.month() as u8,
+        date.day()
+    )
+}
+
+/// "20240101T120000Z".
+fn format_timestamp(at: OffsetDateTime) -> String {
+    let at = at.to_offset(UtcOffset::UTC);
+    format!(
+        "{}T{:02}{:02}{:02}Z",
+        format_ics_date(at.date()),
+        at.hour(),
+        at.minute(),
+        at.second()
+    )
+}
+
+/// "12 March 1950".
+fn format_date(date: Date) -> String {
+    format!("{} {} {}", date.day(), date.month(), date.year())
+}
+
+/// "wedding_anniversary" → "Wedding anniversary".
+fn humanize(key: &str) -> String {
+    let words = key.replace(['_', '-', '.'], " ");
+    let mut chars = words.trim().chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Parse "1950-03-12".
+fn parse_iso_date(value: &str) -> Option<Date> {
+    let mut parts = value.splitn(3, '-');
+    let year: i32 = parts.next()?.parse().ok()?;
+    let month: u8 = parts.next()?.parse().ok()?;
+    let day: u8 = parts.next()?.parse().ok()?;
+    Date::from_calendar_date(year, Month::try_from(month).ok()?, day).ok()
+}
+
+/// "First Last", or just "First".
+fn display_name(friend: &Friend) -> String {
+    match &friend.last_name {
+        Some(last) if !last.trim().is_empty() => format!("{} {}", friend.first_name, last),
+        _ => friend.first_name.clone(),
+    }
+}
+
+/// Exports a user's important dates as an iCalendar feed.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let feed = CalendarFeedRepository::new(ctx.clone()).get_or_create(user_id).await?;
+/// let calendar = IcsExporter::new(ctx.clone())
+///     .export(user_id, "Birthdays", feed.alarm_minutes_before)
+///     .await?;
+/// ```
+pub struct IcsExporter {
+    backups: BackupRepository,
+}
+
+impl IcsExporter {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            backups: BackupRepository::new(ctx),
+        }
+    }
+
+    /// Render the calendar for a user.
+    pub async fn export(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        alarm_minutes_before: Option<i32>,
+    ) -> Result<String, InterchangeError> {
+        let snapshot = self.backups.snapshot_live(user_id).await?;
+        Ok(render_calendar(&snapshot, name, alarm_minutes_before))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: Month, day: u8) -> Date {
+        Date::from_calendar_date(year, month, day).unwrap()
+    }
+
+    fn friend(first_name: &str, date_of_birth: Option<Date>) -> Friend {
+        Friend {
+            id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            first_name: first_name.to_string(),
+            last_name: None,
+            date_of_birth,
+            likes: None,
+            dislikes: None,
+            notes: None,
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: None,
+            version: 1,
+        }
+    }
+
+    fn event(date: Date) -> CalendarEvent {
+        CalendarEvent {
+            uid: "birthday-test@fkb".to_string(),
+            friend_id: Uuid::nil(),
+            stamp: OffsetDateTime::UNIX_EPOCH,
+            date,
+            summary: "Ada's birthday".to_string(),
+            description: String::new(),
+        }
+    }
+
+    #[test]
+    fn february_29_falls_on_the_last_day_of_february() {
+        let leap_day = event(date(2000, Month::February, 29));
+        assert_eq!(
+            leap_day.occurrence_in(2023),
+            Some(date(2023, Month::February, 28))
+        );
+        assert_eq!(
+            leap_day.occurrence_in(2024),
+            Some(date(2024, Month::February, 29))
+        );
+        assert_eq!(
+            yearly_rule(leap_day.date),
+            "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1"
+        );
+    }
+
+    #[test]
+    fn other_dates_recur_on_the_same_day() {
+        let birthday = event(date(1990, Month::March, 12));
+        assert_eq!(
+            birthday.occurrence_in(2023),
+            Some(date(2023, Month::March, 12))
+        );
+        assert_eq!(yearly_rule(birthday.date), "FREQ=YEARLY");
+    }
+
+    #[test]
+    fn nothing_occurs_before_the_first_date() {
+        let birthday = event(date(1990, Month::March, 12));
+        assert_eq!(birthday.occurrence_in(1989), None);
+        assert_eq!(
+            birthday.occurrence_in(1990),
+            Some(date(1990, Month::March, 12))
+        );
+    }
+
+    #[test]
+    fn the_feed_has_a_yearly_all_day_event_per_date() {
+        let snapshot = UserSnapshot {
+            friends: vec![friend("Ada", Some(date(2000, Month::February, 29)))],
+            ..Default::default()
+        };
+
+        let calendar = render_calendar(&snapshot, "Birthdays", Some(90));
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        for line in [
+            "METHOD:PUBLISH",
+            "X-WR-CALNAME:Birthdays",
+            "UID:birthday-00000000-0000-0000-0000-000000000000@fkb",
+            "DTSTAMP:19700101T000000Z",
+            "DTSTART;VALUE=DATE:20000229",
+            "RRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1",
+            "SUMMARY:Ada's birthday",
+            "DESCRIPTION:Born 29 February 2000",
+            "TRIGGER:-PT90M",
+        ] {
+            assert!(calendar.contains(&format!("{line}\r\n")), "missing {line}");
+        }
+    }
+
+    #[test]
+    fn dated_attributes_become_events() {
+        let ada = friend("Ada", None);
+        let attribute = |key: &str, value: &str, value_type: &str| FriendAttribute {
+            id: Uuid::nil(),
+            friend_id: ada.id,
+            key: key.to_string(),
+            value: value.to_string(),
+            value_type: value_type.to_string(),
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: None,
+            version: 1,
+        };
+        let snapshot = UserSnapshot {
+            friend_attributes: vec![
+                attribute("wedding_anniversary", " 2010-06-05 ", "date"),
+                attribute("name_day", "not a date", "date"),
+                attribute("city", "2010-06-05", "text"),
+            ],
+            friends: vec![ada],
+            ..Default::default()
+        };
+
+        let events = events(&snapshot);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].date, date(2010, Month::June, 5));
+        assert_eq!(events[0].summary, "Ada: Wedding anniversary");
+    }
+
+    #[test]
+    fn alarms_fire_before_the_day() {
+        assert_eq!(trigger(90), "-PT90M");
+        assert_eq!(trigger(0), "PT0M");
+        let calendar = render_event(&event(date(1990, Month::March, 12)), None);
+        assert!(!calendar.contains("VALARM"));
+        assert!(!calendar.contains("METHOD"));
+    }
+
+    fn at(year: i32, month: Month, day: u8, hour: u8) -> Option<OffsetDateTime> {
+        Some(
+            date(year, month, day)
+                .with_hms(hour, 0, 0)
+                .unwrap()
+                .assume_utc(),
+        )
+    }
+
+    #[test]
+    fn time_ranges_include_start_and_exclude_end() {
+        let birthday = event(date(1990, Month::March, 12));
+        let day = |hour| at(2024, Month::March, 12, hour);
+        let next_day = at(2024, Month::March, 13, 0);
+
+        assert!(birthday.occurs_between(day(10), day(11)));
+        // Ends as the day starts
+        assert!(!birthday.occurs_between(at(2024, Month::March, 1, 0), day(0)));
+        assert!(birthday.occurs_between(at(2024, Month::March, 1, 0), day(1)));
+        // Starts as the day ends
+        assert!(!birthday.occurs_between(next_day, at(2024, Month::March, 20, 0)));
+        assert!(birthday.occurs_between(day(23), next_day));
+    }
+
+    #[test]
+    fn missing_bounds_are_unbounded() {
+        let birthday = event(date(1990, Month::March, 12));
+        assert!(birthday.occurs_between(None, None));
+        assert!(birthday.occurs_between(at(2500, Month::January, 1, 0), None));
+        assert!(birthday.occurs_between(None, at(1990, Month::March, 13, 0)));
+        assert!(!birthday.occurs_between(None, at(1990, Month::March, 12, 0)));
+    }
+
+    #[test]
+    fn time_ranges_before_the_first_date_are_empty() {
+        let birthday = event(date(1990, Month::March, 12));
+        assert!(!birthday.occurs_between(
+            at(1980, Month::January, 1, 0),
+            at(1989, Month::December, 31, 0)
+        ));
+    }
+
+    #[test]
+    fn time_ranges_can_span_new_year() {
+        let new_year = event(date(1990, Month::January, 1));
+        assert!(new_year.occurs_between(
+            at(2023, Month::December, 31, 0),
+            at(2024, Month::January, 1, 12)
+        ));
+    }
+
+    #[test]
+    fn time_ranges_find_february_29_in_other_years() {
+        let leap_day = event(date(2000, Month::February, 29));
+        assert!(leap_day.occurs_between(
+            at(2023, Month::February, 28, 12),
+            at(2023, Month::February, 28, 13)
+        ));
+        assert!(
+            !leap_day.occurs_between(at(2023, Month::March, 1, 0), at(2023, Month::March, 2, 0))
+        );
+    }
+
+    #[test]
+    fn time_ranges_compare_in_utc() {
+        let birthday = event(date(1990, Month::March, 12));
+        let plus_two = UtcOffset::from_hms(2, 0, 0).unwrap();
+        // 01:00 at +02:00 on the 13th is 23:00 UTC on the 12th
+        let start = at(2024, Month::March, 13, 1)
+            .unwrap()
+            .replace_offset(plus_two);
+        assert!(birthday.occurs_between(Some(start), at(2024, Month::March, 14, 0)));
+    }
+}
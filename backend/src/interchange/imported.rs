@@ -0,0 +1,321 @@
+//! # Imported Friends
+//!
+//! The format-neutral shape of a friend read from an import file. Format
+//! modules parse into `ImportedFriend`, and `ImportWriter` turns it into a
+//! friend, its attributes and its group memberships.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use sqlx::PgConnection;
+use time::Date;
+use uuid::Uuid;
+
+use crate::models::{Friend, FriendAttribute, Group};
+use crate::repositories::{
+    CreateFriendAttributeInput, CreateFriendInput, CreateGroupInput, FriendAttributeRepository,
+    FriendRepository, GroupRepository, Repository, RepositoryContext, RepositoryError,
+    UpdateFriendInput,
+};
+
+use super::attributes::ImportedAttribute;
+
+/// One friend read from an import file, not yet written anywhere.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportedFriend {
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub date_of_birth: Option<Date>,
+    pub likes: Option<String>,
+    pub dislikes: Option<String>,
+    pub notes: Option<String>,
+    /// Group names; matched to the user's groups case-insensitively
+    pub groups: Vec<String>,
+    pub attributes: Vec<ImportedAttribute>,
+}
+
+impl ImportedFriend {
+    /// An imported friend with only a first name.
+    pub fn named(first_name: impl Into<String>) -> Self {
+        Self {
+            first_name: first_name.into(),
+            last_name: None,
+            date_of_birth: None,
+            likes: None,
+            dislikes: None,
+            notes: None,
+            groups: Vec::new(),
+            attributes: Vec::new(),
+        }
+    }
+}
+
+/// Split a display name ("Ada King Lovelace") into first and last name.
+///
+/// The first word is the first name; the rest, if any, is the last name.
+pub fn split_full_name(full_name: &str) -> Option<(String, Option<String>)> {
+    let mut words = full_name.split_whitespace();
+    let first = words.next()?.to_string();
+    let rest = words.collect::<Vec<_>>().join(" ");
+    Some((first, (!rest.is_empty()).then_some(rest)))
+}
+
+/// The update for an optional text column, `None` if it already holds
+/// `wanted`. Blank text counts as no value, so it's stored as NULL.
+pub(crate) fn replaced_text(
+    current: &Option<String>,
+    wanted: &Option<String>,
+) -> Option<Option<String>> {
+    let wanted = wanted.as_deref().filter(|w| !w.trim().is_empty());
+    (current.as_deref() != wanted).then(|| wanted.map(str::to_string))
+}
+
+/// A record that wasn't imported.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RejectedRow {
+    /// 1-based line number in the file
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Writes imported friends on a caller-provided connection.
+///
+/// Group names are matched to the user's groups case-insensitively, and
+/// missing groups are created on first use. Pass `&mut *tx` as the
+/// connection to make a whole import atomic.
+///
+/// # Savepoints
+///
+/// If a savepoint that created groups is rolled back, those groups no
+/// longer exist. Take a `checkpoint()` before the savepoint and call
+/// `rollback_to` after rolling it back so they aren't reused.
+pub struct ImportWriter {
+    friends: FriendRepository,
+    attributes: FriendAttributeRepository,
+    groups: GroupRepository,
+    user_id: Uuid,
+    /// Lower-case group name → group ID
+    known: HashMap<String, Uuid>,
+    created: Vec<Group>,
+}
+
+impl ImportWriter {
+    /// Create a writer for `user_id`, loading their existing groups.
+    pub async fn load(ctx: RepositoryContext, user_id: Uuid) -> Result<Self, RepositoryError> {
+        let groups = GroupRepository::new(ctx.clone());
+        let known = groups
+            .list_by_user(user_id)
+            .await?
+            .into_iter()
+            .map(|g| (g.name.to_lowercase(), g.id))
+            .collect();
+
+        Ok(Self {
+            friends: FriendRepository::new(ctx.clone()),
+            attributes: FriendAttributeRepository::new(ctx),
+            groups,
+            user_id,
+            known,
+            created: Vec::new(),
+        })
+    }
+
+    /// Create a new friend with their attributes and group memberships.
+    pub async fn create_friend(
+        &mut self,
+        conn: &mut PgConnection,
+        imported: ImportedFriend,
+    ) -> Result<Friend, RepositoryError> {
+        let friend = self
+            .friends
+            .create_in(
+                conn,
+                CreateFriendInput {
+                    user_id: self.user_id,
+                    first_name: imported.first_name,
+                    last_name: imported.last_name,
+                    date_of_birth: imported.date_of_birth,
+                    likes: imported.likes,
+                    dislikes: imported.dislikes,
+                    notes: imported.notes,
+                },
+            )
+            .await?;
+
+        for attribute in imported.attributes {
+            self.attributes
+                .create_in(
+                    conn,
+                    CreateFriendAttributeInput {
+                        friend_id: friend.id,
+                        key: attribute.key,
+                        value: attribute.value,
+                        value_type: Some(attribute.value_type),
+                    },
+                )
+                .await?;
+        }
+
+        self.join_groups(conn, friend.id, &imported.groups).await?;
+        Ok(friend)
+    }
+
+    /// Overwrite an existing friend with an imported one.
+    ///
+    /// Fields are replaced (a missing or blank optional field is cleared
+    /// to NULL), attributes are matched by key, and group memberships are
+    /// made to match `imported.groups`.
+    /// Nothing is written for parts that are already equal.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend` - The friend as currently stored
+    /// * `attributes` - The friend's current attributes
+    /// * `groups` - The friend's current groups
+    /// * `imported` - What the friend should look like afterwards
+    pub async fn replace_friend(
+        &mut self,
+        conn: &mut PgConnection,
+        friend: &Friend,
+        attributes: &[FriendAttribute],
+        groups: &[Group],
+        imported: ImportedFriend,
+    ) -> Result<(), RepositoryError> {
+        let update = UpdateFriendInput {
+            first_name: (friend.first_name != imported.first_name)
+                .then(|| imported.first_name.clone()),
+            last_name: replaced_text(&friend.last_name, &imported.last_name),
+            date_of_birth: (friend.date_of_birth != imported.date_of_birth)
+                .then_some(imported.date_of_birth),
+            likes: replaced_text(&friend.likes, &imported.likes),
+            dislikes: replaced_text(&friend.dislikes, &imported.dislikes),
+            notes: replaced_text(&friend.notes, &imported.notes),
+        };
+        let has_update = update.first_name.is_some()
+            || update.last_name.is_some()
+            || update.date_of_birth.is_some()
+            || update.likes.is_some()
+            || update.dislikes.is_some()
+            || update.notes.is_some();
+        if has_update {
+            self.friends
+                .update_in(conn, friend.id, Some(friend.version), update)
+                .await?;
+        }
+
+        for attribute in &imported.attributes {
+            let unchanged = attributes.iter().any(|a| {
+                a.key == attribute.key
+                    && a.value == attribute.value
+                    && a.value_type == attribute.value_type
+            });
+            if unchanged {
+                continue;
+            }
+            self.attributes
+                .upsert_in(
+                    conn,
+                    CreateFriendAttributeInput {
+                        friend_id: friend.id,
+                        key: attribute.key.clone(),
+                        value: attribute.value.clone(),
+                        value_type: Some(attribute.value_type.clone()),
+                    },
+                )
+                .await?;
+        }
+        for attribute in attributes {
+            if !imported.attributes.iter().any(|a| a.key == attribute.key) {
+                self.attributes.delete_in(conn, attribute.id).await?;
+            }
+        }
+
+        let wanted: Vec<String> = imported.groups.iter().map(|g| g.to_lowercase()).collect();
+        let joining: Vec<String> = imported
+            .groups
+            .iter()
+            .filter(|name| {
+                !groups
+                    .iter()
+                    .any(|g| g.name.to_lowercase() == name.to_lowercase())
+            })
+            .cloned()
+            .collect();
+        self.join_groups(conn, friend.id, &joining).await?;
+        for group in groups {
+            if !wanted.contains(&group.name.to_lowercase()) {
+                self.friends
+                    .remove_from_group_in(conn, friend.id, group.id)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add a friend to the named groups, creating any that are missing.
+    pub async fn join_groups(
+        &mut self,
+        conn: &mut PgConnection,
+        friend_id: Uuid,
+        names: &[String],
+    ) -> Result<(), RepositoryError> {
+        for name in names {
+            let group_id = self.group_id(conn, name).await?;
+            self.friends
+                .add_to_group_in(conn, friend_id, group_id)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// ID of the user's group called `name`, creating it if needed.
+    async fn group_id(
+        &mut self,
+        conn: &mut PgConnection,
+        name: &str,
+    ) -> Result<Uuid, RepositoryError> {
+        if let Some(id) = self.known.get(&name.to_lowercase()) {
+            return Ok(*id);
+        }
+
+        let group = self
+            .groups
+            .create_in(
+                conn,
+                CreateGroupInput {
+                    user_id: self.user_id,
+                    name: name.to_string(),
+                    description: None,
+                },
+            )
+            .await?;
+        self.known.insert(name.to_lowercase(), group.id);
+        let id = group.id;
+        self.created.push(group);
+        Ok(id)
+    }
+
+    /// Marker for `rollback_to`.
+    pub fn checkpoint(&self) -> usize {
+        self.created.len()
+    }
+
+    /// Forget groups created since `checkpoint` (their savepoint was
+    /// rolled back).
+    pub fn rollback_to(&mut self, checkpoint: usize) {
+        for group in self.created.drain(checkpoint..) {
+            self.known.remove(&group.name.to_lowercase());
+        }
+    }
+
+    /// Groups created so far.
+    pub fn created_groups(&self) -> &[Group] {
+        &self.created
+    }
+
+    /// Consume the writer, returning the groups it created.
+    pub fn into_created_groups(self) -> Vec<Group> {
+        self.created
+    }
+}
@@ -0,0 +1,416 @@
+//! # YAML Front Matter
+//!
+//! Just enough YAML to write and read the `---` block at the top of a
+//! note: scalar values, lists of scalars and one level of string maps.
+//! Strings are left plain when YAML would read them back unchanged, and
+//! double-quoted otherwise.
+//!
+//! The reader also accepts what note apps typically write when a property
+//! is edited by hand: single-quoted strings, `# comments` and flow lists
+//! (`[a, b]`). Anything deeper than one level of nesting is rejected.
+
+use super::super::error::InterchangeError;
+
+/// A front matter value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Scalar(String),
+    List(Vec<String>),
+    Map(Vec<(String, String)>),
+}
+
+/// Ordered front matter entries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrontMatter {
+    entries: Vec<(String, Value)>,
+    /// Source line of each entry, when parsed
+    lines: Vec<usize>,
+}
+
+impl FrontMatter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a scalar entry.
+    pub fn scalar(&mut self, key: &str, value: impl Into<String>) -> &mut Self {
+        self.entries
+            .push((key.to_string(), Value::Scalar(value.into())));
+        self
+    }
+
+    /// Add a scalar entry if `value` is `Some`.
+    pub fn optional(&mut self, key: &str, value: Option<impl Into<String>>) -> &mut Self {
+        if let Some(value) = value {
+            self.scalar(key, value);
+        }
+        self
+    }
+
+    /// Add a list entry; empty lists are left out.
+    pub fn list(&mut self, key: &str, items: Vec<String>) -> &mut Self {
+        if !items.is_empty() {
+            self.entries.push((key.to_string(), Value::List(items)));
+        }
+        self
+    }
+
+    /// Add a map entry; empty maps are left out.
+    pub fn map(&mut self, key: &str, pairs: Vec<(String, String)>) -> &mut Self {
+        if !pairs.is_empty() {
+            self.entries.push((key.to_string(), Value::Map(pairs)));
+        }
+        self
+    }
+
+    /// Look up an entry by key.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// The 1-based line an entry was read from.
+    pub fn line_of(&self, key: &str) -> Option<usize> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        self.lines.get(index).copied()
+    }
+
+    /// A scalar entry's value.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.get(key) {
+            Some(Value::Scalar(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Render the block, including both `---` fences.
+    pub fn render(&self) -> String {
+        let mut out = String::from("---\n");
+        for (key, value) in &self.entries {
+            match value {
+                Value::Scalar(value) => {
+                    out.push_str(&format!("{}: {}\n", scalar(key), scalar(value)));
+                }
+                Value::List(items) => {
+                    out.push_str(&format!("{}:\n", scalar(key)));
+                    for item in items {
+                        out.push_str(&format!("  - {}\n", scalar(item)));
+                    }
+                }
+                Value::Map(pairs) => {
+                    out.push_str(&format!("{}:\n", scalar(key)));
+                    for (k, v) in pairs {
+                        out.push_str(&format!("  {}: {}\n", scalar(k), scalar(v)));
+                    }
+                }
+            }
+        }
+        out.push_str("---\n");
+        out
+    }
+}
+
+/// Render a string as a YAML scalar, quoting only when needed.
+pub fn scalar(value: &str) -> String {
+    if is_plain(value) {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+/// Whether `value` can be written unquoted and still read back as the
+/// same string.
+fn is_plain(value: &str) -> bool {
+    let Some(first) = value.chars().next() else {
+        return false;
+    };
+
+    // Leading indicators, and surrounding whitespace that would be trimmed
+    if "-?:,[]{}#&*!|>'\"%@`~".contains(first) || value.starts_with(' ') || value.ends_with(' ') {
+        return false;
+    }
+    // Values YAML would read as something other than a string. Dates are
+    // kept plain: Obsidian shows them as date properties.
+    let lower = value.to_lowercase();
+    if matches!(
+        lower.as_str(),
+        "true" | "false" | "yes" | "no" | "on" | "off" | "null"
+    ) || value.parse::<f64>().is_ok()
+    {
+        return false;
+    }
+
+    !value.contains(": ")
+        && !value.contains(" #")
+        && !value.ends_with(':')
+        && value.chars().all(|c| !c.is_control())
+}
+
+/// Double-quote a string, escaping as YAML requires.
+fn quote(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Split a note into its front matter and body.
+///
+/// A note without a leading `---` line has empty front matter.
+///
+/// # Returns
+///
+/// The front matter and the body.
+///
+/// # Errors
+///
+/// `Parse` for an unterminated block or a line this reader doesn't
+/// understand.
+pub fn parse(input: &str) -> Result<(FrontMatter, &str), InterchangeError> {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    let mut lines = input.split_inclusive('\n');
+
+    if lines.next().map(str::trim_end) != Some("---") {
+        return Ok((FrontMatter::new(), input));
+    }
+
+    let mut offset = input.find('\n').map_or(input.len(), |i| i + 1);
+    let mut block = Vec::new();
+    let mut closed = false;
+    for line in lines {
+        offset += line.len();
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line == "---" || line == "..." {
+            closed = true;
+            break;
+        }
+        block.push(line);
+    }
+    if !closed {
+        return Err(InterchangeError::parse(
+            1,
+            "front matter is missing its closing ---",
+        ));
+    }
+
+    let front = parse_block(&block)?;
+    Ok((front, &input[offset..]))
+}
+
+/// Parse the lines between the fences. Line numbers in errors count the
+/// opening fence as line 1.
+fn parse_block(lines: &[&str]) -> Result<FrontMatter, InterchangeError> {
+    let mut front = FrontMatter::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line_no = i + 2;
+        let line = lines[i];
+        i += 1;
+        if is_blank(line) {
+            continue;
+        }
+        if line.starts_with([' ', '\t']) {
+            return Err(InterchangeError::parse(line_no, "unexpected indentation"));
+        }
+
+        let (key, rest) = split_key(line).ok_or_else(|| {
+            InterchangeError::parse(line_no, format!("expected \"key: value\", got {line:?}"))
+        })?;
+        let rest = strip_comment(rest).trim();
+
+        if !rest.is_empty() {
+            let value = if rest.starts_with('[') {
+                Value::List(parse_flow_list(rest).map_err(|m| InterchangeError::parse(line_no, m))?)
+            } else {
+                Value::Scalar(parse_scalar(rest).map_err(|m| InterchangeError::parse(line_no, m))?)
+            };
+            front.entries.push((key, value));
+            front.lines.push(line_no);
+            continue;
+        }
+
+        // Block value: the indented lines that follow
+        let mut items = Vec::new();
+        let mut pairs = Vec::new();
+        while i < lines.len() && (lines[i].starts_with([' ', '\t']) || is_blank(lines[i])) {
+            let line_no = i + 2;
+            let nested = lines[i].trim();
+            i += 1;
+            if nested.is_empty() || nested.starts_with('#') {
+                continue;
+            }
+
+            let parsed = if let Some(item) = nested.strip_prefix('-') {
+                parse_scalar(strip_comment(item).trim()).map(|item| items.push(item))
+            } else if let Some((k, v)) = split_key(nested) {
+                parse_scalar(strip_comment(v).trim()).map(|v| pairs.push((k, v)))
+            } else {
+                Err(format!(
+                    "expected \"- item\" or \"key: value\", got {nested:?}"
+                ))
+            };
+            parsed.map_err(|m| InterchangeError::parse(line_no, m))?;
+
+            if !items.is_empty() && !pairs.is_empty() {
+                return Err(InterchangeError::parse(
+                    line_no,
+                    "mixed list and map entries",
+                ));
+            }
+        }
+
+        let value = if !pairs.is_empty() {
+            Value::Map(pairs)
+        } else if !items.is_empty() {
+            Value::List(items)
+        } else {
+            // "key:" with nothing after it is YAML null
+            Value::Scalar(String::new())
+        };
+        front.entries.push((key, value));
+        front.lines.push(line_no);
+    }
+
+    Ok(front)
+}
+
+fn is_blank(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+/// Split `key: rest` (or `key:` at end of line), honoring a quoted key.
+fn split_key(line: &str) -> Option<(String, &str)> {
+    if line.starts_with(['"', '\'']) {
+        let end = quoted_end(line)?;
+        let key = parse_scalar(&line[..end]).ok()?;
+        let rest = line[end..].trim_start().strip_prefix(':')?;
+        return (rest.is_empty() || rest.starts_with([' ', '\t'])).then_some((key, rest));
+    }
+
+    let end = line
+        .find(": ")
+        .or_else(|| line.find(":\t"))
+        .or_else(|| line.ends_with(':').then(|| line.len() - 1))?;
+    let key = line[..end].trim();
+    (!key.is_empty()).then(|| (key.to_string(), &line[end + 1..]))
+}
+
+/// Byte index just past the closing quote of a string starting at 0.
+fn quoted_end(text: &str) -> Option<usize> {
+    let quote = text.chars().next()?;
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        if quote == '"' && c == '\\' {
+            chars.next();
+        } else if c == quote {
+            // '' is an escaped quote inside single quotes
+            if quote == '\'' && text[i + 1..].starts_with('\'') {
+                chars.next();
+                continue;
+            }
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+/// Drop a trailing ` # comment` outside quotes.
+fn strip_comment(text: &str) -> &str {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with(['"', '\''])
+        && let Some(end) = quoted_end(trimmed)
+    {
+        let offset = text.len() - trimmed.len();
+        return &text[..offset + end];
+    }
+    match text.find(" #") {
+        Some(i) => &text[..i],
+        None => text,
+    }
+}
+
+/// Parse a single scalar: plain, single-quoted or double-quoted.
+fn parse_scalar(text: &str) -> Result<String, String> {
+    let text = text.trim();
+    if let Some(inner) = text.strip_prefix('"') {
+        let inner = inner
+            .strip_suffix('"')
+            .ok_or_else(|| format!("unterminated string {text:?}"))?;
+        return unquote_double(inner);
+    }
+    if let Some(inner) = text.strip_prefix('\'') {
+        let inner = inner
+            .strip_suffix('\'')
+            .ok_or_else(|| format!("unterminated string {text:?}"))?;
+        return Ok(inner.replace("''", "'"));
+    }
+    if matches!(text, "~" | "null" | "Null" | "NULL") {
+        return Ok(String::new());
+    }
+    Ok(text.to_string())
+}
+
+fn unquote_double(inner: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('0') => out.push('\0'),
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('/') => out.push('/'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let c = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("bad escape \\u{hex}"))?;
+                out.push(c);
+            }
+            other => return Err(format!("unsupported escape \\{}", other.unwrap_or(' '))),
+        }
+    }
+    Ok(out)
+}
+
+/// Parse `[a, "b", 'c']`.
+fn parse_flow_list(text: &str) -> Result<Vec<String>, String> {
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|t| t.strip_suffix(']'))
+        .ok_or_else(|| format!("unterminated list {text:?}"))?;
+
+    let mut items = Vec::new();
+    let mut rest = inner.trim();
+    while !rest.is_empty() {
+        let end = if rest.starts_with(['"', '\'']) {
+            quoted_end(rest).ok_or_else(|| format!("unterminated string in {text:?}"))?
+        } else {
+            rest.find(',').unwrap_or(rest.len())
+        };
+        items.push(parse_scalar(&rest[..end])?);
+        rest = rest[end..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+    Ok(items)
+}
@@ -0,0 +1,507 @@
+//! # Markdown Vault Export
+//!
+//! Writes a user's knowledgebase as a folder of Markdown notes that
+//! Obsidian (and most other Markdown note apps) can open as a vault.
+//!
+//! ## Layout
+//!
+//! ```text
+//! Friends/Ada Lovelace.md
+//! Friends/Charles Babbage.md
+//! Groups/Analytical Engine.md
+//! ```
+//!
+//! A friend note has YAML front matter (ID, names, birthday, groups and
+//! attributes) and a body with likes, dislikes, notes and relationships.
+//! Relationships are `[[wiki-links]]` to the other friend's note, labeled
+//! from this friend's side: A's note reads "boss of [[B]]", B's reads
+//! "employee of [[A]]". A group note lists its members as links.
+//!
+//! ## Note Titles
+//!
+//! Wiki-links resolve by file name, so every note needs a unique one.
+//! Titles are the display name with characters that aren't allowed in
+//! file names or links removed. Clashes (compared case-insensitively,
+//! for case-insensitive file systems) get " (2)", " (3)", ... in the
+//! order the friends were created.
+//!
+//! ## Syncing
+//!
+//! `read_friend_note` reads a friend note back, and `sync` uses it to keep
+//! a vault and the database in step in both directions.
+
+pub mod front_matter;
+pub mod sync;
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use time::{Date, Month};
+use uuid::Uuid;
+
+use crate::models::{Friend, FriendRelationship, Group};
+use crate::repositories::{BackupRepository, RepositoryContext, UserSnapshot};
+
+use super::error::InterchangeError;
+use super::imported::split_full_name;
+use front_matter::{FrontMatter, Value};
+
+/// Folder for friend notes.
+pub const FRIENDS_DIR: &str = "Friends";
+
+/// Folder for group index notes.
+pub const GROUPS_DIR: &str = "Groups";
+
+/// What a note describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteKind {
+    Friend,
+    Group,
+}
+
+/// One file in the vault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    /// ID of the friend or group (also the note's `fkb_id`)
+    pub id: Uuid,
+    pub kind: NoteKind,
+    /// Path relative to the vault root, with `/` separators
+    pub path: String,
+    pub contents: String,
+}
+
+/// A friend note read back from the vault.
+///
+/// Only what the note owns is read: names, birthday, groups, attributes
+/// and the likes/dislikes/notes sections. The title heading and the
+/// relationships section are generated and ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FriendNote {
+    /// `None` for a note created in the vault
+    pub id: Option<Uuid>,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub birthday: Option<Date>,
+    pub groups: Vec<String>,
+    pub attributes: Vec<(String, String)>,
+    pub likes: Option<String>,
+    pub dislikes: Option<String>,
+    pub notes: Option<String>,
+}
+
+/// Render a whole vault from a snapshot of the user's data.
+pub fn render_vault(snapshot: &UserSnapshot) -> Vec<Note> {
+    let friend_titles = unique_titles(snapshot.friends.iter().map(|f| (f.id, display_name(f))));
+    let group_titles = unique_titles(snapshot.groups.iter().map(|g| (g.id, g.name.clone())));
+
+    let groups_by_id: HashMap<Uuid, &Group> = snapshot.groups.iter().map(|g| (g.id, g)).collect();
+    let friends_by_id: HashMap<Uuid, &Friend> =
+        snapshot.friends.iter().map(|f| (f.id, f)).collect();
+
+    let mut notes = Vec::with_capacity(snapshot.friends.len() + snapshot.groups.len());
+
+    for friend in &snapshot.friends {
+        let groups: Vec<&Group> = snapshot
+            .friend_groups
+            .iter()
+            .filter(|m| m.friend_id == friend.id)
+            .filter_map(|m| groups_by_id.get(&m.group_id).copied())
+            .collect();
+        let attributes: Vec<(String, String)> = snapshot
+            .friend_attributes
+            .iter()
+            .filter(|a| a.friend_id == friend.id)
+            .map(|a| (a.key.clone(), a.value.clone()))
+            .collect();
+        let relationships: Vec<String> = snapshot
+            .friend_relationships
+            .iter()
+            .filter_map(|r| relationship_line(r, friend.id, &friend_titles))
+            .collect();
+
+        let title = &friend_titles[&friend.id];
+        notes.push(Note {
+            id: friend.id,
+            kind: NoteKind::Friend,
+            path: format!("{FRIENDS_DIR}/{title}.md"),
+            contents: friend_note(friend, title, &groups, attributes, &relationships),
+        });
+    }
+
+    for group in &snapshot.groups {
+        let members: Vec<&str> = snapshot
+            .friend_groups
+            .iter()
+            .filter(|m| m.group_id == group.id)
+            .filter(|m| friends_by_id.contains_key(&m.friend_id))
+            .map(|m| friend_titles[&m.friend_id].as_str())
+            .collect();
+
+        let title = &group_titles[&group.id];
+        notes.push(Note {
+            id: group.id,
+            kind: NoteKind::Group,
+            path: format!("{GROUPS_DIR}/{title}.md"),
+            contents: group_note(group, title, &members),
+        });
+    }
+
+    notes
+}
+
+/// Write notes under `dir`, creating folders as needed.
+///
+/// Existing files with the same paths are overwritten; other files in
+/// the vault are left alone.
+pub fn write_vault(dir: &Path, notes: &[Note]) -> std::io::Result<()> {
+    for note in notes {
+        let path = dir.join(&note.path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, &note.contents)?;
+    }
+    Ok(())
+}
+
+/// "First Last", or just "First".
+fn display_name(friend: &Friend) -> String {
+    match &friend.last_name {
+        Some(last) if !last.trim().is_empty() => format!("{} {}", friend.first_name, last),
+        _ => friend.first_name.clone(),
+    }
+}
+
+/// Strip characters that can't appear in a file name or inside `[[...]]`.
+fn sanitize_title(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let title = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    // Leading dots hide files on most systems
+    let title = title.trim_start_matches('.').trim().to_string();
+
+    if title.is_empty() {
+        "Untitled".to_string()
+    } else {
+        title
+    }
+}
+
+/// Assign each ID a unique title, suffixing clashes in iteration order.
+fn unique_titles(names: impl Iterator<Item = (Uuid, String)>) -> HashMap<Uuid, String> {
+    let mut taken = HashSet::new();
+    let mut titles = HashMap::new();
+
+    for (id, name) in names {
+        let base = sanitize_title(&name);
+        let mut title = base.clone();
+        let mut n = 2;
+        while !taken.insert(title.to_lowercase()) {
+            title = format!("{base} ({n})");
+            n += 1;
+        }
+        titles.insert(id, title);
+    }
+    titles
+}
+
+/// The relationship as seen from `friend_id`, or `None` if it doesn't
+/// involve them.
+fn relationship_line(
+    relationship: &FriendRelationship,
+    friend_id: Uuid,
+    titles: &HashMap<Uuid, String>,
+) -> Option<String> {
+    let (label, other) = if relationship.friend_a_id == friend_id {
+        (relationship.a_to_b.as_str(), relationship.friend_b_id)
+    } else if relationship.friend_b_id == friend_id {
+        (
+            relationship
+                .b_to_a
+                .as_deref()
+                .unwrap_or(&relationship.a_to_b),
+            relationship.friend_a_id,
+        )
+    } else {
+        return None;
+    };
+    let other = titles.get(&other)?;
+
+    let mut details = Vec::new();
+    if relationship.status != "active" {
+        details.push(relationship.status.clone());
+    }
+    if let Some(started) = relationship.started_on {
+        details.push(format!("since {started}"));
+    }
+    if let Some(ended) = relationship.ended_on {
+        details.push(format!("until {ended}"));
+    }
+
+    let mut line = format!("- {label} [[{other}]]");
+    if !details.is_empty() {
+        line.push_str(&format!(" ({})", details.join(", ")));
+    }
+    Some(line)
+}
+
+fn friend_note(
+    friend: &Friend,
+    title: &str,
+    groups: &[&Group],
+    attributes: Vec<(String, String)>,
+    relationships: &[String],
+) -> String {
+    let mut front = FrontMatter::new();
+    front
+        .scalar("fkb_id", friend.id.to_string())
+        .scalar("first_name", friend.first_name.as_str())
+        .optional(
+            "last_name",
+            friend.last_name.as_deref().filter(|l| !l.trim().is_empty()),
+        )
+        .optional("birthday", friend.date_of_birth.map(|d| d.to_string()))
+        .list("groups", groups.iter().map(|g| g.name.clone()).collect())
+        .map("attributes", attributes);
+
+    let mut out = front.render();
+    out.push_str(&format!("\n# {title}\n"));
+
+    for (heading, text) in [
+        ("Likes", &friend.likes),
+        ("Dislikes", &friend.dislikes),
+        ("Notes", &friend.notes),
+    ] {
+        if let Some(text) = text.as_deref().map(str::trim)
+            && !text.is_empty()
+        {
+            out.push_str(&format!("\n## {heading}\n\n{text}\n"));
+        }
+    }
+
+    if !relationships.is_empty() {
+        out.push_str("\n## Relationships\n\n");
+        for line in relationships {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+fn group_note(group: &Group, title: &str, members: &[&str]) -> String {
+    let mut front = FrontMatter::new();
+    front.scalar("fkb_id", group.id.to_string());
+
+    let mut out = front.render();
+    out.push_str(&format!("\n# {title}\n"));
+
+    if let Some(description) = group.description.as_deref().map(str::trim)
+        && !description.is_empty()
+    {
+        out.push_str(&format!("\n{description}\n"));
+    }
+
+    out.push_str("\n## Members\n\n");
+    if members.is_empty() {
+        out.push_str("_No members yet._\n");
+    }
+    for member in members {
+        out.push_str(&format!("- [[{member}]]\n"));
+    }
+
+    out
+}
+
+/// Read a friend note.
+///
+/// `file_stem` (the file name without `.md`) is used as the name when the
+/// front matter has no `first_name`, so a blank note called "Ada
+/// Lovelace.md" becomes a friend called Ada Lovelace. Text outside the
+/// known sections is kept as part of the notes.
+///
+/// # Errors
+///
+/// `Parse` for malformed front matter, an `fkb_id` that isn't a UUID or a
+/// birthday that isn't `YYYY-MM-DD`.
+pub fn read_friend_note(input: &str, file_stem: &str) -> Result<FriendNote, InterchangeError> {
+    let (front, body) = front_matter::parse(input)?;
+    let field_error = |key: &str, message: String| {
+        let line = front.line_of(key).unwrap_or(1);
+        InterchangeError::parse(line, message)
+    };
+
+    let id = match front.get_str("fkb_id").filter(|v| !v.is_empty()) {
+        Some(id) => Some(
+            Uuid::parse_str(id).map_err(|_| field_error("fkb_id", format!("bad fkb_id {id:?}")))?,
+        ),
+        None => None,
+    };
+
+    let (first_name, last_name) = match front.get_str("first_name").map(str::trim) {
+        Some(first) if !first.is_empty() => (
+            first.to_string(),
+            front
+                .get_str("last_name")
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(str::to_string),
+        ),
+        _ => split_full_name(file_stem)
+            .ok_or_else(|| InterchangeError::parse(1, "note has no first_name"))?,
+    };
+
+    let birthday = match front.get_str("birthday").map(str::trim) {
+        Some(value) if !value.is_empty() => Some(parse_iso_date(value).ok_or_else(|| {
+            field_error("birthday", format!("birthday {value:?} isn't YYYY-MM-DD"))
+        })?),
+        _ => None,
+    };
+
+    let groups = match front.get("groups") {
+        Some(Value::List(items)) => items.clone(),
+        Some(Value::Scalar(item)) if !item.is_empty() => vec![item.clone()],
+        _ => Vec::new(),
+    };
+    let groups = groups
+        .into_iter()
+        .map(|g| g.trim().to_string())
+        .filter(|g| !g.is_empty())
+        .collect();
+
+    let attributes = match front.get("attributes") {
+        Some(Value::Map(pairs)) => pairs.clone(),
+        Some(Value::Scalar(value)) if value.is_empty() => Vec::new(),
+        Some(_) => {
+            return Err(field_error(
+                "attributes",
+                "attributes must be a map of key: value".to_string(),
+            ));
+        }
+        None => Vec::new(),
+    };
+
+    let sections = read_sections(body);
+
+    Ok(FriendNote {
+        id,
+        first_name,
+        last_name,
+        birthday,
+        groups,
+        attributes,
+        likes: sections.likes,
+        dislikes: sections.dislikes,
+        notes: sections.notes,
+    })
+}
+
+/// Parse "1950-03-12".
+fn parse_iso_date(value: &str) -> Option<Date> {
+    let mut parts = value.splitn(3, '-');
+    let year: i32 = parts.next()?.parse().ok()?;
+    let month: u8 = parts.next()?.parse().ok()?;
+    let day: u8 = parts.next()?.parse().ok()?;
+    Date::from_calendar_date(year, Month::try_from(month).ok()?, day).ok()
+}
+
+#[derive(Default)]
+struct Sections {
+    likes: Option<String>,
+    dislikes: Option<String>,
+    notes: Option<String>,
+}
+
+/// Split a friend note body into its sections.
+fn read_sections(body: &str) -> Sections {
+    enum Current {
+        Loose,
+        Likes,
+        Dislikes,
+        Notes,
+        Relationships,
+    }
+
+    let mut current = Current::Loose;
+    let mut seen_title = false;
+    let (mut loose, mut likes, mut dislikes, mut notes) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+
+    for line in body.lines() {
+        if !seen_title && line.starts_with("# ") {
+            seen_title = true;
+            continue;
+        }
+        if let Some(heading) = line.strip_prefix("## ") {
+            let next = match heading.trim().to_lowercase().as_str() {
+                "likes" => Some(Current::Likes),
+                "dislikes" => Some(Current::Dislikes),
+                "notes" => Some(Current::Notes),
+                "relationships" => Some(Current::Relationships),
+                _ => None,
+            };
+            if let Some(next) = next {
+                current = next;
+                continue;
+            }
+        }
+        match current {
+            Current::Loose => loose.push(line),
+            Current::Likes => likes.push(line),
+            Current::Dislikes => dislikes.push(line),
+            Current::Notes => notes.push(line),
+            Current::Relationships => {}
+        }
+    }
+
+    let text = |lines: Vec<&str>| {
+        let text = lines.join("\n").trim().to_string();
+        (!text.is_empty()).then_some(text)
+    };
+    let notes = match (text(loose), text(notes)) {
+        (Some(loose), Some(notes)) => Some(format!("{loose}\n\n{notes}")),
+        (loose, notes) => loose.or(notes),
+    };
+
+    Sections {
+        likes: text(likes),
+        dislikes: text(dislikes),
+        notes,
+    }
+}
+
+/// Exports a user's knowledgebase as a Markdown vault.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let notes = MarkdownExporter::new(ctx.clone()).export(user_id).await?;
+/// markdown::write_vault(Path::new("vault"), &notes)?;
+/// ```
+pub struct MarkdownExporter {
+    backups: BackupRepository,
+}
+
+impl MarkdownExporter {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            backups: BackupRepository::new(ctx),
+        }
+    }
+
+    /// Render every friend and group note for a user.
+    ///
+    /// Reads through a consistent snapshot, so links always point at
+    /// notes in the same export.
+    pub async fn export(&self, user_id: Uuid) -> Result<Vec<Note>, InterchangeError> {
+        let snapshot = self.backups.snapshot_live(user_id).await?;
+        Ok(render_vault(&snapshot))
+    }
+}
@@ -0,0 +1,655 @@
+//! # Vault Sync
+//!
+//! Two-way sync between a user's knowledgebase and a Markdown vault
+//! written by `render_vault`. Edits to friend notes are written to the
+//! database; database changes are written back to the notes.
+//!
+//! ## Sync State
+//!
+//! The vault keeps a `.fkb-sync.json` file with the SHA-256 of every note
+//! as of the last sync. Comparing a note on disk, and the note as the
+//! database would render it now, against that hash tells which side
+//! changed:
+//!
+//! | Vault    | Database | Result                                  |
+//! |----------|----------|-----------------------------------------|
+//! | same     | same     | nothing                                 |
+//! | same     | changed  | note rewritten                          |
+//! | changed  | same     | edits imported, note rewritten          |
+//! | changed  | changed  | conflict, both sides left alone         |
+//!
+//! Conflicts stay conflicts on every sync until they're resolved, either
+//! by making the note match the database, by deleting the note (the
+//! database version is written again), or by syncing once with
+//! `ConflictResolution::PreferVault` / `PreferDatabase`.
+//!
+//! ## What Syncs Back
+//!
+//! - Friend notes: names, birthday, groups, attributes, likes, dislikes
+//!   and notes. A note in `Friends/` without an `fkb_id` becomes a new
+//!   friend.
+//! - Group notes are generated; editing one is reported as a conflict.
+//! - Relationships sections are generated and ignored on import.
+//!
+//! Deleting a note doesn't delete the friend: the note is written again.
+//! Deleting a friend in the database removes their note, unless the note
+//! was edited since the last sync. Birthdays can't be cleared from the
+//! vault, since friend updates only ever set fields.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::models::Friend;
+use crate::repositories::{
+    BackupRepository, CreateFriendAttributeInput, FriendAttributeRepository, FriendRepository,
+    Repository, RepositoryContext, RepositoryError, UpdateFriendInput, UserSnapshot,
+};
+
+use super::super::attributes::ImportedAttribute;
+use super::super::error::InterchangeError;
+use super::super::imported::{ImportWriter, ImportedFriend, replaced_text};
+use super::{FRIENDS_DIR, FriendNote, GROUPS_DIR, NoteKind, read_friend_note, render_vault};
+
+/// Name of the sync state file at the vault root.
+pub const STATE_FILE: &str = ".fkb-sync.json";
+
+/// What to do when a note changed on both sides.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    /// Leave both sides alone and report the conflict
+    #[default]
+    Report,
+    /// Import the note, overwriting the database
+    PreferVault,
+    /// Rewrite the note from the database
+    PreferDatabase,
+}
+
+/// A note that couldn't be synced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncConflict {
+    /// Path relative to the vault root
+    pub path: String,
+    pub reason: String,
+}
+
+/// What a sync did. Paths are relative to the vault root.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    /// Notes whose edits were written to the database
+    pub imported: Vec<String>,
+    /// Notes without an `fkb_id` that became new friends
+    pub created: Vec<String>,
+    /// Notes rewritten from the database
+    pub written: Vec<String>,
+    /// Notes removed because their friend or group was deleted
+    pub removed: Vec<String>,
+    pub conflicts: Vec<SyncConflict>,
+}
+
+impl SyncReport {
+    /// `true` if the sync changed nothing and found no conflicts.
+    pub fn is_empty(&self) -> bool {
+        self.imported.is_empty()
+            && self.created.is_empty()
+            && self.written.is_empty()
+            && self.removed.is_empty()
+            && self.conflicts.is_empty()
+    }
+
+    fn conflict(&mut self, path: &str, reason: impl Into<String>) {
+        self.conflicts.push(SyncConflict {
+            path: path.to_string(),
+            reason: reason.into(),
+        });
+    }
+}
+
+/// Contents of `.fkb-sync.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SyncState {
+    user_id: Option<Uuid>,
+    notes: BTreeMap<Uuid, SyncedNote>,
+}
+
+/// A note as of the last sync.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SyncedNote {
+    path: String,
+    hash: String,
+}
+
+/// A note found in the vault.
+struct VaultFile {
+    path: String,
+    kind: NoteKind,
+    hash: String,
+    /// The note's `fkb_id`, if it has a readable one
+    id: Option<Uuid>,
+    /// Friend notes only; `Err` holds why the note couldn't be read
+    note: Option<Result<FriendNote, String>>,
+}
+
+/// Syncs a user's knowledgebase with a vault directory.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let sync = VaultSync::new(ctx.clone());
+/// let report = sync
+///     .sync(user_id, Path::new("vault"), ConflictResolution::Report)
+///     .await?;
+/// for conflict in &report.conflicts {
+///     eprintln!("{}: {}", conflict.path, conflict.reason);
+/// }
+/// ```
+pub struct VaultSync {
+    ctx: RepositoryContext,
+    friends: FriendRepository,
+    attributes: FriendAttributeRepository,
+    backups: BackupRepository,
+}
+
+impl VaultSync {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            friends: FriendRepository::new(ctx.clone()),
+            attributes: FriendAttributeRepository::new(ctx.clone()),
+            backups: BackupRepository::new(ctx.clone()),
+            ctx,
+        }
+    }
+
+    /// Run one sync pass.
+    ///
+    /// Each imported note is written in its own transaction, so one bad
+    /// note doesn't block the rest; it's reported as a conflict instead.
+    ///
+    /// # Errors
+    ///
+    /// - `Unsupported` if the vault was last synced with another account
+    /// - `Io` if the vault can't be read or written
+    /// - `Repository` if the database can't be read
+    pub async fn sync(
+        &self,
+        user_id: Uuid,
+        dir: &Path,
+        resolution: ConflictResolution,
+    ) -> Result<SyncReport, InterchangeError> {
+        let state = load_state(dir, user_id)?;
+        let files = scan(dir)?;
+        let mut report = SyncReport::default();
+
+        // Held notes are conflicts: neither side is touched and their
+        // state entry is kept, so they stay conflicts until resolved.
+        let mut hold: HashSet<Uuid> = HashSet::new();
+        let mut by_id: HashMap<Uuid, &VaultFile> = HashMap::new();
+        for file in &files {
+            let Some(id) = file.id else { continue };
+            if let Some(first) = by_id.get(&id) {
+                report.conflict(
+                    &file.path,
+                    format!("same fkb_id as {}; remove one of them", first.path),
+                );
+                hold.insert(id);
+            } else {
+                by_id.insert(id, file);
+            }
+        }
+
+        // Phase 1: decide each note against the database as it is now
+        let before = self.backups.snapshot_live(user_id).await?;
+        let rendered: HashMap<Uuid, String> = render_vault(&before)
+            .into_iter()
+            .map(|note| (note.id, hash(&note.contents)))
+            .collect();
+
+        let mut apply: Vec<(Uuid, &VaultFile, &FriendNote)> = Vec::new();
+        let mut create: Vec<(&VaultFile, &FriendNote)> = Vec::new();
+        let mut remove: Vec<&VaultFile> = Vec::new();
+
+        for file in &files {
+            let note = match &file.note {
+                Some(Ok(note)) => Some(note),
+                Some(Err(message)) => {
+                    // Preferring the database rewrites a broken note that
+                    // can still be matched to its friend
+                    if resolution != ConflictResolution::PreferDatabase || file.id.is_none() {
+                        report.conflict(&file.path, format!("can't read note: {message}"));
+                        hold.extend(file.id);
+                    }
+                    continue;
+                }
+                None => None,
+            };
+            let Some(id) = file.id else {
+                if let Some(note) = note {
+                    create.push((file, note));
+                }
+                continue;
+            };
+            if hold.contains(&id) {
+                continue;
+            }
+
+            let base = state.notes.get(&id).map(|s| s.hash.as_str());
+            let vault_changed = base != Some(file.hash.as_str());
+
+            let Some(db_hash) = rendered.get(&id) else {
+                // Deleted in the database, or never part of this account
+                match (base, vault_changed) {
+                    (Some(_), false) => remove.push(file),
+                    (Some(_), true) if resolution == ConflictResolution::PreferDatabase => {
+                        remove.push(file)
+                    }
+                    (Some(_), true) => {
+                        report.conflict(
+                            &file.path,
+                            "deleted in the database but edited in the vault",
+                        );
+                        hold.insert(id);
+                    }
+                    (None, _) => {
+                        report
+                            .conflict(&file.path, "fkb_id doesn't match anything in this account");
+                        hold.insert(id);
+                    }
+                }
+                continue;
+            };
+
+            let db_changed = base != Some(db_hash.as_str());
+            if !vault_changed || file.hash == *db_hash {
+                continue;
+            }
+            if !db_changed && let Some(note) = note {
+                apply.push((id, file, note));
+                continue;
+            }
+
+            match (resolution, note) {
+                // Phase 2 rewrites every note that isn't held
+                (ConflictResolution::PreferDatabase, _) => {}
+                (ConflictResolution::PreferVault, Some(note)) => apply.push((id, file, note)),
+                _ => {
+                    let reason = if file.kind == NoteKind::Group {
+                        "group notes are generated from the database; edits here aren't imported"
+                    } else if base.is_none() {
+                        "differs from the database and has never been synced"
+                    } else {
+                        "changed in both the vault and the database since the last sync"
+                    };
+                    report.conflict(&file.path, reason);
+                    hold.insert(id);
+                }
+            }
+        }
+
+        // Write vault edits to the database
+        let mut writer = ImportWriter::load(self.ctx.clone(), user_id).await?;
+        let mut moved_from: HashMap<Uuid, &VaultFile> = HashMap::new();
+        let mut imported: HashSet<Uuid> = HashSet::new();
+
+        for (id, file, note) in apply {
+            let Some(friend) = before.friends.iter().find(|f| f.id == id) else {
+                continue;
+            };
+            let checkpoint = writer.checkpoint();
+            let mut tx = self.ctx.transaction().await?;
+            let result = self
+                .apply_note(&mut tx, &mut writer, &before, friend, note)
+                .await;
+            match result {
+                Ok(()) => {
+                    tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+                    report.imported.push(file.path.clone());
+                    imported.insert(id);
+                }
+                Err(err) => {
+                    writer.rollback_to(checkpoint);
+                    report.conflict(&file.path, format!("couldn't import: {err}"));
+                    hold.insert(id);
+                }
+            }
+        }
+
+        for (file, note) in create {
+            let checkpoint = writer.checkpoint();
+            let mut tx = self.ctx.transaction().await?;
+            let result = writer.create_friend(&mut tx, imported_friend(note)).await;
+            match result {
+                Ok(friend) => {
+                    tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+                    report.created.push(file.path.clone());
+                    imported.insert(friend.id);
+                    moved_from.insert(friend.id, file);
+                }
+                Err(err) => {
+                    writer.rollback_to(checkpoint);
+                    report.conflict(&file.path, format!("couldn't create friend: {err}"));
+                }
+            }
+        }
+
+        // Phase 2: write notes from the database as it is now
+        let after = self.backups.snapshot_live(user_id).await?;
+        let notes = render_vault(&after);
+        let live: HashSet<Uuid> = notes.iter().map(|n| n.id).collect();
+        let mut next = SyncState {
+            user_id: Some(user_id),
+            notes: BTreeMap::new(),
+        };
+
+        for id in &hold {
+            if let Some(synced) = state.notes.get(id) {
+                next.notes.insert(*id, synced.clone());
+            }
+        }
+
+        for note in &notes {
+            if hold.contains(&note.id) {
+                continue;
+            }
+            let new_hash = hash(&note.contents);
+            let current = moved_from
+                .get(&note.id)
+                .or_else(|| by_id.get(&note.id))
+                .copied();
+
+            // Unchanged and in place: just record it
+            if let Some(file) = current
+                && file.path == note.path
+                && file.hash == new_hash
+            {
+                next.notes.insert(note.id, synced(&note.path, new_hash));
+                continue;
+            }
+
+            if let Some(file) = current
+                && !unchanged_on_disk(dir, file)?
+            {
+                report.conflict(&file.path, "changed in the vault during the sync");
+                keep(&state, &mut next, note.id);
+                continue;
+            }
+
+            let target = dir.join(&note.path);
+            if current.is_none_or(|file| file.path != note.path) && target.exists() {
+                report.conflict(
+                    &note.path,
+                    "another file is already at this path; rename or remove it",
+                );
+                keep(&state, &mut next, note.id);
+                continue;
+            }
+
+            write_note(&target, &note.contents)?;
+            if let Some(file) = current
+                && file.path != note.path
+            {
+                remove_note(dir, &file.path)?;
+            }
+
+            if !imported.contains(&note.id) {
+                report.written.push(note.path.clone());
+            }
+            next.notes.insert(note.id, synced(&note.path, new_hash));
+        }
+
+        for file in remove {
+            let Some(id) = file.id else { continue };
+            if live.contains(&id) {
+                continue;
+            }
+            if !unchanged_on_disk(dir, file)? {
+                report.conflict(&file.path, "changed in the vault during the sync");
+                keep(&state, &mut next, id);
+                continue;
+            }
+            remove_note(dir, &file.path)?;
+            report.removed.push(file.path.clone());
+        }
+
+        save_state(dir, &next)?;
+        Ok(report)
+    }
+
+    /// Write one edited note's fields, attributes and groups.
+    async fn apply_note(
+        &self,
+        conn: &mut PgConnection,
+        writer: &mut ImportWriter,
+        snapshot: &UserSnapshot,
+        friend: &Friend,
+        note: &FriendNote,
+    ) -> Result<(), RepositoryError> {
+        let update = UpdateFriendInput {
+            first_name: (friend.first_name != note.first_name).then(|| note.first_name.clone()),
+            last_name: replaced_text(&friend.last_name, &note.last_name),
+            date_of_birth: (friend.date_of_birth != note.birthday).then_some(note.birthday),
+            likes: replaced_text(&friend.likes, &note.likes),
+            dislikes: replaced_text(&friend.dislikes, &note.dislikes),
+            notes: replaced_text(&friend.notes, &note.notes),
+        };
+        let has_update = update.first_name.is_some()
+            || update.last_name.is_some()
+            || update.date_of_birth.is_some()
+            || update.likes.is_some()
+            || update.dislikes.is_some()
+            || update.notes.is_some();
+        if has_update {
+            self.friends
+                .update_in(conn, friend.id, Some(friend.version), update)
+                .await?;
+        }
+
+        let existing: Vec<_> = snapshot
+            .friend_attributes
+            .iter()
+            .filter(|a| a.friend_id == friend.id)
+            .collect();
+        for (key, value) in &note.attributes {
+            let current = existing.iter().find(|a| a.key == *key);
+            if current.is_some_and(|a| a.value == *value) {
+                continue;
+            }
+            self.attributes
+                .upsert_in(
+                    conn,
+                    CreateFriendAttributeInput {
+                        friend_id: friend.id,
+                        key: key.clone(),
+                        value: value.clone(),
+                        value_type: Some(
+                            current.map_or_else(|| "text".to_string(), |a| a.value_type.clone()),
+                        ),
+                    },
+                )
+                .await?;
+        }
+        for attribute in &existing {
+            if !note.attributes.iter().any(|(key, _)| *key == attribute.key) {
+                self.attributes.delete_in(conn, attribute.id).await?;
+            }
+        }
+
+        let groups: HashMap<Uuid, String> = snapshot
+            .groups
+            .iter()
+            .map(|g| (g.id, g.name.to_lowercase()))
+            .collect();
+        let current: Vec<(Uuid, &str)> = snapshot
+            .friend_groups
+            .iter()
+            .filter(|m| m.friend_id == friend.id)
+            .filter_map(|m| groups.get(&m.group_id).map(|n| (m.group_id, n.as_str())))
+            .collect();
+        let wanted: HashSet<String> = note.groups.iter().map(|g| g.to_lowercase()).collect();
+
+        let joining: Vec<String> = note
+            .groups
+            .iter()
+            .filter(|g| !current.iter().any(|(_, n)| *n == g.to_lowercase()))
+            .cloned()
+            .collect();
+        writer.join_groups(conn, friend.id, &joining).await?;
+        for (group_id, name) in current {
+            if !wanted.contains(name) {
+                self.friends
+                    .remove_from_group_in(conn, friend.id, group_id)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn imported_friend(note: &FriendNote) -> ImportedFriend {
+    ImportedFriend {
+        first_name: note.first_name.clone(),
+        last_name: note.last_name.clone(),
+        date_of_birth: note.birthday,
+        likes: note.likes.clone(),
+        dislikes: note.dislikes.clone(),
+        notes: note.notes.clone(),
+        groups: note.groups.clone(),
+        attributes: note
+            .attributes
+            .iter()
+            .map(|(key, value)| ImportedAttribute::new(key.clone(), value.clone(), "text"))
+            .collect(),
+    }
+}
+
+fn hash(contents: &str) -> String {
+    format!("{:x}", Sha256::digest(contents.as_bytes()))
+}
+
+fn synced(path: &str, hash: String) -> SyncedNote {
+    SyncedNote {
+        path: path.to_string(),
+        hash,
+    }
+}
+
+/// Carry a note's previous state entry over to the next state.
+fn keep(state: &SyncState, next: &mut SyncState, id: Uuid) {
+    if let Some(synced) = state.notes.get(&id) {
+        next.notes.insert(id, synced.clone());
+    }
+}
+
+fn load_state(dir: &Path, user_id: Uuid) -> Result<SyncState, InterchangeError> {
+    let path = dir.join(STATE_FILE);
+    if !path.exists() {
+        return Ok(SyncState::default());
+    }
+
+    let json = std::fs::read_to_string(&path)?;
+    let state: SyncState = serde_json::from_str(&json).map_err(|err| {
+        InterchangeError::parse(err.line().max(1), format!("{STATE_FILE}: {err}"))
+    })?;
+    if state.user_id.is_some_and(|id| id != user_id) {
+        return Err(InterchangeError::Unsupported(
+            "this vault is synced with a different account".to_string(),
+        ));
+    }
+    Ok(state)
+}
+
+/// Write the state file via a temporary file, so an interrupted sync
+/// never leaves it half-written.
+fn save_state(dir: &Path, state: &SyncState) -> Result<(), InterchangeError> {
+    let json = serde_json::to_string_pretty(state).map_err(RepositoryError::from)?;
+    let temp = dir.join(format!("{STATE_FILE}.tmp"));
+    std::fs::write(&temp, json)?;
+    std::fs::rename(temp, dir.join(STATE_FILE))?;
+    Ok(())
+}
+
+/// Read every note in the friend and group folders.
+fn scan(dir: &Path) -> Result<Vec<VaultFile>, InterchangeError> {
+    let mut files = Vec::new();
+
+    for (folder, kind) in [
+        (FRIENDS_DIR, NoteKind::Friend),
+        (GROUPS_DIR, NoteKind::Group),
+    ] {
+        let folder_path = dir.join(folder);
+        if !folder_path.is_dir() {
+            continue;
+        }
+
+        let mut entries: Vec<_> = std::fs::read_dir(&folder_path)?.collect::<Result<_, _>>()?;
+        entries.sort_by_key(|e| e.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("md") || !path.is_file() {
+                continue;
+            }
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let contents = std::fs::read_to_string(&path)?;
+
+            let (id, note) = match kind {
+                NoteKind::Friend => match read_friend_note(&contents, stem) {
+                    Ok(note) => (note.id, Some(Ok(note))),
+                    Err(err) => (loose_id(&contents), Some(Err(err.to_string()))),
+                },
+                NoteKind::Group => (loose_id(&contents), None),
+            };
+
+            files.push(VaultFile {
+                path: format!("{folder}/{stem}.md"),
+                kind,
+                hash: hash(&contents),
+                id,
+                note,
+            });
+        }
+    }
+
+    Ok(files)
+}
+
+/// Find an `fkb_id:` line without parsing the whole front matter, so
+/// notes with broken front matter can still be matched to a friend.
+fn loose_id(contents: &str) -> Option<Uuid> {
+    contents.lines().find_map(|line| {
+        let value = line.strip_prefix("fkb_id:")?;
+        Uuid::parse_str(value.trim().trim_matches(['"', '\''])).ok()
+    })
+}
+
+/// Whether a scanned note is still exactly as it was read.
+fn unchanged_on_disk(dir: &Path, file: &VaultFile) -> Result<bool, InterchangeError> {
+    match std::fs::read_to_string(dir.join(&file.path)) {
+        Ok(contents) => Ok(hash(&contents) == file.hash),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn write_note(path: &Path, contents: &str) -> Result<(), InterchangeError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+fn remove_note(dir: &Path, path: &str) -> Result<(), InterchangeError> {
+    match std::fs::remove_file(dir.join(path)) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
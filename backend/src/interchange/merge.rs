@@ -0,0 +1,913 @@
+//! # Import Preview and Merge
+//!
+//! A dry run for any importer: match incoming records against the user's
+//! existing friends, show what would change, then apply exactly that.
+//!
+//! ## Matching
+//!
+//! An incoming record matches an existing friend when:
+//! - They share an email address (any attribute with `value_type` "email"
+//!   or a key starting with "email", in any case), or
+//! - Their first and last names are equal (case-insensitive) and their
+//!   birthdays don't contradict each other (equal, or one is missing)
+//!
+//! Email matches win over name matches. A record that matches more than
+//! one friend, or matches by email but has a different birthday, is a
+//! conflict and is left alone.
+//!
+//! ## Strategies
+//!
+//! | Strategy | Matched friend |
+//! |----------|----------------|
+//! | `KeepExisting` | Left untouched; only unmatched records are created |
+//! | `Overwrite` | Every differing field takes the incoming value |
+//! | `FillBlanks` | Only fields that are empty on the friend are set |
+//!
+//! Attributes are compared by key and groups by name. Imports never delete
+//! anything: a field missing from the file is not a change.
+//!
+//! ## Example
+//!
+//! ```rust,ignore
+//! let planner = ImportPlanner::new(ctx.clone());
+//! let records = vcard::parse(&input)?;
+//!
+//! let plan = planner.plan(user_id, records, MergeStrategy::FillBlanks).await?;
+//! println!("{}", serde_json::to_string_pretty(&plan)?); // show the user
+//!
+//! let report = planner.apply(user_id, plan).await?;
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::models::{Friend, FriendAttribute, Group};
+use crate::repositories::{
+    CreateFriendAttributeInput, FriendAttributeRepository, FriendRepository, Repository,
+    RepositoryContext, RepositoryError, UpdateFriendInput,
+};
+
+use super::attributes::{ImportedAttribute, strip_repeat_suffix};
+use super::error::InterchangeError;
+use super::imported::{ImportWriter, ImportedFriend, RejectedRow};
+
+/// How to treat friends that an incoming record matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// Never modify existing friends
+    #[default]
+    KeepExisting,
+    /// Replace differing values with the incoming ones
+    Overwrite,
+    /// Only set values the existing friend doesn't have
+    FillBlanks,
+}
+
+/// One difference between an incoming record and the friend it matched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// A column on `friends` (e.g., "notes", "date_of_birth")
+    Field {
+        field: String,
+        existing: Option<String>,
+        incoming: String,
+    },
+    /// An attribute to add or replace
+    Attribute {
+        key: String,
+        existing: Option<String>,
+        incoming: String,
+        value_type: String,
+    },
+    /// A group to join
+    Group { name: String },
+}
+
+impl Change {
+    /// `true` if this change only fills something that is empty today.
+    pub fn fills_blank(&self) -> bool {
+        match self {
+            Change::Field { existing, .. } | Change::Attribute { existing, .. } => {
+                existing.as_deref().is_none_or(|e| e.trim().is_empty())
+            }
+            Change::Group { .. } => true,
+        }
+    }
+}
+
+/// What the import would do with one record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlannedAction {
+    /// No existing friend matched; a new one will be created
+    Create,
+    /// The matched friend will get these changes
+    Update {
+        friend_id: Uuid,
+        /// The friend's version the changes were planned against
+        version: i64,
+        matched_by: Vec<String>,
+        changes: Vec<Change>,
+    },
+    /// The matched friend is left as-is
+    Skip {
+        friend_id: Uuid,
+        matched_by: Vec<String>,
+        reason: String,
+    },
+    /// The record can't be applied safely
+    Conflict {
+        candidates: Vec<Uuid>,
+        reason: String,
+    },
+}
+
+/// One incoming record and its planned action.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlannedRecord {
+    pub incoming: ImportedFriend,
+    #[serde(flatten)]
+    pub action: PlannedAction,
+}
+
+/// Counts per action, for a one-line preview summary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PlanSummary {
+    pub create: usize,
+    pub update: usize,
+    pub skip: usize,
+    pub conflict: usize,
+    pub rejected: usize,
+}
+
+/// The result of a dry run. Nothing has been written yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportPlan {
+    pub strategy: MergeStrategy,
+    pub records: Vec<PlannedRecord>,
+    /// Records the importer couldn't read (e.g., CSV rows with bad dates)
+    pub rejected: Vec<RejectedRow>,
+}
+
+impl ImportPlan {
+    pub fn summary(&self) -> PlanSummary {
+        let mut summary = PlanSummary {
+            rejected: self.rejected.len(),
+            ..PlanSummary::default()
+        };
+        for record in &self.records {
+            match record.action {
+                PlannedAction::Create => summary.create += 1,
+                PlannedAction::Update { .. } => summary.update += 1,
+                PlannedAction::Skip { .. } => summary.skip += 1,
+                PlannedAction::Conflict { .. } => summary.conflict += 1,
+            }
+        }
+        summary
+    }
+}
+
+/// What `ImportPlanner::apply` did.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplyReport {
+    pub created: Vec<Friend>,
+    pub updated: Vec<Friend>,
+    pub skipped: usize,
+    pub conflicts: usize,
+    pub groups_created: Vec<Group>,
+    /// The friend each plan record was created as or matched to, in plan
+    /// order (`None` for conflicts)
+    pub friend_ids: Vec<Option<Uuid>>,
+}
+
+/// An existing friend with what matching and diffing need.
+struct Existing {
+    friend: Friend,
+    attributes: Vec<FriendAttribute>,
+    groups: Vec<String>,
+}
+
+/// Email addresses of a record, lower-cased.
+fn emails<'a>(attributes: impl Iterator<Item = (&'a str, &'a str, &'a str)>) -> HashSet<String> {
+    attributes
+        .filter(|(key, _, value_type)| {
+            value_type.eq_ignore_ascii_case("email")
+                || key.to_ascii_lowercase().starts_with("email")
+        })
+        .map(|(_, value, _)| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+fn same_name(incoming: &ImportedFriend, friend: &Friend) -> bool {
+    let normalize = |s: Option<&str>| s.unwrap_or("").trim().to_lowercase();
+    normalize(Some(&incoming.first_name)) == normalize(Some(&friend.first_name))
+        && normalize(incoming.last_name.as_deref()) == normalize(friend.last_name.as_deref())
+}
+
+/// Decide what to do with one record.
+fn plan_record(
+    incoming: &ImportedFriend,
+    existing: &[Existing],
+    strategy: MergeStrategy,
+) -> PlannedAction {
+    let incoming_emails = emails(
+        incoming
+            .attributes
+            .iter()
+            .map(|a| (a.key.as_str(), a.value.as_str(), a.value_type.as_str())),
+    );
+    let birthdays_agree = |friend: &Friend| match (incoming.date_of_birth, friend.date_of_birth) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    };
+
+    let by_email: Vec<&Existing> = existing
+        .iter()
+        .filter(|e| {
+            let theirs = emails(
+                e.attributes
+                    .iter()
+                    .map(|a| (a.key.as_str(), a.value.as_str(), a.value_type.as_str())),
+            );
+            !incoming_emails.is_disjoint(&theirs)
+        })
+        .collect();
+
+    let (candidates, mut matched_by) = if by_email.is_empty() {
+        let by_name: Vec<&Existing> = existing
+            .iter()
+            .filter(|e| same_name(incoming, &e.friend) && birthdays_agree(&e.friend))
+            .collect();
+        (by_name, vec!["name".to_string()])
+    } else {
+        (by_email, vec!["email".to_string()])
+    };
+
+    let matched = match candidates.as_slice() {
+        [] => return PlannedAction::Create,
+        [one] => *one,
+        many => {
+            return PlannedAction::Conflict {
+                candidates: many.iter().map(|e| e.friend.id).collect(),
+                reason: format!(
+                    "matches {} existing friends by {}",
+                    many.len(),
+                    matched_by[0]
+                ),
+            };
+        }
+    };
+    let friend_id = matched.friend.id;
+
+    if !birthdays_agree(&matched.friend) {
+        return PlannedAction::Conflict {
+            candidates: vec![friend_id],
+            reason: "email matches but the birthday differs".to_string(),
+        };
+    }
+    if incoming.date_of_birth.is_some() && incoming.date_of_birth == matched.friend.date_of_birth {
+        matched_by.push("birthday".to_string());
+    }
+
+    if strategy == MergeStrategy::KeepExisting {
+        return PlannedAction::Skip {
+            friend_id,
+            matched_by,
+            reason: "keeping existing friend".to_string(),
+        };
+    }
+
+    let changes: Vec<Change> = diff(incoming, matched)
+        .into_iter()
+        .filter(|change| strategy == MergeStrategy::Overwrite || change.fills_blank())
+        .collect();
+
+    if changes.is_empty() {
+        PlannedAction::Skip {
+            friend_id,
+            matched_by,
+            reason: "nothing to change".to_string(),
+        }
+    } else {
+        PlannedAction::Update {
+            friend_id,
+            version: matched.friend.version,
+            matched_by,
+            changes,
+        }
+    }
+}
+
+/// Every way `incoming` differs from `existing`, ignoring missing values.
+fn diff(incoming: &ImportedFriend, existing: &Existing) -> Vec<Change> {
+    let friend = &existing.friend;
+    let mut changes = Vec::new();
+
+    let fields = [
+        (
+            "first_name",
+            Some(friend.first_name.clone()),
+            Some(incoming.first_name.clone()),
+        ),
+        (
+            "last_name",
+            friend.last_name.clone(),
+            incoming.last_name.clone(),
+        ),
+        (
+            "date_of_birth",
+            friend.date_of_birth.map(|d| d.to_string()),
+            incoming.date_of_birth.map(|d| d.to_string()),
+        ),
+        ("likes", friend.likes.clone(), incoming.likes.clone()),
+        (
+            "dislikes",
+            friend.dislikes.clone(),
+            incoming.dislikes.clone(),
+        ),
+        ("notes", friend.notes.clone(), incoming.notes.clone()),
+    ];
+    for (field, current, new) in fields {
+        let Some(new) = new.filter(|v| !v.trim().is_empty()) else {
+            continue;
+        };
+        if current.as_deref() != Some(new.as_str()) {
+            changes.push(Change::Field {
+                field: field.to_string(),
+                existing: current,
+                incoming: new,
+            });
+        }
+    }
+
+    for attribute in &incoming.attributes {
+        if let Some(change) = attribute_change(attribute, &existing.attributes) {
+            changes.push(change);
+        }
+    }
+
+    for name in &incoming.groups {
+        if !existing.groups.iter().any(|g| g.eq_ignore_ascii_case(name)) {
+            changes.push(Change::Group { name: name.clone() });
+        }
+    }
+
+    changes
+}
+
+/// The change needed to bring one incoming attribute onto the friend.
+///
+/// A value the friend already has under the same base key (e.g., the
+/// incoming `email` equals their `email#2`) is not a change.
+fn attribute_change(incoming: &ImportedAttribute, existing: &[FriendAttribute]) -> Option<Change> {
+    let base = strip_repeat_suffix(&incoming.key);
+    // Email addresses are case-insensitive; other values are compared as-is
+    let same_value = |value: &str| match incoming.value_type.as_str() {
+        "email" => value.eq_ignore_ascii_case(&incoming.value),
+        _ => value == incoming.value,
+    };
+    let already_there = existing
+        .iter()
+        .any(|a| strip_repeat_suffix(&a.key) == base && same_value(&a.value));
+    if already_there {
+        return None;
+    }
+
+    let current = existing.iter().find(|a| a.key == incoming.key);
+    Some(Change::Attribute {
+        key: incoming.key.clone(),
+        existing: current.map(|a| a.value.clone()),
+        incoming: incoming.value.clone(),
+        value_type: incoming.value_type.clone(),
+    })
+}
+
+/// Builds import plans and applies them.
+pub struct ImportPlanner {
+    ctx: RepositoryContext,
+    friends: FriendRepository,
+    attributes: FriendAttributeRepository,
+}
+
+impl ImportPlanner {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            friends: FriendRepository::new(ctx.clone()),
+            attributes: FriendAttributeRepository::new(ctx.clone()),
+            ctx,
+        }
+    }
+
+    /// Dry run: decide what importing `records` would do. Writes nothing.
+    pub async fn plan(
+        &self,
+        user_id: Uuid,
+        records: Vec<ImportedFriend>,
+        strategy: MergeStrategy,
+    ) -> Result<ImportPlan, InterchangeError> {
+        let existing = self.load_existing(user_id).await?;
+
+        let records = records
+            .into_iter()
+            .map(|incoming| PlannedRecord {
+                action: plan_record(&incoming, &existing, strategy),
+                incoming,
+            })
+            .collect();
+
+        Ok(ImportPlan {
+            strategy,
+            records,
+            rejected: Vec::new(),
+        })
+    }
+
+    /// Carry out a plan in one transaction.
+    ///
+    /// Only `Create` and `Update` records are written, and updates only
+    /// touch the fields listed in their changes. If the friends changed
+    /// since the plan was made, re-run `plan` first.
+    ///
+    /// # Errors
+    ///
+    /// - `Conflict` if a friend to update was changed after the plan was
+    ///   made; nothing is written then
+    pub async fn apply(
+        &self,
+        user_id: Uuid,
+        plan: ImportPlan,
+    ) -> Result<ApplyReport, InterchangeError> {
+        let mut writer = ImportWriter::load(self.ctx.clone(), user_id).await?;
+
+        let mut tx = self.ctx.transaction().await?;
+        let report = self.apply_in(&mut tx, &mut writer, plan).await?;
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+
+        Ok(report)
+    }
+
+    /// Carry out a plan on a caller-provided connection.
+    ///
+    /// Like `apply`, but the caller owns the transaction, so an importer
+    /// can write more (e.g., relationships between the friends in
+    /// `friend_ids`) before committing.
+    pub async fn apply_in(
+        &self,
+        conn: &mut PgConnection,
+        writer: &mut ImportWriter,
+        plan: ImportPlan,
+    ) -> Result<ApplyReport, RepositoryError> {
+        let mut report = ApplyReport {
+            created: Vec::new(),
+            updated: Vec::new(),
+            skipped: 0,
+            conflicts: 0,
+            groups_created: Vec::new(),
+            friend_ids: Vec::with_capacity(plan.records.len()),
+        };
+
+        for record in plan.records {
+            let friend_id = match record.action {
+                PlannedAction::Create => {
+                    let friend = writer.create_friend(conn, record.incoming).await?;
+                    let id = friend.id;
+                    report.created.push(friend);
+                    Some(id)
+                }
+                PlannedAction::Update {
+                    friend_id,
+                    version,
+                    changes,
+                    ..
+                } => {
+                    let friend = self
+                        .update(conn, writer, friend_id, version, record.incoming, changes)
+                        .await?;
+                    report.updated.push(friend);
+                    Some(friend_id)
+                }
+                PlannedAction::Skip { friend_id, .. } => {
+                    report.skipped += 1;
+                    Some(friend_id)
+                }
+                PlannedAction::Conflict { .. } => {
+                    report.conflicts += 1;
+                    None
+                }
+            };
+            report.friend_ids.push(friend_id);
+        }
+
+        report.groups_created = writer.created_groups().to_vec();
+        Ok(report)
+    }
+
+    /// Apply one record's changes to an existing friend.
+    async fn update(
+        &self,
+        conn: &mut PgConnection,
+        writer: &mut ImportWriter,
+        friend_id: Uuid,
+        version: i64,
+        incoming: ImportedFriend,
+        changes: Vec<Change>,
+    ) -> Result<Friend, RepositoryError> {
+        let mut input = UpdateFriendInput {
+            first_name: None,
+            last_name: None,
+            date_of_birth: None,
+            likes: None,
+            dislikes: None,
+            notes: None,
+        };
+        let mut attributes = Vec::new();
+        let mut groups = Vec::new();
+
+        for change in changes {
+            match change {
+                // Take typed values from the record rather than the strings
+                // shown in the preview
+                Change::Field { field, .. } => match field.as_str() {
+                    "first_name" => input.first_name = Some(incoming.first_name.clone()),
+                    "last_name" => input.last_name = incoming.last_name.clone().map(Some),
+                    "date_of_birth" => input.date_of_birth = incoming.date_of_birth.map(Some),
+                    "likes" => input.likes = incoming.likes.clone().map(Some),
+                    "dislikes" => input.dislikes = incoming.dislikes.clone().map(Some),
+                    "notes" => input.notes = incoming.notes.clone().map(Some),
+                    _ => {}
+                },
+                Change::Attribute {
+                    key,
+                    incoming,
+                    value_type,
+                    ..
+                } => attributes.push(CreateFriendAttributeInput {
+                    friend_id,
+                    key,
+                    value: incoming,
+                    value_type: Some(value_type),
+                }),
+                Change::Group { name } => groups.push(name),
+            }
+        }
+
+        // Fails with `Conflict` before anything else is written if the
+        // friend was edited after the plan was made
+        let friend = self
+            .friends
+            .update_in(conn, friend_id, Some(version), input)
+            .await?;
+        for attribute in attributes {
+            self.attributes.upsert_in(conn, attribute).await?;
+        }
+        writer.join_groups(conn, friend_id, &groups).await?;
+        Ok(friend)
+    }
+
+    /// Every friend of the user with their attributes and group names.
+    async fn load_existing(&self, user_id: Uuid) -> Result<Vec<Existing>, RepositoryError> {
+        let friends = self.friends.list_by_user(user_id).await?;
+
+        let mut attributes: HashMap<Uuid, Vec<FriendAttribute>> = HashMap::new();
+        for attribute in self.attributes.list_by_user(user_id).await? {
+            attributes
+                .entry(attribute.friend_id)
+                .or_default()
+                .push(attribute);
+        }
+
+        let ids: Vec<Uuid> = friends.iter().map(|friend| friend.id).collect();
+        let mut groups = self.friends.list_groups_by_friends(&ids).await?;
+
+        let existing = friends
+            .into_iter()
+            .map(|friend| Existing {
+                attributes: attributes.remove(&friend.id).unwrap_or_default(),
+                groups: groups
+                    .remove(&friend.id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|g| g.name)
+                    .collect(),
+                friend,
+            })
+            .collect();
+        Ok(existing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+    use time::{Date, Month, OffsetDateTime};
+
+    use super::*;
+    use crate::repositories::{CreateFriendInput, CreateUserInput, UserRepository};
+
+    fn date(year: i32, month: Month, day: u8) -> Date {
+        Date::from_calendar_date(year, month, day).unwrap()
+    }
+
+    fn friend(id: u128, first_name: &str, last_name: &str) -> Friend {
+        Friend {
+            id: Uuid::from_u128(id),
+            user_id: Uuid::nil(),
+            first_name: first_name.to_string(),
+            last_name: Some(last_name.to_string()),
+            date_of_birth: None,
+            likes: None,
+            dislikes: None,
+            notes: None,
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: None,
+            version: 3,
+        }
+    }
+
+    fn attribute(friend: &Friend, key: &str, value: &str, value_type: &str) -> FriendAttribute {
+        FriendAttribute {
+            id: Uuid::nil(),
+            friend_id: friend.id,
+            key: key.to_string(),
+            value: value.to_string(),
+            value_type: value_type.to_string(),
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: None,
+            version: 1,
+        }
+    }
+
+    fn existing(friend: Friend) -> Existing {
+        Existing {
+            friend,
+            attributes: Vec::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    fn incoming(first_name: &str, last_name: &str) -> ImportedFriend {
+        ImportedFriend {
+            last_name: Some(last_name.to_string()),
+            ..ImportedFriend::named(first_name)
+        }
+    }
+
+    #[test]
+    fn unmatched_records_are_created() {
+        let friends = [existing(friend(1, "Ada", "Lovelace"))];
+        for strategy in [
+            MergeStrategy::KeepExisting,
+            MergeStrategy::Overwrite,
+            MergeStrategy::FillBlanks,
+        ] {
+            assert_eq!(
+                plan_record(&incoming("Grace", "Hopper"), &friends, strategy),
+                PlannedAction::Create
+            );
+        }
+    }
+
+    #[test]
+    fn keep_existing_skips_matched_friends() {
+        let mut ada = friend(1, "Ada", "Lovelace");
+        ada.date_of_birth = Some(date(1815, Month::December, 10));
+        let mut record = incoming("ada", "LOVELACE");
+        record.date_of_birth = ada.date_of_birth;
+        record.notes = Some("Wrote the first program".to_string());
+
+        assert_eq!(
+            plan_record(&record, &[existing(ada)], MergeStrategy::KeepExisting),
+            PlannedAction::Skip {
+                friend_id: Uuid::from_u128(1),
+                matched_by: vec!["name".to_string(), "birthday".to_string()],
+                reason: "keeping existing friend".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn overwrite_and_fill_blanks_pick_different_changes() {
+        let mut ada = friend(1, "Ada", "Lovelace");
+        ada.notes = Some("Mathematician".to_string());
+        let mut record = incoming("Ada", "Lovelace");
+        record.notes = Some("Wrote the first program".to_string());
+        record.likes = Some("Poetical science".to_string());
+        let notes = Change::Field {
+            field: "notes".to_string(),
+            existing: Some("Mathematician".to_string()),
+            incoming: "Wrote the first program".to_string(),
+        };
+        let likes = Change::Field {
+            field: "likes".to_string(),
+            existing: None,
+            incoming: "Poetical science".to_string(),
+        };
+        let update = |changes| PlannedAction::Update {
+            friend_id: Uuid::from_u128(1),
+            version: 3,
+            matched_by: vec!["name".to_string()],
+            changes,
+        };
+
+        let friends = [existing(ada)];
+        assert_eq!(
+            plan_record(&record, &friends, MergeStrategy::Overwrite),
+            update(vec![likes.clone(), notes])
+        );
+        assert_eq!(
+            plan_record(&record, &friends, MergeStrategy::FillBlanks),
+            update(vec![likes])
+        );
+    }
+
+    #[test]
+    fn matched_friends_with_nothing_new_are_skipped() {
+        let mut ada = friend(1, "Ada", "Lovelace");
+        ada.notes = Some("Mathematician".to_string());
+        let mut record = incoming("Ada", "Lovelace");
+        record.notes = Some("Wrote the first program".to_string());
+
+        // The only difference would overwrite a value, which FillBlanks won't
+        assert_eq!(
+            plan_record(&record, &[existing(ada)], MergeStrategy::FillBlanks),
+            PlannedAction::Skip {
+                friend_id: Uuid::from_u128(1),
+                matched_by: vec!["name".to_string()],
+                reason: "nothing to change".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn ambiguous_matches_are_conflicts() {
+        let friends = [
+            existing(friend(1, "Ada", "Lovelace")),
+            existing(friend(2, "Ada", "Lovelace")),
+        ];
+        assert_eq!(
+            plan_record(
+                &incoming("Ada", "Lovelace"),
+                &friends,
+                MergeStrategy::Overwrite
+            ),
+            PlannedAction::Conflict {
+                candidates: vec![Uuid::from_u128(1), Uuid::from_u128(2)],
+                reason: "matches 2 existing friends by name".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn email_matches_win_but_must_agree_on_birthdays() {
+        let mut augusta = friend(1, "Augusta", "King");
+        augusta.date_of_birth = Some(date(1815, Month::December, 10));
+        let mut augusta = existing(augusta);
+        augusta.attributes = vec![attribute(
+            &augusta.friend,
+            "Email#2",
+            "ada@example.com",
+            "text",
+        )];
+        let friends = [augusta, existing(friend(2, "Ada", "Lovelace"))];
+
+        // The key is matched case-insensitively, and so is the address
+        let mut record = incoming("Ada", "Lovelace");
+        record.attributes = vec![ImportedAttribute::new(
+            "EMAIL".to_string(),
+            "Ada@Example.com",
+            "text",
+        )];
+        assert!(matches!(
+            plan_record(&record, &friends, MergeStrategy::KeepExisting),
+            PlannedAction::Skip { friend_id, matched_by, .. }
+                if friend_id == Uuid::from_u128(1) && matched_by == ["email"]
+        ));
+
+        record.date_of_birth = Some(date(1816, Month::December, 10));
+        assert_eq!(
+            plan_record(&record, &friends, MergeStrategy::Overwrite),
+            PlannedAction::Conflict {
+                candidates: vec![Uuid::from_u128(1)],
+                reason: "email matches but the birthday differs".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn diff_ignores_missing_and_existing_values() {
+        let mut ada = existing(friend(1, "Ada", "Lovelace"));
+        ada.friend.likes = Some("Mathematics".to_string());
+        ada.attributes = vec![
+            attribute(&ada.friend, "email", "ada@example.com", "email"),
+            attribute(&ada.friend, "email#2", "lovelace@example.com", "email"),
+            attribute(&ada.friend, "phone", "555-0100", "phone"),
+        ];
+        ada.groups = vec!["Analysts".to_string()];
+
+        let mut record = incoming("Ada", "Lovelace");
+        record.likes = Some("  ".to_string());
+        record.groups = vec!["analysts".to_string(), "Poets".to_string()];
+        record.attributes = vec![
+            // Already there as email#2
+            ImportedAttribute::new("email".to_string(), "LOVELACE@example.com", "email"),
+            ImportedAttribute::new("phone".to_string(), "555-0199", "phone"),
+        ];
+
+        assert_eq!(
+            diff(&record, &ada),
+            vec![
+                Change::Attribute {
+                    key: "phone".to_string(),
+                    existing: Some("555-0100".to_string()),
+                    incoming: "555-0199".to_string(),
+                    value_type: "phone".to_string(),
+                },
+                Change::Group {
+                    name: "Poets".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn updates_planned_against_an_old_version_conflict() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is set");
+        let ctx = RepositoryContext::new(PgPool::connect(&url).await.unwrap());
+        let planner = ImportPlanner::new(ctx.clone());
+        // Never committed
+        let mut tx = ctx.transaction().await.unwrap();
+
+        let user = UserRepository::new(ctx.clone())
+            .create_in(
+                &mut tx,
+                CreateUserInput {
+                    first_name: "Im".to_string(),
+                    last_name: "Port".to_string(),
+                    email: format!("{}@example.com", Uuid::now_v7()),
+                    password_hash: String::new(),
+                },
+            )
+            .await
+            .unwrap();
+        let ada = planner
+            .friends
+            .create_in(
+                &mut tx,
+                CreateFriendInput {
+                    user_id: user.id,
+                    first_name: "Ada".to_string(),
+                    last_name: None,
+                    date_of_birth: None,
+                    likes: None,
+                    dislikes: None,
+                    notes: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let mut record = ImportedFriend::named("Ada");
+        record.notes = Some("Wrote the first program".to_string());
+        let plan = ImportPlan {
+            strategy: MergeStrategy::Overwrite,
+            records: vec![PlannedRecord {
+                action: plan_record(&record, &[existing(ada.clone())], MergeStrategy::Overwrite),
+                incoming: record,
+            }],
+            rejected: Vec::new(),
+        };
+
+        // Someone edits Ada between the preview and the apply
+        planner
+            .friends
+            .update_in(
+                &mut tx,
+                ada.id,
+                Some(ada.version),
+                UpdateFriendInput {
+                    first_name: None,
+                    last_name: None,
+                    date_of_birth: None,
+                    likes: Some(Some("Poetical science".to_string())),
+                    dislikes: None,
+                    notes: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let mut writer = ImportWriter::load(ctx.clone(), user.id).await.unwrap();
+        let result = planner.apply_in(&mut tx, &mut writer, plan).await;
+        assert!(matches!(
+            result,
+            Err(RepositoryError::Conflict { expected, current })
+                if expected == ada.version && current == ada.version + 1
+        ));
+    }
+}
@@ -0,0 +1,71 @@
+//! # Interchange Module
+//!
+//! Import and export of knowledgebase data in formats other tools
+//! understand (genealogy software, address books, spreadsheets, ...).
+//!
+//! ## Pattern
+//!
+//! Each format lives in its own submodule and is split into:
+//! - Pure parsing/serialization that works on strings and models
+//! - An importer and/or exporter that takes `RepositoryContext` in its
+//!   constructor and reads or writes through the repositories
+//!
+//! All formats share `InterchangeError`, and number repeated fields the
+//! same way via `AttributeKeys`.
+//!
+//! ## Previewing Imports
+//!
+//! Importers that read people into `ImportedFriend` (vCard, CSV, GEDCOM)
+//! also offer a `preview` that returns an `ImportPlan` without writing
+//! anything. The plan can be shown to the user and then applied with
+//! `ImportPlanner`, which merges into matching friends instead of creating
+//! duplicates. GEDCOM applies its plan with `ImportPlanner::apply_in` so
+//! that family relationships land in the same transaction, between the
+//! friends each record was created as or matched to.
+//!
+//! ## Backups
+//!
+//! `backup` is the one lossless format: it keeps IDs, timestamps and
+//! history, and is driven from the command line by `fkb export` and
+//! `fkb import` (see `src/bin/fkb.rs`).
+//!
+//! ## Note Vaults
+//!
+//! `markdown` writes one note per friend and group for Markdown note apps,
+//! linking relationships and group members with `[[wiki-links]]`.
+//! `markdown::sync` keeps such a vault and the database in step, importing
+//! note edits and reporting notes that changed on both sides.
+//!
+//! ## Static Sites
+//!
+//! `html` renders a browsable offline site: friend and group pages, an
+//! upcoming-birthdays page and a client-side search index.
+//!
+//! ## Graphs
+//!
+//! `graph` exports friends and their relationships as DOT, GraphML or
+//! node-link JSON for Graphviz, Gephi and similar tools.
+//!
+//! ## Calendars
+//!
+//! `ics` renders birthdays and date-typed attributes as yearly recurring
+//! events. It is served as a subscribable feed at a secret URL by the
+//! HTTP server (see `crate::api`).
+
+pub mod attributes;
+pub mod backup;
+pub mod csv;
+pub mod error;
+pub mod gedcom;
+pub mod graph;
+pub mod html;
+pub mod ics;
+pub mod imported;
+pub mod markdown;
+pub mod merge;
+pub mod vcard;
+
+pub use attributes::{AttributeKeys, ImportedAttribute};
+pub use error::InterchangeError;
+pub use imported::{ImportWriter, ImportedFriend, RejectedRow};
+pub use merge::{ImportPlan, ImportPlanner, MergeStrategy};
@@ -0,0 +1,651 @@
+//! # vCard Import/Export
+//!
+//! Moves contacts between the knowledgebase and phones or mail clients.
+//! Reads vCard 2.1, 3.0 and 4.0; writes vCard 4.0.
+//!
+//! ## Mapping
+//!
+//! | vCard | Knowledgebase |
+//! |-------|---------------|
+//! | `N` (or `FN` if `N` is missing) | `first_name` / `last_name` |
+//! | `BDAY` | `date_of_birth` |
+//! | `NOTE` | `notes` |
+//! | `CATEGORIES` | groups (created by name if missing) |
+//! | `TEL`, `EMAIL`, `ADR`, `URL` | `phone`, `email`, `address`, `url` attributes |
+//! | `PHOTO` | `photo` attribute holding a `data:` or `http(s):` URI |
+//! | `ANNIVERSARY` | `anniversary` attribute |
+//! | `X-FKB-LIKES` / `X-FKB-DISLIKES` | `likes` / `dislikes` |
+//! | `X-FKB-ATTRIBUTE;X-KEY=k` | attribute `k` |
+//! | anything else | `vcard.NAME` attribute with the raw value |
+//!
+//! The first `TYPE` of a contact property becomes part of the key, so a
+//! work phone is stored as `phone.work`. Repeats are numbered by
+//! `AttributeKeys` (`email`, `email#2`). Attributes that don't map to a
+//! vCard property are exported as `X-FKB-ATTRIBUTE` so a round trip
+//! through this module is lossless.
+
+pub mod property;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Serialize;
+use time::{Date, Month};
+use uuid::Uuid;
+
+use crate::models::{Friend, FriendAttribute, Group};
+use crate::repositories::{
+    FriendAttributeRepository, FriendRepository, Repository, RepositoryContext, RepositoryError,
+};
+
+use super::attributes::{AttributeKeys, ImportedAttribute, strip_repeat_suffix};
+use super::error::InterchangeError;
+use super::imported::{ImportWriter, ImportedFriend, split_full_name};
+use super::merge::{ImportPlan, ImportPlanner, MergeStrategy};
+use property::{Property, escape, split_unescaped, unescape, write_line};
+
+/// Attribute key prefix for properties kept verbatim.
+pub const ATTRIBUTE_PREFIX: &str = "vcard.";
+
+/// Properties that describe the card itself rather than the contact.
+const STRUCTURAL: [&str; 5] = ["BEGIN", "END", "VERSION", "PRODID", "REV"];
+
+/// Contact properties mapped to plain attribute keys, with their value type.
+const CONTACT_PROPERTIES: [(&str, &str, &str); 4] = [
+    ("TEL", "phone", "phone"),
+    ("EMAIL", "email", "email"),
+    ("ADR", "address", "text"),
+    ("URL", "url", "url"),
+];
+
+/// `TYPE` values that say nothing about what kind of contact it is.
+const IGNORED_TYPES: [&str; 4] = ["pref", "internet", "voice", "x400"];
+
+/// Parse every `BEGIN:VCARD` ... `END:VCARD` block in a file.
+///
+/// # Errors
+///
+/// Returns `InterchangeError::Parse` for malformed lines or unbalanced
+/// `BEGIN`/`END`, and `InterchangeError::Unsupported` for unknown versions.
+pub fn parse(input: &str) -> Result<Vec<ImportedFriend>, InterchangeError> {
+    let mut contacts = Vec::new();
+    let mut card: Option<Vec<Property>> = None;
+
+    for property in property::parse(input)? {
+        let is_vcard = property.value.trim().eq_ignore_ascii_case("VCARD");
+        match property.name.as_str() {
+            "BEGIN" if is_vcard => {
+                if card.is_some() {
+                    return Err(InterchangeError::parse(property.line, "nested BEGIN:VCARD"));
+                }
+                card = Some(Vec::new());
+            }
+            "END" if is_vcard => {
+                let properties = card.take().ok_or_else(|| {
+                    InterchangeError::parse(property.line, "END:VCARD without BEGIN:VCARD")
+                })?;
+                contacts.push(contact_from(&properties)?);
+            }
+            _ => match card.as_mut() {
+                Some(properties) => properties.push(property),
+                None => {
+                    return Err(InterchangeError::parse(
+                        property.line,
+                        "property outside BEGIN:VCARD/END:VCARD",
+                    ));
+                }
+            },
+        }
+    }
+
+    if let Some(properties) = card {
+        let line = properties.last().map(|p| p.line).unwrap_or(0);
+        return Err(InterchangeError::parse(line, "missing END:VCARD"));
+    }
+
+    Ok(contacts)
+}
+
+/// Build a contact from the properties of one card.
+fn contact_from(properties: &[Property]) -> Result<ImportedFriend, InterchangeError> {
+    if let Some(version) = properties.iter().find(|p| p.name == "VERSION")
+        && !["2.1", "3.0", "4.0"].contains(&version.value.trim())
+    {
+        return Err(InterchangeError::Unsupported(format!(
+            "vCard version {}",
+            version.value.trim()
+        )));
+    }
+
+    let mut contact = ImportedFriend::named("");
+    let mut keys = AttributeKeys::default();
+    let mut formatted_name = None;
+    let mut notes = Vec::new();
+
+    for property in properties {
+        let name = property.name.as_str();
+        if STRUCTURAL.contains(&name) {
+            continue;
+        }
+
+        if let Some((_, base, value_type)) = CONTACT_PROPERTIES.iter().find(|(n, _, _)| *n == name)
+        {
+            let value = match name {
+                "ADR" => readable_address(&property.value),
+                "TEL" => {
+                    let value = unescape(&property.value);
+                    value.strip_prefix("tel:").unwrap_or(&value).to_string()
+                }
+                _ => unescape(&property.value),
+            };
+            let key = keys.next(&typed_key(base, property));
+            contact
+                .attributes
+                .push(ImportedAttribute::new(key, value, value_type));
+            continue;
+        }
+
+        match name {
+            "N" => {
+                let parts = split_unescaped(&property.value, ';');
+                let family = parts.first().map(|s| s.trim()).unwrap_or("");
+                let given = parts.get(1).map(|s| s.trim()).unwrap_or("");
+                if !given.is_empty() {
+                    contact.first_name = given.to_string();
+                }
+                if !family.is_empty() {
+                    contact.last_name = Some(family.to_string());
+                }
+            }
+            "FN" => formatted_name = Some(unescape(&property.value)),
+            "BDAY" => match parse_date(&property.value) {
+                Some(date) => contact.date_of_birth = Some(date),
+                // Dates without a year ("--0312") have no column to go in
+                None => contact.attributes.push(ImportedAttribute::new(
+                    keys.next("vcard.BDAY"),
+                    property.value.clone(),
+                    "text",
+                )),
+            },
+            "NOTE" => notes.push(unescape(&property.value)),
+            "CATEGORIES" => contact.groups.extend(
+                split_unescaped(&property.value, ',')
+                    .into_iter()
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty()),
+            ),
+            "PHOTO" => contact.attributes.push(ImportedAttribute::new(
+                keys.next("photo"),
+                photo_uri(property),
+                "url",
+            )),
+            "ANNIVERSARY" => {
+                let (value, value_type) = match parse_date(&property.value) {
+                    Some(date) => (date.to_string(), "date"),
+                    None => (unescape(&property.value), "text"),
+                };
+                contact.attributes.push(ImportedAttribute::new(
+                    keys.next("anniversary"),
+                    value,
+                    value_type,
+                ));
+            }
+            "X-FKB-LIKES" => contact.likes = Some(unescape(&property.value)),
+            "X-FKB-DISLIKES" => contact.dislikes = Some(unescape(&property.value)),
+            "X-FKB-ATTRIBUTE" => {
+                let key = property
+                    .param("X-KEY")
+                    .and_then(|k| k.first())
+                    .ok_or_else(|| InterchangeError::parse(property.line, "missing X-KEY"))?;
+                let value_type = property
+                    .param("X-VALUE-TYPE")
+                    .and_then(|t| t.first())
+                    .map(String::as_str)
+                    .unwrap_or("text");
+                contact.attributes.push(ImportedAttribute::new(
+                    keys.next(key),
+                    unescape(&property.value),
+                    value_type,
+                ));
+            }
+            // Everything else is kept raw so it can be written back as-is
+            _ => contact.attributes.push(ImportedAttribute::new(
+                keys.next(&format!("{ATTRIBUTE_PREFIX}{name}")),
+                property.value.clone(),
+                "text",
+            )),
+        }
+    }
+
+    if !notes.is_empty() {
+        contact.notes = Some(notes.join("\n\n"));
+    }
+
+    // Fall back to FN when N is missing or has no given name
+    if contact.first_name.is_empty() {
+        match formatted_name.as_deref().and_then(split_full_name) {
+            Some((first, last)) => {
+                contact.first_name = first;
+                if contact.last_name.is_none() {
+                    contact.last_name = last;
+                }
+            }
+            None => {
+                contact.first_name = contact
+                    .last_name
+                    .take()
+                    .unwrap_or_else(|| "Unknown".to_string());
+            }
+        }
+    }
+
+    Ok(contact)
+}
+
+/// "phone" + `TYPE=CELL,VOICE` → "phone.cell".
+fn typed_key(base: &str, property: &Property) -> String {
+    match property
+        .types()
+        .into_iter()
+        .find(|t| !IGNORED_TYPES.contains(&t.as_str()))
+    {
+        Some(kind) => format!("{base}.{kind}"),
+        None => base.to_string(),
+    }
+}
+
+/// Join the non-empty `ADR` components into one readable line.
+///
+/// Components are: PO box; extended; street; locality; region; postal
+/// code; country.
+fn readable_address(value: &str) -> String {
+    split_unescaped(value, ';')
+        .into_iter()
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Turn a `PHOTO` into a URI.
+///
+/// vCard 4.0 already uses a URI. vCard 2.1/3.0 inline photos are base64
+/// or quoted-printable with an `ENCODING` parameter and are converted to a
+/// `data:` URI.
+fn photo_uri(property: &Property) -> String {
+    let data = if let Some(bytes) = &property.decoded {
+        STANDARD.encode(bytes)
+    } else if property.has_encoding("b") || property.has_encoding("BASE64") {
+        property
+            .value
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect()
+    } else {
+        return property.value.clone();
+    };
+
+    let media_type = property
+        .types()
+        .into_iter()
+        .next()
+        .map(|t| match t.as_str() {
+            "jpg" => "jpeg".to_string(),
+            _ => t,
+        })
+        .unwrap_or_else(|| "jpeg".to_string());
+
+    format!("data:image/{media_type};base64,{data}")
+}
+
+/// Parse a full date: "19500312", "1950-03-12", optionally with a time.
+///
+/// # Returns
+///
+/// `None` for reduced-accuracy dates like "--0312" or "1950".
+fn parse_date(value: &str) -> Option<Date> {
+    let date = value.trim().split('T').next()?;
+    let digits: String = date.chars().filter(|c| *c != '-').collect();
+    if digits.len() != 8 || !digits.chars().all(|c| c.is_ascii_digit()) || date.starts_with("--") {
+        return None;
+    }
+
+    let year: i32 = digits[0..4].parse().ok()?;
+    let month: u8 = digits[4..6].parse().ok()?;
+    let day: u8 = digits[6..8].parse().ok()?;
+
+    Date::from_calendar_date(year, Month::try_from(month).ok()?, day).ok()
+}
+
+/// Format a date the way vCard 4.0 prefers ("19500312").
+fn format_date(date: Date) -> String {
+    format!(
+        "{:04}{:02}{:02}",
+        date.year(),
+        u8::from(date.month()),
+        date.day()
+    )
+}
+
+/// Render one friend as a vCard 4.0 card.
+///
+/// # Arguments
+///
+/// * `friend` - The friend to render
+/// * `groups` - The friend's groups, written as `CATEGORIES`
+/// * `attributes` - The friend's attributes
+pub fn write_card(friend: &Friend, groups: &[Group], attributes: &[FriendAttribute]) -> String {
+    let mut out = String::new();
+    write_line(&mut out, "BEGIN", &[], "VCARD");
+    write_line(&mut out, "VERSION", &[], "4.0");
+
+    let uid = attributes
+        .iter()
+        .find(|a| a.key == format!("{ATTRIBUTE_PREFIX}UID"))
+        .map(|a| a.value.clone())
+        .unwrap_or_else(|| format!("urn:uuid:{}", friend.id));
+    write_line(&mut out, "UID", &[], &uid);
+
+    let full_name = match &friend.last_name {
+        Some(last) => format!("{} {last}", friend.first_name),
+        None => friend.first_name.clone(),
+    };
+    write_line(&mut out, "FN", &[], &escape(&full_name));
+    let structured = format!(
+        "{};{};;;",
+        escape(friend.last_name.as_deref().unwrap_or("")),
+        escape(&friend.first_name)
+    );
+    write_line(&mut out, "N", &[], &structured);
+
+    if let Some(date) = friend.date_of_birth {
+        write_line(&mut out, "BDAY", &[], &format_date(date));
+    }
+    if let Some(notes) = &friend.notes {
+        write_line(&mut out, "NOTE", &[], &escape(notes));
+    }
+    if let Some(likes) = &friend.likes {
+        write_line(&mut out, "X-FKB-LIKES", &[], &escape(likes));
+    }
+    if let Some(dislikes) = &friend.dislikes {
+        write_line(&mut out, "X-FKB-DISLIKES", &[], &escape(dislikes));
+    }
+    if !groups.is_empty() {
+        let names: Vec<String> = groups.iter().map(|g| escape(&g.name)).collect();
+        write_line(&mut out, "CATEGORIES", &[], &names.join(","));
+    }
+
+    for attribute in attributes {
+        write_attribute(&mut out, attribute);
+    }
+
+    write_line(&mut out, "END", &[], "VCARD");
+    out
+}
+
+/// Write one attribute as the vCard property it came from.
+fn write_attribute(out: &mut String, attribute: &FriendAttribute) {
+    let key = strip_repeat_suffix(&attribute.key);
+
+    if let Some(name) = key.strip_prefix(ATTRIBUTE_PREFIX) {
+        if name != "UID" {
+            write_line(out, name, &[], &attribute.value);
+        }
+        return;
+    }
+
+    let (base, kind) = match key.split_once('.') {
+        Some((base, kind)) => (base, Some(kind)),
+        None => (key, None),
+    };
+    let params: Vec<(&str, &str)> = kind.map(|k| ("TYPE", k)).into_iter().collect();
+
+    if let Some((name, _, _)) = CONTACT_PROPERTIES.iter().find(|(_, b, _)| *b == base) {
+        let value = match *name {
+            // A free-form address goes in the street component
+            "ADR" => format!(";;{};;;;", escape(&attribute.value)),
+            _ => escape(&attribute.value),
+        };
+        write_line(out, name, &params, &value);
+        return;
+    }
+
+    match (base, kind) {
+        ("photo", None) => write_line(out, "PHOTO", &[], &attribute.value),
+        ("anniversary", None) => {
+            let value = match parse_date(&attribute.value) {
+                Some(date) => format_date(date),
+                None => escape(&attribute.value),
+            };
+            write_line(out, "ANNIVERSARY", &[], &value);
+        }
+        _ => write_line(
+            out,
+            "X-FKB-ATTRIBUTE",
+            &[
+                ("X-KEY", &attribute.key),
+                ("X-VALUE-TYPE", &attribute.value_type),
+            ],
+            &escape(&attribute.value),
+        ),
+    }
+}
+
+/// Result of a vCard import.
+#[derive(Debug, Clone, Serialize)]
+pub struct VcardImportReport {
+    /// Friends created, one per card
+    pub friends: Vec<Friend>,
+    /// Groups created for `CATEGORIES` the user didn't have yet
+    pub groups_created: Vec<Group>,
+}
+
+/// Imports vCard contacts for a user in one transaction.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let importer = VcardImporter::new(ctx.clone());
+/// let report = importer.import(user_id, &std::fs::read_to_string("contacts.vcf")?).await?;
+/// println!("Imported {} contacts", report.friends.len());
+/// ```
+pub struct VcardImporter {
+    ctx: RepositoryContext,
+}
+
+impl VcardImporter {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self { ctx }
+    }
+
+    /// Dry run: match every card against the user's friends and report
+    /// what `ImportPlanner::apply` would do with `strategy`.
+    pub async fn preview(
+        &self,
+        user_id: Uuid,
+        input: &str,
+        strategy: MergeStrategy,
+    ) -> Result<ImportPlan, InterchangeError> {
+        ImportPlanner::new(self.ctx.clone())
+            .plan(user_id, parse(input)?, strategy)
+            .await
+    }
+
+    /// Import every card in `input` as a new friend.
+    ///
+    /// Categories are matched to the user's groups by name
+    /// (case-insensitive); missing groups are created.
+    pub async fn import(
+        &self,
+        user_id: Uuid,
+        input: &str,
+    ) -> Result<VcardImportReport, InterchangeError> {
+        let contacts = parse(input)?;
+        let mut writer = ImportWriter::load(self.ctx.clone(), user_id).await?;
+        let mut friends = Vec::new();
+
+        let mut tx = self.ctx.transaction().await?;
+        for contact in contacts {
+            friends.push(writer.create_friend(&mut tx, contact).await?);
+        }
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+
+        Ok(VcardImportReport {
+            friends,
+            groups_created: writer.into_created_groups(),
+        })
+    }
+}
+
+/// Exports friends as vCard 4.0.
+pub struct VcardExporter {
+    friends: FriendRepository,
+    attributes: FriendAttributeRepository,
+}
+
+impl VcardExporter {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            friends: FriendRepository::new(ctx.clone()),
+            attributes: FriendAttributeRepository::new(ctx),
+        }
+    }
+
+    /// Render all of a user's friends as one multi-contact `.vcf` file.
+    pub async fn export(&self, user_id: Uuid) -> Result<String, InterchangeError> {
+        let mut out = String::new();
+        for friend in self.friends.list_by_user(user_id).await? {
+            out.push_str(&self.render(&friend).await?);
+        }
+        Ok(out)
+    }
+
+    /// Render a single friend's card.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the friend doesn't exist.
+    pub async fn export_friend(&self, friend_id: Uuid) -> Result<Option<String>, InterchangeError> {
+        match self.friends.find_by_id(friend_id).await? {
+            Some(friend) => Ok(Some(self.render(&friend).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn render(&self, friend: &Friend) -> Result<String, InterchangeError> {
+        let groups = self.friends.list_groups(friend.id).await?;
+        let attributes = self.attributes.list_by_friend(friend.id).await?;
+        Ok(write_card(friend, &groups, &attributes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::*;
+
+    /// The photo attribute of the only card in `input`.
+    fn photo(input: &str) -> String {
+        let contacts = parse(input).expect("card parses");
+        assert_eq!(contacts.len(), 1);
+        contacts[0]
+            .attributes
+            .iter()
+            .find(|a| a.key == "photo")
+            .map(|a| a.value.clone())
+            .expect("card has a photo")
+    }
+
+    /// Write a card with only this photo, then read its photo back.
+    fn round_trip(uri: &str) -> String {
+        let now = OffsetDateTime::now_utc();
+        let friend = Friend {
+            id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            first_name: "Ada".to_string(),
+            last_name: None,
+            date_of_birth: None,
+            likes: None,
+            dislikes: None,
+            notes: None,
+            created_at: now,
+            updated_at: None,
+            version: 1,
+        };
+        let attribute = FriendAttribute {
+            id: Uuid::nil(),
+            friend_id: friend.id,
+            key: "photo".to_string(),
+            value: uri.to_string(),
+            value_type: "url".to_string(),
+            created_at: now,
+            updated_at: None,
+            version: 1,
+        };
+        photo(&write_card(&friend, &[], &[attribute]))
+    }
+
+    #[test]
+    fn quoted_printable_photos_keep_their_bytes() {
+        // A JPEG header: none of it is valid UTF-8
+        let card = "BEGIN:VCARD\r\nVERSION:2.1\r\nN:;Ada\r\n\
+                    PHOTO;JPEG;ENCODING=QUOTED-PRINTABLE:=FF=D8=FF=E0=00=10JFIF\r\n\
+                    END:VCARD\r\n";
+        let expected = format!(
+            "data:image/jpeg;base64,{}",
+            STANDARD.encode(b"\xFF\xD8\xFF\xE0\x00\x10JFIF")
+        );
+
+        let uri = photo(card);
+        assert_eq!(uri, expected);
+        assert_eq!(round_trip(&uri), expected);
+    }
+
+    #[test]
+    fn quoted_printable_photos_with_soft_line_breaks() {
+        let card = "BEGIN:VCARD\r\nVERSION:2.1\r\nN:;Ada\r\n\
+                    PHOTO;TYPE=PNG;ENCODING=QUOTED-PRINTABLE:=89PNG=0D=\r\n\
+                    =0A=1A=0A\r\n\
+                    END:VCARD\r\n";
+
+        assert_eq!(
+            photo(card),
+            format!(
+                "data:image/png;base64,{}",
+                STANDARD.encode(b"\x89PNG\r\n\x1A\n")
+            )
+        );
+    }
+
+    #[test]
+    fn base64_photos_become_data_uris() {
+        let card = "BEGIN:VCARD\r\nVERSION:3.0\r\nN:;Ada\r\n\
+                    PHOTO;ENCODING=b;TYPE=JPG:/9j/4AAQ\r\n SkZJRg==\r\n\
+                    END:VCARD\r\n";
+
+        let uri = photo(card);
+        assert_eq!(uri, "data:image/jpeg;base64,/9j/4AAQSkZJRg==");
+        assert_eq!(round_trip(&uri), uri);
+    }
+
+    #[test]
+    fn uri_photos_are_kept_as_they_are() {
+        let card = "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Ada\r\n\
+                    PHOTO:https://example.com/ada.jpg\r\n\
+                    END:VCARD\r\n";
+
+        let uri = photo(card);
+        assert_eq!(uri, "https://example.com/ada.jpg");
+        assert_eq!(round_trip(&uri), uri);
+    }
+
+    #[test]
+    fn long_data_uris_survive_folding() {
+        let uri = format!(
+            "data:image/jpeg;base64,{}",
+            STANDARD.encode((0..=255).collect::<Vec<u8>>())
+        );
+
+        assert_eq!(round_trip(&uri), uri);
+    }
+}
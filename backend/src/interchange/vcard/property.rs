@@ -0,0 +1,307 @@
+//! # vCard Content Lines
+//!
+//! Low-level reading and writing of vCard properties:
+//!
+//! ```text
+//! [group.]NAME[;PARAM=value[,value]...]:value
+//! ```
+//!
+//! ## Handled Here
+//!
+//! - Line folding (continuation lines start with a space or tab)
+//! - Quoted-printable soft line breaks from vCard 2.1 (a trailing `=`)
+//! - Quoted-printable and charset decoding of values
+//! - vCard 2.1 bare parameters (`TEL;CELL:...` means `TYPE=CELL`)
+//! - Text escaping (`\n`, `\,`, `\;`, `\\`)
+
+use crate::interchange::error::InterchangeError;
+
+/// vCard lines must not exceed 75 octets, excluding the line break.
+const MAX_LINE_OCTETS: usize = 75;
+
+/// One parsed vCard property.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    /// Upper-case property name (e.g., "TEL", "X-SKYPE")
+    pub name: String,
+    /// Parameters with upper-case names and their values
+    pub params: Vec<(String, Vec<String>)>,
+    /// The value, still escaped (quoted-printable is already decoded)
+    pub value: String,
+    /// The bytes a quoted-printable value decoded to, for values that
+    /// aren't text (an inline `PHOTO`)
+    pub decoded: Option<Vec<u8>>,
+    /// 1-based line number where the property started
+    pub line: usize,
+}
+
+impl Property {
+    /// Values of the named parameter, if present.
+    pub fn param(&self, name: &str) -> Option<&[String]> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, values)| values.as_slice())
+    }
+
+    /// Lower-case `TYPE` values (e.g., ["cell", "pref"]).
+    pub fn types(&self) -> Vec<String> {
+        self.param("TYPE")
+            .unwrap_or_default()
+            .iter()
+            .map(|t| t.to_lowercase())
+            .collect()
+    }
+
+    /// `true` if the `ENCODING` parameter matches (case-insensitive).
+    pub fn has_encoding(&self, encoding: &str) -> bool {
+        self.param("ENCODING")
+            .is_some_and(|values| values.iter().any(|v| v.eq_ignore_ascii_case(encoding)))
+    }
+}
+
+/// Split a file into unfolded logical lines with their starting line number.
+fn unfold(input: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut soft_break = false;
+
+    for (index, raw) in input.lines().enumerate() {
+        let raw = raw.trim_end_matches('\r');
+
+        if soft_break {
+            // Quoted-printable soft break: the next line continues verbatim
+            let (_, current) = lines.last_mut().expect("soft break follows a line");
+            current.push_str(raw.trim_start());
+        } else if raw.starts_with([' ', '\t']) && !lines.is_empty() {
+            let (_, current) = lines.last_mut().expect("checked non-empty");
+            current.push_str(&raw[1..]);
+        } else if !raw.trim().is_empty() {
+            lines.push((index + 1, raw.to_string()));
+        }
+
+        soft_break = false;
+        if let Some((_, current)) = lines.last_mut() {
+            let head = current.split(':').next().unwrap_or("").to_uppercase();
+            if head.contains("QUOTED-PRINTABLE") && current.ends_with('=') {
+                current.pop();
+                soft_break = true;
+            }
+        }
+    }
+
+    lines
+}
+
+/// Parse every property in a file.
+///
+/// `BEGIN`/`END` lines are returned like any other property so callers
+/// can split a multi-contact file into cards.
+pub fn parse(input: &str) -> Result<Vec<Property>, InterchangeError> {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    unfold(input)
+        .into_iter()
+        .map(|(line, text)| parse_line(line, &text))
+        .collect()
+}
+
+/// Parse a single unfolded content line.
+fn parse_line(line: usize, text: &str) -> Result<Property, InterchangeError> {
+    // Find the first ':' that isn't inside a quoted parameter value
+    let mut in_quotes = false;
+    let colon = text
+        .char_indices()
+        .find(|(_, c)| {
+            if *c == '"' {
+                in_quotes = !in_quotes;
+            }
+            *c == ':' && !in_quotes
+        })
+        .map(|(i, _)| i)
+        .ok_or_else(|| InterchangeError::parse(line, "expected NAME:value"))?;
+
+    let (head, value) = (&text[..colon], &text[colon + 1..]);
+    let mut parts = split_params(head).into_iter();
+
+    let name = parts.next().unwrap_or_default();
+    // Drop an optional "group." prefix (e.g., "item1.TEL")
+    let name = name.rsplit('.').next().unwrap_or(&name).to_uppercase();
+    if name.is_empty() {
+        return Err(InterchangeError::parse(line, "missing property name"));
+    }
+
+    let params = parts
+        .map(|param| match param.split_once('=') {
+            Some((key, values)) => (
+                key.trim().to_uppercase(),
+                values
+                    .split(',')
+                    .map(|v| v.trim().trim_matches('"').to_string())
+                    .collect(),
+            ),
+            // vCard 2.1: bare parameters are types, except encodings/charsets
+            None => match param.to_uppercase().as_str() {
+                "QUOTED-PRINTABLE" | "BASE64" | "8BIT" | "7BIT" => {
+                    ("ENCODING".to_string(), vec![param.clone()])
+                }
+                _ => ("TYPE".to_string(), vec![param.clone()]),
+            },
+        })
+        .collect();
+
+    let mut property = Property {
+        name,
+        params,
+        value: value.to_string(),
+        decoded: None,
+        line,
+    };
+
+    if property.has_encoding("QUOTED-PRINTABLE") {
+        let bytes = decode_quoted_printable(&property.value);
+        let latin1 = property
+            .param("CHARSET")
+            .is_some_and(|c| c.iter().any(|c| c.eq_ignore_ascii_case("ISO-8859-1")));
+        property.value = if latin1 {
+            bytes.iter().map(|&b| b as char).collect()
+        } else {
+            String::from_utf8_lossy(&bytes).into_owned()
+        };
+        // Decoded text may contain real newlines; re-escape them so callers
+        // can treat every value the same way
+        property.value = property.value.replace("\r\n", "\n").replace('\n', "\\n");
+        property.decoded = Some(bytes);
+    }
+
+    Ok(property)
+}
+
+/// Split "NAME;A=1;B="x;y"" on semicolons outside quotes.
+fn split_params(head: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut in_quotes = false;
+    for c in head.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                parts.last_mut().expect("non-empty").push(c);
+            }
+            ';' if !in_quotes => parts.push(String::new()),
+            _ => parts.last_mut().expect("non-empty").push(c),
+        }
+    }
+    parts
+}
+
+/// Decode quoted-printable (`=C3=A9` → bytes). Invalid escapes are kept.
+fn decode_quoted_printable(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'=' {
+            let hex = value
+                .get(i + 1..i + 3)
+                .and_then(|h| u8::from_str_radix(h, 16).ok());
+            if let Some(byte) = hex {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Unescape a text value (`\n` → newline, `\,` → `,`, ...).
+pub fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Split a structured or list value on an unescaped separator, then
+/// unescape each component.
+pub fn split_unescaped(value: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            current.push(c);
+            if let Some(next) = chars.next() {
+                current.push(next);
+            }
+        } else if c == separator {
+            parts.push(unescape(&current));
+            current.clear();
+        } else {
+            current.push(c);
+        }
+    }
+    parts.push(unescape(&current));
+    parts
+}
+
+/// Escape a text value for output.
+pub fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+}
+
+/// Write one property line (folded) to `out`.
+///
+/// `value` must already be escaped. Parameter values containing `:`, `;`
+/// or `,` are quoted.
+pub fn write_line(out: &mut String, name: &str, params: &[(&str, &str)], value: &str) {
+    let mut line = name.to_string();
+    for (key, param_value) in params {
+        let needs_quotes = param_value.contains([':', ';', ',']);
+        if needs_quotes {
+            line.push_str(&format!(";{key}=\"{param_value}\""));
+        } else {
+            line.push_str(&format!(";{key}={param_value}"));
+        }
+    }
+    line.push(':');
+    line.push_str(value);
+
+    fold(out, &line);
+}
+
+/// Fold a line at 75 octets without splitting UTF-8 characters.
+fn fold(out: &mut String, line: &str) {
+    let mut limit = MAX_LINE_OCTETS;
+    let mut current = 0;
+    let mut first = true;
+
+    for (index, c) in line.char_indices() {
+        if index + c.len_utf8() - current > limit {
+            out.push_str(&line[current..index]);
+            out.push_str("\r\n ");
+            current = index;
+            // Continuation lines lose one octet to the leading space
+            if first {
+                limit = MAX_LINE_OCTETS - 1;
+                first = false;
+            }
+        }
+    }
+    out.push_str(&line[current..]);
+    out.push_str("\r\n");
+}
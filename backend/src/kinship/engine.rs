@@ -0,0 +1,520 @@
+//! # Kinship Inference Engine
+//!
+//! Rule-based inference over stored `FriendRelationship` rows.
+//!
+//! ## How It Works
+//!
+//! 1. Every stored relationship with a family label becomes one or two
+//!    *base facts* of the form "X is <kind> of Y" (plus their inverses).
+//! 2. Composition rules like `Parent(X, Y) ∧ Sibling(Y, Z) ⇒ Parent(X, Z)`
+//!    are applied repeatedly until no new facts appear (a fixpoint).
+//! 3. Every derived fact remembers the rule and premises that produced it,
+//!    so the full chain back to stored rows can be explained.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::models::FriendRelationship;
+use crate::repositories::{FriendRelationshipRepository, RepositoryContext, RepositoryError};
+use crate::validation::relationships::{check_labels, family_kinds};
+
+use super::kind::Kinship;
+
+/// Upper bound on rule application rounds.
+///
+/// Each round can only extend chains by one hop, and real family graphs
+/// reach a fixpoint in two or three rounds. This guard just keeps a
+/// pathological graph from looping for a long time.
+const MAX_ROUNDS: usize = 8;
+
+/// A composition rule: `first(X, Y) ∧ second(Y, Z) ⇒ result(X, Z)`.
+struct Rule {
+    name: &'static str,
+    first: Kinship,
+    second: Kinship,
+    result: Kinship,
+}
+
+/// The inference rules, applied in order every round.
+///
+/// Inverses (e.g., `Grandparent` ⇒ `Grandchild`) are not listed here;
+/// they are added automatically for every fact.
+const RULES: &[Rule] = &[
+    // A is parent of B, B is sibling of C ⇒ A is parent of C
+    Rule {
+        name: "parent_of_sibling",
+        first: Kinship::Parent,
+        second: Kinship::Sibling,
+        result: Kinship::Parent,
+    },
+    // A is child of P, P is parent of B ⇒ A is sibling of B
+    Rule {
+        name: "shared_parent",
+        first: Kinship::Child,
+        second: Kinship::Parent,
+        result: Kinship::Sibling,
+    },
+    // A is parent of B, B is parent of C ⇒ A is grandparent of C
+    Rule {
+        name: "grandparent",
+        first: Kinship::Parent,
+        second: Kinship::Parent,
+        result: Kinship::Grandparent,
+    },
+    // A is sibling of P, P is parent of C ⇒ A is aunt/uncle of C
+    Rule {
+        name: "aunt_uncle",
+        first: Kinship::Sibling,
+        second: Kinship::Parent,
+        result: Kinship::AuntUncle,
+    },
+    // A is child of P, P is aunt/uncle of C ⇒ A is cousin of C
+    Rule {
+        name: "cousin",
+        first: Kinship::Child,
+        second: Kinship::AuntUncle,
+        result: Kinship::Cousin,
+    },
+    // A is parent of B, B is spouse of C ⇒ A is parent-in-law of C
+    Rule {
+        name: "parent_in_law",
+        first: Kinship::Parent,
+        second: Kinship::Spouse,
+        result: Kinship::ParentInLaw,
+    },
+    // A is sibling of B, B is spouse of C ⇒ A is sibling-in-law of C
+    Rule {
+        name: "spouses_sibling",
+        first: Kinship::Sibling,
+        second: Kinship::Spouse,
+        result: Kinship::SiblingInLaw,
+    },
+    // A is spouse of B, B is sibling of C ⇒ A is sibling-in-law of C
+    Rule {
+        name: "siblings_spouse",
+        first: Kinship::Spouse,
+        second: Kinship::Sibling,
+        result: Kinship::SiblingInLaw,
+    },
+];
+
+/// A single directed statement: "`from_friend_id` is `kind` of `to_friend_id`".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct KinshipFact {
+    pub from_friend_id: Uuid,
+    pub kind: Kinship,
+    pub to_friend_id: Uuid,
+}
+
+impl KinshipFact {
+    fn new(from_friend_id: Uuid, kind: Kinship, to_friend_id: Uuid) -> Self {
+        Self {
+            from_friend_id,
+            kind,
+            to_friend_id,
+        }
+    }
+
+    fn inverse(self) -> Self {
+        Self::new(self.to_friend_id, self.kind.inverse(), self.from_friend_id)
+    }
+
+    /// Render the fact using friend names, falling back to the UUID.
+    fn describe(&self, names: &HashMap<Uuid, String>) -> String {
+        let name = |id: &Uuid| names.get(id).cloned().unwrap_or_else(|| id.to_string());
+        format!(
+            "{} is {} {}",
+            name(&self.from_friend_id),
+            self.kind.label(),
+            name(&self.to_friend_id)
+        )
+    }
+}
+
+/// Where a fact came from.
+#[derive(Debug, Clone)]
+enum FactSource {
+    /// Read directly (or as the inverse) from a stored relationship row
+    Stored { relationship_id: Uuid },
+    /// Produced by a rule from earlier facts
+    Derived {
+        rule: &'static str,
+        premises: Vec<KinshipFact>,
+    },
+}
+
+/// A relationship inferred by the engine (never stored in the database).
+///
+/// # Explanation
+///
+/// - `rule` and `premises` describe the final inference step
+/// - `chain` is the full list of stored facts the inference rests on
+/// - `relationship_ids` are the `friend_relationships` rows behind `chain`
+#[derive(Debug, Clone, Serialize)]
+pub struct InferredRelationship {
+    pub from_friend_id: Uuid,
+    pub to_friend_id: Uuid,
+    pub kind: Kinship,
+    /// Human-readable label, e.g. "grandparent of"
+    pub label: &'static str,
+    /// Name of the rule that produced this relationship
+    pub rule: &'static str,
+    /// The facts the rule was applied to
+    pub premises: Vec<KinshipFact>,
+    /// The stored facts at the bottom of the inference chain, in order
+    pub chain: Vec<KinshipFact>,
+    /// IDs of the stored relationships used anywhere in the chain
+    pub relationship_ids: Vec<Uuid>,
+}
+
+impl InferredRelationship {
+    /// Explain the inference in plain English.
+    ///
+    /// # Example
+    ///
+    /// ```text
+    /// Ann is parent of Bob, Bob is sibling of Cat ⇒ Ann is parent of Cat
+    /// ```
+    pub fn describe(&self, names: &HashMap<Uuid, String>) -> String {
+        let steps: Vec<String> = self.chain.iter().map(|fact| fact.describe(names)).collect();
+        let conclusion = KinshipFact::new(self.from_friend_id, self.kind, self.to_friend_id);
+
+        format!("{} ⇒ {}", steps.join(", "), conclusion.describe(names))
+    }
+}
+
+/// Stored and inferred relationships, kept separate.
+#[derive(Debug, Clone, Serialize)]
+pub struct KinshipReport {
+    /// Relationships exactly as stored in `friend_relationships`
+    pub stored: Vec<FriendRelationship>,
+    /// Relationships derived from the stored ones
+    pub derived: Vec<InferredRelationship>,
+}
+
+impl KinshipReport {
+    /// Narrow the report to relationships involving one friend.
+    pub fn for_friend(self, friend_id: Uuid) -> KinshipReport {
+        KinshipReport {
+            stored: self
+                .stored
+                .into_iter()
+                .filter(|r| r.friend_a_id == friend_id || r.friend_b_id == friend_id)
+                .collect(),
+            derived: self
+                .derived
+                .into_iter()
+                .filter(|r| r.from_friend_id == friend_id || r.to_friend_id == friend_id)
+                .collect(),
+        }
+    }
+}
+
+/// Working set of facts during inference.
+///
+/// `order` preserves insertion order so results are deterministic and
+/// facts derived in earlier rounds (shorter chains) come first.
+#[derive(Default)]
+struct FactSet {
+    sources: HashMap<KinshipFact, FactSource>,
+    order: Vec<KinshipFact>,
+}
+
+impl FactSet {
+    /// Insert a fact if it is new. Returns `true` if it was inserted.
+    fn insert(&mut self, fact: KinshipFact, source: FactSource) -> bool {
+        // Nobody is their own relative
+        if fact.from_friend_id == fact.to_friend_id || self.sources.contains_key(&fact) {
+            return false;
+        }
+        self.sources.insert(fact, source);
+        self.order.push(fact);
+        true
+    }
+
+    /// Expand a fact down to the stored facts it rests on.
+    fn chain(&self, fact: KinshipFact, out: &mut Vec<KinshipFact>, ids: &mut Vec<Uuid>) {
+        match self.sources.get(&fact) {
+            Some(FactSource::Stored { relationship_id }) => {
+                if !out.contains(&fact) {
+                    out.push(fact);
+                }
+                if !ids.contains(relationship_id) {
+                    ids.push(*relationship_id);
+                }
+            }
+            Some(FactSource::Derived { premises, .. }) => {
+                for premise in premises {
+                    self.chain(*premise, out, ids);
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+/// Infer family relationships from stored relationships.
+///
+/// Relationships with non-family labels (e.g., "coworker of") are ignored,
+/// and so are rows whose labels contradict each other ("parent of" with a
+/// NULL `b_to_a`, which means symmetric), as the doctor reports them.
+/// Derived relationships that duplicate a stored one are not returned.
+///
+/// # Arguments
+///
+/// * `relationships` - All stored relationships for one user
+///
+/// # Returns
+///
+/// Derived relationships in both directions (e.g., both "grandparent of"
+/// and "grandchild of"), ordered by chain length.
+pub fn infer(relationships: &[FriendRelationship]) -> Vec<InferredRelationship> {
+    let mut facts = FactSet::default();
+
+    // Seed with base facts from stored rows
+    for relationship in relationships {
+        let stored = FactSource::Stored {
+            relationship_id: relationship.id,
+        };
+        let (a, b) = (relationship.friend_a_id, relationship.friend_b_id);
+        let (a_to_b, b_to_a) = (&relationship.a_to_b, relationship.b_to_a.as_deref());
+        if check_labels(a_to_b, b_to_a).is_some() {
+            continue;
+        }
+
+        let (forward, reverse) = family_kinds(a_to_b, b_to_a);
+        let forward = forward.map(|kind| KinshipFact::new(a, kind, b));
+        let reverse = reverse.map(|kind| KinshipFact::new(b, kind, a));
+
+        for fact in forward.into_iter().chain(reverse) {
+            facts.insert(fact, stored.clone());
+            facts.insert(fact.inverse(), stored.clone());
+        }
+    }
+    let base_count = facts.order.len();
+
+    for _ in 0..MAX_ROUNDS {
+        let mut changed = false;
+
+        // Index outgoing facts by their subject for the second hop
+        let mut outgoing: HashMap<Uuid, Vec<KinshipFact>> = HashMap::new();
+        for fact in &facts.order {
+            outgoing.entry(fact.from_friend_id).or_default().push(*fact);
+        }
+
+        let snapshot = facts.order.clone();
+        for rule in RULES {
+            for first in snapshot.iter().filter(|f| f.kind == rule.first) {
+                let Some(candidates) = outgoing.get(&first.to_friend_id) else {
+                    continue;
+                };
+                for second in candidates.iter().filter(|f| f.kind == rule.second) {
+                    let derived =
+                        KinshipFact::new(first.from_friend_id, rule.result, second.to_friend_id);
+                    let source = FactSource::Derived {
+                        rule: rule.name,
+                        premises: vec![*first, *second],
+                    };
+
+                    if facts.insert(derived, source) {
+                        changed = true;
+                        facts.insert(
+                            derived.inverse(),
+                            FactSource::Derived {
+                                rule: rule.name,
+                                premises: vec![*first, *second],
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    facts.order[base_count..]
+        .iter()
+        .filter_map(|fact| {
+            let FactSource::Derived { rule, premises } = facts.sources.get(fact)? else {
+                return None;
+            };
+            let mut chain = Vec::new();
+            let mut relationship_ids = Vec::new();
+            facts.chain(*fact, &mut chain, &mut relationship_ids);
+
+            Some(InferredRelationship {
+                from_friend_id: fact.from_friend_id,
+                to_friend_id: fact.to_friend_id,
+                kind: fact.kind,
+                label: fact.kind.label(),
+                rule,
+                premises: premises.clone(),
+                chain,
+                relationship_ids,
+            })
+        })
+        .collect()
+}
+
+/// Loads stored relationships and runs kinship inference over them.
+///
+/// # Why a Whole-User Graph?
+///
+/// Inferences for a single friend can depend on relationships that don't
+/// involve them at all (a cousin is found through two parents and a
+/// sibling). So we always infer over the user's full relationship graph
+/// and filter afterwards.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let inference = KinshipInference::new(ctx.clone());
+/// let report = inference.report_for_friend(user_id, friend_id).await?;
+///
+/// for derived in &report.derived {
+///     println!("{}", derived.describe(&names));
+/// }
+/// ```
+pub struct KinshipInference {
+    relationships: FriendRelationshipRepository,
+}
+
+impl KinshipInference {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            relationships: FriendRelationshipRepository::new(ctx),
+        }
+    }
+
+    /// Stored and derived relationships for all of a user's friends.
+    pub async fn report_for_user(&self, user_id: Uuid) -> Result<KinshipReport, RepositoryError> {
+        let stored = self.relationships.list_by_user(user_id).await?;
+        let derived = infer(&stored);
+
+        Ok(KinshipReport { stored, derived })
+    }
+
+    /// Stored and derived relationships involving a single friend.
+    pub async fn report_for_friend(
+        &self,
+        user_id: Uuid,
+        friend_id: Uuid,
+    ) -> Result<KinshipReport, RepositoryError> {
+        Ok(self.report_for_user(user_id).await?.for_friend(friend_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::*;
+
+    const ANN: Uuid = Uuid::from_u128(1);
+    const BOB: Uuid = Uuid::from_u128(2);
+    const CAT: Uuid = Uuid::from_u128(3);
+    const DAN: Uuid = Uuid::from_u128(4);
+
+    fn relationship(a: Uuid, a_to_b: &str, b: Uuid, b_to_a: Option<&str>) -> FriendRelationship {
+        FriendRelationship {
+            id: Uuid::from_u128(a.as_u128() * 100 + b.as_u128()),
+            user_id: Uuid::nil(),
+            friend_a_id: a,
+            friend_b_id: b,
+            a_to_b: a_to_b.to_string(),
+            b_to_a: b_to_a.map(str::to_string),
+            started_on: None,
+            ended_on: None,
+            status: "active".to_string(),
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: None,
+            version: 1,
+        }
+    }
+
+    fn parent(parent: Uuid, child: Uuid) -> FriendRelationship {
+        relationship(parent, "parent of", child, Some("child of"))
+    }
+
+    fn find(derived: &[InferredRelationship], from: Uuid, to: Uuid) -> Option<Kinship> {
+        derived
+            .iter()
+            .find(|r| r.from_friend_id == from && r.to_friend_id == to)
+            .map(|r| r.kind)
+    }
+
+    #[test]
+    fn a_parents_parent_is_a_grandparent() {
+        let rows = [parent(ANN, BOB), parent(BOB, CAT)];
+        let derived = infer(&rows);
+
+        assert_eq!(find(&derived, ANN, CAT), Some(Kinship::Grandparent));
+        assert_eq!(find(&derived, CAT, ANN), Some(Kinship::Grandchild));
+
+        let grandparent = derived.iter().find(|r| r.from_friend_id == ANN).unwrap();
+        assert_eq!(grandparent.rule, "grandparent");
+        assert_eq!(grandparent.relationship_ids, vec![rows[0].id, rows[1].id]);
+        // Stored facts are never repeated as derived
+        assert_eq!(find(&derived, ANN, BOB), None);
+    }
+
+    #[test]
+    fn children_of_a_parent_are_siblings() {
+        let derived = infer(&[parent(ANN, BOB), parent(ANN, CAT)]);
+
+        assert_eq!(find(&derived, BOB, CAT), Some(Kinship::Sibling));
+        assert_eq!(find(&derived, CAT, BOB), Some(Kinship::Sibling));
+        // Nobody is their own sibling
+        assert_eq!(find(&derived, BOB, BOB), None);
+    }
+
+    #[test]
+    fn a_spouses_family_are_in_laws() {
+        let rows = [
+            parent(ANN, BOB),
+            relationship(BOB, "husband of", CAT, Some("wife of")),
+            relationship(DAN, "sister of", BOB, Some("brother of")),
+        ];
+        let derived = infer(&rows);
+
+        assert_eq!(find(&derived, ANN, CAT), Some(Kinship::ParentInLaw));
+        assert_eq!(find(&derived, CAT, ANN), Some(Kinship::ChildInLaw));
+        assert_eq!(find(&derived, DAN, CAT), Some(Kinship::SiblingInLaw));
+        assert_eq!(find(&derived, CAT, DAN), Some(Kinship::SiblingInLaw));
+    }
+
+    #[test]
+    fn a_missing_b_to_a_means_symmetric() {
+        // Siblings both ways
+        let derived = infer(&[parent(ANN, BOB), relationship(BOB, "sibling of", CAT, None)]);
+        assert_eq!(find(&derived, ANN, CAT), Some(Kinship::Parent));
+
+        // "parent of" both ways contradicts itself, so says nothing
+        let derived = infer(&[relationship(ANN, "parent of", BOB, None), parent(BOB, CAT)]);
+        assert!(derived.is_empty());
+    }
+
+    #[test]
+    fn cycles_reach_a_fixpoint() {
+        // Impossible, but storable: everyone is the next one's parent
+        let rows = [parent(ANN, BOB), parent(BOB, CAT), parent(CAT, ANN)];
+        let derived = infer(&rows);
+
+        assert_eq!(find(&derived, ANN, CAT), Some(Kinship::Grandparent));
+        assert!(derived.iter().all(|r| r.from_friend_id != r.to_friend_id));
+        // Every derived fact is distinct
+        let mut facts: Vec<_> = derived
+            .iter()
+            .map(|r| (r.from_friend_id, r.kind, r.to_friend_id))
+            .collect();
+        let count = facts.len();
+        facts.sort();
+        facts.dedup();
+        assert_eq!(facts.len(), count);
+    }
+}
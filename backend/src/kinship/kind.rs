@@ -0,0 +1,97 @@
+//! # Kinship Kinds
+//!
+//! Maps the freeform relationship labels stored in `friend_relationships`
+//! (e.g., "mother of", "brother of") onto a small set of kinship kinds
+//! the inference engine can reason about.
+
+use serde::{Deserialize, Serialize};
+
+/// A family relationship kind.
+///
+/// Every kind reads as "X is <kind> of Y". For example, `Parent` stored
+/// as (X, Y) means X is Y's parent.
+///
+/// # Base vs Derived Kinds
+///
+/// Only the four base kinds (`Parent`, `Child`, `Sibling`, `Spouse`) are
+/// recognized from stored labels. The remaining kinds are only ever
+/// produced by the inference rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kinship {
+    Parent,
+    Child,
+    Sibling,
+    Spouse,
+    Grandparent,
+    Grandchild,
+    AuntUncle,
+    NieceNephew,
+    Cousin,
+    ParentInLaw,
+    ChildInLaw,
+    SiblingInLaw,
+}
+
+impl Kinship {
+    /// Parse a stored relationship label into a base kinship kind.
+    ///
+    /// Matching is case-insensitive and ignores a trailing "of" / "to",
+    /// so "Mother of", "mother" and "MOTHER TO" all map to `Parent`.
+    ///
+    /// # Returns
+    ///
+    /// - `Some(kind)` for recognized family labels
+    /// - `None` for everything else (e.g., "coworker of", "boss of")
+    pub fn from_label(label: &str) -> Option<Self> {
+        let normalized = label.trim().to_lowercase();
+        let word = normalized
+            .strip_suffix(" of")
+            .or_else(|| normalized.strip_suffix(" to"))
+            .unwrap_or(&normalized)
+            .trim();
+
+        match word {
+            "parent" | "mother" | "father" | "mom" | "dad" => Some(Kinship::Parent),
+            "child" | "son" | "daughter" => Some(Kinship::Child),
+            "sibling" | "brother" | "sister" => Some(Kinship::Sibling),
+            "spouse" | "husband" | "wife" | "married" => Some(Kinship::Spouse),
+            _ => None,
+        }
+    }
+
+    /// The kind that holds in the opposite direction.
+    ///
+    /// If X is `self` of Y, then Y is `self.inverse()` of X.
+    pub fn inverse(self) -> Self {
+        match self {
+            Kinship::Parent => Kinship::Child,
+            Kinship::Child => Kinship::Parent,
+            Kinship::Grandparent => Kinship::Grandchild,
+            Kinship::Grandchild => Kinship::Grandparent,
+            Kinship::AuntUncle => Kinship::NieceNephew,
+            Kinship::NieceNephew => Kinship::AuntUncle,
+            Kinship::ParentInLaw => Kinship::ChildInLaw,
+            Kinship::ChildInLaw => Kinship::ParentInLaw,
+            Kinship::Sibling | Kinship::Spouse | Kinship::Cousin | Kinship::SiblingInLaw => self,
+        }
+    }
+
+    /// Human-readable label in the same style as stored relationships.
+    pub fn label(self) -> &'static str {
+        match self {
+            Kinship::Parent => "parent of",
+            Kinship::Child => "child of",
+            Kinship::Sibling => "sibling of",
+            Kinship::Spouse => "spouse of",
+            Kinship::Grandparent => "grandparent of",
+            Kinship::Grandchild => "grandchild of",
+            Kinship::AuntUncle => "aunt/uncle of",
+            Kinship::NieceNephew => "niece/nephew of",
+            Kinship::Cousin => "cousin of",
+            Kinship::ParentInLaw => "parent-in-law of",
+            Kinship::ChildInLaw => "child-in-law of",
+            Kinship::SiblingInLaw => "sibling-in-law of",
+        }
+    }
+}
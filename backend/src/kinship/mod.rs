@@ -0,0 +1,28 @@
+//! # Kinship Module
+//!
+//! Rule-based inference of family relationships from the stored
+//! `friend_relationships` table.
+//!
+//! ## Example
+//!
+//! If Ann is stored as "parent of" Bob and Bob as "sibling of" Cat, the
+//! engine derives that Ann is Cat's parent, and explains the chain:
+//!
+//! ```text
+//! Ann is parent of Bob, Bob is sibling of Cat ⇒ Ann is parent of Cat
+//! ```
+//!
+//! ## Derived Kinds
+//!
+//! Parents, children, siblings, grandparents/grandchildren, aunts/uncles,
+//! nieces/nephews, cousins, and in-laws (parent, child and sibling).
+//!
+//! Derived relationships are never written to the database - they are
+//! returned alongside the stored ones in a `KinshipReport`, which
+//! `GET /friends/<id>/kinship` serves.
+
+pub mod engine;
+pub mod kind;
+
+pub use engine::{InferredRelationship, KinshipFact, KinshipInference, KinshipReport, infer};
+pub use kind::Kinship;
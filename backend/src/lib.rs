@@ -1,2 +1,9 @@
+pub mod api;
+pub mod doctor;
+pub mod duplicates;
+pub mod interchange;
+pub mod kinship;
 pub mod models;
 pub mod repositories;
+pub mod services;
+pub mod validation;
@@ -1,7 +1,102 @@
 //! # Friend Knowledgebase Backend
 //!
 //! Entry point for the FKB backend server.
+//!
+//! The database is taken from `--database-url` or `DATABASE_URL`, and the
+//! listen address from `--bind` or `FKB_BIND` (a `.env` file is read if
+//! present).
+//!
+//! Logs go to stderr, at `info` and above unless `RUST_LOG` says
+//! otherwise (e.g. `RUST_LOG=debug`).
+//!
+//! Alongside the HTTP server runs the trash purge job: once an hour it
+//! permanently deletes friends that have been in the trash for longer
+//! than `--trash-retention-days` (`FKB_TRASH_RETENTION_DAYS`). Purges
+//! aren't user changes, so they never show up on anyone's undo stack.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use clap::Parser;
+use serde_json::json;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+
+use friend_knowledgebase_backend::api;
+use friend_knowledgebase_backend::repositories::{FriendRepository, RepositoryContext};
+
+/// How often the trash purge job runs.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Parser)]
+#[command(
+    name = "friend-knowledgebase-backend",
+    about = "Friend Knowledgebase server"
+)]
+struct Args {
+    /// PostgreSQL connection string
+    #[arg(long, env = "DATABASE_URL", hide_env_values = true)]
+    database_url: String,
+
+    /// Address to listen on
+    #[arg(long, env = "FKB_BIND", default_value = "127.0.0.1:3000")]
+    bind: String,
+
+    /// Days a deleted friend stays in the trash before being purged
+    #[arg(long, env = "FKB_TRASH_RETENTION_DAYS", default_value_t = 30)]
+    trash_retention_days: u32,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenv::dotenv().ok();
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+
+    let pool = PgPool::connect(&args.database_url)
+        .await
+        .context("connecting to the database")?;
+    let ctx = RepositoryContext::new(pool);
+
+    tokio::spawn(purge_trash(ctx.clone(), args.trash_retention_days));
+
+    let listener = tokio::net::TcpListener::bind(&args.bind)
+        .await
+        .with_context(|| format!("listening on {}", args.bind))?;
+    log::info!("Friend Knowledgebase Backend listening on {}", args.bind);
+
+    axum::serve(listener, api::router(ctx)).await?;
+    Ok(())
+}
+
+/// The trash purge job: purge now, then every `PURGE_INTERVAL`.
+///
+/// Each run logs one structured line; a failed run is logged and retried
+/// at the next interval rather than stopping the job.
+async fn purge_trash(ctx: RepositoryContext, retention_days: u32) {
+    let friends = FriendRepository::new(ctx);
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        let started = Instant::now();
+        let cutoff = OffsetDateTime::now_utc() - time::Duration::days(retention_days.into());
 
-fn main() {
-    println!("Friend Knowledgebase Backend");
+        let outcome = friends.purge_trashed_before(cutoff).await;
+        let mut event = json!({
+            "job": "purge_trash",
+            "retention_days": retention_days,
+            "duration_ms": started.elapsed().as_millis() as u64,
+        });
+        match outcome {
+            Ok(purged) => {
+                event["purged"] = json!(purged);
+                log::info!("{event}");
+            }
+            Err(e) => {
+                event["error"] = json!(e.to_string());
+                log::error!("{event}");
+            }
+        }
+    }
 }
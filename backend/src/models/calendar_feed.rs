@@ -0,0 +1,42 @@
+//! # Calendar Feed Model
+//!
+//! Represents a user's subscribable iCalendar feed of friends' birthdays
+//! and other important dates.
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Database model for the `calendar_feeds` table.
+///
+/// # Fields
+/// - `id`: Unique identifier
+/// - `user_id`: Foreign key to the owning user (one feed per user)
+/// - `token`: Secret that makes up the feed URL
+/// - `alarm_minutes_before`: Reminder lead time, or `None` for no reminders
+/// - `created_at`: When the feed was created
+/// - `updated_at`: When the feed was last modified (e.g., token rotated)
+///
+/// # Security
+/// Anyone holding the token can read the feed, so it must never be logged
+/// or shown outside the owner's own settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalendarFeed {
+    /// Primary key - UUID generated by the database
+    pub id: Uuid,
+
+    /// Foreign key to the owning user
+    pub user_id: Uuid,
+
+    /// Secret URL token, generated by the database
+    pub token: String,
+
+    /// Minutes before each event to fire a reminder
+    pub alarm_minutes_before: Option<i32>,
+
+    /// Timestamp when the feed was created
+    pub created_at: OffsetDateTime,
+
+    /// Timestamp when the feed was last updated
+    pub updated_at: Option<OffsetDateTime>,
+}
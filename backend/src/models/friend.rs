@@ -21,6 +21,7 @@ use uuid::Uuid;
 /// - `notes`: General notes about the friend
 /// - `created_at`: When the record was created
 /// - `updated_at`: When the record was last modified
+/// - `version`: Change counter for optimistic concurrency (managed by trigger)
 ///
 /// # Data Isolation
 /// Friends are always filtered by `user_id` to ensure users only see
@@ -58,4 +59,22 @@ pub struct Friend {
 
     /// Timestamp when the friend was last updated
     pub updated_at: Option<OffsetDateTime>,
+
+    /// Version of the friend, increased by the database on every change
+    pub version: i64,
+}
+
+/// A friend in the trash.
+///
+/// Trashed friends keep their row (and their attributes, groups and
+/// relationships) until they're restored or purged; `deleted_at` is when
+/// they were moved to the trash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashedFriend {
+    /// The friend as it was when deleted
+    #[serde(flatten)]
+    pub friend: Friend,
+
+    /// Timestamp when the friend was moved to the trash
+    pub deleted_at: OffsetDateTime,
 }
@@ -17,6 +17,7 @@ use uuid::Uuid;
 /// - `value_type`: Type hint for the value (e.g., "text", "date", "number")
 /// - `created_at`: When the attribute was created
 /// - `updated_at`: When the attribute was last modified
+/// - `version`: Change counter for optimistic concurrency (managed by trigger)
 ///
 /// # Key-Value Pattern
 /// This is a flexible schema pattern (sometimes called EAV - Entity-Attribute-Value).
@@ -54,4 +55,7 @@ pub struct FriendAttribute {
 
     /// Timestamp when the attribute was last updated
     pub updated_at: Option<OffsetDateTime>,
+
+    /// Version of the attribute, increased by the database on every change
+    pub version: i64,
 }
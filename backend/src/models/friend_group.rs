@@ -0,0 +1,25 @@
+//! # Friend Group Membership Model
+//!
+//! Represents one row of the `friend_groups` join table.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Database model for the `friend_groups` table.
+///
+/// # Fields
+/// - `friend_id`: Foreign key to the friend
+/// - `group_id`: Foreign key to the group
+///
+/// # Usage
+/// Day-to-day code manages membership through `FriendRepository`
+/// (`add_to_group`, `list_groups`) and never needs this type. It exists
+/// for bulk work like backups, where the join rows themselves are data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FriendGroup {
+    /// Foreign key to the friend
+    pub friend_id: Uuid,
+
+    /// Foreign key to the group
+    pub group_id: Uuid,
+}
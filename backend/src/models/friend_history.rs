@@ -0,0 +1,59 @@
+//! # Friend History Model
+//!
+//! Represents one entry in the append-only `friend_history` table, which
+//! database triggers fill with every change to a friend and the rows that
+//! hang off them.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Database model for the `friend_history` table.
+///
+/// # Fields
+/// - `id`: Position in the history (increasing)
+/// - `user_id`: Foreign key to the owning user
+/// - `friend_id`: The friend the change concerns (not a foreign key, so
+///   the history of purged friends survives)
+/// - `table_name`: Which table changed, e.g. "friend_attributes"
+/// - `row_key`: The changed row's key within that table
+/// - `operation`: "INSERT", "UPDATE", "DELETE", or "BASELINE" for rows
+///   that already existed when history recording started
+/// - `before`: The row as JSON before the change (`None` for inserts)
+/// - `after`: The row as JSON after the change (`None` for deletes)
+/// - `changed_at`: When the change was made
+///
+/// # JSON Columns
+/// `before` and `after` are PostgreSQL's `to_jsonb` of the row, so
+/// timestamps are ISO 8601 strings. Membership rows also carry the
+/// group's name at the time, as `group_name`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FriendHistoryEntry {
+    /// Primary key - increases with every change
+    pub id: i64,
+
+    /// Foreign key to the owning user
+    pub user_id: Uuid,
+
+    /// The friend this change belongs to
+    pub friend_id: Uuid,
+
+    /// Name of the table the changed row lives in
+    pub table_name: String,
+
+    /// Key of the changed row within its table
+    pub row_key: String,
+
+    /// INSERT, UPDATE, DELETE or BASELINE
+    pub operation: String,
+
+    /// The row before the change
+    pub before: Option<Value>,
+
+    /// The row after the change
+    pub after: Option<Value>,
+
+    /// Timestamp of the change
+    pub changed_at: OffsetDateTime,
+}
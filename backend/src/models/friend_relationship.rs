@@ -4,7 +4,7 @@
 //! This tracks how friends know each other (e.g., siblings, coworkers).
 
 use serde::{Deserialize, Serialize};
-use time::OffsetDateTime;
+use time::{Date, OffsetDateTime};
 use uuid::Uuid;
 
 /// Database model for the `friend_relationships` table.
@@ -16,8 +16,12 @@ use uuid::Uuid;
 /// - `friend_b_id`: Second friend in the relationship
 /// - `a_to_b`: How friend A relates to friend B (required)
 /// - `b_to_a`: How friend B relates to friend A (optional for symmetric relationships)
+/// - `started_on`: When the relationship began (optional)
+/// - `ended_on`: When the relationship ended (optional)
+/// - `status`: Current status: "active", "ended" or "deceased"
 /// - `created_at`: When the relationship was created
 /// - `updated_at`: When the relationship was last modified
+/// - `version`: Change counter for optimistic concurrency (managed by trigger)
 ///
 /// # Relationship Types
 ///
@@ -32,6 +36,11 @@ use uuid::Uuid;
 /// - Example: a_to_b = "boss of", b_to_a = "employee of"
 /// - Example: a_to_b = "mentor to", b_to_a = "mentee of"
 ///
+/// # Timeline
+/// A relationship is *current* when its status is "active" and today falls
+/// between `started_on` and `ended_on` (either may be open-ended). Every
+/// status change is recorded in `friend_relationship_status_history`.
+///
 /// # Data Isolation
 /// The `user_id` ensures users only see relationships between their own friends.
 /// Both `friend_a_id` and `friend_b_id` must belong to the same user.
@@ -56,9 +65,21 @@ pub struct FriendRelationship {
     /// If NULL, the relationship is symmetric (same in both directions)
     pub b_to_a: Option<String>,
 
+    /// Date the relationship began, if known
+    pub started_on: Option<Date>,
+
+    /// Date the relationship ended, if it has
+    pub ended_on: Option<Date>,
+
+    /// Current status (database default: "active")
+    pub status: String,
+
     /// Timestamp when the relationship was created
     pub created_at: OffsetDateTime,
 
     /// Timestamp when the relationship was last updated
     pub updated_at: Option<OffsetDateTime>,
+
+    /// Version of the relationship, increased by the database on every change
+    pub version: i64,
 }
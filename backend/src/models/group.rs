@@ -16,6 +16,7 @@ use uuid::Uuid;
 /// - `description`: Optional longer description of the group
 /// - `created_at`: When the group was created
 /// - `updated_at`: When the group was last modified
+/// - `version`: Change counter for optimistic concurrency (managed by trigger)
 ///
 /// # Usage
 /// Groups help users organize their friends into categories.
@@ -45,4 +46,7 @@ pub struct Group {
 
     /// Timestamp when the group was last updated
     pub updated_at: Option<OffsetDateTime>,
+
+    /// Version of the group, increased by the database on every change
+    pub version: i64,
 }
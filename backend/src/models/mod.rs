@@ -16,12 +16,20 @@ pub mod group;
 pub mod friend_attribute;
 pub mod friend_relationship;
 pub mod user_friend_relationship;
+pub mod relationship_status_change;
+pub mod friend_group;
+pub mod calendar_feed;
+pub mod friend_history;
 
 // Re-export all models for convenient access
 // e.g., `use crate::models::User;` instead of `use crate::models::user::User;`
 pub use user::User;
-pub use friend::Friend;
+pub use friend::{Friend, TrashedFriend};
 pub use group::Group;
 pub use friend_attribute::FriendAttribute;
 pub use friend_relationship::FriendRelationship;
 pub use user_friend_relationship::UserFriendRelationship;
+pub use relationship_status_change::RelationshipStatusChange;
+pub use friend_group::FriendGroup;
+pub use calendar_feed::CalendarFeed;
+pub use friend_history::FriendHistoryEntry;
@@ -0,0 +1,44 @@
+//! # Relationship Status Change Model
+//!
+//! Represents one entry in a relationship's status history.
+//! Both relationship tables have their own history table with this shape.
+
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
+use uuid::Uuid;
+
+/// Database model for the `friend_relationship_status_history` and
+/// `user_friend_relationship_status_history` tables.
+///
+/// # Fields
+/// - `id`: Unique identifier (UUIDv7 generated by database)
+/// - `relationship_id`: Foreign key to the relationship that changed
+/// - `status`: The status the relationship moved to
+/// - `effective_on`: The date the change took effect (may be backdated)
+/// - `created_at`: When the change was recorded
+///
+/// # Append-Only
+/// History rows are written by the repositories whenever a relationship is
+/// created or its status changes, and are never updated. They are removed
+/// only when the relationship itself is deleted.
+///
+/// # Example History
+/// - status: "active", effective_on: 2015-09-01 (started dating)
+/// - status: "ended", effective_on: 2021-03-14 (split up)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelationshipStatusChange {
+    /// Primary key - UUID generated by the database
+    pub id: Uuid,
+
+    /// Foreign key to the relationship this entry belongs to
+    pub relationship_id: Uuid,
+
+    /// The new status ("active", "ended" or "deceased")
+    pub status: String,
+
+    /// When the change took effect in real life
+    pub effective_on: Date,
+
+    /// Timestamp when the change was recorded
+    pub created_at: OffsetDateTime,
+}
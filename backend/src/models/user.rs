@@ -17,6 +17,7 @@ use uuid::Uuid;
 /// - `password_hash`: Bcrypt hashed password (never serialized to JSON)
 /// - `created_at`: When the user was created (set by database)
 /// - `updated_at`: When the user was last modified (managed by trigger)
+/// - `version`: Change counter for optimistic concurrency (managed by trigger)
 ///
 /// # Note on password_hash
 /// The `#[serde(skip_serializing)]` attribute ensures the password hash
@@ -46,4 +47,7 @@ pub struct User {
     /// Timestamp when the user was last updated (managed by database trigger)
     /// None if the user has never been updated after creation
     pub updated_at: Option<OffsetDateTime>,
+
+    /// Version of the user, increased by the database on every change
+    pub version: i64,
 }
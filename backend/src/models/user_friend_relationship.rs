@@ -5,7 +5,7 @@
 //! the user's personal connection to each friend.
 
 use serde::{Deserialize, Serialize};
-use time::OffsetDateTime;
+use time::{Date, OffsetDateTime};
 use uuid::Uuid;
 
 /// Database model for the `user_friend_relationships` table.
@@ -14,8 +14,12 @@ use uuid::Uuid;
 /// - `id`: Unique identifier (UUIDv7 generated by database)
 /// - `friend_id`: Foreign key to the friend
 /// - `relationship_type`: How the user knows this friend
+/// - `started_on`: When the relationship began (optional)
+/// - `ended_on`: When the relationship ended (optional)
+/// - `status`: Current status: "active", "ended" or "deceased"
 /// - `created_at`: When the relationship was created
 /// - `updated_at`: When the relationship was last modified
+/// - `version`: Change counter for optimistic concurrency (managed by trigger)
 ///
 /// # Purpose
 /// While `friend_relationships` tracks how friends know each other,
@@ -30,6 +34,11 @@ use uuid::Uuid;
 /// - "met_at_party" - met at a social event
 /// - "childhood_friend" - known since childhood
 ///
+/// # Timeline
+/// Same rules as `FriendRelationship`: current means status "active" and
+/// today within the date range. Status changes are recorded in
+/// `user_friend_relationship_status_history`.
+///
 /// # Data Isolation
 /// This table doesn't need a `user_id` column because it's already
 /// isolated through the `friend_id` foreign key - friends are always
@@ -45,9 +54,21 @@ pub struct UserFriendRelationship {
     /// How the user knows this friend (e.g., "coworker", "neighbor")
     pub relationship_type: String,
 
+    /// Date the relationship began, if known
+    pub started_on: Option<Date>,
+
+    /// Date the relationship ended, if it has
+    pub ended_on: Option<Date>,
+
+    /// Current status (database default: "active")
+    pub status: String,
+
     /// Timestamp when the relationship was created
     pub created_at: OffsetDateTime,
 
     /// Timestamp when the relationship was last updated
     pub updated_at: Option<OffsetDateTime>,
+
+    /// Version of the relationship, increased by the database on every change
+    pub version: i64,
 }
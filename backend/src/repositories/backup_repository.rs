@@ -0,0 +1,534 @@
+//! # Backup Repository
+//!
+//! Reads and writes everything a user owns in one go, with IDs and
+//! timestamps exactly as stored. Used for backups and restores; normal
+//! code should go through the entity repositories instead.
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::models::{
+    Friend, FriendAttribute, FriendGroup, FriendRelationship, Group, RelationshipStatusChange,
+    TrashedFriend, UserFriendRelationship,
+};
+
+use super::base::RepositoryContext;
+use super::error::RepositoryError;
+
+/// Every row a user owns, grouped by table.
+///
+/// Rows within a table are ordered by creation time (then ID), so two
+/// snapshots of the same data serialize identically.
+///
+/// `friends` only holds friends outside the trash; trashed ones are in
+/// `trashed_friends`, with their attributes, memberships and
+/// relationships in the other tables as usual. Live snapshots (see
+/// `BackupRepository::snapshot_live`) leave the trash out altogether.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserSnapshot {
+    pub friends: Vec<Friend>,
+    #[serde(default)]
+    pub trashed_friends: Vec<TrashedFriend>,
+    pub groups: Vec<Group>,
+    pub friend_groups: Vec<FriendGroup>,
+    pub friend_attributes: Vec<FriendAttribute>,
+    pub friend_relationships: Vec<FriendRelationship>,
+    pub friend_relationship_status_history: Vec<RelationshipStatusChange>,
+    pub user_friend_relationships: Vec<UserFriendRelationship>,
+    pub user_friend_relationship_status_history: Vec<RelationshipStatusChange>,
+}
+
+/// The `users` row fields a restore needs to recreate an account.
+pub struct RestoredAccount<'a> {
+    pub id: Uuid,
+    pub first_name: &'a str,
+    pub last_name: &'a str,
+    pub email: &'a str,
+    pub password_hash: &'a str,
+    pub created_at: OffsetDateTime,
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+/// Repository for whole-account reads and writes.
+///
+/// # Why Not the Entity Repositories?
+///
+/// Their `create` methods let the database pick IDs and timestamps, and
+/// relationship creates run validation. A restore must reproduce rows
+/// byte for byte, including ones today's validation would reject.
+pub struct BackupRepository {
+    ctx: RepositoryContext,
+}
+
+impl BackupRepository {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self { ctx }
+    }
+
+    /// Read every row owned by a user, the trash included.
+    ///
+    /// This is what backups are made of. Runs in a read-only REPEATABLE
+    /// READ transaction so the snapshot is consistent even if the user is
+    /// editing at the same time.
+    pub async fn snapshot(&self, user_id: Uuid) -> Result<UserSnapshot, RepositoryError> {
+        self.read(user_id, true).await
+    }
+
+    /// Read every row owned by a user, as if the trash were empty.
+    ///
+    /// Friends in the trash, and the attributes, memberships and
+    /// relationships hanging off them, are left out: exports and sync
+    /// clients (CardDAV, CalDAV, vaults, HTML, ICS) treat them as deleted.
+    pub async fn snapshot_live(&self, user_id: Uuid) -> Result<UserSnapshot, RepositoryError> {
+        self.read(user_id, false).await
+    }
+
+    /// Read a snapshot, with or without the trash.
+    async fn read(&self, user_id: Uuid, with_trash: bool) -> Result<UserSnapshot, RepositoryError> {
+        let mut tx = self.ctx.transaction().await?;
+        sqlx::query!("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .execute(&mut *tx)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+
+        let friends = sqlx::query_as!(
+            Friend,
+            r#"
+            SELECT id, user_id, first_name, last_name, date_of_birth,
+                   likes, dislikes, notes, created_at, updated_at, version
+            FROM friends
+            WHERE user_id = $1 AND deleted_at IS NULL
+            ORDER BY created_at, id
+            "#,
+            user_id
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let trashed_friends = sqlx::query!(
+            r#"
+            SELECT id, user_id, first_name, last_name, date_of_birth,
+                   likes, dislikes, notes, created_at, updated_at, version,
+                   deleted_at AS "deleted_at!"
+            FROM friends
+            WHERE user_id = $1 AND deleted_at IS NOT NULL AND $2
+            ORDER BY created_at, id
+            "#,
+            user_id,
+            with_trash
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?
+        .into_iter()
+        .map(|row| TrashedFriend {
+            friend: Friend {
+                id: row.id,
+                user_id: row.user_id,
+                first_name: row.first_name,
+                last_name: row.last_name,
+                date_of_birth: row.date_of_birth,
+                likes: row.likes,
+                dislikes: row.dislikes,
+                notes: row.notes,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version,
+            },
+            deleted_at: row.deleted_at,
+        })
+        .collect();
+
+        let groups = sqlx::query_as!(
+            Group,
+            r#"
+            SELECT id, user_id, name, description, created_at, updated_at, version
+            FROM groups
+            WHERE user_id = $1
+            ORDER BY created_at, id
+            "#,
+            user_id
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let friend_groups = sqlx::query_as!(
+            FriendGroup,
+            r#"
+            SELECT fg.friend_id, fg.group_id
+            FROM friend_groups fg
+            JOIN friends f ON f.id = fg.friend_id
+            WHERE f.user_id = $1 AND ($2 OR f.deleted_at IS NULL)
+            ORDER BY fg.friend_id, fg.group_id
+            "#,
+            user_id,
+            with_trash
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let friend_attributes = sqlx::query_as!(
+            FriendAttribute,
+            r#"
+            SELECT a.id, a.friend_id, a.key, a.value, a.value_type, a.created_at, a.updated_at,
+                   a.version
+            FROM friend_attributes a
+            JOIN friends f ON f.id = a.friend_id
+            WHERE f.user_id = $1 AND ($2 OR f.deleted_at IS NULL)
+            ORDER BY a.created_at, a.id
+            "#,
+            user_id,
+            with_trash
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let friend_relationships = sqlx::query_as!(
+            FriendRelationship,
+            r#"
+            SELECT id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a,
+                   started_on, ended_on, status, created_at, updated_at, version
+            FROM friend_relationships r
+            WHERE user_id = $1
+              AND ($2 OR NOT EXISTS (
+                  SELECT 1 FROM friends f
+                  WHERE f.id IN (r.friend_a_id, r.friend_b_id) AND f.deleted_at IS NOT NULL
+              ))
+            ORDER BY created_at, id
+            "#,
+            user_id,
+            with_trash
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let friend_relationship_status_history = sqlx::query_as!(
+            RelationshipStatusChange,
+            r#"
+            SELECT h.id, h.relationship_id, h.status, h.effective_on, h.created_at
+            FROM friend_relationship_status_history h
+            JOIN friend_relationships r ON r.id = h.relationship_id
+            WHERE r.user_id = $1
+              AND ($2 OR NOT EXISTS (
+                  SELECT 1 FROM friends f
+                  WHERE f.id IN (r.friend_a_id, r.friend_b_id) AND f.deleted_at IS NOT NULL
+              ))
+            ORDER BY h.created_at, h.id
+            "#,
+            user_id,
+            with_trash
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        // user_friend_relationships has no user_id; ownership is via the friend
+        let user_friend_relationships = sqlx::query_as!(
+            UserFriendRelationship,
+            r#"
+            SELECT r.id, r.friend_id, r.relationship_type, r.started_on, r.ended_on, r.status,
+                   r.created_at, r.updated_at, r.version
+            FROM user_friend_relationships r
+            JOIN friends f ON f.id = r.friend_id
+            WHERE f.user_id = $1 AND ($2 OR f.deleted_at IS NULL)
+            ORDER BY r.created_at, r.id
+            "#,
+            user_id,
+            with_trash
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let user_friend_relationship_status_history = sqlx::query_as!(
+            RelationshipStatusChange,
+            r#"
+            SELECT h.id, h.relationship_id, h.status, h.effective_on, h.created_at
+            FROM user_friend_relationship_status_history h
+            JOIN user_friend_relationships r ON r.id = h.relationship_id
+            JOIN friends f ON f.id = r.friend_id
+            WHERE f.user_id = $1 AND ($2 OR f.deleted_at IS NULL)
+            ORDER BY h.created_at, h.id
+            "#,
+            user_id,
+            with_trash
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+
+        Ok(UserSnapshot {
+            friends,
+            trashed_friends,
+            groups,
+            friend_groups,
+            friend_attributes,
+            friend_relationships,
+            friend_relationship_status_history,
+            user_friend_relationships,
+            user_friend_relationship_status_history,
+        })
+    }
+
+    /// `true` if the user owns no friends and no groups.
+    ///
+    /// Every other table hangs off one of those two, so this covers the
+    /// whole account.
+    pub async fn is_empty(&self, user_id: Uuid) -> Result<bool, RepositoryError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                NOT EXISTS (SELECT 1 FROM friends WHERE user_id = $1)
+                AND NOT EXISTS (SELECT 1 FROM groups WHERE user_id = $1) AS "empty!"
+            "#,
+            user_id
+        )
+        .fetch_one(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(row.empty)
+    }
+
+    /// Insert a `users` row with a fixed ID and timestamps.
+    pub async fn restore_account_in(
+        &self,
+        conn: &mut PgConnection,
+        account: RestoredAccount<'_>,
+    ) -> Result<(), RepositoryError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO users (id, first_name, last_name, email, password_hash, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            account.id,
+            account.first_name,
+            account.last_name,
+            account.email,
+            account.password_hash,
+            account.created_at,
+            account.updated_at
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(())
+    }
+
+    /// Insert every row of a snapshot for `user_id`.
+    ///
+    /// IDs and timestamps are kept; `user_id` columns are set to the target
+    /// user, so a snapshot can be restored into a different account. Tables
+    /// are written parents-first so foreign keys are satisfied.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Duplicate` if any row's ID already exists (e.g., restoring
+    /// into the same database the backup came from without deleting first).
+    pub async fn restore_in(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        snapshot: &UserSnapshot,
+    ) -> Result<(), RepositoryError> {
+        for f in &snapshot.friends {
+            sqlx::query!(
+                r#"
+                INSERT INTO friends (id, user_id, first_name, last_name, date_of_birth,
+                                     likes, dislikes, notes, created_at, updated_at, version)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                "#,
+                f.id,
+                user_id,
+                f.first_name,
+                f.last_name,
+                f.date_of_birth,
+                f.likes,
+                f.dislikes,
+                f.notes,
+                f.created_at,
+                f.updated_at,
+                f.version
+            )
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        }
+
+        for t in &snapshot.trashed_friends {
+            let f = &t.friend;
+            sqlx::query!(
+                r#"
+                INSERT INTO friends (id, user_id, first_name, last_name, date_of_birth,
+                                     likes, dislikes, notes, created_at, updated_at, version,
+                                     deleted_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                "#,
+                f.id,
+                user_id,
+                f.first_name,
+                f.last_name,
+                f.date_of_birth,
+                f.likes,
+                f.dislikes,
+                f.notes,
+                f.created_at,
+                f.updated_at,
+                f.version,
+                t.deleted_at
+            )
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        }
+
+        for g in &snapshot.groups {
+            sqlx::query!(
+                r#"
+                INSERT INTO groups (id, user_id, name, description, created_at, updated_at,
+                                    version)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                "#,
+                g.id,
+                user_id,
+                g.name,
+                g.description,
+                g.created_at,
+                g.updated_at,
+                g.version
+            )
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        }
+
+        for m in &snapshot.friend_groups {
+            sqlx::query!(
+                "INSERT INTO friend_groups (friend_id, group_id) VALUES ($1, $2)",
+                m.friend_id,
+                m.group_id
+            )
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        }
+
+        for a in &snapshot.friend_attributes {
+            sqlx::query!(
+                r#"
+                INSERT INTO friend_attributes (id, friend_id, key, value, value_type,
+                                               created_at, updated_at, version)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                "#,
+                a.id,
+                a.friend_id,
+                a.key,
+                a.value,
+                a.value_type,
+                a.created_at,
+                a.updated_at,
+                a.version
+            )
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        }
+
+        for r in &snapshot.friend_relationships {
+            sqlx::query!(
+                r#"
+                INSERT INTO friend_relationships (id, user_id, friend_a_id, friend_b_id, a_to_b,
+                                                  b_to_a, started_on, ended_on, status,
+                                                  created_at, updated_at, version)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                "#,
+                r.id,
+                user_id,
+                r.friend_a_id,
+                r.friend_b_id,
+                r.a_to_b,
+                r.b_to_a,
+                r.started_on,
+                r.ended_on,
+                r.status,
+                r.created_at,
+                r.updated_at,
+                r.version
+            )
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        }
+
+        for h in &snapshot.friend_relationship_status_history {
+            sqlx::query!(
+                r#"
+                INSERT INTO friend_relationship_status_history (id, relationship_id, status,
+                                                                effective_on, created_at)
+                VALUES ($1, $2, $3, $4, $5)
+                "#,
+                h.id,
+                h.relationship_id,
+                h.status,
+                h.effective_on,
+                h.created_at
+            )
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        }
+
+        for r in &snapshot.user_friend_relationships {
+            sqlx::query!(
+                r#"
+                INSERT INTO user_friend_relationships (id, friend_id, relationship_type,
+                                                       started_on, ended_on, status,
+                                                       created_at, updated_at, version)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                "#,
+                r.id,
+                r.friend_id,
+                r.relationship_type,
+                r.started_on,
+                r.ended_on,
+                r.status,
+                r.created_at,
+                r.updated_at,
+                r.version
+            )
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        }
+
+        for h in &snapshot.user_friend_relationship_status_history {
+            sqlx::query!(
+                r#"
+                INSERT INTO user_friend_relationship_status_history (id, relationship_id, status,
+                                                                     effective_on, created_at)
+                VALUES ($1, $2, $3, $4, $5)
+                "#,
+                h.id,
+                h.relationship_id,
+                h.status,
+                h.effective_on,
+                h.created_at
+            )
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        }
+
+        Ok(())
+    }
+}
@@ -3,9 +3,10 @@
 //! This module defines the core types used by all repositories:
 //! - `RepositoryContext` - Holds the database connection pool
 //! - `Repository` trait - Generic CRUD interface
+//! - `Timeline` - Filter for relationship queries (current vs full history)
 
 use async_trait::async_trait;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
 use uuid::Uuid;
 
 use super::error::RepositoryError;
@@ -92,6 +93,41 @@ impl RepositoryContext {
     }
 }
 
+/// Which relationships a timeline-aware query should return.
+///
+/// # Current
+///
+/// A relationship is current when its status is "active" and today falls
+/// within its optional `started_on` / `ended_on` range. `ended_on` is
+/// exclusive - a relationship that ended today is no longer current.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// // Only people the friend is still connected to
+/// let current = repo.list_by_friend(friend_id, Timeline::Current).await?;
+///
+/// // Everyone they've ever been connected to
+/// let all = repo.list_by_friend(friend_id, Timeline::Full).await?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timeline {
+    /// Only relationships that are in effect today
+    Current,
+    /// Every relationship, including ended ones
+    #[default]
+    Full,
+}
+
+impl Timeline {
+    /// `true` if the query should drop relationships that aren't current.
+    ///
+    /// Passed to SQL as a boolean parameter so a single query handles both.
+    pub fn current_only(self) -> bool {
+        matches!(self, Timeline::Current)
+    }
+}
+
 /// Generic repository trait for CRUD operations.
 ///
 /// # Type Parameters
@@ -110,11 +146,24 @@ impl RepositoryContext {
 ///
 /// # Required Methods
 ///
-/// All repositories must implement these four methods:
-/// - `find_by_id` - Get a single record by primary key
-/// - `create` - Insert a new record
-/// - `update` - Modify an existing record
-/// - `delete` - Remove a record
+/// All repositories must implement these four methods, each running on
+/// the connection it is given:
+/// - `find_by_id_in` - Get a single record by primary key
+/// - `create_in` - Insert a new record
+/// - `update_in` - Modify an existing record
+/// - `delete_in` - Remove a record
+///
+/// plus `context`, which the provided `find_by_id`, `create`, `update`
+/// and `delete` take their connections from.
+///
+/// # Transactions
+///
+/// The `_in` methods take a `&mut PgConnection`, so passing `&mut *tx`
+/// makes them part of a larger transaction (see `UnitOfWork`, which binds
+/// every repository to one). The provided write methods run each call in
+/// a transaction of its own, so a write that takes several statements
+/// (a relationship and its first status history entry) is atomic either
+/// way.
 ///
 /// # Custom Methods
 ///
@@ -131,13 +180,16 @@ impl RepositoryContext {
 #[async_trait]
 pub trait Repository: Send + Sync {
     /// The entity model returned by this repository
-    type Entity;
+    type Entity: Send;
 
     /// Input type for creating new records
-    type CreateInput;
+    type CreateInput: Send + 'static;
 
     /// Input type for updating existing records
-    type UpdateInput;
+    type UpdateInput: Send + 'static;
+
+    /// The context holding the pool the provided methods use.
+    fn context(&self) -> &RepositoryContext;
 
     /// Find a record by its primary key.
     ///
@@ -150,7 +202,22 @@ pub trait Repository: Send + Sync {
     /// - `Ok(Some(entity))` if found
     /// - `Ok(None)` if no record exists with that ID
     /// - `Err(RepositoryError)` on database error
-    async fn find_by_id(&self, id: Uuid) -> Result<Option<Self::Entity>, RepositoryError>;
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<Self::Entity>, RepositoryError> {
+        let mut conn = self
+            .context()
+            .pool
+            .acquire()
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        self.find_by_id_in(&mut conn, id).await
+    }
+
+    /// Same as `find_by_id`, but runs on the given connection.
+    async fn find_by_id_in(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+    ) -> Result<Option<Self::Entity>, RepositoryError>;
 
     /// Create a new record in the database.
     ///
@@ -164,24 +231,74 @@ pub trait Repository: Send + Sync {
     /// - `Err(Duplicate)` if a unique constraint is violated
     /// - `Err(ForeignKeyViolation)` if a referenced record doesn't exist
     /// - `Err(Database)` on other database errors
-    async fn create(&self, input: Self::CreateInput) -> Result<Self::Entity, RepositoryError>;
+    async fn create(&self, input: Self::CreateInput) -> Result<Self::Entity, RepositoryError> {
+        let mut tx = self.context().transaction().await?;
+        let entity = self.create_in(&mut tx, input).await?;
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(entity)
+    }
+
+    /// Same as `create`, but runs on the given connection.
+    ///
+    /// Pass `&mut *tx` to make the insert part of a larger transaction
+    /// (e.g., an import that must succeed or fail as a whole).
+    async fn create_in(
+        &self,
+        conn: &mut PgConnection,
+        input: Self::CreateInput,
+    ) -> Result<Self::Entity, RepositoryError>;
 
     /// Update an existing record.
     ///
+    /// # Optimistic Concurrency
+    ///
+    /// Every record carries a `version` the database bumps on each change.
+    /// Pass the version the caller last read as `expected_version`, and the
+    /// update only goes through if nobody has changed the record since.
+    /// `None` updates whatever is there.
+    ///
     /// # Arguments
     ///
     /// * `id` - The UUID of the record to update
+    /// * `expected_version` - The version the update is based on, if any
     /// * `input` - The new data for the record
     ///
     /// # Returns
     ///
-    /// - `Ok(entity)` with the updated record
+    /// - `Ok(entity)` with the updated record (and its new version)
     /// - `Err(NotFound)` if no record exists with that ID
+    /// - `Err(Conflict)` if the record is no longer at `expected_version`
     /// - `Err(Duplicate)` if update violates a unique constraint
     /// - `Err(Database)` on other database errors
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let friend = repo.find_by_id(id).await?.ok_or(RepositoryError::NotFound)?;
+    /// match repo.update(id, Some(friend.version), input).await {
+    ///     Ok(updated) => println!("Now at version {}", updated.version),
+    ///     Err(RepositoryError::Conflict { current, .. }) => println!("Now at {current}"),
+    ///     Err(e) => return Err(e),
+    /// }
+    /// ```
     async fn update(
         &self,
         id: Uuid,
+        expected_version: Option<i64>,
+        input: Self::UpdateInput,
+    ) -> Result<Self::Entity, RepositoryError> {
+        let mut tx = self.context().transaction().await?;
+        let entity = self.update_in(&mut tx, id, expected_version, input).await?;
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(entity)
+    }
+
+    /// Same as `update`, but runs on the given connection.
+    async fn update_in(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+        expected_version: Option<i64>,
         input: Self::UpdateInput,
     ) -> Result<Self::Entity, RepositoryError>;
 
@@ -196,5 +313,13 @@ pub trait Repository: Send + Sync {
     /// - `Ok(true)` if the record was deleted
     /// - `Ok(false)` if no record existed with that ID
     /// - `Err(Database)` on database error
-    async fn delete(&self, id: Uuid) -> Result<bool, RepositoryError>;
+    async fn delete(&self, id: Uuid) -> Result<bool, RepositoryError> {
+        let mut tx = self.context().transaction().await?;
+        let deleted = self.delete_in(&mut tx, id).await?;
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(deleted)
+    }
+
+    /// Same as `delete`, but runs on the given connection.
+    async fn delete_in(&self, conn: &mut PgConnection, id: Uuid) -> Result<bool, RepositoryError>;
 }
@@ -0,0 +1,174 @@
+//! # Calendar Feed Repository
+//!
+//! Repository for per-user iCalendar feed settings. Each user has at most
+//! one feed; it is created on first use and its token can be rotated to
+//! cut off every existing subscription.
+
+use uuid::Uuid;
+
+use crate::models::CalendarFeed;
+
+use super::base::RepositoryContext;
+use super::error::RepositoryError;
+
+/// Repository for calendar feed database operations.
+///
+/// # Why No `Repository` Impl?
+///
+/// Feeds are keyed by user rather than managed as free-standing records,
+/// so generic create/update/delete by ID would only add ways to end up
+/// with a half-configured feed.
+pub struct CalendarFeedRepository {
+    ctx: RepositoryContext,
+}
+
+impl CalendarFeedRepository {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self { ctx }
+    }
+
+    /// Find the feed a URL token belongs to.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The secret token from the feed URL
+    ///
+    /// # Returns
+    ///
+    /// `Ok(None)` if no feed uses this token (never existed or rotated away).
+    pub async fn find_by_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<CalendarFeed>, RepositoryError> {
+        let feed = sqlx::query_as!(
+            CalendarFeed,
+            r#"
+            SELECT id, user_id, token, alarm_minutes_before, created_at, updated_at
+            FROM calendar_feeds
+            WHERE token = $1
+            "#,
+            token
+        )
+        .fetch_optional(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(feed)
+    }
+
+    /// Find a user's feed, if they have set one up.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the user
+    pub async fn find_by_user(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Option<CalendarFeed>, RepositoryError> {
+        let feed = sqlx::query_as!(
+            CalendarFeed,
+            r#"
+            SELECT id, user_id, token, alarm_minutes_before, created_at, updated_at
+            FROM calendar_feeds
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_optional(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(feed)
+    }
+
+    /// Return a user's feed, creating it (with a fresh token) if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the user
+    ///
+    /// # Errors
+    ///
+    /// Returns `RepositoryError::ForeignKeyViolation` if the user doesn't exist.
+    pub async fn get_or_create(&self, user_id: Uuid) -> Result<CalendarFeed, RepositoryError> {
+        // The no-op update makes RETURNING yield the existing row on conflict
+        let feed = sqlx::query_as!(
+            CalendarFeed,
+            r#"
+            INSERT INTO calendar_feeds (user_id)
+            VALUES ($1)
+            ON CONFLICT (user_id) DO UPDATE SET user_id = EXCLUDED.user_id
+            RETURNING id, user_id, token, alarm_minutes_before, created_at, updated_at
+            "#,
+            user_id
+        )
+        .fetch_one(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(feed)
+    }
+
+    /// Replace a user's feed token, invalidating the old URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the user
+    ///
+    /// # Errors
+    ///
+    /// Returns `RepositoryError::NotFound` if the user has no feed.
+    pub async fn rotate_token(&self, user_id: Uuid) -> Result<CalendarFeed, RepositoryError> {
+        let feed = sqlx::query_as!(
+            CalendarFeed,
+            r#"
+            UPDATE calendar_feeds
+            SET token = replace(gen_random_uuid()::text || gen_random_uuid()::text, '-', '')
+            WHERE user_id = $1
+            RETURNING id, user_id, token, alarm_minutes_before, created_at, updated_at
+            "#,
+            user_id
+        )
+        .fetch_optional(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?
+        .ok_or(RepositoryError::NotFound)?;
+
+        Ok(feed)
+    }
+
+    /// Set (or clear, with `None`) the reminder lead time for a user's feed.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the user
+    /// * `minutes_before` - Minutes before each event's start; `None` disables reminders
+    ///
+    /// # Errors
+    ///
+    /// Returns `RepositoryError::NotFound` if the user has no feed, or
+    /// `RepositoryError::Validation` if `minutes_before` is negative.
+    pub async fn set_alarm(
+        &self,
+        user_id: Uuid,
+        minutes_before: Option<i32>,
+    ) -> Result<CalendarFeed, RepositoryError> {
+        let feed = sqlx::query_as!(
+            CalendarFeed,
+            r#"
+            UPDATE calendar_feeds
+            SET alarm_minutes_before = $2
+            WHERE user_id = $1
+            RETURNING id, user_id, token, alarm_minutes_before, created_at, updated_at
+            "#,
+            user_id,
+            minutes_before
+        )
+        .fetch_optional(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?
+        .ok_or(RepositoryError::NotFound)?;
+
+        Ok(feed)
+    }
+}
@@ -0,0 +1,171 @@
+//! # CardDAV Repository
+//!
+//! Remembers the resource names CardDAV clients gave the cards they
+//! created, so the card stays at the URL the client put it at.
+
+use std::collections::HashMap;
+
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use super::base::RepositoryContext;
+use super::error::RepositoryError;
+
+/// Repository for CardDAV resource names.
+///
+/// # Default Names
+///
+/// Only client-created cards have a row. Every other friend is served as
+/// `<friend id>.vcf`, which callers work out themselves.
+pub struct CardDavRepository {
+    ctx: RepositoryContext,
+}
+
+impl CardDavRepository {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self { ctx }
+    }
+
+    /// Every stored resource name for a user, by friend ID.
+    ///
+    /// Includes names of deleted friends, which sync reports still need.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the user
+    pub async fn names_by_user(
+        &self,
+        user_id: Uuid,
+    ) -> Result<HashMap<Uuid, String>, RepositoryError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT friend_id, name
+            FROM carddav_resources
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_all(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(rows.into_iter().map(|r| (r.friend_id, r.name)).collect())
+    }
+
+    /// Record the name a client created a friend's card under.
+    ///
+    /// A stale row holding the same name (its friend was deleted, or is in
+    /// the trash) is replaced, so clients can reuse names.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to run on; pass `&mut *tx` to include it in
+    ///   the transaction that created the friend
+    /// * `user_id` - The UUID of the user
+    /// * `friend_id` - The friend the card belongs to
+    /// * `name` - The last path segment of the card's URL
+    ///
+    /// # Errors
+    ///
+    /// Returns `RepositoryError::Duplicate` if a live friend already has the name.
+    pub async fn set_name_in(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        friend_id: Uuid,
+        name: &str,
+    ) -> Result<(), RepositoryError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM carddav_resources r
+            WHERE r.user_id = $1 AND r.name = $2
+              AND NOT EXISTS (
+                  SELECT 1 FROM friends f
+                  WHERE f.id = r.friend_id AND f.deleted_at IS NULL
+              )
+            "#,
+            user_id,
+            name
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO carddav_resources (friend_id, user_id, name)
+            VALUES ($1, $2, $3)
+            "#,
+            friend_id,
+            user_id,
+            name
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{Acquire, PgPool};
+
+    use super::*;
+    use crate::repositories::{
+        CreateFriendInput, CreateUserInput, FriendRepository, Repository, UserRepository,
+    };
+
+    #[tokio::test]
+    async fn names_of_trashed_friends_can_be_reused() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is set");
+        let ctx = RepositoryContext::new(PgPool::connect(&url).await.unwrap());
+        let users = UserRepository::new(ctx.clone());
+        let friends = FriendRepository::new(ctx.clone());
+        let cards = CardDavRepository::new(ctx.clone());
+        // Never committed
+        let mut tx = ctx.transaction().await.unwrap();
+
+        let user = users
+            .create_in(
+                &mut tx,
+                CreateUserInput {
+                    first_name: "Card".to_string(),
+                    last_name: "Dav".to_string(),
+                    email: format!("{}@example.com", Uuid::now_v7()),
+                    password_hash: String::new(),
+                },
+            )
+            .await
+            .unwrap();
+        let new_friend = || CreateFriendInput {
+            user_id: user.id,
+            first_name: "Ada".to_string(),
+            last_name: None,
+            date_of_birth: None,
+            likes: None,
+            dislikes: None,
+            notes: None,
+        };
+
+        let first = friends.create_in(&mut tx, new_friend()).await.unwrap();
+        cards
+            .set_name_in(&mut tx, user.id, first.id, "ada.vcf")
+            .await
+            .unwrap();
+        let second = friends.create_in(&mut tx, new_friend()).await.unwrap();
+        let mut attempt = tx.begin().await.unwrap();
+        let taken = cards
+            .set_name_in(&mut attempt, user.id, second.id, "ada.vcf")
+            .await;
+        assert!(matches!(taken, Err(RepositoryError::Duplicate(_))));
+        attempt.rollback().await.unwrap();
+
+        assert!(friends.delete_in(&mut tx, first.id).await.unwrap());
+        cards
+            .set_name_in(&mut tx, user.id, second.id, "ada.vcf")
+            .await
+            .unwrap();
+    }
+}
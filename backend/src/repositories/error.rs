@@ -12,14 +12,18 @@ use thiserror::Error;
 /// - `NotFound` - The requested record doesn't exist
 /// - `Duplicate` - A unique constraint was violated (e.g., duplicate email)
 /// - `ForeignKeyViolation` - Referenced record doesn't exist
+/// - `Validation` - Input failed a consistency check before reaching the database
+/// - `Conflict` - An update expected a version the record has moved on from
 /// - `Database` - Generic database error
 /// - `Serialization` - JSON serialization/deserialization failed
+/// - `Internal` - Something outside the database failed (e.g., hashing)
 ///
 /// # PostgreSQL Error Codes
 ///
 /// This error type maps PostgreSQL error codes to semantic variants:
 /// - `23505` → `Duplicate` (unique_violation)
 /// - `23503` → `ForeignKeyViolation` (foreign_key_violation)
+/// - `23514` → `Validation` (check_violation)
 ///
 /// # Example
 ///
@@ -46,6 +50,16 @@ pub enum RepositoryError {
     #[error("Foreign key violation: {0}")]
     ForeignKeyViolation(String),
 
+    /// The input was rejected by a repository-level consistency check
+    /// The string describes every problem that was found
+    #[error("Validation failed: {0}")]
+    Validation(String),
+
+    /// An update was made against an outdated copy of the record
+    /// (optimistic concurrency: someone else changed it in between)
+    #[error("Version conflict: expected version {expected}, found {current}")]
+    Conflict { expected: i64, current: i64 },
+
     /// A generic database error that doesn't fit other categories
     /// Wraps the underlying SQLx error for debugging
     #[error("Database error: {0}")]
@@ -55,6 +69,11 @@ pub enum RepositoryError {
     /// This can happen when working with JSONB columns
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
+
+    /// Work a repository caller does outside the database failed, through
+    /// no fault of the input (e.g., bcrypt couldn't hash a password)
+    #[error("Internal error: {0}")]
+    Internal(String),
 }
 
 impl RepositoryError {
@@ -68,6 +87,7 @@ impl RepositoryError {
     ///
     /// - `23505` - unique_violation: Attempt to insert/update a duplicate value
     /// - `23503` - foreign_key_violation: Referenced key doesn't exist
+    /// - `23514` - check_violation: A CHECK constraint rejected the row
     ///
     /// See: https://www.postgresql.org/docs/current/errcodes-appendix.html
     ///
@@ -96,6 +116,9 @@ impl RepositoryError {
                             RepositoryError::ForeignKeyViolation(db_err.message().to_string())
                         }
 
+                        // 23514: check_violation - e.g., ended_on before started_on
+                        "23514" => RepositoryError::Validation(db_err.message().to_string()),
+
                         // Unknown error code - wrap as generic Database error
                         _ => RepositoryError::Database(err),
                     }
@@ -109,4 +132,23 @@ impl RepositoryError {
             _ => RepositoryError::Database(err),
         }
     }
+
+    /// The error for an update whose `WHERE` matched no row.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected_version` - The version the update was made against
+    /// * `current_version` - The record's version now, `None` if it
+    ///   doesn't exist
+    ///
+    /// # Returns
+    ///
+    /// `Conflict` if the record exists but at another version, otherwise
+    /// `NotFound`
+    pub fn missed_update(expected_version: Option<i64>, current_version: Option<i64>) -> Self {
+        match (expected_version, current_version) {
+            (Some(expected), Some(current)) => RepositoryError::Conflict { expected, current },
+            _ => RepositoryError::NotFound,
+        }
+    }
 }
@@ -3,7 +3,11 @@
 //! Repository for friend attribute database operations.
 //! Attributes are key-value pairs for storing custom friend data.
 
+use std::collections::{HashMap, HashSet};
+
 use async_trait::async_trait;
+use serde::Serialize;
+use sqlx::PgConnection;
 use uuid::Uuid;
 
 use crate::models::FriendAttribute;
@@ -29,6 +33,29 @@ pub struct UpdateFriendAttributeInput {
     pub value_type: Option<String>,
 }
 
+/// One attribute of the complete set given to `replace_all`.
+pub struct AttributeInput {
+    /// Attribute key (unique within the set)
+    pub key: String,
+    /// Attribute value as text
+    pub value: String,
+    /// Type hint for the value (default: "text")
+    pub value_type: Option<String>,
+}
+
+/// What `replace_all` changed, by attribute key (sorted).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AttributeChanges {
+    /// Keys the friend didn't have before
+    pub added: Vec<String>,
+    /// Keys whose value or type changed
+    pub updated: Vec<String>,
+    /// Keys missing from the new set, now deleted
+    pub removed: Vec<String>,
+    /// How many attributes were already as given
+    pub unchanged: usize,
+}
+
 /// Repository for friend attribute database operations.
 pub struct FriendAttributeRepository {
     ctx: RepositoryContext,
@@ -47,17 +74,189 @@ impl FriendAttributeRepository {
     pub async fn list_by_friend(
         &self,
         friend_id: Uuid,
+    ) -> Result<Vec<FriendAttribute>, RepositoryError> {
+        let mut conn = self
+            .ctx
+            .pool
+            .acquire()
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        self.list_by_friend_in(&mut conn, friend_id).await
+    }
+
+    /// Same as `list_by_friend`, but runs on the given connection so it
+    /// can join a transaction.
+    pub async fn list_by_friend_in(
+        &self,
+        conn: &mut PgConnection,
+        friend_id: Uuid,
     ) -> Result<Vec<FriendAttribute>, RepositoryError> {
         let attributes = sqlx::query_as!(
             FriendAttribute,
             r#"
-            SELECT id, friend_id, key, value, value_type, created_at, updated_at
+            SELECT id, friend_id, key, value, value_type, created_at, updated_at, version
             FROM friend_attributes
             WHERE friend_id = $1
             ORDER BY key ASC
             "#,
             friend_id
         )
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(attributes)
+    }
+
+    /// Make a friend's attributes exactly the given set.
+    ///
+    /// The set is compared with the friend's current attributes by key:
+    /// new keys are inserted, changed values updated and keys missing
+    /// from the set deleted, all in one transaction. Attributes that are
+    /// already as given aren't touched (and leave no history behind).
+    ///
+    /// # Arguments
+    ///
+    /// * `friend_id` - The UUID of the friend
+    /// * `attributes` - The complete set; an empty set deletes them all
+    ///
+    /// # Returns
+    ///
+    /// The keys that were added, updated and removed.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the friend doesn't exist or is in the trash
+    /// - `Validation` if a key is empty or appears more than once;
+    ///   nothing is changed then
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let changes = attributes
+    ///     .replace_all(friend.id, vec![AttributeInput {
+    ///         key: "email".to_string(),
+    ///         value: "ada@example.com".to_string(),
+    ///         value_type: Some("email".to_string()),
+    ///     }])
+    ///     .await?;
+    /// println!("removed: {:?}", changes.removed);
+    /// ```
+    pub async fn replace_all(
+        &self,
+        friend_id: Uuid,
+        attributes: Vec<AttributeInput>,
+    ) -> Result<AttributeChanges, RepositoryError> {
+        let mut tx = self.ctx.transaction().await?;
+        let changes = self.replace_all_in(&mut tx, friend_id, attributes).await?;
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(changes)
+    }
+
+    /// Same as `replace_all`, but runs on the given connection so it can
+    /// join a transaction.
+    pub async fn replace_all_in(
+        &self,
+        conn: &mut PgConnection,
+        friend_id: Uuid,
+        attributes: Vec<AttributeInput>,
+    ) -> Result<AttributeChanges, RepositoryError> {
+        let mut problems = Vec::new();
+        let mut seen = HashSet::new();
+        for attribute in &attributes {
+            let problem = if attribute.key.trim().is_empty() {
+                "attribute key is required".to_string()
+            } else if !seen.insert(attribute.key.as_str()) {
+                format!("attribute key \"{}\" appears more than once", attribute.key)
+            } else {
+                continue;
+            };
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+        if !problems.is_empty() {
+            return Err(RepositoryError::Validation(problems.join("; ")));
+        }
+
+        // Locking the friend keeps two replacements from interleaving
+        sqlx::query_scalar!(
+            "SELECT id FROM friends WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+            friend_id
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?
+        .ok_or(RepositoryError::NotFound)?;
+
+        let mut current: HashMap<String, FriendAttribute> = self
+            .list_by_friend_in(&mut *conn, friend_id)
+            .await?
+            .into_iter()
+            .map(|attribute| (attribute.key.clone(), attribute))
+            .collect();
+
+        let mut changes = AttributeChanges::default();
+        for attribute in attributes {
+            let value_type = attribute.value_type.unwrap_or_else(|| "text".to_string());
+            match current.remove(&attribute.key) {
+                Some(existing)
+                    if existing.value == attribute.value && existing.value_type == value_type =>
+                {
+                    changes.unchanged += 1;
+                    continue;
+                }
+                Some(_) => changes.updated.push(attribute.key.clone()),
+                None => changes.added.push(attribute.key.clone()),
+            }
+            self.upsert_in(
+                &mut *conn,
+                CreateFriendAttributeInput {
+                    friend_id,
+                    key: attribute.key,
+                    value: attribute.value,
+                    value_type: Some(value_type),
+                },
+            )
+            .await?;
+        }
+
+        // Whatever wasn't in the new set
+        for (key, attribute) in current {
+            self.delete_in(&mut *conn, attribute.id).await?;
+            changes.removed.push(key);
+        }
+
+        changes.added.sort();
+        changes.updated.sort();
+        changes.removed.sort();
+        Ok(changes)
+    }
+
+    /// List the attributes of every friend a user owns.
+    ///
+    /// One query instead of a `list_by_friend` call per friend, for bulk
+    /// work like matching imported records against existing friends.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the owning user
+    pub async fn list_by_user(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<FriendAttribute>, RepositoryError> {
+        let attributes = sqlx::query_as!(
+            FriendAttribute,
+            r#"
+            SELECT a.id, a.friend_id, a.key, a.value, a.value_type, a.created_at, a.updated_at,
+                   a.version
+            FROM friend_attributes a
+            JOIN friends f ON f.id = a.friend_id
+            WHERE f.user_id = $1 AND f.deleted_at IS NULL
+            ORDER BY a.friend_id, a.key ASC
+            "#,
+            user_id
+        )
         .fetch_all(&self.ctx.pool)
         .await
         .map_err(RepositoryError::from_sqlx)?;
@@ -82,7 +281,7 @@ impl FriendAttributeRepository {
         let attribute = sqlx::query_as!(
             FriendAttribute,
             r#"
-            SELECT id, friend_id, key, value, value_type, created_at, updated_at
+            SELECT id, friend_id, key, value, value_type, created_at, updated_at, version
             FROM friend_attributes
             WHERE friend_id = $1 AND key = $2
             "#,
@@ -107,6 +306,22 @@ impl FriendAttributeRepository {
     pub async fn upsert(
         &self,
         input: CreateFriendAttributeInput,
+    ) -> Result<FriendAttribute, RepositoryError> {
+        let mut conn = self
+            .ctx
+            .pool
+            .acquire()
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        self.upsert_in(&mut conn, input).await
+    }
+
+    /// Same as `upsert`, but runs on the given connection so it can join a
+    /// transaction.
+    pub async fn upsert_in(
+        &self,
+        conn: &mut PgConnection,
+        input: CreateFriendAttributeInput,
     ) -> Result<FriendAttribute, RepositoryError> {
         // ON CONFLICT ... DO UPDATE is PostgreSQL's upsert syntax.
         // It inserts if no conflict, or updates if there's a duplicate key.
@@ -119,14 +334,14 @@ impl FriendAttributeRepository {
             VALUES ($1, $2, $3, $4)
             ON CONFLICT (friend_id, key) DO UPDATE
             SET value = EXCLUDED.value, value_type = EXCLUDED.value_type
-            RETURNING id, friend_id, key, value, value_type, created_at, updated_at
+            RETURNING id, friend_id, key, value, value_type, created_at, updated_at, version
             "#,
             input.friend_id,
             input.key,
             input.value,
             value_type
         )
-        .fetch_one(&self.ctx.pool)
+        .fetch_one(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
@@ -140,25 +355,34 @@ impl Repository for FriendAttributeRepository {
     type CreateInput = CreateFriendAttributeInput;
     type UpdateInput = UpdateFriendAttributeInput;
 
-    async fn find_by_id(&self, id: Uuid) -> Result<Option<FriendAttribute>, RepositoryError> {
+    fn context(&self) -> &RepositoryContext {
+        &self.ctx
+    }
+
+    async fn find_by_id_in(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+    ) -> Result<Option<FriendAttribute>, RepositoryError> {
         let attribute = sqlx::query_as!(
             FriendAttribute,
             r#"
-            SELECT id, friend_id, key, value, value_type, created_at, updated_at
+            SELECT id, friend_id, key, value, value_type, created_at, updated_at, version
             FROM friend_attributes
             WHERE id = $1
             "#,
             id
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
         Ok(attribute)
     }
 
-    async fn create(
+    async fn create_in(
         &self,
+        conn: &mut PgConnection,
         input: CreateFriendAttributeInput,
     ) -> Result<FriendAttribute, RepositoryError> {
         let value_type = input.value_type.unwrap_or_else(|| "text".to_string());
@@ -168,23 +392,25 @@ impl Repository for FriendAttributeRepository {
             r#"
             INSERT INTO friend_attributes (friend_id, key, value, value_type)
             VALUES ($1, $2, $3, $4)
-            RETURNING id, friend_id, key, value, value_type, created_at, updated_at
+            RETURNING id, friend_id, key, value, value_type, created_at, updated_at, version
             "#,
             input.friend_id,
             input.key,
             input.value,
             value_type
         )
-        .fetch_one(&self.ctx.pool)
+        .fetch_one(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
         Ok(attribute)
     }
 
-    async fn update(
+    async fn update_in(
         &self,
+        conn: &mut PgConnection,
         id: Uuid,
+        expected_version: Option<i64>,
         input: UpdateFriendAttributeInput,
     ) -> Result<FriendAttribute, RepositoryError> {
         let attribute = sqlx::query_as!(
@@ -194,22 +420,31 @@ impl Repository for FriendAttributeRepository {
             SET
                 value = COALESCE($2, value),
                 value_type = COALESCE($3, value_type)
-            WHERE id = $1
-            RETURNING id, friend_id, key, value, value_type, created_at, updated_at
+            WHERE id = $1 AND ($4::bigint IS NULL OR version = $4)
+            RETURNING id, friend_id, key, value, value_type, created_at, updated_at, version
             "#,
             id,
             input.value,
-            input.value_type
+            input.value_type,
+            expected_version
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
-        .map_err(RepositoryError::from_sqlx)?
-        .ok_or(RepositoryError::NotFound)?;
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let Some(attribute) = attribute else {
+            let current =
+                sqlx::query_scalar!("SELECT version FROM friend_attributes WHERE id = $1", id)
+                    .fetch_optional(&mut *conn)
+                    .await
+                    .map_err(RepositoryError::from_sqlx)?;
+            return Err(RepositoryError::missed_update(expected_version, current));
+        };
 
         Ok(attribute)
     }
 
-    async fn delete(&self, id: Uuid) -> Result<bool, RepositoryError> {
+    async fn delete_in(&self, conn: &mut PgConnection, id: Uuid) -> Result<bool, RepositoryError> {
         let result = sqlx::query!(
             r#"
             DELETE FROM friend_attributes
@@ -217,7 +452,7 @@ impl Repository for FriendAttributeRepository {
             "#,
             id
         )
-        .execute(&self.ctx.pool)
+        .execute(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
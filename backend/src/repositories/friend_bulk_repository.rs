@@ -0,0 +1,506 @@
+//! # Friend Bulk Repository
+//!
+//! Batch versions of the everyday friend operations, for organizing many
+//! friends at once:
+//!
+//! | Operation | Single-friend equivalent |
+//! |-----------|--------------------------|
+//! | `add_to_group` | `FriendRepository::add_to_group` |
+//! | `remove_from_group` | `FriendRepository::remove_from_group` |
+//! | `set_attribute` | `FriendAttributeRepository::upsert` |
+//! | `delete` | `Repository::delete` (to the trash) |
+//! | `create` | `Repository::create` |
+//!
+//! ## Per-Item Results
+//!
+//! A batch runs in one transaction (and so is a single undo step), but a
+//! bad item doesn't sink the others: each item runs in a savepoint, and
+//! the report says for every item whether it changed something, was
+//! already as asked, or failed and why. Only database failures abort the
+//! whole batch.
+//!
+//! Friends that don't exist, are in the trash or belong to another user
+//! fail with "Record not found", one by one.
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+use sqlx::{Connection, PgConnection, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::base::{Repository, RepositoryContext};
+use super::error::RepositoryError;
+use super::friend_attribute_repository::{CreateFriendAttributeInput, FriendAttributeRepository};
+use super::friend_repository::{CreateFriendInput, FriendRepository};
+
+/// Most items one batch may have.
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+/// What happened to one item of a batch.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BulkOutcome {
+    /// The item was applied
+    Changed,
+    /// Nothing to do (e.g. already in the group)
+    Unchanged,
+    /// The item was skipped; the rest of the batch went ahead
+    Failed { error: String },
+}
+
+/// The result for one item, in the order the items were given.
+#[derive(Debug, Clone, Serialize)]
+pub struct BulkItemResult {
+    /// Position of the item in the batch
+    pub index: usize,
+    /// The friend the item is about (the new friend's ID for creates;
+    /// absent if a create failed)
+    pub friend_id: Option<Uuid>,
+    #[serde(flatten)]
+    pub outcome: BulkOutcome,
+}
+
+/// What a batch did.
+#[derive(Debug, Clone, Serialize)]
+pub struct BulkReport {
+    pub changed: usize,
+    pub unchanged: usize,
+    pub failed: usize,
+    pub items: Vec<BulkItemResult>,
+}
+
+impl BulkReport {
+    fn new(items: Vec<BulkItemResult>) -> Self {
+        let count = |f: fn(&BulkOutcome) -> bool| items.iter().filter(|i| f(&i.outcome)).count();
+        Self {
+            changed: count(|o| *o == BulkOutcome::Changed),
+            unchanged: count(|o| *o == BulkOutcome::Unchanged),
+            failed: count(|o| matches!(o, BulkOutcome::Failed { .. })),
+            items,
+        }
+    }
+}
+
+/// The attribute `set_attribute` gives every friend.
+pub struct BulkAttributeInput {
+    pub key: String,
+    pub value: String,
+    /// Type hint for the value (default: "text")
+    pub value_type: Option<String>,
+}
+
+/// Repository for batch operations on a user's friends.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let report = bulk.add_to_group(user.id, book_club.id, &friend_ids).await?;
+/// println!("{} added, {} already members", report.changed, report.unchanged);
+/// ```
+pub struct FriendBulkRepository {
+    ctx: RepositoryContext,
+    friends: FriendRepository,
+    attributes: FriendAttributeRepository,
+}
+
+impl FriendBulkRepository {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            friends: FriendRepository::new(ctx.clone()),
+            attributes: FriendAttributeRepository::new(ctx.clone()),
+            ctx,
+        }
+    }
+
+    /// Add many friends to one of the user's groups.
+    ///
+    /// Friends already in the group are `Unchanged`.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the group doesn't exist or isn't the user's
+    /// - `Validation` if there are more than `MAX_BATCH_SIZE` friends
+    pub async fn add_to_group(
+        &self,
+        user_id: Uuid,
+        group_id: Uuid,
+        friend_ids: &[Uuid],
+    ) -> Result<BulkReport, RepositoryError> {
+        check_size(friend_ids.len())?;
+        let mut tx = self.ctx.transaction().await?;
+        check_group(&mut tx, user_id, group_id).await?;
+        let owned = owned_friends(&mut tx, user_id, friend_ids).await?;
+        let mut members = members(&mut tx, group_id, friend_ids).await?;
+
+        let mut items = Vec::with_capacity(friend_ids.len());
+        for (index, &friend_id) in friend_ids.iter().enumerate() {
+            let outcome = if !owned.contains(&friend_id) {
+                failed(RepositoryError::NotFound)
+            } else if !members.insert(friend_id) {
+                BulkOutcome::Unchanged
+            } else {
+                let mut savepoint = savepoint(&mut tx).await?;
+                let result = self
+                    .friends
+                    .add_to_group_in(&mut savepoint, friend_id, group_id)
+                    .await
+                    .map(|()| BulkOutcome::Changed);
+                settle(savepoint, result).await?
+            };
+            items.push(BulkItemResult {
+                index,
+                friend_id: Some(friend_id),
+                outcome,
+            });
+        }
+
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(BulkReport::new(items))
+    }
+
+    /// Remove many friends from one of the user's groups.
+    ///
+    /// Friends that weren't in the group are `Unchanged`.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the group doesn't exist or isn't the user's
+    /// - `Validation` if there are more than `MAX_BATCH_SIZE` friends
+    pub async fn remove_from_group(
+        &self,
+        user_id: Uuid,
+        group_id: Uuid,
+        friend_ids: &[Uuid],
+    ) -> Result<BulkReport, RepositoryError> {
+        check_size(friend_ids.len())?;
+        let mut tx = self.ctx.transaction().await?;
+        check_group(&mut tx, user_id, group_id).await?;
+        let owned = owned_friends(&mut tx, user_id, friend_ids).await?;
+
+        let mut items = Vec::with_capacity(friend_ids.len());
+        for (index, &friend_id) in friend_ids.iter().enumerate() {
+            let outcome = if !owned.contains(&friend_id) {
+                failed(RepositoryError::NotFound)
+            } else {
+                let mut savepoint = savepoint(&mut tx).await?;
+                let result = self
+                    .friends
+                    .remove_from_group_in(&mut savepoint, friend_id, group_id)
+                    .await
+                    .map(changed_if);
+                settle(savepoint, result).await?
+            };
+            items.push(BulkItemResult {
+                index,
+                friend_id: Some(friend_id),
+                outcome,
+            });
+        }
+
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(BulkReport::new(items))
+    }
+
+    /// Give many friends the same attribute, replacing the value of any
+    /// they already have under that key.
+    ///
+    /// Friends that already have exactly this value and type are
+    /// `Unchanged`.
+    ///
+    /// # Errors
+    ///
+    /// - `Validation` if the key is empty or there are more than
+    ///   `MAX_BATCH_SIZE` friends
+    pub async fn set_attribute(
+        &self,
+        user_id: Uuid,
+        friend_ids: &[Uuid],
+        attribute: BulkAttributeInput,
+    ) -> Result<BulkReport, RepositoryError> {
+        check_size(friend_ids.len())?;
+        let key = attribute.key.trim();
+        if key.is_empty() {
+            return Err(RepositoryError::Validation(
+                "attribute key is required".to_string(),
+            ));
+        }
+        let value_type = attribute.value_type.unwrap_or_else(|| "text".to_string());
+
+        let mut tx = self.ctx.transaction().await?;
+        let owned = owned_friends(&mut tx, user_id, friend_ids).await?;
+
+        let mut items = Vec::with_capacity(friend_ids.len());
+        for (index, &friend_id) in friend_ids.iter().enumerate() {
+            let outcome = if !owned.contains(&friend_id) {
+                failed(RepositoryError::NotFound)
+            } else {
+                let mut savepoint = savepoint(&mut tx).await?;
+                let result = self
+                    .set_attribute_in(
+                        &mut savepoint,
+                        friend_id,
+                        key,
+                        &attribute.value,
+                        &value_type,
+                    )
+                    .await;
+                settle(savepoint, result).await?
+            };
+            items.push(BulkItemResult {
+                index,
+                friend_id: Some(friend_id),
+                outcome,
+            });
+        }
+
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(BulkReport::new(items))
+    }
+
+    /// Move many friends to the trash.
+    ///
+    /// A friend listed twice is `Unchanged` the second time.
+    ///
+    /// # Errors
+    ///
+    /// - `Validation` if there are more than `MAX_BATCH_SIZE` friends
+    pub async fn delete(
+        &self,
+        user_id: Uuid,
+        friend_ids: &[Uuid],
+    ) -> Result<BulkReport, RepositoryError> {
+        check_size(friend_ids.len())?;
+        let mut tx = self.ctx.transaction().await?;
+        let owned = owned_friends(&mut tx, user_id, friend_ids).await?;
+
+        let mut items = Vec::with_capacity(friend_ids.len());
+        for (index, &friend_id) in friend_ids.iter().enumerate() {
+            let outcome = if !owned.contains(&friend_id) {
+                failed(RepositoryError::NotFound)
+            } else {
+                let mut savepoint = savepoint(&mut tx).await?;
+                let result = self
+                    .friends
+                    .delete_in(&mut savepoint, friend_id)
+                    .await
+                    .map(changed_if);
+                settle(savepoint, result).await?
+            };
+            items.push(BulkItemResult {
+                index,
+                friend_id: Some(friend_id),
+                outcome,
+            });
+        }
+
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(BulkReport::new(items))
+    }
+
+    /// Create many friends.
+    ///
+    /// Friends without a first name fail with `Validation`; the others
+    /// are created and their new IDs reported.
+    ///
+    /// # Errors
+    ///
+    /// - `Validation` if there are more than `MAX_BATCH_SIZE` friends
+    pub async fn create(
+        &self,
+        inputs: Vec<CreateFriendInput>,
+    ) -> Result<BulkReport, RepositoryError> {
+        check_size(inputs.len())?;
+        let mut tx = self.ctx.transaction().await?;
+
+        let mut items = Vec::with_capacity(inputs.len());
+        for (index, input) in inputs.into_iter().enumerate() {
+            if input.first_name.trim().is_empty() {
+                items.push(BulkItemResult {
+                    index,
+                    friend_id: None,
+                    outcome: failed(RepositoryError::Validation(
+                        "first name is required".to_string(),
+                    )),
+                });
+                continue;
+            }
+
+            let mut savepoint = savepoint(&mut tx).await?;
+            let result = self.friends.create_in(&mut savepoint, input).await;
+            let friend_id = result.as_ref().ok().map(|friend| friend.id);
+            let outcome = settle(savepoint, result.map(|_| BulkOutcome::Changed)).await?;
+            items.push(BulkItemResult {
+                index,
+                friend_id,
+                outcome,
+            });
+        }
+
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(BulkReport::new(items))
+    }
+
+    /// Upsert one friend's attribute, skipping the write if the value is
+    /// already there (so no-op items leave no history behind).
+    async fn set_attribute_in(
+        &self,
+        conn: &mut PgConnection,
+        friend_id: Uuid,
+        key: &str,
+        value: &str,
+        value_type: &str,
+    ) -> Result<BulkOutcome, RepositoryError> {
+        let current = sqlx::query!(
+            r#"
+            SELECT value, value_type
+            FROM friend_attributes
+            WHERE friend_id = $1 AND key = $2
+            "#,
+            friend_id,
+            key
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        if current.is_some_and(|row| row.value == value && row.value_type == value_type) {
+            return Ok(BulkOutcome::Unchanged);
+        }
+
+        self.attributes
+            .upsert_in(
+                conn,
+                CreateFriendAttributeInput {
+                    friend_id,
+                    key: key.to_string(),
+                    value: value.to_string(),
+                    value_type: Some(value_type.to_string()),
+                },
+            )
+            .await?;
+        Ok(BulkOutcome::Changed)
+    }
+}
+
+fn check_size(len: usize) -> Result<(), RepositoryError> {
+    if len > MAX_BATCH_SIZE {
+        return Err(RepositoryError::Validation(format!(
+            "a batch can have at most {MAX_BATCH_SIZE} items, not {len}"
+        )));
+    }
+    Ok(())
+}
+
+/// `NotFound` unless the group exists and is the user's.
+async fn check_group(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    group_id: Uuid,
+) -> Result<(), RepositoryError> {
+    sqlx::query_scalar!(
+        "SELECT id FROM groups WHERE id = $1 AND user_id = $2",
+        group_id,
+        user_id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(RepositoryError::from_sqlx)?
+    .ok_or(RepositoryError::NotFound)?;
+    Ok(())
+}
+
+/// Which of the friends are the user's and not in the trash.
+async fn owned_friends(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    friend_ids: &[Uuid],
+) -> Result<HashSet<Uuid>, RepositoryError> {
+    let ids = sqlx::query_scalar!(
+        r#"
+        SELECT id
+        FROM friends
+        WHERE id = ANY($1) AND user_id = $2 AND deleted_at IS NULL
+        "#,
+        friend_ids,
+        user_id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(RepositoryError::from_sqlx)?;
+
+    Ok(ids.into_iter().collect())
+}
+
+/// Which of the friends are already in the group.
+async fn members(
+    conn: &mut PgConnection,
+    group_id: Uuid,
+    friend_ids: &[Uuid],
+) -> Result<HashSet<Uuid>, RepositoryError> {
+    let ids = sqlx::query_scalar!(
+        r#"
+        SELECT friend_id
+        FROM friend_groups
+        WHERE group_id = $1 AND friend_id = ANY($2)
+        "#,
+        group_id,
+        friend_ids
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(RepositoryError::from_sqlx)?;
+
+    Ok(ids.into_iter().collect())
+}
+
+/// Start a savepoint for one item within the batch's transaction.
+async fn savepoint<'c>(
+    conn: &'c mut PgConnection,
+) -> Result<Transaction<'c, Postgres>, RepositoryError> {
+    conn.begin().await.map_err(RepositoryError::from_sqlx)
+}
+
+/// Keep an item's changes if it succeeded; roll them back and report the
+/// failure if it was the item's fault. Database failures abort the batch.
+async fn settle(
+    savepoint: Transaction<'_, Postgres>,
+    result: Result<BulkOutcome, RepositoryError>,
+) -> Result<BulkOutcome, RepositoryError> {
+    match result {
+        Ok(outcome) => {
+            savepoint
+                .commit()
+                .await
+                .map_err(RepositoryError::from_sqlx)?;
+            Ok(outcome)
+        }
+        Err(
+            e @ (RepositoryError::NotFound
+            | RepositoryError::Duplicate(_)
+            | RepositoryError::ForeignKeyViolation(_)
+            | RepositoryError::Validation(_)
+            | RepositoryError::Conflict { .. }),
+        ) => {
+            savepoint
+                .rollback()
+                .await
+                .map_err(RepositoryError::from_sqlx)?;
+            Ok(failed(e))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn failed(error: RepositoryError) -> BulkOutcome {
+    BulkOutcome::Failed {
+        error: error.to_string(),
+    }
+}
+
+fn changed_if(changed: bool) -> BulkOutcome {
+    if changed {
+        BulkOutcome::Changed
+    } else {
+        BulkOutcome::Unchanged
+    }
+}
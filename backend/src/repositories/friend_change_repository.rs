@@ -0,0 +1,94 @@
+//! # Friend Change Repository
+//!
+//! Reads the `friend_changes` log that database triggers append to
+//! whenever anything on a friend's card changes. Sync protocols use it to
+//! answer "what changed since position n?".
+
+use uuid::Uuid;
+
+use super::base::RepositoryContext;
+use super::error::RepositoryError;
+
+/// Repository for the friend change log.
+///
+/// # Positions
+///
+/// Positions are log IDs. The triggers make a user's IDs become visible
+/// in increasing order, so a position handed out once never has changes
+/// appear before it later. Position 0 means "before anything".
+///
+/// # Deletions
+///
+/// The log doesn't say what kind of change happened. A friend that
+/// changed and no longer exists was deleted.
+pub struct FriendChangeRepository {
+    ctx: RepositoryContext,
+}
+
+impl FriendChangeRepository {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self { ctx }
+    }
+
+    /// The latest position in a user's log.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the user
+    ///
+    /// # Returns
+    ///
+    /// 0 if nothing has been logged for the user yet.
+    pub async fn current_position(&self, user_id: Uuid) -> Result<i64, RepositoryError> {
+        let position = sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(MAX(id), 0) AS "position!"
+            FROM friend_changes
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_one(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(position)
+    }
+
+    /// Friends that changed after one position, up to and including another.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the user
+    /// * `after` - A position from an earlier `current_position`
+    /// * `up_to` - A later position; changes past it are left for next time
+    ///
+    /// # Returns
+    ///
+    /// Friend IDs, each listed once, in order of their latest change.
+    /// Includes IDs of friends that have since been deleted.
+    pub async fn changed_between(
+        &self,
+        user_id: Uuid,
+        after: i64,
+        up_to: i64,
+    ) -> Result<Vec<Uuid>, RepositoryError> {
+        let friend_ids = sqlx::query_scalar!(
+            r#"
+            SELECT friend_id
+            FROM friend_changes
+            WHERE user_id = $1 AND id > $2 AND id <= $3
+            GROUP BY friend_id
+            ORDER BY MAX(id) ASC
+            "#,
+            user_id,
+            after,
+            up_to
+        )
+        .fetch_all(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(friend_ids)
+    }
+}
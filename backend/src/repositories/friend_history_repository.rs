@@ -0,0 +1,176 @@
+//! # Friend History Repository
+//!
+//! Reads the append-only `friend_history` table that database triggers
+//! write on every change to a friend, their attributes, their group
+//! memberships and their relationships, and replays it to show a friend
+//! as they were at any moment.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::models::FriendHistoryEntry;
+
+use super::base::RepositoryContext;
+use super::error::RepositoryError;
+
+/// A friend as they were at some moment, rebuilt from their history.
+///
+/// Every value is a row as recorded in the history (see
+/// `FriendHistoryEntry` for the JSON shape). Lists are in the order the
+/// rows were last changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FriendAsOf {
+    /// The moment this view reconstructs
+    pub as_of: OffsetDateTime,
+    /// The `friends` row; `deleted_at` is set if they were in the trash
+    pub friend: Value,
+    pub attributes: Vec<Value>,
+    /// Membership rows, each with the group's `group_name` at the time
+    pub groups: Vec<Value>,
+    /// Relationships with other friends, from either side
+    pub friend_relationships: Vec<Value>,
+    /// The user's own relationships with the friend
+    pub user_friend_relationships: Vec<Value>,
+}
+
+/// Repository for friend history.
+///
+/// # Why No Writes?
+///
+/// The history is written by triggers (migration 006), so it also covers
+/// changes that bypass the repositories, and the table rejects updates
+/// and deletes. There is nothing for application code to write.
+///
+/// # Coverage
+///
+/// Recording started with migration 006, which stored every row that
+/// existed then as a "BASELINE" entry. Earlier moments have no history,
+/// so views as of them find nothing.
+pub struct FriendHistoryRepository {
+    ctx: RepositoryContext,
+}
+
+impl FriendHistoryRepository {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self { ctx }
+    }
+
+    /// List every recorded change to a friend, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the owning user
+    /// * `friend_id` - The UUID of the friend
+    ///
+    /// # Returns
+    ///
+    /// An empty list if the friend doesn't belong to the user (or never
+    /// existed).
+    pub async fn list_by_friend(
+        &self,
+        user_id: Uuid,
+        friend_id: Uuid,
+    ) -> Result<Vec<FriendHistoryEntry>, RepositoryError> {
+        let entries = sqlx::query_as!(
+            FriendHistoryEntry,
+            r#"
+            SELECT id, user_id, friend_id, table_name, row_key, operation,
+                   before, after, changed_at
+            FROM friend_history
+            WHERE user_id = $1 AND friend_id = $2
+            ORDER BY id ASC
+            "#,
+            user_id,
+            friend_id
+        )
+        .fetch_all(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(entries)
+    }
+
+    /// Reconstruct a friend as they were at a moment.
+    ///
+    /// Takes the latest recorded version of each row up to `at`, dropping
+    /// rows whose latest change was a delete.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the owning user
+    /// * `friend_id` - The UUID of the friend
+    /// * `at` - The moment to reconstruct
+    ///
+    /// # Returns
+    ///
+    /// `Ok(None)` if the friend didn't exist at that moment (not yet
+    /// created, already purged, before history recording began, or not
+    /// the user's).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let last_new_year = datetime!(2025-01-01 0:00 UTC);
+    /// if let Some(then) = history.as_of(user.id, friend.id, last_new_year).await? {
+    ///     println!("Notes back then: {}", then.friend["notes"]);
+    /// }
+    /// ```
+    pub async fn as_of(
+        &self,
+        user_id: Uuid,
+        friend_id: Uuid,
+        at: OffsetDateTime,
+    ) -> Result<Option<FriendAsOf>, RepositoryError> {
+        // The latest entry per row, then back into change order
+        let rows = sqlx::query!(
+            r#"
+            SELECT table_name, after
+            FROM (
+                SELECT DISTINCT ON (table_name, row_key) id, table_name, after
+                FROM friend_history
+                WHERE user_id = $1 AND friend_id = $2 AND changed_at <= $3
+                ORDER BY table_name, row_key, id DESC
+            ) latest
+            ORDER BY id ASC
+            "#,
+            user_id,
+            friend_id,
+            at
+        )
+        .fetch_all(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let mut friend = None;
+        let mut attributes = Vec::new();
+        let mut groups = Vec::new();
+        let mut friend_relationships = Vec::new();
+        let mut user_friend_relationships = Vec::new();
+
+        for row in rows {
+            // A delete leaves no row behind
+            let Some(after) = row.after else {
+                continue;
+            };
+            match row.table_name.as_str() {
+                "friends" => friend = Some(after),
+                "friend_attributes" => attributes.push(after),
+                "friend_groups" => groups.push(after),
+                "friend_relationships" => friend_relationships.push(after),
+                "user_friend_relationships" => user_friend_relationships.push(after),
+                _ => {}
+            }
+        }
+
+        Ok(friend.map(|friend| FriendAsOf {
+            as_of: at,
+            friend,
+            attributes,
+            groups,
+            friend_relationships,
+            user_friend_relationships,
+        }))
+    }
+}
@@ -0,0 +1,407 @@
+//! # Friend Merge Repository
+//!
+//! Combines two friends that turned out to be the same person (typically
+//! two imports of one contact) into one.
+//!
+//! ## What Happens to the Other Friend
+//!
+//! | Data | Result |
+//! |------|--------|
+//! | Last name, birthday | Fill in blanks on the kept friend |
+//! | Likes, dislikes, notes | Appended to the kept friend's, if different |
+//! | Attributes | Moved over; same-key conflicts follow `AttributeChoice` |
+//! | Groups | Union of both friends' groups |
+//! | Relationships | Re-pointed to the kept friend (see below) |
+//! | The friend itself | Deleted |
+//!
+//! Relationships between the two friends would become self-relationships
+//! and are dropped, as are relationships with someone the kept friend is
+//! already related to (only one relationship per pair is allowed). The
+//! user's own relationships with the other friend move over, except for
+//! types the user already has with the kept friend.
+//!
+//! Everything runs in one transaction, so a merge is also a single undo
+//! step.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::models::{Friend, FriendAttribute};
+
+use super::base::{Repository, RepositoryContext};
+use super::error::RepositoryError;
+use super::friend_repository::{FriendRepository, UpdateFriendInput};
+
+/// How to resolve an attribute key both friends have, with different
+/// values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttributeChoice {
+    /// Keep both: the other friend's value moves over under a numbered
+    /// key (`phone#2`)
+    #[default]
+    Both,
+    /// Keep the kept friend's value
+    Keep,
+    /// Take the other friend's value
+    Other,
+}
+
+/// Input for merging two friends.
+pub struct MergeFriendsInput {
+    /// The friend that remains
+    pub keep_id: Uuid,
+    /// The friend merged into it, then deleted
+    pub other_id: Uuid,
+    /// Choices for conflicting attribute keys; keys not listed get
+    /// `AttributeChoice::Both`
+    pub attribute_choices: HashMap<String, AttributeChoice>,
+}
+
+/// What a merge did.
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeReport {
+    /// The merged friend
+    pub friend: Friend,
+    /// Attributes moved to the kept friend (including renamed ones)
+    pub attributes_moved: usize,
+    /// Kept friend's attributes overwritten by the other's value
+    pub attributes_replaced: usize,
+    /// Other friend's attributes dropped (same value, or `Keep`)
+    pub attributes_dropped: usize,
+    /// Groups the kept friend joined
+    pub groups_added: usize,
+    /// Relationships re-pointed to the kept friend
+    pub relationships_moved: usize,
+    /// Relationships dropped as self-relationships or duplicate pairs
+    pub relationships_dropped: usize,
+    /// The user's own relationships re-pointed to the kept friend
+    pub user_relationships_moved: usize,
+    /// The user's own relationships dropped because the kept friend
+    /// already has one of that type
+    pub user_relationships_dropped: usize,
+}
+
+/// Repository for merging friends.
+pub struct FriendMergeRepository {
+    ctx: RepositoryContext,
+}
+
+impl FriendMergeRepository {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self { ctx }
+    }
+
+    /// Merge one friend into another, in a transaction of its own.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if either friend doesn't exist or is in the trash
+    /// - `Validation` if they are the same friend or belong to different
+    ///   users
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let report = merges
+    ///     .merge(MergeFriendsInput {
+    ///         keep_id: bob.id,
+    ///         other_id: robert.id,
+    ///         attribute_choices: HashMap::from([("phone".into(), AttributeChoice::Other)]),
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn merge(&self, input: MergeFriendsInput) -> Result<MergeReport, RepositoryError> {
+        let mut tx = self.ctx.transaction().await?;
+        let report = self.merge_in(&mut tx, input).await?;
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(report)
+    }
+
+    /// Same as `merge`, but runs on the given connection so it can join a
+    /// transaction.
+    pub async fn merge_in(
+        &self,
+        conn: &mut PgConnection,
+        input: MergeFriendsInput,
+    ) -> Result<MergeReport, RepositoryError> {
+        let (keep_id, other_id) = (input.keep_id, input.other_id);
+        if keep_id == other_id {
+            return Err(RepositoryError::Validation(
+                "a friend can't be merged with themselves".to_string(),
+            ));
+        }
+
+        let friends = sqlx::query_as!(
+            Friend,
+            r#"
+            SELECT id, user_id, first_name, last_name, date_of_birth,
+                   likes, dislikes, notes, created_at, updated_at, version
+            FROM friends
+            WHERE id = ANY($1) AND deleted_at IS NULL
+            FOR UPDATE
+            "#,
+            &[keep_id, other_id][..]
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let find = |id| friends.iter().find(|f| f.id == id);
+        let (Some(keep), Some(other)) = (find(keep_id), find(other_id)) else {
+            return Err(RepositoryError::NotFound);
+        };
+        if keep.user_id != other.user_id {
+            return Err(RepositoryError::Validation(
+                "friends of different users can't be merged".to_string(),
+            ));
+        }
+
+        let mut report = MergeReport {
+            friend: keep.clone(),
+            attributes_moved: 0,
+            attributes_replaced: 0,
+            attributes_dropped: 0,
+            groups_added: 0,
+            relationships_moved: 0,
+            relationships_dropped: 0,
+            user_relationships_moved: 0,
+            user_relationships_dropped: 0,
+        };
+
+        self.merge_attributes(conn, &input, &mut report).await?;
+
+        let update = UpdateFriendInput {
+            first_name: None,
+            last_name: keep
+                .last_name
+                .clone()
+                .or_else(|| other.last_name.clone())
+                .map(Some),
+            date_of_birth: keep.date_of_birth.or(other.date_of_birth).map(Some),
+            likes: combine(&keep.likes, &other.likes).map(Some),
+            dislikes: combine(&keep.dislikes, &other.dislikes).map(Some),
+            notes: combine(&keep.notes, &other.notes).map(Some),
+        };
+
+        report.groups_added = sqlx::query!(
+            r#"
+            INSERT INTO friend_groups (friend_id, group_id)
+            SELECT $1, group_id FROM friend_groups WHERE friend_id = $2
+            ON CONFLICT DO NOTHING
+            "#,
+            keep_id,
+            other_id
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?
+        .rows_affected() as usize;
+
+        // Relationships with each other, or with someone the kept friend is
+        // already related to, have no place to go
+        report.relationships_dropped = sqlx::query!(
+            r#"
+            DELETE FROM friend_relationships r
+            WHERE (r.friend_a_id = $2 OR r.friend_b_id = $2)
+              AND (
+                  r.friend_a_id = $1 OR r.friend_b_id = $1
+                  OR EXISTS (
+                      SELECT 1 FROM friend_relationships k
+                      WHERE (k.friend_a_id = $1 OR k.friend_b_id = $1)
+                        AND (CASE WHEN r.friend_a_id = $2 THEN r.friend_b_id ELSE r.friend_a_id END)
+                            IN (k.friend_a_id, k.friend_b_id)
+                  )
+              )
+            "#,
+            keep_id,
+            other_id
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?
+        .rows_affected() as usize;
+
+        report.relationships_moved = sqlx::query!(
+            r#"
+            UPDATE friend_relationships
+            SET
+                friend_a_id = CASE WHEN friend_a_id = $2 THEN $1 ELSE friend_a_id END,
+                friend_b_id = CASE WHEN friend_b_id = $2 THEN $1 ELSE friend_b_id END
+            WHERE friend_a_id = $2 OR friend_b_id = $2
+            "#,
+            keep_id,
+            other_id
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?
+        .rows_affected() as usize;
+
+        report.user_relationships_dropped = sqlx::query!(
+            r#"
+            DELETE FROM user_friend_relationships r
+            WHERE r.friend_id = $2
+              AND EXISTS (
+                  SELECT 1 FROM user_friend_relationships k
+                  WHERE k.friend_id = $1 AND k.relationship_type = r.relationship_type
+              )
+            "#,
+            keep_id,
+            other_id
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?
+        .rows_affected() as usize;
+
+        report.user_relationships_moved = sqlx::query!(
+            "UPDATE user_friend_relationships SET friend_id = $1 WHERE friend_id = $2",
+            keep_id,
+            other_id
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?
+        .rows_affected() as usize;
+
+        // Removed explicitly rather than by cascade, so the history (and
+        // with it undo) sees them go
+        sqlx::query!("DELETE FROM friend_groups WHERE friend_id = $1", other_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        sqlx::query!("DELETE FROM friends WHERE id = $1", other_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+
+        report.friend = FriendRepository::new(self.ctx.clone())
+            .update_in(conn, keep_id, None, update)
+            .await?;
+
+        Ok(report)
+    }
+
+    /// Move the other friend's attributes over, resolving key conflicts.
+    async fn merge_attributes(
+        &self,
+        conn: &mut PgConnection,
+        input: &MergeFriendsInput,
+        report: &mut MergeReport,
+    ) -> Result<(), RepositoryError> {
+        let attributes = sqlx::query_as!(
+            FriendAttribute,
+            r#"
+            SELECT id, friend_id, key, value, value_type, created_at, updated_at, version
+            FROM friend_attributes
+            WHERE friend_id = ANY($1)
+            ORDER BY key
+            "#,
+            &[input.keep_id, input.other_id][..]
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let (kept, others): (Vec<_>, Vec<_>) = attributes
+            .into_iter()
+            .partition(|a| a.friend_id == input.keep_id);
+        let kept: HashMap<&str, &FriendAttribute> =
+            kept.iter().map(|a| (a.key.as_str(), a)).collect();
+
+        // Renamed keys must not collide with either friend's keys
+        let mut taken: HashSet<String> = kept.keys().map(|key| key.to_string()).collect();
+        taken.extend(others.iter().map(|a| a.key.clone()));
+
+        for attribute in &others {
+            let Some(existing) = kept.get(attribute.key.as_str()) else {
+                move_attribute(conn, attribute.id, input.keep_id, &attribute.key).await?;
+                report.attributes_moved += 1;
+                continue;
+            };
+
+            let choice = if existing.value.trim() == attribute.value.trim() {
+                AttributeChoice::Keep
+            } else {
+                input
+                    .attribute_choices
+                    .get(&attribute.key)
+                    .copied()
+                    .unwrap_or_default()
+            };
+
+            match choice {
+                AttributeChoice::Keep => {
+                    delete_attribute(conn, attribute.id).await?;
+                    report.attributes_dropped += 1;
+                }
+                AttributeChoice::Other => {
+                    sqlx::query!(
+                        "UPDATE friend_attributes SET value = $2, value_type = $3 WHERE id = $1",
+                        existing.id,
+                        attribute.value,
+                        attribute.value_type
+                    )
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(RepositoryError::from_sqlx)?;
+                    delete_attribute(conn, attribute.id).await?;
+                    report.attributes_replaced += 1;
+                }
+                AttributeChoice::Both => {
+                    let base = attribute.key.split('#').next().unwrap_or(&attribute.key);
+                    let key = (2..)
+                        .map(|n| format!("{base}#{n}"))
+                        .find(|key| !taken.contains(key))
+                        .expect("unbounded range");
+                    taken.insert(key.clone());
+                    move_attribute(conn, attribute.id, input.keep_id, &key).await?;
+                    report.attributes_moved += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn move_attribute(
+    conn: &mut PgConnection,
+    id: Uuid,
+    friend_id: Uuid,
+    key: &str,
+) -> Result<(), RepositoryError> {
+    sqlx::query!(
+        "UPDATE friend_attributes SET friend_id = $2, key = $3 WHERE id = $1",
+        id,
+        friend_id,
+        key
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(RepositoryError::from_sqlx)?;
+    Ok(())
+}
+
+async fn delete_attribute(conn: &mut PgConnection, id: Uuid) -> Result<(), RepositoryError> {
+    sqlx::query!("DELETE FROM friend_attributes WHERE id = $1", id)
+        .execute(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+    Ok(())
+}
+
+/// Freeform text of both friends: the other's is appended unless it's
+/// empty or already there. `None` leaves the kept friend's as it is.
+fn combine(keep: &Option<String>, other: &Option<String>) -> Option<String> {
+    let other = other.as_deref().map(str::trim).filter(|s| !s.is_empty())?;
+    match keep.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        None => Some(other.to_string()),
+        Some(keep) if keep.contains(other) => None,
+        Some(keep) => Some(format!("{keep}\n\n{other}")),
+    }
+}
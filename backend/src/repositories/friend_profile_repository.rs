@@ -0,0 +1,376 @@
+//! # Friend Profile Repository
+//!
+//! Loads everything a friend detail screen shows in one go: the friend,
+//! their groups, attributes, relationships with other friends (with the
+//! other friend's name) and the user's own relationships with them.
+//!
+//! ## Queries
+//!
+//! Profiles are loaded in batches: one query per kind of data, each for
+//! every requested friend at once (`= ANY($1)`), then stitched together
+//! in memory. A single profile costs five queries on one connection
+//! instead of five calls through five repositories, and a list of a
+//! thousand profiles costs the same five, with no query per friend.
+//!
+//! All queries run in one read-only transaction with a single snapshot,
+//! so a profile never mixes data from before and after a change.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::models::{Friend, FriendAttribute, FriendRelationship, Group, UserFriendRelationship};
+
+use super::base::{RepositoryContext, Timeline};
+use super::error::RepositoryError;
+
+/// A friend with everything that hangs off them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FriendProfile {
+    pub friend: Friend,
+    /// Groups the friend is in, by name
+    pub groups: Vec<Group>,
+    /// By key
+    pub attributes: Vec<FriendAttribute>,
+    /// Relationships with other friends, from either side, newest first
+    pub friend_relationships: Vec<RelatedFriend>,
+    /// The user's own relationships with the friend, by type
+    pub user_friend_relationships: Vec<UserFriendRelationship>,
+}
+
+/// A relationship seen from one of its two friends.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelatedFriend {
+    pub relationship: FriendRelationship,
+    /// The friend on the other side
+    pub other_friend_id: Uuid,
+    /// The other friend's display name
+    pub other_name: String,
+    /// How the profile's friend relates to the other one, e.g. "boss of"
+    /// (`a_to_b` or `b_to_a`, whichever side they are on)
+    pub relation: String,
+}
+
+/// Repository for loading friend profiles.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let profiles = FriendProfileRepository::new(ctx.clone())
+///     .list_by_user(user.id, Timeline::Current)
+///     .await?;
+/// for profile in &profiles {
+///     println!("{}: {} groups", profile.friend.first_name, profile.groups.len());
+/// }
+/// ```
+pub struct FriendProfileRepository {
+    ctx: RepositoryContext,
+}
+
+impl FriendProfileRepository {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self { ctx }
+    }
+
+    /// Load one friend's profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend_id` - The UUID of the friend
+    /// * `timeline` - `Current` for only relationships in effect today,
+    ///   `Full` to include ended ones
+    ///
+    /// # Returns
+    ///
+    /// `None` if the friend doesn't exist or is in the trash.
+    pub async fn find_by_id(
+        &self,
+        friend_id: Uuid,
+        timeline: Timeline,
+    ) -> Result<Option<FriendProfile>, RepositoryError> {
+        Ok(self
+            .find_many(&[friend_id], timeline)
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Load the profiles of several friends.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend_ids` - The friends to load
+    /// * `timeline` - Which relationships to include (see `find_by_id`)
+    ///
+    /// # Returns
+    ///
+    /// Profiles in the order of `friend_ids`. Friends that don't exist or
+    /// are in the trash are left out, and duplicates are loaded once.
+    pub async fn find_many(
+        &self,
+        friend_ids: &[Uuid],
+        timeline: Timeline,
+    ) -> Result<Vec<FriendProfile>, RepositoryError> {
+        let mut tx = self.ctx.transaction().await?;
+        snapshot(&mut tx).await?;
+
+        let friends = sqlx::query_as!(
+            Friend,
+            r#"
+            SELECT id, user_id, first_name, last_name, date_of_birth,
+                   likes, dislikes, notes, created_at, updated_at, version
+            FROM friends
+            WHERE id = ANY($1) AND deleted_at IS NULL
+            "#,
+            friend_ids
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let mut by_id: HashMap<Uuid, Friend> = friends
+            .into_iter()
+            .map(|friend| (friend.id, friend))
+            .collect();
+        let friends: Vec<Friend> = friend_ids
+            .iter()
+            .filter_map(|id| by_id.remove(id))
+            .collect();
+
+        let profiles = assemble(&mut tx, friends, timeline).await?;
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(profiles)
+    }
+
+    /// Load the profiles of all of a user's friends, for list views.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the user
+    /// * `timeline` - Which relationships to include (see `find_by_id`)
+    ///
+    /// # Returns
+    ///
+    /// Profiles ordered by first name, like `FriendRepository::list_by_user`.
+    pub async fn list_by_user(
+        &self,
+        user_id: Uuid,
+        timeline: Timeline,
+    ) -> Result<Vec<FriendProfile>, RepositoryError> {
+        let mut tx = self.ctx.transaction().await?;
+        snapshot(&mut tx).await?;
+
+        let friends = sqlx::query_as!(
+            Friend,
+            r#"
+            SELECT id, user_id, first_name, last_name, date_of_birth,
+                   likes, dislikes, notes, created_at, updated_at, version
+            FROM friends
+            WHERE user_id = $1 AND deleted_at IS NULL
+            ORDER BY first_name ASC
+            "#,
+            user_id
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let profiles = assemble(&mut tx, friends, timeline).await?;
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(profiles)
+    }
+}
+
+/// Make the transaction read-only, with one snapshot for all its queries.
+async fn snapshot(conn: &mut PgConnection) -> Result<(), RepositoryError> {
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+    Ok(())
+}
+
+/// Load what hangs off the friends and build their profiles, in order.
+async fn assemble(
+    conn: &mut PgConnection,
+    friends: Vec<Friend>,
+    timeline: Timeline,
+) -> Result<Vec<FriendProfile>, RepositoryError> {
+    if friends.is_empty() {
+        return Ok(Vec::new());
+    }
+    let ids: Vec<Uuid> = friends.iter().map(|friend| friend.id).collect();
+    let requested: HashSet<Uuid> = ids.iter().copied().collect();
+
+    let mut groups: HashMap<Uuid, Vec<Group>> = HashMap::new();
+    let rows = sqlx::query!(
+        r#"
+        SELECT fg.friend_id, g.id, g.user_id, g.name, g.description,
+               g.created_at, g.updated_at, g.version
+        FROM groups g
+        INNER JOIN friend_groups fg ON fg.group_id = g.id
+        WHERE fg.friend_id = ANY($1)
+        ORDER BY g.name ASC
+        "#,
+        &ids
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(RepositoryError::from_sqlx)?;
+    for row in rows {
+        groups.entry(row.friend_id).or_default().push(Group {
+            id: row.id,
+            user_id: row.user_id,
+            name: row.name,
+            description: row.description,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            version: row.version,
+        });
+    }
+
+    let mut attributes: HashMap<Uuid, Vec<FriendAttribute>> = HashMap::new();
+    let rows = sqlx::query_as!(
+        FriendAttribute,
+        r#"
+        SELECT id, friend_id, key, value, value_type, created_at, updated_at, version
+        FROM friend_attributes
+        WHERE friend_id = ANY($1)
+        ORDER BY key ASC
+        "#,
+        &ids
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(RepositoryError::from_sqlx)?;
+    for attribute in rows {
+        attributes
+            .entry(attribute.friend_id)
+            .or_default()
+            .push(attribute);
+    }
+
+    // Both sides' names come along, since either may be the other friend
+    let mut related: HashMap<Uuid, Vec<RelatedFriend>> = HashMap::new();
+    let rows = sqlx::query!(
+        r#"
+        SELECT r.id, r.user_id, r.friend_a_id, r.friend_b_id, r.a_to_b, r.b_to_a,
+               r.started_on, r.ended_on, r.status, r.created_at, r.updated_at, r.version,
+               fa.first_name AS a_first_name, fa.last_name AS a_last_name,
+               fb.first_name AS b_first_name, fb.last_name AS b_last_name
+        FROM friend_relationships r
+        INNER JOIN friends fa ON fa.id = r.friend_a_id
+        INNER JOIN friends fb ON fb.id = r.friend_b_id
+        WHERE (r.friend_a_id = ANY($1) OR r.friend_b_id = ANY($1))
+          AND fa.deleted_at IS NULL AND fb.deleted_at IS NULL
+          AND (
+              NOT $2
+              OR (r.status = 'active'
+                  AND (r.started_on IS NULL OR r.started_on <= CURRENT_DATE)
+                  AND (r.ended_on IS NULL OR r.ended_on > CURRENT_DATE))
+          )
+        ORDER BY r.created_at DESC
+        "#,
+        &ids,
+        timeline.current_only()
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(RepositoryError::from_sqlx)?;
+    for row in rows {
+        let a_name = display_name(&row.a_first_name, row.a_last_name.as_deref());
+        let b_name = display_name(&row.b_first_name, row.b_last_name.as_deref());
+        let relationship = FriendRelationship {
+            id: row.id,
+            user_id: row.user_id,
+            friend_a_id: row.friend_a_id,
+            friend_b_id: row.friend_b_id,
+            a_to_b: row.a_to_b,
+            b_to_a: row.b_to_a,
+            started_on: row.started_on,
+            ended_on: row.ended_on,
+            status: row.status,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            version: row.version,
+        };
+
+        // A relationship between two requested friends shows up in both
+        // profiles
+        if requested.contains(&relationship.friend_a_id) {
+            related
+                .entry(relationship.friend_a_id)
+                .or_default()
+                .push(RelatedFriend {
+                    other_friend_id: relationship.friend_b_id,
+                    other_name: b_name,
+                    relation: relationship.a_to_b.clone(),
+                    relationship: relationship.clone(),
+                });
+        }
+        if requested.contains(&relationship.friend_b_id) {
+            related
+                .entry(relationship.friend_b_id)
+                .or_default()
+                .push(RelatedFriend {
+                    other_friend_id: relationship.friend_a_id,
+                    other_name: a_name,
+                    // Symmetric relationships read the same both ways
+                    relation: relationship
+                        .b_to_a
+                        .clone()
+                        .unwrap_or_else(|| relationship.a_to_b.clone()),
+                    relationship,
+                });
+        }
+    }
+
+    let mut user_relationships: HashMap<Uuid, Vec<UserFriendRelationship>> = HashMap::new();
+    let rows = sqlx::query_as!(
+        UserFriendRelationship,
+        r#"
+        SELECT id, friend_id, relationship_type, started_on, ended_on, status,
+               created_at, updated_at, version
+        FROM user_friend_relationships
+        WHERE friend_id = ANY($1)
+          AND (
+              NOT $2
+              OR (status = 'active'
+                  AND (started_on IS NULL OR started_on <= CURRENT_DATE)
+                  AND (ended_on IS NULL OR ended_on > CURRENT_DATE))
+          )
+        ORDER BY relationship_type ASC
+        "#,
+        &ids,
+        timeline.current_only()
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(RepositoryError::from_sqlx)?;
+    for relationship in rows {
+        user_relationships
+            .entry(relationship.friend_id)
+            .or_default()
+            .push(relationship);
+    }
+
+    Ok(friends
+        .into_iter()
+        .map(|friend| FriendProfile {
+            groups: groups.remove(&friend.id).unwrap_or_default(),
+            attributes: attributes.remove(&friend.id).unwrap_or_default(),
+            friend_relationships: related.remove(&friend.id).unwrap_or_default(),
+            user_friend_relationships: user_relationships.remove(&friend.id).unwrap_or_default(),
+            friend,
+        })
+        .collect())
+}
+
+fn display_name(first_name: &str, last_name: Option<&str>) -> String {
+    match last_name {
+        Some(last) => format!("{first_name} {last}"),
+        None => first_name.to_string(),
+    }
+}
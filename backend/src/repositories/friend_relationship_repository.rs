@@ -4,12 +4,21 @@
 //! These track how friends know each other (e.g., siblings, coworkers).
 
 use async_trait::async_trait;
+use sqlx::PgConnection;
+use time::Date;
 use uuid::Uuid;
 
-use crate::models::FriendRelationship;
+use crate::models::{FriendRelationship, RelationshipStatusChange};
+use crate::validation::relationships::{
+    RelationshipIssue, check_labels, check_ownership, check_row,
+};
 
-use super::base::{Repository, RepositoryContext};
+use super::base::{Repository, RepositoryContext, Timeline};
 use super::error::RepositoryError;
+use super::friend_repository::FriendRepository;
+
+/// Unique index on a user's unordered friend pair (migration 011).
+const PAIR_INDEX: &str = "friend_relationships_pair_unique";
 
 /// Input for creating a new friend relationship.
 pub struct CreateFriendRelationshipInput {
@@ -23,15 +32,51 @@ pub struct CreateFriendRelationshipInput {
     pub a_to_b: String,
     /// How B relates to A (optional, NULL means symmetric)
     pub b_to_a: Option<String>,
+    /// When the relationship began (optional)
+    pub started_on: Option<Date>,
+    /// When the relationship ended (optional)
+    pub ended_on: Option<Date>,
+    /// Initial status (default: "active")
+    pub status: Option<String>,
 }
 
 /// Input for updating an existing friend relationship.
+///
+/// Setting `status` to a new value appends an entry to the status history,
+/// effective on `status_effective_on` (default: today).
 pub struct UpdateFriendRelationshipInput {
     pub a_to_b: Option<String>,
-    pub b_to_a: Option<String>,
+    /// `Some(None)` clears the reverse label, making the relationship
+    /// symmetric
+    pub b_to_a: Option<Option<String>>,
+    pub started_on: Option<Date>,
+    /// `Some(None)` clears the end date, reopening the relationship
+    pub ended_on: Option<Option<Date>>,
+    pub status: Option<String>,
+    pub status_effective_on: Option<Date>,
 }
 
 /// Repository for friend relationship database operations.
+///
+/// # Validation
+///
+/// `create` and `update` reject inconsistent data with
+/// `RepositoryError::Validation` before touching the table:
+/// - self-relationships (`friend_a_id == friend_b_id`)
+/// - contradictory family labels (e.g., "parent of" in both directions)
+/// - a second relationship between the same pair, in either direction
+///   (backed by a unique index, so of two concurrent creates only one
+///   succeeds)
+/// - friends owned by a different user than the relationship
+///
+/// Rows written before these checks existed can be found with the
+/// `Doctor` full-scan report.
+///
+/// # Status History
+///
+/// `create` records the initial status and `update` records every status
+/// change in `friend_relationship_status_history`, in the same transaction
+/// as the write itself.
 pub struct FriendRelationshipRepository {
     ctx: RepositoryContext,
 }
@@ -41,8 +86,31 @@ impl FriendRelationshipRepository {
         Self { ctx }
     }
 
+    /// List every relationship across all users.
+    ///
+    /// Only intended for maintenance scans like the `Doctor` report -
+    /// request handlers should always filter by user.
+    pub async fn list_all(&self) -> Result<Vec<FriendRelationship>, RepositoryError> {
+        let relationships = sqlx::query_as!(
+            FriendRelationship,
+            r#"
+            SELECT id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a,
+                   started_on, ended_on, status, created_at, updated_at, version
+            FROM friend_relationships
+            ORDER BY created_at ASC
+            "#
+        )
+        .fetch_all(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(relationships)
+    }
+
     /// List all relationships for a user.
     ///
+    /// Relationships with a trashed friend on either side are left out.
+    ///
     /// # Arguments
     ///
     /// * `user_id` - The UUID of the user
@@ -53,9 +121,14 @@ impl FriendRelationshipRepository {
         let relationships = sqlx::query_as!(
             FriendRelationship,
             r#"
-            SELECT id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a, created_at, updated_at
+            SELECT id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a,
+                   started_on, ended_on, status, created_at, updated_at, version
             FROM friend_relationships
             WHERE user_id = $1
+              AND NOT EXISTS (
+                  SELECT 1 FROM friends f
+                  WHERE f.id IN (friend_a_id, friend_b_id) AND f.deleted_at IS NOT NULL
+              )
             ORDER BY created_at DESC
             "#,
             user_id
@@ -69,24 +142,40 @@ impl FriendRelationshipRepository {
 
     /// List all relationships involving a specific friend.
     ///
-    /// This returns relationships where the friend is either friend_a or friend_b.
+    /// This returns relationships where the friend is either friend_a or friend_b,
+    /// leaving out those whose other friend is in the trash.
     ///
     /// # Arguments
     ///
     /// * `friend_id` - The UUID of the friend
+    /// * `timeline` - `Current` for only relationships in effect today,
+    ///   `Full` to include ended ones
     pub async fn list_by_friend(
         &self,
         friend_id: Uuid,
+        timeline: Timeline,
     ) -> Result<Vec<FriendRelationship>, RepositoryError> {
         let relationships = sqlx::query_as!(
             FriendRelationship,
             r#"
-            SELECT id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a, created_at, updated_at
+            SELECT id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a,
+                   started_on, ended_on, status, created_at, updated_at, version
             FROM friend_relationships
-            WHERE friend_a_id = $1 OR friend_b_id = $1
+            WHERE (friend_a_id = $1 OR friend_b_id = $1)
+              AND NOT EXISTS (
+                  SELECT 1 FROM friends f
+                  WHERE f.id IN (friend_a_id, friend_b_id) AND f.deleted_at IS NOT NULL
+              )
+              AND (
+                  NOT $2
+                  OR (status = 'active'
+                      AND (started_on IS NULL OR started_on <= CURRENT_DATE)
+                      AND (ended_on IS NULL OR ended_on > CURRENT_DATE))
+              )
             ORDER BY created_at DESC
             "#,
-            friend_id
+            friend_id,
+            timeline.current_only()
         )
         .fetch_all(&self.ctx.pool)
         .await
@@ -100,16 +189,36 @@ impl FriendRelationshipRepository {
     /// # Note
     ///
     /// This checks both directions - the relationship could be stored as
-    /// (A, B) or (B, A) in the database.
+    /// (A, B) or (B, A) in the database. `create` rejects a second row for
+    /// the same pair, so at most one row should match.
     pub async fn find_between(
         &self,
         friend_a_id: Uuid,
         friend_b_id: Uuid,
+    ) -> Result<Option<FriendRelationship>, RepositoryError> {
+        let mut conn = self
+            .ctx
+            .pool
+            .acquire()
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        self.find_between_in(&mut conn, friend_a_id, friend_b_id)
+            .await
+    }
+
+    /// Same as `find_between`, but runs on the given connection so it can
+    /// join a transaction.
+    pub async fn find_between_in(
+        &self,
+        conn: &mut PgConnection,
+        friend_a_id: Uuid,
+        friend_b_id: Uuid,
     ) -> Result<Option<FriendRelationship>, RepositoryError> {
         let relationship = sqlx::query_as!(
             FriendRelationship,
             r#"
-            SELECT id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a, created_at, updated_at
+            SELECT id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a,
+                   started_on, ended_on, status, created_at, updated_at, version
             FROM friend_relationships
             WHERE (friend_a_id = $1 AND friend_b_id = $2)
                OR (friend_a_id = $2 AND friend_b_id = $1)
@@ -117,12 +226,107 @@ impl FriendRelationshipRepository {
             friend_a_id,
             friend_b_id
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
         Ok(relationship)
     }
+
+    /// List the status history of a relationship, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `relationship_id` - The UUID of the relationship
+    pub async fn list_status_history(
+        &self,
+        relationship_id: Uuid,
+    ) -> Result<Vec<RelationshipStatusChange>, RepositoryError> {
+        let history = sqlx::query_as!(
+            RelationshipStatusChange,
+            r#"
+            SELECT id, relationship_id, status, effective_on, created_at
+            FROM friend_relationship_status_history
+            WHERE relationship_id = $1
+            ORDER BY effective_on ASC, created_at ASC
+            "#,
+            relationship_id
+        )
+        .fetch_all(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(history)
+    }
+
+    /// Validate a new relationship before inserting it.
+    ///
+    /// Runs the cheap in-memory checks first, then the ownership and
+    /// duplicate checks that need the database (on `conn`, so friends
+    /// created earlier in the same transaction count).
+    async fn validate_create(
+        &self,
+        conn: &mut PgConnection,
+        input: &CreateFriendRelationshipInput,
+    ) -> Result<(), RepositoryError> {
+        let (a, b) = (input.friend_a_id, input.friend_b_id);
+        let mut issues = check_row(a, b, &input.a_to_b, input.b_to_a.as_deref());
+
+        let owners = FriendRepository::new(self.ctx.clone())
+            .find_owners_in(&mut *conn, &[a, b])
+            .await?;
+        issues.extend(check_ownership(input.user_id, [a, b], &owners));
+
+        if let Some(existing) = self.find_between_in(&mut *conn, a, b).await? {
+            issues.push(RelationshipIssue::DuplicatePair {
+                duplicate_of: existing.id,
+            });
+        }
+
+        reject_issues(issues)
+    }
+
+    /// Validate an update against the row it will produce.
+    ///
+    /// The friends can't change on update, so only the labels are checked.
+    /// Labels not provided in `input` keep their stored values, the same
+    /// as in the UPDATE query; a cleared `b_to_a` is checked as NULL.
+    ///
+    /// # Returns
+    ///
+    /// The row as it is before the update, so the caller can tell whether
+    /// the status changed.
+    async fn validate_update(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+        input: &UpdateFriendRelationshipInput,
+    ) -> Result<FriendRelationship, RepositoryError> {
+        let existing = self
+            .find_by_id_in(conn, id)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+
+        let a_to_b = input.a_to_b.as_deref().unwrap_or(&existing.a_to_b);
+        let b_to_a = match &input.b_to_a {
+            Some(b_to_a) => b_to_a.as_deref(),
+            None => existing.b_to_a.as_deref(),
+        };
+
+        reject_issues(check_labels(a_to_b, b_to_a).into_iter().collect())?;
+
+        Ok(existing)
+    }
+}
+
+/// Turn a list of issues into a `Validation` error, or `Ok` if empty.
+fn reject_issues(issues: Vec<RelationshipIssue>) -> Result<(), RepositoryError> {
+    if issues.is_empty() {
+        return Ok(());
+    }
+
+    let messages: Vec<String> = issues.iter().map(ToString::to_string).collect();
+    Err(RepositoryError::Validation(messages.join("; ")))
 }
 
 #[async_trait]
@@ -131,75 +335,154 @@ impl Repository for FriendRelationshipRepository {
     type CreateInput = CreateFriendRelationshipInput;
     type UpdateInput = UpdateFriendRelationshipInput;
 
-    async fn find_by_id(&self, id: Uuid) -> Result<Option<FriendRelationship>, RepositoryError> {
+    fn context(&self) -> &RepositoryContext {
+        &self.ctx
+    }
+
+    async fn find_by_id_in(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+    ) -> Result<Option<FriendRelationship>, RepositoryError> {
         let relationship = sqlx::query_as!(
             FriendRelationship,
             r#"
-            SELECT id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a, created_at, updated_at
+            SELECT id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a,
+                   started_on, ended_on, status, created_at, updated_at, version
             FROM friend_relationships
             WHERE id = $1
             "#,
             id
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
         Ok(relationship)
     }
 
-    async fn create(
+    async fn create_in(
         &self,
+        conn: &mut PgConnection,
         input: CreateFriendRelationshipInput,
     ) -> Result<FriendRelationship, RepositoryError> {
+        self.validate_create(&mut *conn, &input).await?;
+
+        // The relationship and its first history entry are written together
         let relationship = sqlx::query_as!(
             FriendRelationship,
             r#"
-            INSERT INTO friend_relationships (user_id, friend_a_id, friend_b_id, a_to_b, b_to_a)
-            VALUES ($1, $2, $3, $4, $5)
-            RETURNING id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a, created_at, updated_at
+            INSERT INTO friend_relationships
+                (user_id, friend_a_id, friend_b_id, a_to_b, b_to_a, started_on, ended_on, status)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, COALESCE($8, 'active'))
+            RETURNING id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a,
+                      started_on, ended_on, status, created_at, updated_at, version
             "#,
             input.user_id,
             input.friend_a_id,
             input.friend_b_id,
             input.a_to_b,
-            input.b_to_a
+            input.b_to_a,
+            input.started_on,
+            input.ended_on,
+            input.status
         )
-        .fetch_one(&self.ctx.pool)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|err| {
+            // Another create for the pair committed after validate_create
+            // looked
+            if err.as_database_error().and_then(|e| e.constraint()) == Some(PAIR_INDEX) {
+                return RepositoryError::Validation(
+                    "these friends are already related".to_string(),
+                );
+            }
+            RepositoryError::from_sqlx(err)
+        })?;
+
+        // The initial status takes effect when the relationship started
+        sqlx::query!(
+            r#"
+            INSERT INTO friend_relationship_status_history (relationship_id, status, effective_on)
+            VALUES ($1, $2, COALESCE($3, CURRENT_DATE))
+            "#,
+            relationship.id,
+            relationship.status,
+            relationship.started_on
+        )
+        .execute(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
         Ok(relationship)
     }
 
-    async fn update(
+    async fn update_in(
         &self,
+        conn: &mut PgConnection,
         id: Uuid,
+        expected_version: Option<i64>,
         input: UpdateFriendRelationshipInput,
     ) -> Result<FriendRelationship, RepositoryError> {
+        let existing = self.validate_update(&mut *conn, id, &input).await?;
+
         let relationship = sqlx::query_as!(
             FriendRelationship,
             r#"
             UPDATE friend_relationships
             SET
                 a_to_b = COALESCE($2, a_to_b),
-                b_to_a = COALESCE($3, b_to_a)
-            WHERE id = $1
-            RETURNING id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a, created_at, updated_at
+                b_to_a = CASE WHEN $9 THEN $3 ELSE b_to_a END,
+                started_on = COALESCE($4, started_on),
+                ended_on = CASE WHEN $8 THEN $5 ELSE ended_on END,
+                status = COALESCE($6, status)
+            WHERE id = $1 AND ($7::bigint IS NULL OR version = $7)
+            RETURNING id, user_id, friend_a_id, friend_b_id, a_to_b, b_to_a,
+                      started_on, ended_on, status, created_at, updated_at, version
             "#,
             id,
             input.a_to_b,
-            input.b_to_a
+            input.b_to_a.clone().flatten(),
+            input.started_on,
+            input.ended_on.flatten(),
+            input.status,
+            expected_version,
+            input.ended_on.is_some(),
+            input.b_to_a.is_some()
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
-        .map_err(RepositoryError::from_sqlx)?
-        .ok_or(RepositoryError::NotFound)?;
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let Some(relationship) = relationship else {
+            let current =
+                sqlx::query_scalar!("SELECT version FROM friend_relationships WHERE id = $1", id)
+                    .fetch_optional(&mut *conn)
+                    .await
+                    .map_err(RepositoryError::from_sqlx)?;
+            return Err(RepositoryError::missed_update(expected_version, current));
+        };
+
+        // Only actual status changes go into the history
+        if relationship.status != existing.status {
+            sqlx::query!(
+                r#"
+                INSERT INTO friend_relationship_status_history (relationship_id, status, effective_on)
+                VALUES ($1, $2, COALESCE($3, CURRENT_DATE))
+                "#,
+                relationship.id,
+                relationship.status,
+                input.status_effective_on
+            )
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        }
 
         Ok(relationship)
     }
 
-    async fn delete(&self, id: Uuid) -> Result<bool, RepositoryError> {
+    async fn delete_in(&self, conn: &mut PgConnection, id: Uuid) -> Result<bool, RepositoryError> {
         let result = sqlx::query!(
             r#"
             DELETE FROM friend_relationships
@@ -207,10 +490,130 @@ impl Repository for FriendRelationshipRepository {
             "#,
             id
         )
-        .execute(&self.ctx.pool)
+        .execute(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
         Ok(result.rows_affected() > 0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{PgPool, Postgres, Transaction};
+
+    use super::*;
+    use crate::repositories::{
+        CreateFriendInput, CreateUserInput, FriendRepository, UserRepository,
+    };
+
+    /// A user with two friends, in a transaction that is never committed.
+    async fn two_friends(ctx: &RepositoryContext) -> (Transaction<'_, Postgres>, Uuid, Uuid, Uuid) {
+        let mut tx = ctx.transaction().await.unwrap();
+        let user = UserRepository::new(ctx.clone())
+            .create_in(
+                &mut tx,
+                CreateUserInput {
+                    first_name: "Rel".to_string(),
+                    last_name: "Ations".to_string(),
+                    email: format!("{}@example.com", Uuid::now_v7()),
+                    password_hash: String::new(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let friends = FriendRepository::new(ctx.clone());
+        let mut ids = Vec::new();
+        for first_name in ["Ada", "Byron"] {
+            let friend = friends
+                .create_in(
+                    &mut tx,
+                    CreateFriendInput {
+                        user_id: user.id,
+                        first_name: first_name.to_string(),
+                        last_name: None,
+                        date_of_birth: None,
+                        likes: None,
+                        dislikes: None,
+                        notes: None,
+                    },
+                )
+                .await
+                .unwrap();
+            ids.push(friend.id);
+        }
+        (tx, user.id, ids[0], ids[1])
+    }
+
+    #[tokio::test]
+    async fn unknown_statuses_are_rejected() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is set");
+        let ctx = RepositoryContext::new(PgPool::connect(&url).await.unwrap());
+        let relationships = FriendRelationshipRepository::new(ctx.clone());
+        let (mut tx, user_id, ada, byron) = two_friends(&ctx).await;
+
+        let created = relationships
+            .create_in(
+                &mut tx,
+                CreateFriendRelationshipInput {
+                    user_id,
+                    friend_a_id: ada,
+                    friend_b_id: byron,
+                    a_to_b: "friend of".to_string(),
+                    b_to_a: None,
+                    started_on: None,
+                    ended_on: None,
+                    status: Some("actve".to_string()),
+                },
+            )
+            .await;
+        assert!(matches!(created, Err(RepositoryError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn b_to_a_can_be_cleared() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is set");
+        let ctx = RepositoryContext::new(PgPool::connect(&url).await.unwrap());
+        let relationships = FriendRelationshipRepository::new(ctx.clone());
+        let (mut tx, user_id, ada, byron) = two_friends(&ctx).await;
+
+        let created = relationships
+            .create_in(
+                &mut tx,
+                CreateFriendRelationshipInput {
+                    user_id,
+                    friend_a_id: ada,
+                    friend_b_id: byron,
+                    a_to_b: "mentor of".to_string(),
+                    b_to_a: Some("mentee of".to_string()),
+                    started_on: None,
+                    ended_on: None,
+                    status: None,
+                },
+            )
+            .await
+            .unwrap();
+        let update = |b_to_a| UpdateFriendRelationshipInput {
+            a_to_b: Some("friend of".to_string()),
+            b_to_a,
+            started_on: None,
+            ended_on: None,
+            status: None,
+            status_effective_on: None,
+        };
+
+        // Left out, it's kept
+        let kept = relationships
+            .update_in(&mut tx, created.id, None, update(None))
+            .await
+            .unwrap();
+        assert_eq!(kept.b_to_a.as_deref(), Some("mentee of"));
+
+        let cleared = relationships
+            .update_in(&mut tx, created.id, None, update(Some(None)))
+            .await
+            .unwrap();
+        assert_eq!(cleared.b_to_a, None);
+    }
+}
@@ -3,11 +3,14 @@
 //! Repository for friend database operations.
 //! This is the core entity of FKB - handles CRUD and group membership.
 
+use std::collections::HashMap;
+
 use async_trait::async_trait;
-use time::Date;
+use sqlx::PgConnection;
+use time::{Date, OffsetDateTime};
 use uuid::Uuid;
 
-use crate::models::{Friend, Group};
+use crate::models::{Friend, Group, TrashedFriend};
 
 use super::base::{Repository, RepositoryContext};
 use super::error::RepositoryError;
@@ -32,13 +35,14 @@ pub struct CreateFriendInput {
 
 /// Input for updating an existing friend.
 /// All fields optional - only provided fields are updated.
+/// For the optional columns, `Some(None)` clears the value to NULL.
 pub struct UpdateFriendInput {
     pub first_name: Option<String>,
-    pub last_name: Option<String>,
-    pub date_of_birth: Option<Date>,
-    pub likes: Option<String>,
-    pub dislikes: Option<String>,
-    pub notes: Option<String>,
+    pub last_name: Option<Option<String>>,
+    pub date_of_birth: Option<Option<Date>>,
+    pub likes: Option<Option<String>>,
+    pub dislikes: Option<Option<String>>,
+    pub notes: Option<Option<String>>,
 }
 
 /// Repository for friend database operations.
@@ -48,6 +52,15 @@ pub struct UpdateFriendInput {
 /// This repository also handles the friend-group relationship (many-to-many).
 /// Methods like `add_to_group`, `remove_from_group`, and `list_groups` manage
 /// the `friend_groups` join table.
+///
+/// # Soft Delete
+///
+/// `delete` moves a friend to the trash by setting `deleted_at`. Trashed
+/// friends are invisible to every other method (and to the other
+/// repositories' listings), but their attributes, group memberships and
+/// relationships are left in place, so `restore` brings all of it back.
+/// `purge` and `purge_trashed_before` delete for good, and only then do
+/// the cascades run.
 pub struct FriendRepository {
     ctx: RepositoryContext,
 }
@@ -70,9 +83,9 @@ impl FriendRepository {
             Friend,
             r#"
             SELECT id, user_id, first_name, last_name, date_of_birth,
-                   likes, dislikes, notes, created_at, updated_at
+                   likes, dislikes, notes, created_at, updated_at, version
             FROM friends
-            WHERE user_id = $1
+            WHERE user_id = $1 AND deleted_at IS NULL
             ORDER BY first_name ASC
             "#,
             user_id
@@ -84,6 +97,55 @@ impl FriendRepository {
         Ok(friends)
     }
 
+    /// Look up the owning user of each friend.
+    ///
+    /// Used by consistency checks that need to confirm a set of friends
+    /// all belong to the same user.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend_ids` - The friends to look up
+    ///
+    /// # Returns
+    ///
+    /// A map of friend ID → user ID. Friends that don't exist are absent;
+    /// trashed friends are included, since they still belong to the user.
+    pub async fn find_owners(
+        &self,
+        friend_ids: &[Uuid],
+    ) -> Result<HashMap<Uuid, Uuid>, RepositoryError> {
+        let mut conn = self
+            .ctx
+            .pool
+            .acquire()
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        self.find_owners_in(&mut conn, friend_ids).await
+    }
+
+    /// Same as `find_owners`, but runs on the given connection so it can
+    /// join a transaction.
+    pub async fn find_owners_in(
+        &self,
+        conn: &mut PgConnection,
+        friend_ids: &[Uuid],
+    ) -> Result<HashMap<Uuid, Uuid>, RepositoryError> {
+        // ANY($1) matches against every element of the array parameter
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, user_id
+            FROM friends
+            WHERE id = ANY($1)
+            "#,
+            friend_ids
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row.user_id)).collect())
+    }
+
     /// Add a friend to a group.
     ///
     /// # Arguments
@@ -99,6 +161,23 @@ impl FriendRepository {
         &self,
         friend_id: Uuid,
         group_id: Uuid,
+    ) -> Result<(), RepositoryError> {
+        let mut conn = self
+            .ctx
+            .pool
+            .acquire()
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        self.add_to_group_in(&mut conn, friend_id, group_id).await
+    }
+
+    /// Same as `add_to_group`, but runs on the given connection so it can
+    /// join a transaction.
+    pub async fn add_to_group_in(
+        &self,
+        conn: &mut PgConnection,
+        friend_id: Uuid,
+        group_id: Uuid,
     ) -> Result<(), RepositoryError> {
         // ON CONFLICT DO NOTHING makes this idempotent.
         // If the friend is already in the group, this does nothing.
@@ -111,7 +190,7 @@ impl FriendRepository {
             friend_id,
             group_id
         )
-        .execute(&self.ctx.pool)
+        .execute(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
@@ -128,6 +207,24 @@ impl FriendRepository {
         &self,
         friend_id: Uuid,
         group_id: Uuid,
+    ) -> Result<bool, RepositoryError> {
+        let mut conn = self
+            .ctx
+            .pool
+            .acquire()
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        self.remove_from_group_in(&mut conn, friend_id, group_id)
+            .await
+    }
+
+    /// Same as `remove_from_group`, but runs on the given connection so it
+    /// can join a transaction.
+    pub async fn remove_from_group_in(
+        &self,
+        conn: &mut PgConnection,
+        friend_id: Uuid,
+        group_id: Uuid,
     ) -> Result<bool, RepositoryError> {
         let result = sqlx::query!(
             r#"
@@ -137,7 +234,7 @@ impl FriendRepository {
             friend_id,
             group_id
         )
-        .execute(&self.ctx.pool)
+        .execute(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
@@ -154,7 +251,7 @@ impl FriendRepository {
         let groups = sqlx::query_as!(
             Group,
             r#"
-            SELECT g.id, g.user_id, g.name, g.description, g.created_at, g.updated_at
+            SELECT g.id, g.user_id, g.name, g.description, g.created_at, g.updated_at, g.version
             FROM groups g
             INNER JOIN friend_groups fg ON fg.group_id = g.id
             WHERE fg.friend_id = $1
@@ -168,6 +265,214 @@ impl FriendRepository {
 
         Ok(groups)
     }
+
+    /// List the groups of many friends in one query.
+    ///
+    /// # Returns
+    ///
+    /// Map of friend ID → the friend's groups, sorted by name. Friends in
+    /// no group are missing from the map.
+    pub async fn list_groups_by_friends(
+        &self,
+        friend_ids: &[Uuid],
+    ) -> Result<HashMap<Uuid, Vec<Group>>, RepositoryError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT fg.friend_id, g.id, g.user_id, g.name, g.description,
+                   g.created_at, g.updated_at, g.version
+            FROM groups g
+            INNER JOIN friend_groups fg ON fg.group_id = g.id
+            WHERE fg.friend_id = ANY($1)
+            ORDER BY g.name ASC
+            "#,
+            friend_ids
+        )
+        .fetch_all(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let mut groups: HashMap<Uuid, Vec<Group>> = HashMap::new();
+        for row in rows {
+            groups.entry(row.friend_id).or_default().push(Group {
+                id: row.id,
+                user_id: row.user_id,
+                name: row.name,
+                description: row.description,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version,
+            });
+        }
+        Ok(groups)
+    }
+
+    /// List a user's trashed friends, most recently deleted first.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the user whose trash to list
+    pub async fn list_trash(&self, user_id: Uuid) -> Result<Vec<TrashedFriend>, RepositoryError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, user_id, first_name, last_name, date_of_birth,
+                   likes, dislikes, notes, created_at, updated_at, version,
+                   deleted_at AS "deleted_at!"
+            FROM friends
+            WHERE user_id = $1 AND deleted_at IS NOT NULL
+            ORDER BY deleted_at DESC, id
+            "#,
+            user_id
+        )
+        .fetch_all(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TrashedFriend {
+                friend: Friend {
+                    id: row.id,
+                    user_id: row.user_id,
+                    first_name: row.first_name,
+                    last_name: row.last_name,
+                    date_of_birth: row.date_of_birth,
+                    likes: row.likes,
+                    dislikes: row.dislikes,
+                    notes: row.notes,
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                    version: row.version,
+                },
+                deleted_at: row.deleted_at,
+            })
+            .collect())
+    }
+
+    /// Take a friend out of the trash.
+    ///
+    /// Their attributes, group memberships and relationships were never
+    /// removed, so they come back with them.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RepositoryError::NotFound` if the friend isn't in the trash
+    /// (including if it has already been purged).
+    pub async fn restore(&self, id: Uuid) -> Result<Friend, RepositoryError> {
+        let friend = sqlx::query_as!(
+            Friend,
+            r#"
+            UPDATE friends
+            SET deleted_at = NULL
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            RETURNING id, user_id, first_name, last_name, date_of_birth,
+                      likes, dislikes, notes, created_at, updated_at, version
+            "#,
+            id
+        )
+        .fetch_optional(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?
+        .ok_or(RepositoryError::NotFound)?;
+
+        Ok(friend)
+    }
+
+    /// Permanently delete one trashed friend, cascading to their
+    /// attributes, group memberships and relationships.
+    ///
+    /// A purge can't be undone: it doesn't become an undo step, and the
+    /// undo steps that touched the friend are dropped (see `purge_in`).
+    ///
+    /// # Returns
+    ///
+    /// `false` if the friend isn't in the trash. Live friends have to be
+    /// deleted (trashed) first.
+    pub async fn purge(&self, id: Uuid) -> Result<bool, RepositoryError> {
+        let mut tx = self.ctx.transaction().await?;
+        let purged = purge_in(&mut tx, &[id], None).await?;
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+
+        Ok(purged > 0)
+    }
+
+    /// Permanently delete every friend, across all users, that was moved
+    /// to the trash before a cutoff.
+    ///
+    /// This is the purge job's query; see `purge` for what gets deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `cutoff` - Friends trashed before this moment are purged
+    ///
+    /// # Returns
+    ///
+    /// How many friends were purged.
+    pub async fn purge_trashed_before(
+        &self,
+        cutoff: OffsetDateTime,
+    ) -> Result<u64, RepositoryError> {
+        let mut tx = self.ctx.transaction().await?;
+        let purged = purge_in(&mut tx, &[], Some(cutoff)).await?;
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+
+        Ok(purged)
+    }
+}
+
+/// Delete trashed friends outside of undo: the given ones, or with a
+/// cutoff every one trashed before it.
+///
+/// Runs with `fkb.purging` set, so the delete isn't recorded as an undo
+/// step (migration 009). The cascaded deletes of the friends' child rows
+/// aren't in the history, so undoing a purge could only bring back an
+/// empty friend. For the same reason the undo steps that touched a purged
+/// friend are dropped: they could never be applied again.
+async fn purge_in(
+    conn: &mut PgConnection,
+    ids: &[Uuid],
+    cutoff: Option<OffsetDateTime>,
+) -> Result<u64, RepositoryError> {
+    sqlx::query_scalar!("SELECT set_config('fkb.purging', 'on', true)")
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+    let purged = sqlx::query_scalar!(
+        r#"
+        DELETE FROM friends
+        WHERE deleted_at IS NOT NULL
+          AND (id = ANY($1) OR deleted_at < $2)
+        RETURNING id
+        "#,
+        ids,
+        cutoff
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(RepositoryError::from_sqlx)?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM undo_steps s
+        WHERE EXISTS (
+            SELECT 1 FROM friend_history h
+            WHERE h.user_id = s.user_id
+              AND h.transaction_id = s.transaction_id
+              AND h.friend_id = ANY($1)
+        )
+        "#,
+        &purged
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(RepositoryError::from_sqlx)?;
+
+    sqlx::query_scalar!("SELECT set_config('fkb.purging', 'off', true)")
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+    Ok(purged.len() as u64)
 }
 
 #[async_trait]
@@ -176,32 +481,44 @@ impl Repository for FriendRepository {
     type CreateInput = CreateFriendInput;
     type UpdateInput = UpdateFriendInput;
 
-    async fn find_by_id(&self, id: Uuid) -> Result<Option<Friend>, RepositoryError> {
+    fn context(&self) -> &RepositoryContext {
+        &self.ctx
+    }
+
+    async fn find_by_id_in(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+    ) -> Result<Option<Friend>, RepositoryError> {
         let friend = sqlx::query_as!(
             Friend,
             r#"
             SELECT id, user_id, first_name, last_name, date_of_birth,
-                   likes, dislikes, notes, created_at, updated_at
+                   likes, dislikes, notes, created_at, updated_at, version
             FROM friends
-            WHERE id = $1
+            WHERE id = $1 AND deleted_at IS NULL
             "#,
             id
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
         Ok(friend)
     }
 
-    async fn create(&self, input: CreateFriendInput) -> Result<Friend, RepositoryError> {
+    async fn create_in(
+        &self,
+        conn: &mut PgConnection,
+        input: CreateFriendInput,
+    ) -> Result<Friend, RepositoryError> {
         let friend = sqlx::query_as!(
             Friend,
             r#"
             INSERT INTO friends (user_id, first_name, last_name, date_of_birth, likes, dislikes, notes)
             VALUES ($1, $2, $3, $4, $5, $6, $7)
             RETURNING id, user_id, first_name, last_name, date_of_birth,
-                      likes, dislikes, notes, created_at, updated_at
+                      likes, dislikes, notes, created_at, updated_at, version
             "#,
             input.user_id,
             input.first_name,
@@ -211,54 +528,82 @@ impl Repository for FriendRepository {
             input.dislikes,
             input.notes
         )
-        .fetch_one(&self.ctx.pool)
+        .fetch_one(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
         Ok(friend)
     }
 
-    async fn update(&self, id: Uuid, input: UpdateFriendInput) -> Result<Friend, RepositoryError> {
+    async fn update_in(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+        expected_version: Option<i64>,
+        input: UpdateFriendInput,
+    ) -> Result<Friend, RepositoryError> {
         let friend = sqlx::query_as!(
             Friend,
             r#"
             UPDATE friends
             SET
                 first_name = COALESCE($2, first_name),
-                last_name = COALESCE($3, last_name),
-                date_of_birth = COALESCE($4, date_of_birth),
-                likes = COALESCE($5, likes),
-                dislikes = COALESCE($6, dislikes),
-                notes = COALESCE($7, notes)
-            WHERE id = $1
+                last_name = CASE WHEN $9 THEN $3 ELSE last_name END,
+                date_of_birth = CASE WHEN $10 THEN $4 ELSE date_of_birth END,
+                likes = CASE WHEN $11 THEN $5 ELSE likes END,
+                dislikes = CASE WHEN $12 THEN $6 ELSE dislikes END,
+                notes = CASE WHEN $13 THEN $7 ELSE notes END
+            WHERE id = $1 AND deleted_at IS NULL
+              AND ($8::bigint IS NULL OR version = $8)
             RETURNING id, user_id, first_name, last_name, date_of_birth,
-                      likes, dislikes, notes, created_at, updated_at
+                      likes, dislikes, notes, created_at, updated_at, version
             "#,
             id,
             input.first_name,
-            input.last_name,
-            input.date_of_birth,
-            input.likes,
-            input.dislikes,
-            input.notes
+            input.last_name.clone().flatten(),
+            input.date_of_birth.flatten(),
+            input.likes.clone().flatten(),
+            input.dislikes.clone().flatten(),
+            input.notes.clone().flatten(),
+            expected_version,
+            input.last_name.is_some(),
+            input.date_of_birth.is_some(),
+            input.likes.is_some(),
+            input.dislikes.is_some(),
+            input.notes.is_some()
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
-        .map_err(RepositoryError::from_sqlx)?
-        .ok_or(RepositoryError::NotFound)?;
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let Some(friend) = friend else {
+            let current = sqlx::query_scalar!(
+                "SELECT version FROM friends WHERE id = $1 AND deleted_at IS NULL",
+                id
+            )
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+            return Err(RepositoryError::missed_update(expected_version, current));
+        };
 
         Ok(friend)
     }
 
-    async fn delete(&self, id: Uuid) -> Result<bool, RepositoryError> {
+    /// Move a friend to the trash (see "Soft Delete" above).
+    ///
+    /// Returns `Ok(false)` if the friend doesn't exist or is already in
+    /// the trash.
+    async fn delete_in(&self, conn: &mut PgConnection, id: Uuid) -> Result<bool, RepositoryError> {
         let result = sqlx::query!(
             r#"
-            DELETE FROM friends
-            WHERE id = $1
+            UPDATE friends
+            SET deleted_at = now()
+            WHERE id = $1 AND deleted_at IS NULL
             "#,
             id
         )
-        .execute(&self.ctx.pool)
+        .execute(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
@@ -4,6 +4,7 @@
 //! Groups help organize friends into categories.
 
 use async_trait::async_trait;
+use sqlx::PgConnection;
 use uuid::Uuid;
 
 use crate::models::{Friend, Group};
@@ -52,7 +53,7 @@ impl GroupRepository {
         let groups = sqlx::query_as!(
             Group,
             r#"
-            SELECT id, user_id, name, description, created_at, updated_at
+            SELECT id, user_id, name, description, created_at, updated_at, version
             FROM groups
             WHERE user_id = $1
             ORDER BY name ASC
@@ -80,10 +81,10 @@ impl GroupRepository {
             Friend,
             r#"
             SELECT f.id, f.user_id, f.first_name, f.last_name, f.date_of_birth,
-                   f.likes, f.dislikes, f.notes, f.created_at, f.updated_at
+                   f.likes, f.dislikes, f.notes, f.created_at, f.updated_at, f.version
             FROM friends f
             INNER JOIN friend_groups fg ON fg.friend_id = f.id
-            WHERE fg.group_id = $1
+            WHERE fg.group_id = $1 AND f.deleted_at IS NULL
             ORDER BY f.first_name ASC
             "#,
             group_id
@@ -102,43 +103,61 @@ impl Repository for GroupRepository {
     type CreateInput = CreateGroupInput;
     type UpdateInput = UpdateGroupInput;
 
-    async fn find_by_id(&self, id: Uuid) -> Result<Option<Group>, RepositoryError> {
+    fn context(&self) -> &RepositoryContext {
+        &self.ctx
+    }
+
+    async fn find_by_id_in(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+    ) -> Result<Option<Group>, RepositoryError> {
         let group = sqlx::query_as!(
             Group,
             r#"
-            SELECT id, user_id, name, description, created_at, updated_at
+            SELECT id, user_id, name, description, created_at, updated_at, version
             FROM groups
             WHERE id = $1
             "#,
             id
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
         Ok(group)
     }
 
-    async fn create(&self, input: CreateGroupInput) -> Result<Group, RepositoryError> {
+    async fn create_in(
+        &self,
+        conn: &mut PgConnection,
+        input: CreateGroupInput,
+    ) -> Result<Group, RepositoryError> {
         let group = sqlx::query_as!(
             Group,
             r#"
             INSERT INTO groups (user_id, name, description)
             VALUES ($1, $2, $3)
-            RETURNING id, user_id, name, description, created_at, updated_at
+            RETURNING id, user_id, name, description, created_at, updated_at, version
             "#,
             input.user_id,
             input.name,
             input.description
         )
-        .fetch_one(&self.ctx.pool)
+        .fetch_one(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
         Ok(group)
     }
 
-    async fn update(&self, id: Uuid, input: UpdateGroupInput) -> Result<Group, RepositoryError> {
+    async fn update_in(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+        expected_version: Option<i64>,
+        input: UpdateGroupInput,
+    ) -> Result<Group, RepositoryError> {
         let group = sqlx::query_as!(
             Group,
             r#"
@@ -146,22 +165,30 @@ impl Repository for GroupRepository {
             SET
                 name = COALESCE($2, name),
                 description = COALESCE($3, description)
-            WHERE id = $1
-            RETURNING id, user_id, name, description, created_at, updated_at
+            WHERE id = $1 AND ($4::bigint IS NULL OR version = $4)
+            RETURNING id, user_id, name, description, created_at, updated_at, version
             "#,
             id,
             input.name,
-            input.description
+            input.description,
+            expected_version
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
-        .map_err(RepositoryError::from_sqlx)?
-        .ok_or(RepositoryError::NotFound)?;
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let Some(group) = group else {
+            let current = sqlx::query_scalar!("SELECT version FROM groups WHERE id = $1", id)
+                .fetch_optional(&mut *conn)
+                .await
+                .map_err(RepositoryError::from_sqlx)?;
+            return Err(RepositoryError::missed_update(expected_version, current));
+        };
 
         Ok(group)
     }
 
-    async fn delete(&self, id: Uuid) -> Result<bool, RepositoryError> {
+    async fn delete_in(&self, conn: &mut PgConnection, id: Uuid) -> Result<bool, RepositoryError> {
         let result = sqlx::query!(
             r#"
             DELETE FROM groups
@@ -169,7 +196,7 @@ impl Repository for GroupRepository {
             "#,
             id
         )
-        .execute(&self.ctx.pool)
+        .execute(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
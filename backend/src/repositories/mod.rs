@@ -17,6 +17,8 @@
 //! - `RepositoryContext` - Holds the database connection pool
 //! - `Repository` trait - Generic CRUD interface
 //! - `RepositoryError` - Error types for database operations
+//! - `Timeline` - Current vs full-history filter for relationship queries
+//! - `UnitOfWork` - One transaction with every repository bound to it
 //!
 //! ## Pattern
 //!
@@ -25,10 +27,13 @@
 //! 2. Implements the `Repository` trait for standard CRUD
 //! 3. Adds custom finder methods as needed (e.g., `find_by_email`)
 //! 4. Uses `sqlx::query!` macro for compile-time SQL validation
+//! 5. Implements the `Repository` methods on a `&mut PgConnection`
+//!    (`create_in`, ...), so they can join a caller's transaction
 
 // Core infrastructure
 pub mod base;
 pub mod error;
+pub mod unit_of_work;
 
 // Entity repositories
 pub mod user_repository;
@@ -37,15 +42,38 @@ pub mod group_repository;
 pub mod friend_attribute_repository;
 pub mod friend_relationship_repository;
 pub mod user_friend_relationship_repository;
+pub mod calendar_feed_repository;
+pub mod friend_change_repository;
+pub mod carddav_repository;
+pub mod friend_history_repository;
+pub mod undo_repository;
+
+// Multi-table operations on friends
+pub mod friend_merge_repository;
+pub mod friend_bulk_repository;
+pub mod friend_profile_repository;
+
+// Whole-account reads and writes (backup/restore)
+pub mod backup_repository;
 
 // Re-export core types for convenient access
-pub use base::{Repository, RepositoryContext};
+pub use base::{Repository, RepositoryContext, Timeline};
 pub use error::RepositoryError;
+pub use unit_of_work::{Bound, UnitOfWork};
 
 // Re-export repositories
 pub use user_repository::{UserRepository, CreateUserInput, UpdateUserInput};
 pub use friend_repository::{FriendRepository, CreateFriendInput, UpdateFriendInput};
 pub use group_repository::{GroupRepository, CreateGroupInput, UpdateGroupInput};
-pub use friend_attribute_repository::{FriendAttributeRepository, CreateFriendAttributeInput, UpdateFriendAttributeInput};
+pub use friend_attribute_repository::{FriendAttributeRepository, AttributeChanges, AttributeInput, CreateFriendAttributeInput, UpdateFriendAttributeInput};
 pub use friend_relationship_repository::{FriendRelationshipRepository, CreateFriendRelationshipInput, UpdateFriendRelationshipInput};
 pub use user_friend_relationship_repository::{UserFriendRelationshipRepository, CreateUserFriendRelationshipInput, UpdateUserFriendRelationshipInput};
+pub use calendar_feed_repository::CalendarFeedRepository;
+pub use friend_change_repository::FriendChangeRepository;
+pub use carddav_repository::CardDavRepository;
+pub use friend_history_repository::{FriendAsOf, FriendHistoryRepository};
+pub use undo_repository::{UndoRepository, UndoStep};
+pub use friend_merge_repository::{AttributeChoice, FriendMergeRepository, MergeFriendsInput, MergeReport};
+pub use friend_bulk_repository::{BulkAttributeInput, BulkItemResult, BulkOutcome, BulkReport, FriendBulkRepository};
+pub use friend_profile_repository::{FriendProfile, FriendProfileRepository, RelatedFriend};
+pub use backup_repository::{BackupRepository, RestoredAccount, UserSnapshot};
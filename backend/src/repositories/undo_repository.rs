@@ -0,0 +1,276 @@
+//! # Undo Repository
+//!
+//! Per-user undo and redo of changes to friends, their attributes, their
+//! group memberships and their relationships.
+//!
+//! ## How It Works
+//!
+//! Every transaction that changes a user's friends is one undo step
+//! (migration 007 records them as the history triggers of migration 006
+//! fire). The history rows a step wrote hold each touched row before and
+//! after, so undoing puts every row back to its first `before` and redoing
+//! puts it forward to its last `after`.
+//!
+//! ## Stacks
+//!
+//! Undo takes the newest step that hasn't been undone; redo takes the one
+//! undone last. Any other change clears the redo stack, and only the
+//! newest 50 steps are kept.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use super::base::RepositoryContext;
+use super::error::RepositoryError;
+
+/// A step that was just undone or redone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoStep {
+    /// The database transaction that made the original change
+    pub transaction_id: i64,
+    /// When the original change was made
+    pub changed_at: OffsetDateTime,
+    /// The friends the step touched
+    pub friend_ids: Vec<Uuid>,
+    /// How many rows were put back (or forward)
+    pub rows: usize,
+}
+
+/// Which way a step is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Undo,
+    Redo,
+}
+
+/// One row a step touched, collapsed over all its history entries.
+struct StepRow {
+    table_name: String,
+    row_key: String,
+    /// Before the step's first change to the row
+    before: Option<Value>,
+    /// After the step's last change to the row
+    after: Option<Value>,
+}
+
+/// Repository for undoing and redoing changes.
+pub struct UndoRepository {
+    ctx: RepositoryContext,
+}
+
+impl UndoRepository {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self { ctx }
+    }
+
+    /// Revert the user's most recent change.
+    ///
+    /// Every row the change touched is restored in one transaction, and
+    /// the step moves to the redo stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the user
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is nothing to undo
+    /// - `Validation` if a row has changed since in a way the step can't
+    ///   be reverted over; nothing is changed then
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// friends.delete(friend.id).await?;
+    /// undo.undo(user.id).await?; // the friend is back out of the trash
+    /// ```
+    pub async fn undo(&self, user_id: Uuid) -> Result<UndoStep, RepositoryError> {
+        self.apply(user_id, Direction::Undo).await
+    }
+
+    /// Re-apply the change undone last.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the user
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is nothing to redo
+    /// - `Validation` if a row has changed since; nothing is changed then
+    pub async fn redo(&self, user_id: Uuid) -> Result<UndoStep, RepositoryError> {
+        self.apply(user_id, Direction::Redo).await
+    }
+
+    async fn apply(
+        &self,
+        user_id: Uuid,
+        direction: Direction,
+    ) -> Result<UndoStep, RepositoryError> {
+        let mut tx = self.ctx.transaction().await?;
+
+        // Same lock as the change log: one writer per user at a time
+        sqlx::query!(
+            "SELECT pg_advisory_xact_lock(hashtextextended($1::text, 0))",
+            user_id.to_string()
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        // Keeps this transaction's own changes off the stacks
+        sqlx::query_scalar!("SELECT set_config('fkb.undoing', 'on', true)")
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+
+        // Undone steps are always the newest, so the one undone last is
+        // the oldest of them
+        let step = match direction {
+            Direction::Undo => sqlx::query!(
+                r#"
+                    SELECT id, transaction_id
+                    FROM undo_steps
+                    WHERE user_id = $1 AND undone_at IS NULL
+                    ORDER BY id DESC
+                    LIMIT 1
+                    "#,
+                user_id
+            )
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(RepositoryError::from_sqlx)?
+            .map(|row| (row.id, row.transaction_id)),
+            Direction::Redo => sqlx::query!(
+                r#"
+                    SELECT id, transaction_id
+                    FROM undo_steps
+                    WHERE user_id = $1 AND undone_at IS NOT NULL
+                    ORDER BY id ASC
+                    LIMIT 1
+                    "#,
+                user_id
+            )
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(RepositoryError::from_sqlx)?
+            .map(|row| (row.id, row.transaction_id)),
+        };
+        let (step_id, transaction_id) = step.ok_or(RepositoryError::NotFound)?;
+
+        let entries = sqlx::query!(
+            r#"
+            SELECT friend_id, table_name, row_key, before, after, changed_at
+            FROM friend_history
+            WHERE user_id = $1 AND transaction_id = $2
+            ORDER BY id ASC
+            "#,
+            user_id,
+            transaction_id
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let changed_at = entries
+            .first()
+            .map(|entry| entry.changed_at)
+            .ok_or(RepositoryError::NotFound)?;
+
+        // Relationship changes are recorded once per side, and a row may
+        // change several times in one step
+        let mut friend_ids = Vec::new();
+        let mut rows: Vec<StepRow> = Vec::new();
+        let mut positions: HashMap<(String, String), usize> = HashMap::new();
+        for entry in entries {
+            if !friend_ids.contains(&entry.friend_id) {
+                friend_ids.push(entry.friend_id);
+            }
+            let key = (entry.table_name.clone(), entry.row_key.clone());
+            match positions.get(&key) {
+                Some(&i) => rows[i].after = entry.after,
+                None => {
+                    positions.insert(key, rows.len());
+                    rows.push(StepRow {
+                        table_name: entry.table_name,
+                        row_key: entry.row_key,
+                        before: entry.before,
+                        after: entry.after,
+                    });
+                }
+            }
+        }
+
+        let mut changes: Vec<(&StepRow, Option<&Value>, Option<&Value>)> = rows
+            .iter()
+            .map(|row| match direction {
+                Direction::Undo => (row, row.after.as_ref(), row.before.as_ref()),
+                Direction::Redo => (row, row.before.as_ref(), row.after.as_ref()),
+            })
+            .collect();
+
+        // Friends exist before the rows that hang off them are written,
+        // and are deleted after them
+        changes.sort_by_key(|(row, _, target)| {
+            match (target.is_some(), row.table_name == "friends") {
+                (true, true) => 0,
+                (true, false) => 1,
+                (false, false) => 2,
+                (false, true) => 3,
+            }
+        });
+
+        for (row, expected, target) in &changes {
+            let applied = sqlx::query_scalar!(
+                r#"SELECT apply_history_row($1, $2, $3, $4) AS "applied!""#,
+                row.table_name,
+                row.row_key,
+                *expected,
+                *target
+            )
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| match RepositoryError::from_sqlx(e) {
+                RepositoryError::ForeignKeyViolation(_) => RepositoryError::Validation(format!(
+                    "{} {} refers to something that no longer exists",
+                    row.table_name, row.row_key
+                )),
+                other => other,
+            })?;
+
+            if !applied {
+                return Err(RepositoryError::Validation(format!(
+                    "{} {} has changed since",
+                    row.table_name, row.row_key
+                )));
+            }
+        }
+
+        match direction {
+            Direction::Undo => sqlx::query!(
+                "UPDATE undo_steps SET undone_at = now() WHERE id = $1",
+                step_id
+            ),
+            Direction::Redo => sqlx::query!(
+                "UPDATE undo_steps SET undone_at = NULL WHERE id = $1",
+                step_id
+            ),
+        }
+        .execute(&mut *tx)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        tx.commit().await.map_err(RepositoryError::from_sqlx)?;
+
+        Ok(UndoStep {
+            transaction_id,
+            changed_at,
+            friend_ids,
+            rows: changes.len(),
+        })
+    }
+}
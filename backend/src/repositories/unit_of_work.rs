@@ -0,0 +1,225 @@
+//! # Unit of Work
+//!
+//! Binds the repositories to one transaction, so a multi-step operation
+//! (create a friend, put them in a group, give them attributes) commits
+//! or rolls back as a whole.
+//!
+//! ## Usage
+//!
+//! ```rust,ignore
+//! let mut work = UnitOfWork::begin(&ctx).await?;
+//!
+//! let friend = work.friends().create(input).await?;
+//! work.friends().add_to_group(friend.id, group.id).await?;
+//! work.attributes().upsert(email_input).await?;
+//!
+//! work.commit().await?;
+//! ```
+//!
+//! Each accessor (`friends()`, `groups()`, ...) hands out the repository
+//! bound to the transaction for as long as the borrow lasts. Dropping the
+//! unit of work without `commit` rolls everything back, so an early
+//! return with `?` leaves the database untouched.
+//!
+//! ## Other Repositories
+//!
+//! Anything with an `_in` method that the bound repositories don't wrap
+//! can still join the transaction through `conn()`:
+//!
+//! ```rust,ignore
+//! let report = FriendMergeRepository::new(ctx.clone())
+//!     .merge_in(work.conn(), input)
+//!     .await?;
+//! ```
+
+use sqlx::{PgConnection, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::models::{FriendAttribute, FriendRelationship};
+
+use super::base::{Repository, RepositoryContext};
+use super::error::RepositoryError;
+use super::friend_attribute_repository::{
+    AttributeChanges, AttributeInput, CreateFriendAttributeInput, FriendAttributeRepository,
+};
+use super::friend_relationship_repository::FriendRelationshipRepository;
+use super::friend_repository::FriendRepository;
+use super::group_repository::GroupRepository;
+use super::user_friend_relationship_repository::UserFriendRelationshipRepository;
+use super::user_repository::UserRepository;
+
+/// A transaction with every entity repository bound to it.
+pub struct UnitOfWork {
+    ctx: RepositoryContext,
+    tx: Transaction<'static, Postgres>,
+}
+
+impl UnitOfWork {
+    /// Start a unit of work in a new transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns a RepositoryError if the transaction cannot be started
+    /// (e.g., pool exhausted, database connection lost).
+    pub async fn begin(ctx: &RepositoryContext) -> Result<Self, RepositoryError> {
+        let tx = ctx.pool.begin().await.map_err(RepositoryError::from_sqlx)?;
+        Ok(Self {
+            ctx: ctx.clone(),
+            tx,
+        })
+    }
+
+    /// The transaction's connection, for `_in` methods of any repository.
+    pub fn conn(&mut self) -> &mut PgConnection {
+        &mut self.tx
+    }
+
+    pub fn users(&mut self) -> Bound<'_, UserRepository> {
+        self.bind(UserRepository::new(self.ctx.clone()))
+    }
+
+    pub fn friends(&mut self) -> Bound<'_, FriendRepository> {
+        self.bind(FriendRepository::new(self.ctx.clone()))
+    }
+
+    pub fn groups(&mut self) -> Bound<'_, GroupRepository> {
+        self.bind(GroupRepository::new(self.ctx.clone()))
+    }
+
+    pub fn attributes(&mut self) -> Bound<'_, FriendAttributeRepository> {
+        self.bind(FriendAttributeRepository::new(self.ctx.clone()))
+    }
+
+    pub fn friend_relationships(&mut self) -> Bound<'_, FriendRelationshipRepository> {
+        self.bind(FriendRelationshipRepository::new(self.ctx.clone()))
+    }
+
+    pub fn user_friend_relationships(&mut self) -> Bound<'_, UserFriendRelationshipRepository> {
+        self.bind(UserFriendRelationshipRepository::new(self.ctx.clone()))
+    }
+
+    /// Make every change of the unit of work permanent.
+    pub async fn commit(self) -> Result<(), RepositoryError> {
+        self.tx.commit().await.map_err(RepositoryError::from_sqlx)
+    }
+
+    /// Undo every change of the unit of work.
+    ///
+    /// Dropping it does the same; this just makes it explicit.
+    pub async fn rollback(self) -> Result<(), RepositoryError> {
+        self.tx.rollback().await.map_err(RepositoryError::from_sqlx)
+    }
+
+    fn bind<R>(&mut self, repository: R) -> Bound<'_, R> {
+        Bound {
+            repository,
+            conn: &mut self.tx,
+        }
+    }
+}
+
+/// A repository whose methods run in a unit of work's transaction.
+///
+/// Offers the `Repository` CRUD methods for every repository, plus the
+/// custom methods listed on the `impl` blocks below.
+pub struct Bound<'a, R> {
+    repository: R,
+    conn: &'a mut PgConnection,
+}
+
+impl<R: Repository> Bound<'_, R> {
+    /// See `Repository::find_by_id`.
+    pub async fn find_by_id(&mut self, id: Uuid) -> Result<Option<R::Entity>, RepositoryError> {
+        self.repository.find_by_id_in(self.conn, id).await
+    }
+
+    /// See `Repository::create`.
+    pub async fn create(&mut self, input: R::CreateInput) -> Result<R::Entity, RepositoryError> {
+        self.repository.create_in(self.conn, input).await
+    }
+
+    /// See `Repository::update`.
+    pub async fn update(
+        &mut self,
+        id: Uuid,
+        expected_version: Option<i64>,
+        input: R::UpdateInput,
+    ) -> Result<R::Entity, RepositoryError> {
+        self.repository
+            .update_in(self.conn, id, expected_version, input)
+            .await
+    }
+
+    /// See `Repository::delete`.
+    pub async fn delete(&mut self, id: Uuid) -> Result<bool, RepositoryError> {
+        self.repository.delete_in(self.conn, id).await
+    }
+}
+
+impl Bound<'_, FriendRepository> {
+    /// See `FriendRepository::add_to_group`.
+    pub async fn add_to_group(
+        &mut self,
+        friend_id: Uuid,
+        group_id: Uuid,
+    ) -> Result<(), RepositoryError> {
+        self.repository
+            .add_to_group_in(self.conn, friend_id, group_id)
+            .await
+    }
+
+    /// See `FriendRepository::remove_from_group`.
+    pub async fn remove_from_group(
+        &mut self,
+        friend_id: Uuid,
+        group_id: Uuid,
+    ) -> Result<bool, RepositoryError> {
+        self.repository
+            .remove_from_group_in(self.conn, friend_id, group_id)
+            .await
+    }
+}
+
+impl Bound<'_, FriendAttributeRepository> {
+    /// See `FriendAttributeRepository::list_by_friend`.
+    pub async fn list_by_friend(
+        &mut self,
+        friend_id: Uuid,
+    ) -> Result<Vec<FriendAttribute>, RepositoryError> {
+        self.repository
+            .list_by_friend_in(self.conn, friend_id)
+            .await
+    }
+
+    /// See `FriendAttributeRepository::upsert`.
+    pub async fn upsert(
+        &mut self,
+        input: CreateFriendAttributeInput,
+    ) -> Result<FriendAttribute, RepositoryError> {
+        self.repository.upsert_in(self.conn, input).await
+    }
+
+    /// See `FriendAttributeRepository::replace_all`.
+    pub async fn replace_all(
+        &mut self,
+        friend_id: Uuid,
+        attributes: Vec<AttributeInput>,
+    ) -> Result<AttributeChanges, RepositoryError> {
+        self.repository
+            .replace_all_in(self.conn, friend_id, attributes)
+            .await
+    }
+}
+
+impl Bound<'_, FriendRelationshipRepository> {
+    /// See `FriendRelationshipRepository::find_between`.
+    pub async fn find_between(
+        &mut self,
+        friend_a_id: Uuid,
+        friend_b_id: Uuid,
+    ) -> Result<Option<FriendRelationship>, RepositoryError> {
+        self.repository
+            .find_between_in(self.conn, friend_a_id, friend_b_id)
+            .await
+    }
+}
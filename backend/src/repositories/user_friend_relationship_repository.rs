@@ -4,11 +4,13 @@
 //! These track how the user personally knows each friend.
 
 use async_trait::async_trait;
+use sqlx::PgConnection;
+use time::Date;
 use uuid::Uuid;
 
-use crate::models::UserFriendRelationship;
+use crate::models::{RelationshipStatusChange, UserFriendRelationship};
 
-use super::base::{Repository, RepositoryContext};
+use super::base::{Repository, RepositoryContext, Timeline};
 use super::error::RepositoryError;
 
 /// Input for creating a new user-friend relationship.
@@ -17,14 +19,33 @@ pub struct CreateUserFriendRelationshipInput {
     pub friend_id: Uuid,
     /// How the user knows this friend (e.g., "coworker", "neighbor")
     pub relationship_type: String,
+    /// When the relationship began (optional)
+    pub started_on: Option<Date>,
+    /// When the relationship ended (optional)
+    pub ended_on: Option<Date>,
+    /// Initial status (default: "active")
+    pub status: Option<String>,
 }
 
 /// Input for updating an existing user-friend relationship.
+///
+/// Setting `status` to a new value appends an entry to the status history,
+/// effective on `status_effective_on` (default: today).
 pub struct UpdateUserFriendRelationshipInput {
     pub relationship_type: Option<String>,
+    pub started_on: Option<Date>,
+    /// `Some(None)` clears the end date, reopening the relationship
+    pub ended_on: Option<Option<Date>>,
+    pub status: Option<String>,
+    pub status_effective_on: Option<Date>,
 }
 
 /// Repository for user-friend relationship database operations.
+///
+/// # Status History
+///
+/// Like `FriendRelationshipRepository`, `create` and `update` record status
+/// changes in `user_friend_relationship_status_history` transactionally.
 pub struct UserFriendRelationshipRepository {
     ctx: RepositoryContext,
 }
@@ -34,27 +55,90 @@ impl UserFriendRelationshipRepository {
         Self { ctx }
     }
 
+    /// List every user-friend relationship across all users.
+    ///
+    /// Only intended for maintenance scans like the `Doctor` report -
+    /// request handlers should always filter by user.
+    pub async fn list_all(&self) -> Result<Vec<UserFriendRelationship>, RepositoryError> {
+        let relationships = sqlx::query_as!(
+            UserFriendRelationship,
+            r#"
+            SELECT id, friend_id, relationship_type, started_on, ended_on, status,
+                   created_at, updated_at, version
+            FROM user_friend_relationships
+            ORDER BY created_at ASC
+            "#
+        )
+        .fetch_all(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(relationships)
+    }
+
+    /// List all of a user's relationships with their friends.
+    ///
+    /// Relationships with a trashed friend are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The UUID of the user
+    pub async fn list_by_user(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<UserFriendRelationship>, RepositoryError> {
+        let relationships = sqlx::query_as!(
+            UserFriendRelationship,
+            r#"
+            SELECT r.id, r.friend_id, r.relationship_type, r.started_on, r.ended_on,
+                   r.status, r.created_at, r.updated_at, r.version
+            FROM user_friend_relationships r
+            JOIN friends f ON f.id = r.friend_id
+            WHERE f.user_id = $1 AND f.deleted_at IS NULL
+            ORDER BY r.created_at ASC
+            "#,
+            user_id
+        )
+        .fetch_all(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(relationships)
+    }
+
     /// List all user-friend relationships for a specific friend.
     ///
     /// A friend can have multiple relationship types (e.g., both
-    /// "coworker" and "neighbor" if you work with a neighbor).
+    /// "coworker" and "neighbor" if you work with a neighbor), but each
+    /// type only once: creating a second one is a `Duplicate`.
     ///
     /// # Arguments
     ///
     /// * `friend_id` - The UUID of the friend
+    /// * `timeline` - `Current` for only relationships in effect today,
+    ///   `Full` to include ended ones
     pub async fn list_by_friend(
         &self,
         friend_id: Uuid,
+        timeline: Timeline,
     ) -> Result<Vec<UserFriendRelationship>, RepositoryError> {
         let relationships = sqlx::query_as!(
             UserFriendRelationship,
             r#"
-            SELECT id, friend_id, relationship_type, created_at, updated_at
+            SELECT id, friend_id, relationship_type, started_on, ended_on, status,
+                   created_at, updated_at, version
             FROM user_friend_relationships
             WHERE friend_id = $1
+              AND (
+                  NOT $2
+                  OR (status = 'active'
+                      AND (started_on IS NULL OR started_on <= CURRENT_DATE)
+                      AND (ended_on IS NULL OR ended_on > CURRENT_DATE))
+              )
             ORDER BY relationship_type ASC
             "#,
-            friend_id
+            friend_id,
+            timeline.current_only()
         )
         .fetch_all(&self.ctx.pool)
         .await
@@ -79,7 +163,8 @@ impl UserFriendRelationshipRepository {
         let relationship = sqlx::query_as!(
             UserFriendRelationship,
             r#"
-            SELECT id, friend_id, relationship_type, created_at, updated_at
+            SELECT id, friend_id, relationship_type, started_on, ended_on, status,
+                   created_at, updated_at, version
             FROM user_friend_relationships
             WHERE friend_id = $1 AND relationship_type = $2
             "#,
@@ -92,6 +177,32 @@ impl UserFriendRelationshipRepository {
 
         Ok(relationship)
     }
+
+    /// List the status history of a relationship, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `relationship_id` - The UUID of the relationship
+    pub async fn list_status_history(
+        &self,
+        relationship_id: Uuid,
+    ) -> Result<Vec<RelationshipStatusChange>, RepositoryError> {
+        let history = sqlx::query_as!(
+            RelationshipStatusChange,
+            r#"
+            SELECT id, relationship_id, status, effective_on, created_at
+            FROM user_friend_relationship_status_history
+            WHERE relationship_id = $1
+            ORDER BY effective_on ASC, created_at ASC
+            "#,
+            relationship_id
+        )
+        .fetch_all(&self.ctx.pool)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        Ok(history)
+    }
 }
 
 #[async_trait]
@@ -100,72 +211,141 @@ impl Repository for UserFriendRelationshipRepository {
     type CreateInput = CreateUserFriendRelationshipInput;
     type UpdateInput = UpdateUserFriendRelationshipInput;
 
-    async fn find_by_id(
+    fn context(&self) -> &RepositoryContext {
+        &self.ctx
+    }
+
+    async fn find_by_id_in(
         &self,
+        conn: &mut PgConnection,
         id: Uuid,
     ) -> Result<Option<UserFriendRelationship>, RepositoryError> {
         let relationship = sqlx::query_as!(
             UserFriendRelationship,
             r#"
-            SELECT id, friend_id, relationship_type, created_at, updated_at
+            SELECT id, friend_id, relationship_type, started_on, ended_on, status,
+                   created_at, updated_at, version
             FROM user_friend_relationships
             WHERE id = $1
             "#,
             id
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
         Ok(relationship)
     }
 
-    async fn create(
+    async fn create_in(
         &self,
+        conn: &mut PgConnection,
         input: CreateUserFriendRelationshipInput,
     ) -> Result<UserFriendRelationship, RepositoryError> {
+        // The relationship and its first history entry are written together
         let relationship = sqlx::query_as!(
             UserFriendRelationship,
             r#"
-            INSERT INTO user_friend_relationships (friend_id, relationship_type)
-            VALUES ($1, $2)
-            RETURNING id, friend_id, relationship_type, created_at, updated_at
+            INSERT INTO user_friend_relationships
+                (friend_id, relationship_type, started_on, ended_on, status)
+            VALUES ($1, $2, $3, $4, COALESCE($5, 'active'))
+            RETURNING id, friend_id, relationship_type, started_on, ended_on, status,
+                      created_at, updated_at, version
             "#,
             input.friend_id,
-            input.relationship_type
+            input.relationship_type,
+            input.started_on,
+            input.ended_on,
+            input.status
         )
-        .fetch_one(&self.ctx.pool)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(RepositoryError::from_sqlx)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO user_friend_relationship_status_history (relationship_id, status, effective_on)
+            VALUES ($1, $2, COALESCE($3, CURRENT_DATE))
+            "#,
+            relationship.id,
+            relationship.status,
+            relationship.started_on
+        )
+        .execute(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
         Ok(relationship)
     }
 
-    async fn update(
+    async fn update_in(
         &self,
+        conn: &mut PgConnection,
         id: Uuid,
+        expected_version: Option<i64>,
         input: UpdateUserFriendRelationshipInput,
     ) -> Result<UserFriendRelationship, RepositoryError> {
+        let existing = self
+            .find_by_id_in(&mut *conn, id)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+
         let relationship = sqlx::query_as!(
             UserFriendRelationship,
             r#"
             UPDATE user_friend_relationships
-            SET relationship_type = COALESCE($2, relationship_type)
-            WHERE id = $1
-            RETURNING id, friend_id, relationship_type, created_at, updated_at
+            SET
+                relationship_type = COALESCE($2, relationship_type),
+                started_on = COALESCE($3, started_on),
+                ended_on = CASE WHEN $7 THEN $4 ELSE ended_on END,
+                status = COALESCE($5, status)
+            WHERE id = $1 AND ($6::bigint IS NULL OR version = $6)
+            RETURNING id, friend_id, relationship_type, started_on, ended_on, status,
+                      created_at, updated_at, version
             "#,
             id,
-            input.relationship_type
+            input.relationship_type,
+            input.started_on,
+            input.ended_on.flatten(),
+            input.status,
+            expected_version,
+            input.ended_on.is_some()
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
-        .map_err(RepositoryError::from_sqlx)?
-        .ok_or(RepositoryError::NotFound)?;
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let Some(relationship) = relationship else {
+            let current = sqlx::query_scalar!(
+                "SELECT version FROM user_friend_relationships WHERE id = $1",
+                id
+            )
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+            return Err(RepositoryError::missed_update(expected_version, current));
+        };
+
+        // Only actual status changes go into the history
+        if relationship.status != existing.status {
+            sqlx::query!(
+                r#"
+                INSERT INTO user_friend_relationship_status_history (relationship_id, status, effective_on)
+                VALUES ($1, $2, COALESCE($3, CURRENT_DATE))
+                "#,
+                relationship.id,
+                relationship.status,
+                input.status_effective_on
+            )
+            .execute(&mut *conn)
+            .await
+            .map_err(RepositoryError::from_sqlx)?;
+        }
 
         Ok(relationship)
     }
 
-    async fn delete(&self, id: Uuid) -> Result<bool, RepositoryError> {
+    async fn delete_in(&self, conn: &mut PgConnection, id: Uuid) -> Result<bool, RepositoryError> {
         let result = sqlx::query!(
             r#"
             DELETE FROM user_friend_relationships
@@ -173,7 +353,7 @@ impl Repository for UserFriendRelationshipRepository {
             "#,
             id
         )
-        .execute(&self.ctx.pool)
+        .execute(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
@@ -4,6 +4,7 @@
 //! Handles CRUD operations and custom queries for the `users` table.
 
 use async_trait::async_trait;
+use sqlx::PgConnection;
 use uuid::Uuid;
 
 use crate::models::User;
@@ -101,7 +102,7 @@ impl UserRepository {
         let user = sqlx::query_as!(
             User,
             r#"
-            SELECT id, first_name, last_name, email, password_hash, created_at, updated_at
+            SELECT id, first_name, last_name, email, password_hash, created_at, updated_at, version
             FROM users
             WHERE email = $1
             "#,
@@ -121,18 +122,26 @@ impl Repository for UserRepository {
     type CreateInput = CreateUserInput;
     type UpdateInput = UpdateUserInput;
 
+    fn context(&self) -> &RepositoryContext {
+        &self.ctx
+    }
+
     /// Find a user by their UUID.
-    async fn find_by_id(&self, id: Uuid) -> Result<Option<User>, RepositoryError> {
+    async fn find_by_id_in(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+    ) -> Result<Option<User>, RepositoryError> {
         let user = sqlx::query_as!(
             User,
             r#"
-            SELECT id, first_name, last_name, email, password_hash, created_at, updated_at
+            SELECT id, first_name, last_name, email, password_hash, created_at, updated_at, version
             FROM users
             WHERE id = $1
             "#,
             id
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
@@ -144,7 +153,11 @@ impl Repository for UserRepository {
     /// # Errors
     ///
     /// Returns `Duplicate` if the email already exists (unique constraint).
-    async fn create(&self, input: CreateUserInput) -> Result<User, RepositoryError> {
+    async fn create_in(
+        &self,
+        conn: &mut PgConnection,
+        input: CreateUserInput,
+    ) -> Result<User, RepositoryError> {
         // INSERT ... RETURNING gives us the created row back, including
         // the generated UUID and timestamps.
         let user = sqlx::query_as!(
@@ -152,14 +165,15 @@ impl Repository for UserRepository {
             r#"
             INSERT INTO users (first_name, last_name, email, password_hash)
             VALUES ($1, $2, $3, $4)
-            RETURNING id, first_name, last_name, email, password_hash, created_at, updated_at
+            RETURNING id, first_name, last_name, email, password_hash, created_at, updated_at,
+                      version
             "#,
             input.first_name,
             input.last_name,
             input.email,
             input.password_hash
         )
-        .fetch_one(&self.ctx.pool)
+        .fetch_one(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
@@ -170,7 +184,13 @@ impl Repository for UserRepository {
     ///
     /// Uses COALESCE to only update fields that are provided (not NULL).
     /// This is a common pattern for partial updates.
-    async fn update(&self, id: Uuid, input: UpdateUserInput) -> Result<User, RepositoryError> {
+    async fn update_in(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+        expected_version: Option<i64>,
+        input: UpdateUserInput,
+    ) -> Result<User, RepositoryError> {
         // COALESCE returns the first non-NULL argument.
         // So COALESCE($2, first_name) means: use $2 if provided, else keep current value.
         let user = sqlx::query_as!(
@@ -182,19 +202,28 @@ impl Repository for UserRepository {
                 last_name = COALESCE($3, last_name),
                 email = COALESCE($4, email),
                 password_hash = COALESCE($5, password_hash)
-            WHERE id = $1
-            RETURNING id, first_name, last_name, email, password_hash, created_at, updated_at
+            WHERE id = $1 AND ($6::bigint IS NULL OR version = $6)
+            RETURNING id, first_name, last_name, email, password_hash, created_at, updated_at,
+                      version
             "#,
             id,
             input.first_name,
             input.last_name,
             input.email,
-            input.password_hash
+            input.password_hash,
+            expected_version
         )
-        .fetch_optional(&self.ctx.pool)
+        .fetch_optional(&mut *conn)
         .await
-        .map_err(RepositoryError::from_sqlx)?
-        .ok_or(RepositoryError::NotFound)?;
+        .map_err(RepositoryError::from_sqlx)?;
+
+        let Some(user) = user else {
+            let current = sqlx::query_scalar!("SELECT version FROM users WHERE id = $1", id)
+                .fetch_optional(&mut *conn)
+                .await
+                .map_err(RepositoryError::from_sqlx)?;
+            return Err(RepositoryError::missed_update(expected_version, current));
+        };
 
         Ok(user)
     }
@@ -203,7 +232,7 @@ impl Repository for UserRepository {
     ///
     /// Due to cascading deletes, this will also delete all of the user's
     /// friends, groups, and related data.
-    async fn delete(&self, id: Uuid) -> Result<bool, RepositoryError> {
+    async fn delete_in(&self, conn: &mut PgConnection, id: Uuid) -> Result<bool, RepositoryError> {
         // result.rows_affected() tells us how many rows were deleted.
         // Should be 0 or 1 since id is a primary key.
         let result = sqlx::query!(
@@ -213,7 +242,7 @@ impl Repository for UserRepository {
             "#,
             id
         )
-        .execute(&self.ctx.pool)
+        .execute(&mut *conn)
         .await
         .map_err(RepositoryError::from_sqlx)?;
 
@@ -0,0 +1,220 @@
+//! # Auth Service
+//!
+//! Accounts and their passwords. Only bcrypt hashes are stored; the
+//! hashing runs on the blocking thread pool, since bcrypt is deliberately
+//! slow and would otherwise stall the async workers.
+//!
+//! ## Password Rules
+//!
+//! At least `MIN_PASSWORD_LENGTH` characters and at most 72 bytes, the
+//! most bcrypt looks at: anything longer would silently count only its
+//! first 72 bytes.
+//!
+//! ## Timing
+//!
+//! An unknown email, or an account whose hash is locked, is checked
+//! against `DUMMY_HASH`, so it takes as long as a wrong password and can't
+//! be told apart by the response time.
+
+use uuid::Uuid;
+
+use crate::models::User;
+use crate::repositories::{
+    CreateUserInput, Repository, RepositoryContext, RepositoryError, UpdateUserInput,
+    UserRepository,
+};
+
+/// Fewest characters a new password may have.
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Most bytes of a password bcrypt uses.
+const MAX_PASSWORD_BYTES: usize = 72;
+
+/// A bcrypt hash at `bcrypt::DEFAULT_COST` of a discarded random UUID,
+/// verified when the email is unknown.
+const DUMMY_HASH: &str = "$2b$12$GtvkmZ6fYzINvGIp.Dtqk.NSh9OIxlfY6/K2u4pFuvke1k0uIy1Ea";
+
+/// An account to create.
+pub struct RegisterInput {
+    pub first_name: String,
+    pub last_name: String,
+    /// Also the login name
+    pub email: String,
+    /// In plain text; only its hash is stored
+    pub password: String,
+}
+
+/// Logging in, signing up and changing passwords.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let auth = AuthService::new(ctx.clone());
+///
+/// match auth.authenticate(&email, &password).await? {
+///     Some(user) => println!("Welcome back, {}", user.first_name),
+///     None => println!("Wrong email or password"),
+/// }
+/// ```
+pub struct AuthService {
+    users: UserRepository,
+}
+
+impl AuthService {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            users: UserRepository::new(ctx),
+        }
+    }
+
+    /// The user with this email, if the password is theirs.
+    ///
+    /// # Returns
+    ///
+    /// `None` for an unknown email and for a wrong password alike, after
+    /// the same bcrypt work (see "Timing").
+    pub async fn authenticate(
+        &self,
+        email: &str,
+        password: &str,
+    ) -> Result<Option<User>, RepositoryError> {
+        let Some(user) = self.users.find_by_email(email).await? else {
+            verify(password, DUMMY_HASH).await;
+            return Ok(None);
+        };
+        if !verify(password, &user.password_hash).await {
+            return Ok(None);
+        }
+        Ok(Some(user))
+    }
+
+    /// Create an account.
+    ///
+    /// # Errors
+    ///
+    /// - `Validation` if the first name is blank, the email has no "@" or
+    ///   the password breaks the password rules
+    /// - `Duplicate` if there already is an account with this email
+    pub async fn register(&self, input: RegisterInput) -> Result<User, RepositoryError> {
+        let mut problems = Vec::new();
+        if input.first_name.trim().is_empty() {
+            problems.push("first name is required".to_string());
+        }
+        if !input.email.contains('@') {
+            problems.push("email must be an email address".to_string());
+        }
+        problems.extend(check_password(&input.password));
+        if !problems.is_empty() {
+            return Err(RepositoryError::Validation(problems.join("; ")));
+        }
+
+        let password_hash = hash(input.password).await?;
+        self.users
+            .create(CreateUserInput {
+                first_name: input.first_name,
+                last_name: input.last_name,
+                email: input.email,
+                password_hash,
+            })
+            .await
+    }
+
+    /// Replace the user's password, given their current one.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such user
+    /// - `Validation` if `current` is wrong or `new` breaks the password
+    ///   rules
+    pub async fn change_password(
+        &self,
+        user_id: Uuid,
+        current: &str,
+        new: String,
+    ) -> Result<User, RepositoryError> {
+        let user = self
+            .users
+            .find_by_id(user_id)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+        if !verify(current, &user.password_hash).await {
+            return Err(RepositoryError::Validation(
+                "current password is wrong".to_string(),
+            ));
+        }
+        if let Some(problem) = check_password(&new) {
+            return Err(RepositoryError::Validation(problem));
+        }
+
+        let password_hash = hash(new).await?;
+        self.users
+            .update(
+                user_id,
+                Some(user.version),
+                UpdateUserInput {
+                    first_name: None,
+                    last_name: None,
+                    email: None,
+                    password_hash: Some(password_hash),
+                },
+            )
+            .await
+    }
+}
+
+/// What is wrong with a new password, if anything.
+fn check_password(password: &str) -> Option<String> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        Some(format!(
+            "password must have at least {MIN_PASSWORD_LENGTH} characters"
+        ))
+    } else if password.len() > MAX_PASSWORD_BYTES {
+        Some(format!(
+            "password must have at most {MAX_PASSWORD_BYTES} bytes"
+        ))
+    } else {
+        None
+    }
+}
+
+/// `true` if the password matches the hash; `false` for a malformed hash,
+/// too (e.g. the locked password of a restored account), after checking
+/// it against `DUMMY_HASH` so that takes as long as a wrong password.
+async fn verify(password: &str, hash: &str) -> bool {
+    let well_formed = hash.parse::<bcrypt::HashParts>().is_ok();
+    let (password, hash) = (password.to_string(), hash.to_string());
+    tokio::task::spawn_blocking(move || {
+        if well_formed {
+            bcrypt::verify(password, &hash)
+        } else {
+            bcrypt::verify(password, DUMMY_HASH).map(|_| false)
+        }
+    })
+    .await
+    .is_ok_and(|result| result.unwrap_or(false))
+}
+
+/// Hash a password that passed `check_password`.
+///
+/// # Errors
+///
+/// - `Internal` if bcrypt fails; the password itself was already checked
+async fn hash(password: String) -> Result<String, RepositoryError> {
+    tokio::task::spawn_blocking(move || bcrypt::non_truncating_hash(password, bcrypt::DEFAULT_COST))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result.map_err(|e| e.to_string()))
+        .map_err(|e| RepositoryError::Internal(format!("password can't be hashed: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_dummy_hash_costs_as_much_as_a_real_one() {
+        // A malformed hash would fail at once and give unknown emails away
+        let parts: bcrypt::HashParts = DUMMY_HASH.parse().unwrap();
+        assert_eq!(parts.get_cost(), bcrypt::DEFAULT_COST);
+    }
+}
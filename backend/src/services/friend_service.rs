@@ -0,0 +1,288 @@
+//! # Friend Service
+//!
+//! A user's friends: reading and changing them, their attributes, their
+//! profiles, and merging duplicates.
+
+use std::collections::HashMap;
+
+use time::Date;
+use uuid::Uuid;
+
+use crate::models::{Friend, FriendAttribute};
+use crate::repositories::{
+    AttributeChanges, AttributeChoice, AttributeInput, CreateFriendInput,
+    FriendAttributeRepository, FriendMergeRepository, FriendProfile, FriendProfileRepository,
+    FriendRepository, MergeFriendsInput, MergeReport, Repository, RepositoryContext,
+    RepositoryError, Timeline, UnitOfWork, UpdateFriendInput,
+};
+
+use super::{owned, require_name};
+
+/// A friend to create, with their attributes and groups.
+#[derive(Default)]
+pub struct NewFriend {
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub date_of_birth: Option<Date>,
+    pub likes: Option<String>,
+    pub dislikes: Option<String>,
+    pub notes: Option<String>,
+    /// Attributes to give the friend; keys must be unique
+    pub attributes: Vec<AttributeInput>,
+    /// Groups of the same user to put the friend in
+    pub groups: Vec<Uuid>,
+}
+
+/// A user's friends.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let friends = FriendService::new(ctx.clone());
+///
+/// let friend = friends.get(user.id, friend_id).await?;
+/// let friend = friends
+///     .update(user.id, friend.id, Some(friend.version), patch)
+///     .await?;
+/// ```
+pub struct FriendService {
+    ctx: RepositoryContext,
+    friends: FriendRepository,
+    attributes: FriendAttributeRepository,
+    profiles: FriendProfileRepository,
+    merges: FriendMergeRepository,
+}
+
+impl FriendService {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            friends: FriendRepository::new(ctx.clone()),
+            attributes: FriendAttributeRepository::new(ctx.clone()),
+            profiles: FriendProfileRepository::new(ctx.clone()),
+            merges: FriendMergeRepository::new(ctx.clone()),
+            ctx,
+        }
+    }
+
+    /// The user's friend.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such friend or it isn't the user's
+    pub async fn get(&self, user_id: Uuid, friend_id: Uuid) -> Result<Friend, RepositoryError> {
+        owned(self.friends.find_by_id(friend_id).await?, user_id, |f| {
+            f.user_id
+        })
+    }
+
+    /// All of the user's friends.
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<Friend>, RepositoryError> {
+        self.friends.list_by_user(user_id).await
+    }
+
+    /// Create a friend, put them in their groups and give them their
+    /// attributes, all or nothing.
+    ///
+    /// # Errors
+    ///
+    /// - `Validation` if the first name is blank or an attribute key is
+    ///   empty or repeated
+    /// - `NotFound` if a group doesn't exist or isn't the user's
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let friend = friends
+    ///     .create(user.id, NewFriend {
+    ///         first_name: "Ada".to_string(),
+    ///         groups: vec![work.id],
+    ///         ..Default::default()
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn create(&self, user_id: Uuid, input: NewFriend) -> Result<Friend, RepositoryError> {
+        require_name("first name", &input.first_name)?;
+
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        for &group_id in &input.groups {
+            owned(work.groups().find_by_id(group_id).await?, user_id, |g| {
+                g.user_id
+            })?;
+        }
+
+        let friend = work
+            .friends()
+            .create(CreateFriendInput {
+                user_id,
+                first_name: input.first_name,
+                last_name: input.last_name,
+                date_of_birth: input.date_of_birth,
+                likes: input.likes,
+                dislikes: input.dislikes,
+                notes: input.notes,
+            })
+            .await?;
+        for &group_id in &input.groups {
+            work.friends().add_to_group(friend.id, group_id).await?;
+        }
+        if !input.attributes.is_empty() {
+            work.attributes()
+                .replace_all(friend.id, input.attributes)
+                .await?;
+        }
+
+        // Read back, in case the attributes moved the version on
+        let friend = work
+            .friends()
+            .find_by_id(friend.id)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+        work.commit().await?;
+        Ok(friend)
+    }
+
+    /// Change some of the user's friend's fields.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected_version` - The version the change was made against, if
+    ///   it must still be current (see `Repository::update`)
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such friend or it isn't the user's
+    /// - `Validation` if the first name is changed to a blank one
+    /// - `Conflict` if the friend is no longer at `expected_version`
+    pub async fn update(
+        &self,
+        user_id: Uuid,
+        friend_id: Uuid,
+        expected_version: Option<i64>,
+        input: UpdateFriendInput,
+    ) -> Result<Friend, RepositoryError> {
+        if let Some(first_name) = &input.first_name {
+            require_name("first name", first_name)?;
+        }
+
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        owned(work.friends().find_by_id(friend_id).await?, user_id, |f| {
+            f.user_id
+        })?;
+        let friend = work
+            .friends()
+            .update(friend_id, expected_version, input)
+            .await?;
+        work.commit().await?;
+        Ok(friend)
+    }
+
+    /// Move the user's friend to the trash.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such friend or it isn't the user's
+    pub async fn delete(&self, user_id: Uuid, friend_id: Uuid) -> Result<(), RepositoryError> {
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        owned(work.friends().find_by_id(friend_id).await?, user_id, |f| {
+            f.user_id
+        })?;
+        work.friends().delete(friend_id).await?;
+        work.commit().await
+    }
+
+    /// The user's friend's attributes, by key.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such friend or it isn't the user's
+    pub async fn attributes(
+        &self,
+        user_id: Uuid,
+        friend_id: Uuid,
+    ) -> Result<Vec<FriendAttribute>, RepositoryError> {
+        self.get(user_id, friend_id).await?;
+        self.attributes.list_by_friend(friend_id).await
+    }
+
+    /// Replace the user's friend's attributes with a complete set.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such friend or it isn't the user's
+    /// - `Validation` if a key is empty or repeated
+    pub async fn replace_attributes(
+        &self,
+        user_id: Uuid,
+        friend_id: Uuid,
+        attributes: Vec<AttributeInput>,
+    ) -> Result<AttributeChanges, RepositoryError> {
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        owned(work.friends().find_by_id(friend_id).await?, user_id, |f| {
+            f.user_id
+        })?;
+        let changes = work.attributes().replace_all(friend_id, attributes).await?;
+        work.commit().await?;
+        Ok(changes)
+    }
+
+    /// The user's friend with everything attached.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such friend or it isn't the user's
+    pub async fn profile(
+        &self,
+        user_id: Uuid,
+        friend_id: Uuid,
+        timeline: Timeline,
+    ) -> Result<FriendProfile, RepositoryError> {
+        owned(
+            self.profiles.find_by_id(friend_id, timeline).await?,
+            user_id,
+            |profile| profile.friend.user_id,
+        )
+    }
+
+    /// Every one of the user's friends with everything attached.
+    pub async fn profiles(
+        &self,
+        user_id: Uuid,
+        timeline: Timeline,
+    ) -> Result<Vec<FriendProfile>, RepositoryError> {
+        self.profiles.list_by_user(user_id, timeline).await
+    }
+
+    /// Merge the user's friend `other_id` into their friend `keep_id`.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if either friend doesn't exist or isn't the user's
+    /// - `Validation` if they are the same friend
+    pub async fn merge(
+        &self,
+        user_id: Uuid,
+        keep_id: Uuid,
+        other_id: Uuid,
+        attribute_choices: HashMap<String, AttributeChoice>,
+    ) -> Result<MergeReport, RepositoryError> {
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        for friend_id in [keep_id, other_id] {
+            owned(work.friends().find_by_id(friend_id).await?, user_id, |f| {
+                f.user_id
+            })?;
+        }
+        let report = self
+            .merges
+            .merge_in(
+                work.conn(),
+                MergeFriendsInput {
+                    keep_id,
+                    other_id,
+                    attribute_choices,
+                },
+            )
+            .await?;
+        work.commit().await?;
+        Ok(report)
+    }
+}
@@ -0,0 +1,197 @@
+//! # Group Service
+//!
+//! A user's groups and who is in them. A group only ever holds friends of
+//! the user who owns it.
+
+use uuid::Uuid;
+
+use crate::models::{Friend, Group};
+use crate::repositories::{
+    CreateGroupInput, GroupRepository, Repository, RepositoryContext, RepositoryError, UnitOfWork,
+    UpdateGroupInput,
+};
+
+use super::{owned, require_name};
+
+/// A group to create.
+pub struct NewGroup {
+    /// e.g. "Work", "Family"
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// A user's groups.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let groups = GroupService::new(ctx.clone());
+///
+/// let group = groups
+///     .create(user.id, NewGroup { name: "Climbing".to_string(), description: None })
+///     .await?;
+/// groups.add_member(user.id, group.id, friend.id).await?;
+/// ```
+pub struct GroupService {
+    ctx: RepositoryContext,
+    groups: GroupRepository,
+}
+
+impl GroupService {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            groups: GroupRepository::new(ctx.clone()),
+            ctx,
+        }
+    }
+
+    /// The user's group.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such group or it isn't the user's
+    pub async fn get(&self, user_id: Uuid, group_id: Uuid) -> Result<Group, RepositoryError> {
+        owned(self.groups.find_by_id(group_id).await?, user_id, |g| {
+            g.user_id
+        })
+    }
+
+    /// All of the user's groups, by name.
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<Group>, RepositoryError> {
+        self.groups.list_by_user(user_id).await
+    }
+
+    /// The friends in the user's group, by first name.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such group or it isn't the user's
+    pub async fn members(
+        &self,
+        user_id: Uuid,
+        group_id: Uuid,
+    ) -> Result<Vec<Friend>, RepositoryError> {
+        self.get(user_id, group_id).await?;
+        self.groups.list_friends(group_id).await
+    }
+
+    /// Create a group for the user.
+    ///
+    /// # Errors
+    ///
+    /// - `Validation` if the name is blank
+    pub async fn create(&self, user_id: Uuid, input: NewGroup) -> Result<Group, RepositoryError> {
+        require_name("name", &input.name)?;
+        self.groups
+            .create(CreateGroupInput {
+                user_id,
+                name: input.name,
+                description: input.description,
+            })
+            .await
+    }
+
+    /// Change the user's group.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such group or it isn't the user's
+    /// - `Validation` if the name is changed to a blank one
+    /// - `Conflict` if the group is no longer at `expected_version`
+    pub async fn update(
+        &self,
+        user_id: Uuid,
+        group_id: Uuid,
+        expected_version: Option<i64>,
+        input: UpdateGroupInput,
+    ) -> Result<Group, RepositoryError> {
+        if let Some(name) = &input.name {
+            require_name("name", name)?;
+        }
+
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        owned(work.groups().find_by_id(group_id).await?, user_id, |g| {
+            g.user_id
+        })?;
+        let group = work
+            .groups()
+            .update(group_id, expected_version, input)
+            .await?;
+        work.commit().await?;
+        Ok(group)
+    }
+
+    /// Delete the user's group. Its friends stay; only the membership goes.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such group or it isn't the user's
+    pub async fn delete(&self, user_id: Uuid, group_id: Uuid) -> Result<(), RepositoryError> {
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        owned(work.groups().find_by_id(group_id).await?, user_id, |g| {
+            g.user_id
+        })?;
+        work.groups().delete(group_id).await?;
+        work.commit().await
+    }
+
+    /// Put the user's friend in the user's group.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the group or the friend doesn't exist or isn't the
+    ///   user's
+    pub async fn add_member(
+        &self,
+        user_id: Uuid,
+        group_id: Uuid,
+        friend_id: Uuid,
+    ) -> Result<(), RepositoryError> {
+        let mut work = self.membership(user_id, group_id, friend_id).await?;
+        work.friends().add_to_group(friend_id, group_id).await?;
+        work.commit().await
+    }
+
+    /// Take the user's friend out of the user's group.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the friend was in the group.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the group or the friend doesn't exist or isn't the
+    ///   user's
+    pub async fn remove_member(
+        &self,
+        user_id: Uuid,
+        group_id: Uuid,
+        friend_id: Uuid,
+    ) -> Result<bool, RepositoryError> {
+        let mut work = self.membership(user_id, group_id, friend_id).await?;
+        let removed = work
+            .friends()
+            .remove_from_group(friend_id, group_id)
+            .await?;
+        work.commit().await?;
+        Ok(removed)
+    }
+
+    /// Start a unit of work for a membership change, once both the group
+    /// and the friend are known to be the user's.
+    async fn membership(
+        &self,
+        user_id: Uuid,
+        group_id: Uuid,
+        friend_id: Uuid,
+    ) -> Result<UnitOfWork, RepositoryError> {
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        owned(work.groups().find_by_id(group_id).await?, user_id, |g| {
+            g.user_id
+        })?;
+        owned(work.friends().find_by_id(friend_id).await?, user_id, |f| {
+            f.user_id
+        })?;
+        Ok(work)
+    }
+}
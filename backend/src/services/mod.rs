@@ -0,0 +1,68 @@
+//! # Services Module
+//!
+//! The business layer between the HTTP handlers and the repositories.
+//!
+//! ## Architecture
+//!
+//! ```text
+//! Controllers → Services → Repositories → Database
+//! ```
+//!
+//! Repositories know tables; services know users. Every service method
+//! takes the acting user and:
+//!
+//! 1. Enforces ownership: records of other users answer `NotFound`, the
+//!    same as records that don't exist, so IDs can't be probed
+//! 2. Validates input the database can't (blank names, passwords)
+//! 3. Applies the business rules spanning several records, e.g. both
+//!    friends in a relationship belong to the relationship's user
+//! 4. Runs multi-repository changes in one `UnitOfWork`, so they commit
+//!    or roll back as a whole
+//!
+//! Handlers for friends, groups, relationships, merges and logins then only
+//! translate between HTTP and service calls. Bulk changes, history and
+//! undo have no service: their repositories already take the acting user
+//! and only ever touch that user's rows.
+//!
+//! ## Services
+//!
+//! - `FriendService` - Friends, their attributes, profiles and merges
+//! - `GroupService` - Groups and their members
+//! - `RelationshipService` - Friend-to-friend and user-to-friend relationships
+//! - `AuthService` - Passwords: checking, registering, changing
+//!
+//! Errors are `RepositoryError`s, with `Validation` for rejected input.
+
+pub mod auth_service;
+pub mod friend_service;
+pub mod group_service;
+pub mod relationship_service;
+
+use uuid::Uuid;
+
+use crate::repositories::RepositoryError;
+
+pub use auth_service::{AuthService, MIN_PASSWORD_LENGTH, RegisterInput};
+pub use friend_service::{FriendService, NewFriend};
+pub use group_service::{GroupService, NewGroup};
+pub use relationship_service::{NewRelationship, RelationshipService};
+
+/// The record if `user_id` owns it, `NotFound` if not (or if there is no
+/// record).
+fn owned<T>(
+    record: Option<T>,
+    user_id: Uuid,
+    owner: impl Fn(&T) -> Uuid,
+) -> Result<T, RepositoryError> {
+    record
+        .filter(|record| owner(record) == user_id)
+        .ok_or(RepositoryError::NotFound)
+}
+
+/// `Validation` unless the name has something besides whitespace.
+fn require_name(field: &str, name: &str) -> Result<(), RepositoryError> {
+    if name.trim().is_empty() {
+        return Err(RepositoryError::Validation(format!("{field} is required")));
+    }
+    Ok(())
+}
@@ -0,0 +1,338 @@
+//! # Relationship Service
+//!
+//! Relationships between two of a user's friends ("Alice is Bob's
+//! sister") and between the user and a friend ("my coworker").
+//!
+//! ## Ownership
+//!
+//! A friend-to-friend relationship belongs to a user, and so must both of
+//! its friends. The service makes the acting user the owner and only
+//! accepts their own friends; the repository's validation (labels,
+//! self-relationships, duplicate pairs) runs on top. A user-to-friend
+//! relationship belongs to whoever owns its friend.
+
+use time::Date;
+use uuid::Uuid;
+
+use crate::kinship::{KinshipInference, KinshipReport};
+use crate::models::{FriendRelationship, UserFriendRelationship};
+use crate::repositories::{
+    CreateFriendRelationshipInput, CreateUserFriendRelationshipInput, FriendRelationshipRepository,
+    Repository, RepositoryContext, RepositoryError, Timeline, UnitOfWork,
+    UpdateFriendRelationshipInput, UpdateUserFriendRelationshipInput,
+    UserFriendRelationshipRepository,
+};
+
+use super::{FriendService, owned};
+
+/// A relationship between two of the user's friends.
+pub struct NewRelationship {
+    pub friend_a_id: Uuid,
+    pub friend_b_id: Uuid,
+    /// How A relates to B, e.g. "parent of"
+    pub a_to_b: String,
+    /// How B relates to A, e.g. "child of"; `None` means symmetric
+    pub b_to_a: Option<String>,
+    pub started_on: Option<Date>,
+    pub ended_on: Option<Date>,
+    /// Defaults to "active"
+    pub status: Option<String>,
+}
+
+/// A user's relationships.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let relationships = RelationshipService::new(ctx.clone());
+///
+/// relationships
+///     .create(user.id, NewRelationship {
+///         friend_a_id: alice.id,
+///         friend_b_id: bob.id,
+///         a_to_b: "sister of".to_string(),
+///         b_to_a: Some("brother of".to_string()),
+///         started_on: None,
+///         ended_on: None,
+///         status: None,
+///     })
+///     .await?;
+/// ```
+pub struct RelationshipService {
+    ctx: RepositoryContext,
+    friends: FriendService,
+    relationships: FriendRelationshipRepository,
+    user_relationships: UserFriendRelationshipRepository,
+    kinship: KinshipInference,
+}
+
+impl RelationshipService {
+    pub fn new(ctx: RepositoryContext) -> Self {
+        Self {
+            friends: FriendService::new(ctx.clone()),
+            relationships: FriendRelationshipRepository::new(ctx.clone()),
+            user_relationships: UserFriendRelationshipRepository::new(ctx.clone()),
+            kinship: KinshipInference::new(ctx.clone()),
+            ctx,
+        }
+    }
+
+    /// The user's relationship between two friends.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such relationship or it isn't the user's
+    pub async fn get(
+        &self,
+        user_id: Uuid,
+        relationship_id: Uuid,
+    ) -> Result<FriendRelationship, RepositoryError> {
+        owned(
+            self.relationships.find_by_id(relationship_id).await?,
+            user_id,
+            |r| r.user_id,
+        )
+    }
+
+    /// The relationships of the user's friend with their other friends.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such friend or it isn't the user's
+    pub async fn list(
+        &self,
+        user_id: Uuid,
+        friend_id: Uuid,
+        timeline: Timeline,
+    ) -> Result<Vec<FriendRelationship>, RepositoryError> {
+        self.friends.get(user_id, friend_id).await?;
+        self.relationships.list_by_friend(friend_id, timeline).await
+    }
+
+    /// The friend's stored relationships, plus the family relationships
+    /// they imply (see `kinship`).
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such friend or it isn't the user's
+    pub async fn kinship(
+        &self,
+        user_id: Uuid,
+        friend_id: Uuid,
+    ) -> Result<KinshipReport, RepositoryError> {
+        self.friends.get(user_id, friend_id).await?;
+        self.kinship.report_for_friend(user_id, friend_id).await
+    }
+
+    /// Relate two of the user's friends.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if either friend doesn't exist or isn't the user's
+    /// - `Validation` if the relationship is with the friend themselves,
+    ///   the labels contradict each other, or the two are already related
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        input: NewRelationship,
+    ) -> Result<FriendRelationship, RepositoryError> {
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        for friend_id in [input.friend_a_id, input.friend_b_id] {
+            owned(work.friends().find_by_id(friend_id).await?, user_id, |f| {
+                f.user_id
+            })?;
+        }
+
+        let relationship = work
+            .friend_relationships()
+            .create(CreateFriendRelationshipInput {
+                user_id,
+                friend_a_id: input.friend_a_id,
+                friend_b_id: input.friend_b_id,
+                a_to_b: input.a_to_b,
+                b_to_a: input.b_to_a,
+                started_on: input.started_on,
+                ended_on: input.ended_on,
+                status: input.status,
+            })
+            .await?;
+        work.commit().await?;
+        Ok(relationship)
+    }
+
+    /// Change the user's relationship between two friends.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such relationship or it isn't the user's
+    /// - `Validation` if the labels would contradict each other
+    /// - `Conflict` if the relationship is no longer at `expected_version`
+    pub async fn update(
+        &self,
+        user_id: Uuid,
+        relationship_id: Uuid,
+        expected_version: Option<i64>,
+        input: UpdateFriendRelationshipInput,
+    ) -> Result<FriendRelationship, RepositoryError> {
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        owned(
+            work.friend_relationships()
+                .find_by_id(relationship_id)
+                .await?,
+            user_id,
+            |r| r.user_id,
+        )?;
+        let relationship = work
+            .friend_relationships()
+            .update(relationship_id, expected_version, input)
+            .await?;
+        work.commit().await?;
+        Ok(relationship)
+    }
+
+    /// Delete the user's relationship between two friends.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such relationship or it isn't the user's
+    pub async fn delete(
+        &self,
+        user_id: Uuid,
+        relationship_id: Uuid,
+    ) -> Result<(), RepositoryError> {
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        owned(
+            work.friend_relationships()
+                .find_by_id(relationship_id)
+                .await?,
+            user_id,
+            |r| r.user_id,
+        )?;
+        work.friend_relationships().delete(relationship_id).await?;
+        work.commit().await
+    }
+
+    /// The user's relationships with their friend.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such friend or it isn't the user's
+    pub async fn list_user_relationships(
+        &self,
+        user_id: Uuid,
+        friend_id: Uuid,
+        timeline: Timeline,
+    ) -> Result<Vec<UserFriendRelationship>, RepositoryError> {
+        self.friends.get(user_id, friend_id).await?;
+        self.user_relationships
+            .list_by_friend(friend_id, timeline)
+            .await
+    }
+
+    /// The user's relationship with one of their friends.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such relationship or its friend isn't
+    ///   the user's
+    pub async fn get_user_relationship(
+        &self,
+        user_id: Uuid,
+        relationship_id: Uuid,
+    ) -> Result<UserFriendRelationship, RepositoryError> {
+        let relationship = self
+            .user_relationships
+            .find_by_id(relationship_id)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+        self.friends.get(user_id, relationship.friend_id).await?;
+        Ok(relationship)
+    }
+
+    /// Relate the user to their friend.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such friend or it isn't the user's
+    /// - `Duplicate` if the user already has a relationship of this type
+    ///   with the friend
+    pub async fn create_user_relationship(
+        &self,
+        user_id: Uuid,
+        input: CreateUserFriendRelationshipInput,
+    ) -> Result<UserFriendRelationship, RepositoryError> {
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        owned(
+            work.friends().find_by_id(input.friend_id).await?,
+            user_id,
+            |f| f.user_id,
+        )?;
+        let relationship = work.user_friend_relationships().create(input).await?;
+        work.commit().await?;
+        Ok(relationship)
+    }
+
+    /// Change the user's relationship with one of their friends.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such relationship or its friend isn't
+    ///   the user's
+    /// - `Duplicate` if the type is changed to one the user already has
+    ///   with the friend
+    /// - `Conflict` if the relationship is no longer at `expected_version`
+    pub async fn update_user_relationship(
+        &self,
+        user_id: Uuid,
+        relationship_id: Uuid,
+        expected_version: Option<i64>,
+        input: UpdateUserFriendRelationshipInput,
+    ) -> Result<UserFriendRelationship, RepositoryError> {
+        let mut work = self.own_user_relationship(user_id, relationship_id).await?;
+        let relationship = work
+            .user_friend_relationships()
+            .update(relationship_id, expected_version, input)
+            .await?;
+        work.commit().await?;
+        Ok(relationship)
+    }
+
+    /// Delete the user's relationship with one of their friends.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if there is no such relationship or its friend isn't
+    ///   the user's
+    pub async fn delete_user_relationship(
+        &self,
+        user_id: Uuid,
+        relationship_id: Uuid,
+    ) -> Result<(), RepositoryError> {
+        let mut work = self.own_user_relationship(user_id, relationship_id).await?;
+        work.user_friend_relationships()
+            .delete(relationship_id)
+            .await?;
+        work.commit().await
+    }
+
+    /// Start a unit of work for a change to a user-to-friend relationship,
+    /// once its friend is known to be the user's.
+    async fn own_user_relationship(
+        &self,
+        user_id: Uuid,
+        relationship_id: Uuid,
+    ) -> Result<UnitOfWork, RepositoryError> {
+        let mut work = UnitOfWork::begin(&self.ctx).await?;
+        let relationship = work
+            .user_friend_relationships()
+            .find_by_id(relationship_id)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+        owned(
+            work.friends().find_by_id(relationship.friend_id).await?,
+            user_id,
+            |f| f.user_id,
+        )?;
+        Ok(work)
+    }
+}
@@ -0,0 +1,11 @@
+//! # Validation Module
+//!
+//! Rules for what may be stored, without any database access.
+//!
+//! The repositories apply them to every write, and the `doctor` to data
+//! already stored (rows from before a rule existed, or inserted by hand),
+//! so both always agree on what is valid.
+
+pub mod relationships;
+
+pub use relationships::RelationshipIssue;
@@ -0,0 +1,245 @@
+//! # Relationship Rules
+//!
+//! Pure checks for `friend_relationships` rows: what a single row may
+//! say, and whose friends it may link.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::kinship::Kinship;
+
+/// A single consistency problem with a relationship.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "issue", rename_all = "snake_case")]
+pub enum RelationshipIssue {
+    /// `friend_a_id` and `friend_b_id` are the same friend
+    SelfRelationship,
+
+    /// The two directions disagree, e.g. "parent of" both ways, or an
+    /// asymmetric family label like "parent of" with a NULL `b_to_a`
+    ContradictoryLabels {
+        a_to_b: String,
+        b_to_a: Option<String>,
+    },
+
+    /// Another relationship already links the same two friends
+    /// (in either direction)
+    DuplicatePair { duplicate_of: Uuid },
+
+    /// Another relationship between the same two friends asserts a
+    /// different family relationship (e.g., A parent of B vs B parent of A)
+    ConflictingPair { conflicts_with: Uuid },
+
+    /// The user already knows the friend this way through another
+    /// relationship (user relationships only)
+    DuplicateType { duplicate_of: Uuid },
+
+    /// One of the friends is owned by a different user than the
+    /// relationship (`owner_id` is None if the friend doesn't exist)
+    ForeignFriend {
+        friend_id: Uuid,
+        owner_id: Option<Uuid>,
+    },
+}
+
+impl fmt::Display for RelationshipIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelationshipIssue::SelfRelationship => {
+                write!(f, "a friend cannot have a relationship with themselves")
+            }
+            RelationshipIssue::ContradictoryLabels { a_to_b, b_to_a } => match b_to_a {
+                Some(b_to_a) => write!(f, "\"{a_to_b}\" contradicts \"{b_to_a}\""),
+                None => write!(f, "\"{a_to_b}\" is not symmetric but no b_to_a was given"),
+            },
+            RelationshipIssue::DuplicatePair { duplicate_of } => {
+                write!(f, "these friends are already related by {duplicate_of}")
+            }
+            RelationshipIssue::DuplicateType { duplicate_of } => {
+                write!(f, "the same relationship type as {duplicate_of}")
+            }
+            RelationshipIssue::ConflictingPair { conflicts_with } => {
+                write!(f, "conflicts with relationship {conflicts_with}")
+            }
+            RelationshipIssue::ForeignFriend {
+                friend_id,
+                owner_id,
+            } => match owner_id {
+                Some(_) => write!(f, "friend {friend_id} belongs to a different user"),
+                None => write!(f, "friend {friend_id} does not exist"),
+            },
+        }
+    }
+}
+
+/// The family kinds a row asserts, oriented as (friend_a → friend_b).
+///
+/// # Returns
+///
+/// `(forward, reverse)` where `forward` is what A is to B and `reverse` is
+/// what B is to A. A NULL `b_to_a` means symmetric, so `reverse` repeats
+/// `forward` in that case. Non-family labels map to `None`.
+///
+/// The kinship engine reads stored rows through this too, so inference
+/// and validation agree on what a row says.
+pub fn family_kinds(a_to_b: &str, b_to_a: Option<&str>) -> (Option<Kinship>, Option<Kinship>) {
+    let forward = Kinship::from_label(a_to_b);
+    let reverse = match b_to_a {
+        Some(label) => Kinship::from_label(label),
+        None => forward,
+    };
+    (forward, reverse)
+}
+
+/// Run the checks that only need the row itself.
+///
+/// - Self-relationships
+/// - Contradictory labels (family labels only - freeform labels like
+///   "boss of" / "employee of" can't be checked)
+///
+/// # Arguments
+///
+/// * `friend_a_id` / `friend_b_id` - The two friends
+/// * `a_to_b` / `b_to_a` - The labels as they would be stored
+pub fn check_row(
+    friend_a_id: Uuid,
+    friend_b_id: Uuid,
+    a_to_b: &str,
+    b_to_a: Option<&str>,
+) -> Vec<RelationshipIssue> {
+    let mut issues = Vec::new();
+
+    if friend_a_id == friend_b_id {
+        issues.push(RelationshipIssue::SelfRelationship);
+    }
+    issues.extend(check_labels(a_to_b, b_to_a));
+
+    issues
+}
+
+/// Check that both directions of a relationship agree.
+///
+/// If both labels are recognized family labels, B's relation to A must
+/// be the inverse of A's relation to B ("parent of" ↔ "child of").
+pub fn check_labels(a_to_b: &str, b_to_a: Option<&str>) -> Option<RelationshipIssue> {
+    match family_kinds(a_to_b, b_to_a) {
+        (Some(forward), Some(reverse)) if forward.inverse() != reverse => {
+            Some(RelationshipIssue::ContradictoryLabels {
+                a_to_b: a_to_b.to_string(),
+                b_to_a: b_to_a.map(str::to_string),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Check that both friends are owned by the relationship's user.
+///
+/// # Arguments
+///
+/// * `owners` - Map of friend ID → owning user ID (missing = doesn't exist)
+pub fn check_ownership(
+    user_id: Uuid,
+    friend_ids: [Uuid; 2],
+    owners: &HashMap<Uuid, Uuid>,
+) -> Vec<RelationshipIssue> {
+    let mut friend_ids = friend_ids.to_vec();
+    friend_ids.dedup();
+
+    friend_ids
+        .into_iter()
+        .filter_map(|friend_id| {
+            let owner_id = owners.get(&friend_id).copied();
+            (owner_id != Some(user_id)).then_some(RelationshipIssue::ForeignFriend {
+                friend_id,
+                owner_id,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADA: Uuid = Uuid::from_u128(1);
+    const BOB: Uuid = Uuid::from_u128(2);
+    const USER: Uuid = Uuid::from_u128(10);
+    const OTHER_USER: Uuid = Uuid::from_u128(11);
+
+    #[test]
+    fn a_friend_cannot_be_related_to_themselves() {
+        assert_eq!(
+            check_row(ADA, ADA, "friend of", None),
+            vec![RelationshipIssue::SelfRelationship]
+        );
+        assert_eq!(check_row(ADA, BOB, "friend of", None), vec![]);
+    }
+
+    #[test]
+    fn family_labels_must_be_inverses() {
+        assert_eq!(check_labels("mother of", Some("son of")), None);
+        assert_eq!(check_labels("Sister of", Some("brother of")), None);
+        assert_eq!(
+            check_labels("parent of", Some("parent of")),
+            Some(RelationshipIssue::ContradictoryLabels {
+                a_to_b: "parent of".to_string(),
+                b_to_a: Some("parent of".to_string()),
+            })
+        );
+        assert!(check_labels("sibling of", Some("child of")).is_some());
+    }
+
+    #[test]
+    fn a_missing_b_to_a_means_symmetric() {
+        assert_eq!(
+            family_kinds("parent of", None),
+            (Some(Kinship::Parent), Some(Kinship::Parent))
+        );
+        assert_eq!(check_labels("spouse of", None), None);
+        assert_eq!(
+            check_labels("parent of", None),
+            Some(RelationshipIssue::ContradictoryLabels {
+                a_to_b: "parent of".to_string(),
+                b_to_a: None,
+            })
+        );
+    }
+
+    #[test]
+    fn freeform_labels_are_not_checked() {
+        assert_eq!(check_labels("boss of", Some("boss of")), None);
+        assert_eq!(check_labels("boss of", None), None);
+        // Only one side is a family label, so there's nothing to compare
+        assert_eq!(check_labels("parent of", Some("mentor of")), None);
+        assert_eq!(
+            family_kinds("parent of", Some("mentor of")),
+            (Some(Kinship::Parent), None)
+        );
+    }
+
+    #[test]
+    fn both_friends_must_belong_to_the_user() {
+        let owners = HashMap::from([(ADA, USER), (BOB, OTHER_USER)]);
+        assert_eq!(check_ownership(USER, [ADA, ADA], &owners), vec![]);
+        assert_eq!(
+            check_ownership(USER, [ADA, BOB], &owners),
+            vec![RelationshipIssue::ForeignFriend {
+                friend_id: BOB,
+                owner_id: Some(OTHER_USER),
+            }]
+        );
+
+        let missing = Uuid::from_u128(3);
+        assert_eq!(
+            check_ownership(USER, [missing, missing], &owners),
+            vec![RelationshipIssue::ForeignFriend {
+                friend_id: missing,
+                owner_id: None,
+            }]
+        );
+    }
+}